@@ -0,0 +1,475 @@
+//! HTTP remote control for a media player.
+//!
+//! Serves a JSON API and a small web page for controlling the player from a
+//! phone or another computer. Every `/api` route needs the token given to
+//! [`HttpRemote::bind`], as `Authorization: Bearer <token>` or as a `token`
+//! query parameter (which is how the page and `EventSource` send it).
+//!
+//! | Route | Body | Command |
+//! |---|---|---|
+//! | `GET /` | | the web page |
+//! | `GET /api/status` | | the last [`HttpRemote::update`] |
+//! | `GET /api/events` | | server-sent `status` events |
+//! | `GET /api/search?q=&limit=` | | [`Command::Search`] |
+//! | `POST /api/play` | `{"id"}` | [`Command::Play`] |
+//! | `POST /api/queue` | `{"id"}` | [`Command::Enqueue`] |
+//! | `POST /api/queue/next` | `{"id"}` | [`Command::PlayNext`] |
+//! | `POST /api/queue/{index}/play` | | [`Command::PlayIndex`] |
+//! | `POST /api/queue/move` | `{"from", "to"}` | [`Command::Move`] |
+//! | `DELETE /api/queue/{index}` | | [`Command::Remove`] |
+//! | `DELETE /api/queue` | | [`Command::Clear`] |
+//! | `POST /api/{pause,resume,next,previous,stop}` | | the same |
+//! | `POST /api/seek` | `{"secs"}` | [`Command::Seek`] |
+//! | `POST /api/volume` | `{"level"}`, `0.0..=1.0` | [`Command::SetVolume`] |
+//! | `POST /api/shuffle` | `{"on"}` | [`Command::SetShuffle`] |
+//! | `POST /api/repeat` | `{"mode"}`: `off`, `all` or `one` | [`Command::SetRepeat`] |
+//!
+//! Replies are `{"ok": true, "result": ...}` or `{"ok": false, "error": ...}`.
+//! Commands arrive as [`Request`]s on the channel given to
+//! [`HttpRemote::bind`]; whatever their reply carries becomes `result`.
+
+use std::convert::Infallible;
+use std::io;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use bytes::Bytes;
+use http_body::Frame;
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full, Limited};
+use hyper::body::Incoming;
+use hyper::header::{AUTHORIZATION, CACHE_CONTROL, CONTENT_TYPE};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde_json::{Value, json};
+use tokio::net::{TcpListener, ToSocketAddrs};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::JoinHandle;
+
+/// The web page served at `/`.
+const INDEX: &str = include_str!("../static/index.html");
+
+/// Largest request body read.
+const MAX_BODY: usize = 64 * 1024;
+
+/// Comment sent on an idle event stream so proxies keep it open.
+const KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+/// Search results returned when the request sets no `limit`.
+const DEFAULT_SEARCH_LIMIT: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repeat {
+    Off,
+    All,
+    One,
+}
+
+impl Repeat {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "off" => Some(Self::Off),
+            "all" => Some(Self::All),
+            "one" => Some(Self::One),
+            _ => None,
+        }
+    }
+}
+
+/// What clients ask of the player.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Play this video now, on its own.
+    Play(String),
+    /// Add this video to the end of the queue.
+    Enqueue(String),
+    /// Add this video right after the current one.
+    PlayNext(String),
+    /// Play the queue entry at this index.
+    PlayIndex(usize),
+    Remove(usize),
+    Move {
+        from: usize,
+        to: usize,
+    },
+    Clear,
+    Pause,
+    Resume,
+    Next,
+    Previous,
+    Stop,
+    Seek(f64),
+    /// `0.0..=1.0`.
+    SetVolume(f64),
+    SetShuffle(bool),
+    SetRepeat(Repeat),
+    Search {
+        query: String,
+        limit: usize,
+    },
+}
+
+/// A [`Command`] and where its outcome goes.
+#[derive(Debug)]
+pub struct Request {
+    pub command: Command,
+    pub reply: oneshot::Sender<Result<Value, String>>,
+}
+
+type Body = BoxBody<Bytes, Infallible>;
+type Response = hyper::Response<Body>;
+
+struct Shared {
+    token: String,
+    status: watch::Receiver<Value>,
+    tx: mpsc::UnboundedSender<Request>,
+}
+
+/// A running server; stops accepting connections when dropped.
+pub struct HttpRemote {
+    status: watch::Sender<Value>,
+    local_addr: SocketAddr,
+    accept: JoinHandle<()>,
+}
+
+impl HttpRemote {
+    /// Listen on `addr`, accept requests carrying `token` and forward their
+    /// commands to `tx`.
+    pub async fn bind(
+        addr: impl ToSocketAddrs,
+        token: impl Into<String>,
+        tx: mpsc::UnboundedSender<Request>,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let (status, status_rx) = watch::channel(Value::Null);
+        let shared = Arc::new(Shared {
+            token: token.into(),
+            status: status_rx,
+            tx,
+        });
+        let accept = tokio::spawn(accept_loop(listener, shared));
+        Ok(Self {
+            status,
+            local_addr,
+            accept,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Publish the player state; event streams get it if it changed.
+    pub fn update(&self, status: Value) {
+        self.status.send_if_modified(|current| {
+            let changed = *current != status;
+            *current = status;
+            changed
+        });
+    }
+}
+
+impl Drop for HttpRemote {
+    fn drop(&mut self) {
+        self.accept.abort();
+    }
+}
+
+async fn accept_loop(listener: TcpListener, shared: Arc<Shared>) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                tracing::warn!(error = %e, "HTTP accept failed");
+                continue;
+            }
+        };
+        let shared = Arc::clone(&shared);
+        tokio::spawn(async move {
+            let service = service_fn(move |req| {
+                let shared = Arc::clone(&shared);
+                async move { Ok::<_, Infallible>(shared.route(req).await) }
+            });
+            if let Err(e) = http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .await
+            {
+                tracing::debug!(error = %e, "HTTP connection ended");
+            }
+        });
+    }
+}
+
+fn full(body: impl Into<Bytes>) -> Body {
+    Full::new(body.into()).boxed()
+}
+
+fn reply(status: StatusCode, body: &Value) -> Response {
+    hyper::Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, "application/json")
+        .body(full(body.to_string()))
+        .unwrap_or_default()
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    reply(status, &json!({ "ok": false, "error": message }))
+}
+
+fn bad_request(message: &str) -> Response {
+    error(StatusCode::BAD_REQUEST, message)
+}
+
+impl Shared {
+    async fn route(&self, req: hyper::Request<Incoming>) -> Response {
+        let method = req.method().clone();
+        let path = req.uri().path().to_owned();
+        let params = req.uri().query().unwrap_or_default().to_owned();
+
+        if method == Method::GET && (path == "/" || path == "/index.html") {
+            return hyper::Response::builder()
+                .header(CONTENT_TYPE, "text/html; charset=utf-8")
+                .body(full(INDEX))
+                .unwrap_or_default();
+        }
+        let Some(route) = path.strip_prefix("/api/") else {
+            return error(StatusCode::NOT_FOUND, "not found");
+        };
+        let header_token = req
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .map(str::to_owned);
+        let token = header_token.or_else(|| query_param(&params, "token"));
+        if !token.is_some_and(|t| same(&t, &self.token)) {
+            return error(StatusCode::UNAUTHORIZED, "missing or wrong token");
+        }
+
+        let body = match Limited::new(req.into_body(), MAX_BODY).collect().await {
+            Ok(body) => body.to_bytes(),
+            Err(_) => return bad_request("unreadable body"),
+        };
+        let body: Value = if body.is_empty() {
+            Value::Null
+        } else {
+            match serde_json::from_slice(&body) {
+                Ok(body) => body,
+                Err(e) => return bad_request(&format!("invalid JSON: {e}")),
+            }
+        };
+
+        let segments: Vec<&str> = route.split('/').collect();
+        let command = match (&method, segments.as_slice()) {
+            (&Method::GET, ["status"]) => {
+                let status = self.status.borrow().clone();
+                return reply(StatusCode::OK, &json!({ "ok": true, "result": status }));
+            }
+            (&Method::GET, ["events"]) => return self.events(),
+            (&Method::GET, ["search"]) => {
+                let Some(query) = query_param(&params, "q").filter(|q| !q.trim().is_empty()) else {
+                    return bad_request("missing `q`");
+                };
+                let limit = query_param(&params, "limit")
+                    .and_then(|n| n.parse().ok())
+                    .unwrap_or(DEFAULT_SEARCH_LIMIT);
+                Command::Search { query, limit }
+            }
+            (&Method::POST, ["play"]) => match video_id(&body) {
+                Some(id) => Command::Play(id),
+                None => return bad_request("missing `id`"),
+            },
+            (&Method::POST, ["queue"]) => match video_id(&body) {
+                Some(id) => Command::Enqueue(id),
+                None => return bad_request("missing `id`"),
+            },
+            (&Method::POST, ["queue", "next"]) => match video_id(&body) {
+                Some(id) => Command::PlayNext(id),
+                None => return bad_request("missing `id`"),
+            },
+            (&Method::POST, ["queue", "move"]) => {
+                match (index(&body["from"]), index(&body["to"])) {
+                    (Some(from), Some(to)) => Command::Move { from, to },
+                    _ => return bad_request("needs `from` and `to` indices"),
+                }
+            }
+            (&Method::POST, ["queue", at, "play"]) => match at.parse() {
+                Ok(at) => Command::PlayIndex(at),
+                Err(_) => return bad_request("invalid queue index"),
+            },
+            (&Method::DELETE, ["queue", at]) => match at.parse() {
+                Ok(at) => Command::Remove(at),
+                Err(_) => return bad_request("invalid queue index"),
+            },
+            (&Method::DELETE, ["queue"]) => Command::Clear,
+            (&Method::POST, ["pause"]) => Command::Pause,
+            (&Method::POST, ["resume"]) => Command::Resume,
+            (&Method::POST, ["next"]) => Command::Next,
+            (&Method::POST, ["previous"]) => Command::Previous,
+            (&Method::POST, ["stop"]) => Command::Stop,
+            (&Method::POST, ["seek"]) => match body["secs"].as_f64() {
+                Some(secs) if secs >= 0. => Command::Seek(secs),
+                _ => return bad_request("needs a non-negative `secs`"),
+            },
+            (&Method::POST, ["volume"]) => match body["level"].as_f64() {
+                Some(level) if (0. ..=1.).contains(&level) => Command::SetVolume(level),
+                _ => return bad_request("needs a `level` between 0 and 1"),
+            },
+            (&Method::POST, ["shuffle"]) => match body["on"].as_bool() {
+                Some(on) => Command::SetShuffle(on),
+                None => return bad_request("needs `on`"),
+            },
+            (&Method::POST, ["repeat"]) => match body["mode"].as_str().and_then(Repeat::parse) {
+                Some(mode) => Command::SetRepeat(mode),
+                None => return bad_request("`mode` must be off, all or one"),
+            },
+            _ => return error(StatusCode::NOT_FOUND, "not found"),
+        };
+
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let request = Request {
+            command,
+            reply: reply_tx,
+        };
+        if self.tx.send(request).is_err() {
+            return error(StatusCode::SERVICE_UNAVAILABLE, "player is not running");
+        }
+        match reply_rx.await {
+            Ok(Ok(result)) => reply(StatusCode::OK, &json!({ "ok": true, "result": result })),
+            Ok(Err(message)) => error(StatusCode::INTERNAL_SERVER_ERROR, &message),
+            Err(_) => error(StatusCode::SERVICE_UNAVAILABLE, "player is not running"),
+        }
+    }
+
+    /// Server-sent events: the current status right away, then every change.
+    fn events(&self) -> Response {
+        let (tx, rx) = mpsc::channel::<Bytes>(8);
+        let mut status = self.status.clone();
+        tokio::spawn(async move {
+            let event = |status: &Value| Bytes::from(format!("event: status\ndata: {status}\n\n"));
+            let first = event(&status.borrow_and_update());
+            if tx.send(first).await.is_err() {
+                return;
+            }
+            loop {
+                let chunk = tokio::select! {
+                    changed = status.changed() => match changed {
+                        Ok(()) => event(&status.borrow_and_update()),
+                        Err(_) => return,
+                    },
+                    () = tokio::time::sleep(KEEP_ALIVE) => Bytes::from_static(b": keep-alive\n\n"),
+                };
+                // The client went away.
+                if tx.send(chunk).await.is_err() {
+                    return;
+                }
+            }
+        });
+        hyper::Response::builder()
+            .header(CONTENT_TYPE, "text/event-stream")
+            .header(CACHE_CONTROL, "no-cache")
+            .body(EventStream(rx).boxed())
+            .unwrap_or_default()
+    }
+}
+
+/// Body of an event stream, fed by the task writing its events.
+struct EventStream(mpsc::Receiver<Bytes>);
+
+impl http_body::Body for EventStream {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+        self.0
+            .poll_recv(cx)
+            .map(|chunk| chunk.map(|c| Ok(Frame::data(c))))
+    }
+}
+
+fn video_id(body: &Value) -> Option<String> {
+    body["id"]
+        .as_str()
+        .filter(|id| !id.trim().is_empty())
+        .map(str::to_owned)
+}
+
+fn index(value: &Value) -> Option<usize> {
+    value.as_u64().and_then(|n| usize::try_from(n).ok())
+}
+
+/// Compare tokens without returning early at the first difference.
+fn same(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+/// The percent-decoded value of `key` in a query string.
+fn query_param(query: &str, key: &str) -> Option<String> {
+    query.split('&').find_map(|pair| {
+        let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+        (decode(k) == key).then(|| decode(v))
+    })
+}
+
+fn decode(s: &str) -> String {
+    let mut out = Vec::with_capacity(s.len());
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'+' => out.push(b' '),
+            b'%' => {
+                let hex = [bytes.next(), bytes.next()];
+                let decoded = match hex {
+                    [Some(h), Some(l)] => std::str::from_utf8(&[h, l])
+                        .ok()
+                        .and_then(|h| u8::from_str_radix(h, 16).ok()),
+                    _ => None,
+                };
+                match decoded {
+                    Some(d) => out.push(d),
+                    None => out.extend([Some(b'%'), hex[0], hex[1]].into_iter().flatten()),
+                }
+            }
+            _ => out.push(b),
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_params() {
+        let query = "token=a%2Bb&q=daft+punk%21&empty&limit=5";
+        assert_eq!(query_param(query, "token").as_deref(), Some("a+b"));
+        assert_eq!(query_param(query, "q").as_deref(), Some("daft punk!"));
+        assert_eq!(query_param(query, "empty").as_deref(), Some(""));
+        assert_eq!(query_param(query, "missing"), None);
+        assert_eq!(decode("100%"), "100%");
+        assert_eq!(decode("%zz%41"), "%zzA");
+        assert_eq!(decode("caf%C3%A9"), "café");
+    }
+
+    #[test]
+    fn token_comparison() {
+        assert!(same("secret", "secret"));
+        assert!(!same("secret", "secreT"));
+        assert!(!same("secret", "secrets"));
+        assert!(!same("", "secret"));
+    }
+}
@@ -0,0 +1,263 @@
+//! Raw HTTP/1.1 requests against a server whose player replies with a
+//! description of each command it got.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use http_remote::{Command, HttpRemote, Repeat};
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc::{UnboundedReceiver, unbounded_channel};
+
+const TOKEN: &str = "s3cret";
+
+struct Fixture {
+    server: HttpRemote,
+    /// Every command the player got, in order.
+    commands: UnboundedReceiver<Command>,
+}
+
+async fn start() -> Fixture {
+    let (tx, mut rx) = unbounded_channel::<http_remote::Request>();
+    let (seen_tx, commands) = unbounded_channel();
+    let server = HttpRemote::bind("127.0.0.1:0", TOKEN, tx).await.unwrap();
+    tokio::spawn(async move {
+        while let Some(request) = rx.recv().await {
+            let result = match &request.command {
+                Command::Play(id) if id == "missing" => Err("no such video".to_owned()),
+                Command::Search { query, limit } => {
+                    Ok(json!([{ "video_id": "abc", "title": query, "limit": limit }]))
+                }
+                _ => Ok(Value::Null),
+            };
+            seen_tx.send(request.command).ok();
+            request.reply.send(result).ok();
+        }
+    });
+    Fixture { server, commands }
+}
+
+/// Send one request and return its status code and body.
+async fn send(addr: SocketAddr, method: &str, path: &str, body: Option<Value>) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let body = body.map(|b| b.to_string()).unwrap_or_default();
+    let request = format!(
+        "{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+         Content-Length: {}\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    let code = response[9..12].parse().unwrap();
+    let body = response
+        .split_once("\r\n\r\n")
+        .map(|(_, body)| body.to_owned())
+        .unwrap_or_default();
+    (code, body)
+}
+
+/// Like [`send`], with the token and a JSON reply.
+async fn api(addr: SocketAddr, method: &str, path: &str, body: Option<Value>) -> (u16, Value) {
+    let sep = if path.contains('?') { '&' } else { '?' };
+    let path = format!("/api/{path}{sep}token={TOKEN}");
+    let (code, body) = send(addr, method, &path, body).await;
+    (code, serde_json::from_str(&body).unwrap())
+}
+
+#[tokio::test]
+async fn serves_the_page_without_a_token() {
+    let fixture = start().await;
+    let (code, body) = send(fixture.server.local_addr(), "GET", "/", None).await;
+    assert_eq!(code, 200);
+    assert!(body.contains("EventSource"));
+}
+
+#[tokio::test]
+async fn rejects_missing_or_wrong_tokens() {
+    let fixture = start().await;
+    let addr = fixture.server.local_addr();
+    assert_eq!(send(addr, "GET", "/api/status", None).await.0, 401);
+    assert_eq!(
+        send(addr, "GET", "/api/status?token=nope", None).await.0,
+        401
+    );
+
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    let request = format!(
+        "GET /api/status HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+         Authorization: Bearer {TOKEN}\r\n\r\n"
+    );
+    stream.write_all(request.as_bytes()).await.unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).await.unwrap();
+    assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+}
+
+#[tokio::test]
+async fn reports_the_last_status() {
+    let fixture = start().await;
+    let addr = fixture.server.local_addr();
+    fixture
+        .server
+        .update(json!({ "state": "playing", "position": 3.0 }));
+    let (code, body) = api(addr, "GET", "status", None).await;
+    assert_eq!(code, 200);
+    assert_eq!(
+        body,
+        json!({ "ok": true, "result": { "state": "playing", "position": 3.0 } })
+    );
+}
+
+#[tokio::test]
+async fn maps_routes_to_commands() {
+    let mut fixture = start().await;
+    let addr = fixture.server.local_addr();
+    let calls = [
+        (
+            "POST",
+            "play",
+            Some(json!({ "id": "abc" })),
+            Command::Play("abc".into()),
+        ),
+        (
+            "POST",
+            "queue",
+            Some(json!({ "id": "def" })),
+            Command::Enqueue("def".into()),
+        ),
+        (
+            "POST",
+            "queue/next",
+            Some(json!({ "id": "ghi" })),
+            Command::PlayNext("ghi".into()),
+        ),
+        ("POST", "queue/2/play", None, Command::PlayIndex(2)),
+        (
+            "POST",
+            "queue/move",
+            Some(json!({ "from": 0, "to": 3 })),
+            Command::Move { from: 0, to: 3 },
+        ),
+        ("DELETE", "queue/1", None, Command::Remove(1)),
+        ("DELETE", "queue", None, Command::Clear),
+        ("POST", "pause", None, Command::Pause),
+        ("POST", "resume", None, Command::Resume),
+        ("POST", "next", None, Command::Next),
+        ("POST", "previous", None, Command::Previous),
+        ("POST", "stop", None, Command::Stop),
+        (
+            "POST",
+            "seek",
+            Some(json!({ "secs": 12.5 })),
+            Command::Seek(12.5),
+        ),
+        (
+            "POST",
+            "volume",
+            Some(json!({ "level": 0.25 })),
+            Command::SetVolume(0.25),
+        ),
+        (
+            "POST",
+            "shuffle",
+            Some(json!({ "on": true })),
+            Command::SetShuffle(true),
+        ),
+        (
+            "POST",
+            "repeat",
+            Some(json!({ "mode": "one" })),
+            Command::SetRepeat(Repeat::One),
+        ),
+    ];
+    for (method, path, body, expected) in calls {
+        let (code, reply) = api(addr, method, path, body).await;
+        assert_eq!(
+            (code, reply),
+            (200, json!({ "ok": true, "result": null })),
+            "{path}"
+        );
+        assert_eq!(fixture.commands.recv().await.unwrap(), expected);
+    }
+}
+
+#[tokio::test]
+async fn searches_with_a_decoded_query() {
+    let mut fixture = start().await;
+    let addr = fixture.server.local_addr();
+    let (code, reply) = api(addr, "GET", "search?q=daft+punk%21&limit=5", None).await;
+    assert_eq!(code, 200);
+    assert_eq!(reply["result"][0]["title"], "daft punk!");
+    assert_eq!(
+        fixture.commands.recv().await.unwrap(),
+        Command::Search {
+            query: "daft punk!".into(),
+            limit: 5
+        }
+    );
+    assert_eq!(api(addr, "GET", "search?q=", None).await.0, 400);
+}
+
+#[tokio::test]
+async fn rejects_bad_arguments_without_calling_the_player() {
+    let mut fixture = start().await;
+    let addr = fixture.server.local_addr();
+    let bad = [
+        ("POST", "play", Some(json!({}))),
+        ("POST", "seek", Some(json!({ "secs": -1 }))),
+        ("POST", "volume", Some(json!({ "level": 1.5 }))),
+        ("POST", "repeat", Some(json!({ "mode": "sometimes" }))),
+        ("POST", "queue/move", Some(json!({ "from": 1 }))),
+        ("DELETE", "queue/first", None),
+    ];
+    for (method, path, body) in bad {
+        let (code, reply) = api(addr, method, path, body).await;
+        assert_eq!(code, 400, "{path}");
+        assert_eq!(reply["ok"], false);
+    }
+    assert_eq!(api(addr, "GET", "nowhere", None).await.0, 404);
+    assert_eq!(api(addr, "GET", "pause", None).await.0, 404);
+
+    let (code, reply) = api(addr, "POST", "play", Some(json!({ "id": "missing" }))).await;
+    assert_eq!(
+        (code, reply),
+        (500, json!({ "ok": false, "error": "no such video" }))
+    );
+    // Only the last request reached the player.
+    assert_eq!(
+        fixture.commands.recv().await.unwrap(),
+        Command::Play("missing".into())
+    );
+    assert!(fixture.commands.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn streams_status_changes() {
+    let fixture = start().await;
+    fixture.server.update(json!({ "position": 1 }));
+    let stream = TcpStream::connect(fixture.server.local_addr())
+        .await
+        .unwrap();
+    let (read, mut write) = stream.into_split();
+    let request = format!("GET /api/events?token={TOKEN} HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    write.write_all(request.as_bytes()).await.unwrap();
+
+    let mut lines = BufReader::new(read).lines();
+    let mut next_data = async || loop {
+        let line = tokio::time::timeout(Duration::from_secs(5), lines.next_line())
+            .await
+            .expect("no event")
+            .unwrap()
+            .unwrap();
+        if let Some(data) = line.strip_prefix("data: ") {
+            return serde_json::from_str::<Value>(data).unwrap();
+        }
+    };
+    assert_eq!(next_data().await, json!({ "position": 1 }));
+    // Unchanged updates send nothing; the next event is the change.
+    fixture.server.update(json!({ "position": 1 }));
+    fixture.server.update(json!({ "position": 2 }));
+    assert_eq!(next_data().await, json!({ "position": 2 }));
+}
@@ -0,0 +1,726 @@
+//! MPD protocol server for a media player.
+//!
+//! Listens on TCP and speaks enough of the [MPD protocol] for frontends such
+//! as ncmpcpp, MPDroid or `mpc` to show the queue and control playback:
+//! `play`, `pause`, `next`, `seek`, `playlistinfo`, `add`, `status`,
+//! `currentsong`, `idle`, `search` and the commands around them that clients
+//! send on connect.
+//!
+//! The player pushes its state with [`MpdServer::update`]; clients waiting in
+//! `idle` are told which subsystems changed. Commands that act on the player
+//! arrive as [`Request`]s on the channel given to [`MpdServer::bind`] and are
+//! answered through their `reply`.
+//!
+//! Songs are addressed by queue position. A song's ID is its position plus
+//! one, so IDs stay valid only until the queue changes.
+//!
+//! [MPD protocol]: https://mpd.readthedocs.io/en/latest/protocol.html
+
+mod protocol;
+
+use std::fmt::Write as _;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::JoinHandle;
+
+use protocol::{
+    ACK_ERROR_NO_EXIST, ACK_ERROR_PLAYER_SYNC, ACK_ERROR_SYSTEM, ACK_ERROR_UNKNOWN, Ack, parse,
+    parse_bool, parse_range, search_query, split,
+};
+
+/// Protocol version announced to clients.
+const PROTOCOL_VERSION: &str = "0.23.5";
+
+/// Subsystems `idle` reports, indexing [`Shared::events`].
+const SUBSYSTEMS: [&str; 4] = ["player", "playlist", "mixer", "options"];
+const PLAYER: usize = 0;
+const PLAYLIST: usize = 1;
+const MIXER: usize = 2;
+const OPTIONS: usize = 3;
+
+/// Listed by `commands`.
+const COMMANDS: &[&str] = &[
+    "add",
+    "addid",
+    "clear",
+    "close",
+    "commands",
+    "consume",
+    "currentsong",
+    "delete",
+    "deleteid",
+    "find",
+    "getvol",
+    "idle",
+    "move",
+    "moveid",
+    "next",
+    "noidle",
+    "notcommands",
+    "outputs",
+    "password",
+    "pause",
+    "ping",
+    "play",
+    "playid",
+    "playlistid",
+    "playlistinfo",
+    "plchanges",
+    "plchangesposid",
+    "previous",
+    "random",
+    "repeat",
+    "search",
+    "seek",
+    "seekcur",
+    "seekid",
+    "setvol",
+    "single",
+    "stats",
+    "status",
+    "stop",
+    "tagtypes",
+    "urlhandlers",
+    "volume",
+];
+
+/// Tags songs are described with.
+const TAG_TYPES: [&str; 3] = ["Artist", "Album", "Title"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PlayState {
+    Play,
+    Pause,
+    #[default]
+    Stop,
+}
+
+impl PlayState {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Play => "play",
+            Self::Pause => "pause",
+            Self::Stop => "stop",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Repeat {
+    #[default]
+    Off,
+    /// Start the queue over after its last song (`repeat 1`).
+    All,
+    /// Repeat the current song (`repeat 1` plus `single 1`).
+    One,
+}
+
+/// A queue entry or search result.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Song {
+    /// URI the song can be `add`ed by.
+    pub file: String,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    /// Length in seconds, when known.
+    pub duration: Option<f64>,
+}
+
+/// Everything the server mirrors.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct State {
+    pub state: PlayState,
+    pub queue: Vec<Song>,
+    /// Queue position of the current song.
+    pub current: Option<usize>,
+    /// Seconds into the current song.
+    pub elapsed: f64,
+    /// Length of the current song in seconds; overrides its `duration`.
+    pub duration: Option<f64>,
+    /// `0..=100`.
+    pub volume: u8,
+    pub random: bool,
+    pub repeat: Repeat,
+}
+
+/// What clients ask of the player.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Start the song at this queue position.
+    Play(usize),
+    Resume,
+    Pause,
+    Stop,
+    Next,
+    Previous,
+    /// Jump to this many seconds into the current song.
+    Seek(f64),
+    /// `0..=100`.
+    SetVolume(u8),
+    SetRandom(bool),
+    SetRepeat(Repeat),
+    /// Append the song at this URI to the queue.
+    Add(String),
+    /// Remove the song at this queue position.
+    Delete(usize),
+    Move {
+        from: usize,
+        to: usize,
+    },
+    Clear,
+    /// Find songs for a free-text query; the reply carries them.
+    Search(String),
+}
+
+/// A [`Command`] and where its outcome goes: songs for
+/// [`Command::Search`], nothing otherwise, or an error message.
+#[derive(Debug)]
+pub struct Request {
+    pub command: Command,
+    pub reply: oneshot::Sender<Result<Vec<Song>, String>>,
+}
+
+struct Shared {
+    state: State,
+    /// Bumped whenever the queue changes, as `playlist` in `status`.
+    version: u32,
+    /// Change counters per entry of [`SUBSYSTEMS`].
+    events: [u64; SUBSYSTEMS.len()],
+    started: Instant,
+}
+
+/// A running server; stops accepting connections when dropped.
+pub struct MpdServer {
+    shared: watch::Sender<Shared>,
+    local_addr: SocketAddr,
+    accept: JoinHandle<()>,
+}
+
+impl MpdServer {
+    /// Listen on `addr` and forward client commands to `tx`.
+    pub async fn bind(
+        addr: impl ToSocketAddrs,
+        tx: mpsc::UnboundedSender<Request>,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr).await?;
+        let local_addr = listener.local_addr()?;
+        let (shared, _) = watch::channel(Shared {
+            state: State::default(),
+            version: 1,
+            events: [0; SUBSYSTEMS.len()],
+            started: Instant::now(),
+        });
+        let accept = tokio::spawn(accept_loop(listener, shared.clone(), tx));
+        Ok(Self {
+            shared,
+            local_addr,
+            accept,
+        })
+    }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Mirror `state`, waking idle clients for the subsystems it changed.
+    pub fn update(&self, state: State) {
+        self.shared.send_if_modified(|shared| {
+            let old = &shared.state;
+            let current_file = |s: &State| {
+                s.current
+                    .and_then(|i| s.queue.get(i))
+                    .map(|song| song.file.clone())
+            };
+            let mut changed = [false; SUBSYSTEMS.len()];
+            changed[PLAYER] = old.state != state.state
+                || old.current != state.current
+                || current_file(old) != current_file(&state);
+            changed[PLAYLIST] = old.queue != state.queue;
+            changed[MIXER] = old.volume != state.volume;
+            changed[OPTIONS] = old.random != state.random || old.repeat != state.repeat;
+            if changed[PLAYLIST] {
+                shared.version += 1;
+            }
+            for (events, changed) in shared.events.iter_mut().zip(changed) {
+                *events += u64::from(changed);
+            }
+            shared.state = state;
+            changed.contains(&true)
+        });
+    }
+}
+
+impl Drop for MpdServer {
+    fn drop(&mut self) {
+        self.accept.abort();
+    }
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    shared: watch::Sender<Shared>,
+    tx: mpsc::UnboundedSender<Request>,
+) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, addr)) => {
+                tracing::debug!(%addr, "MPD client connected");
+                tokio::spawn(serve_client(stream, shared.subscribe(), tx.clone()));
+            }
+            Err(e) => tracing::warn!(error = %e, "MPD accept failed"),
+        }
+    }
+}
+
+async fn serve_client(
+    stream: TcpStream,
+    shared: watch::Receiver<Shared>,
+    tx: mpsc::UnboundedSender<Request>,
+) {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    let seen = shared.borrow().events;
+    let mut client = Client { shared, tx, seen };
+    let greeting = format!("OK MPD {PROTOCOL_VERSION}\n");
+    if write.write_all(greeting.as_bytes()).await.is_err() {
+        return;
+    }
+
+    // Commands collected between `command_list_begin` and its end, and
+    // whether each gets a `list_OK`.
+    let mut list: Option<(bool, Vec<String>)> = None;
+    while let Ok(Some(line)) = lines.next_line().await {
+        let out = match (&mut list, line.trim()) {
+            (Some(_), "command_list_end") => {
+                let (ok_each, commands) = list.take().unwrap_or_default();
+                client.run_list(ok_each, &commands).await
+            }
+            (Some((_, commands)), _) => {
+                commands.push(line);
+                continue;
+            }
+            (None, "command_list_begin") => {
+                list = Some((false, Vec::new()));
+                continue;
+            }
+            (None, "command_list_ok_begin") => {
+                list = Some((true, Vec::new()));
+                continue;
+            }
+            (None, "close") => break,
+            (None, trimmed) if trimmed == "idle" || trimmed.starts_with("idle ") => {
+                let Some(out) = client.idle(trimmed, &mut lines).await else {
+                    break;
+                };
+                out
+            }
+            (None, _) => match client.run(&line, 0).await {
+                Ok(out) => out + "OK\n",
+                Err(ack) => ack.to_string(),
+            },
+        };
+        if write.write_all(out.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+struct Client {
+    shared: watch::Receiver<Shared>,
+    tx: mpsc::UnboundedSender<Request>,
+    /// [`Shared::events`] as of the last `idle` that reported them.
+    seen: [u64; SUBSYSTEMS.len()],
+}
+
+impl Client {
+    async fn run_list(&mut self, ok_each: bool, commands: &[String]) -> String {
+        let mut out = String::new();
+        for (index, command) in commands.iter().enumerate() {
+            match self.run(command, index).await {
+                Ok(text) => out.push_str(&text),
+                Err(ack) => return out + &ack.to_string(),
+            }
+            if ok_each {
+                out.push_str("list_OK\n");
+            }
+        }
+        out + "OK\n"
+    }
+
+    /// Wait until a subsystem of the `idle` line changes, or the client sends
+    /// `noidle`. `None` when the connection should close.
+    async fn idle(
+        &mut self,
+        line: &str,
+        lines: &mut Lines<BufReader<OwnedReadHalf>>,
+    ) -> Option<String> {
+        let names = split(line).ok()?;
+        let wanted: Vec<usize> = (0..SUBSYSTEMS.len())
+            .filter(|&i| names.len() == 1 || names[1..].iter().any(|n| n == SUBSYSTEMS[i]))
+            .collect();
+        loop {
+            let events = self.shared.borrow_and_update().events;
+            let changed: Vec<usize> = wanted
+                .iter()
+                .copied()
+                .filter(|&i| events[i] != self.seen[i])
+                .collect();
+            if !changed.is_empty() {
+                let mut out = String::new();
+                for i in changed {
+                    self.seen[i] = events[i];
+                    writeln!(out, "changed: {}", SUBSYSTEMS[i]).ok();
+                }
+                return Some(out + "OK\n");
+            }
+            tokio::select! {
+                changed = self.shared.changed() => changed.ok()?,
+                line = lines.next_line() => {
+                    // Anything but `noidle` while idle is a protocol error.
+                    return match line {
+                        Ok(Some(line)) if line.trim() == "noidle" => Some("OK\n".into()),
+                        _ => None,
+                    };
+                }
+            }
+        }
+    }
+
+    /// Run one command line, returning its response without the final `OK`.
+    async fn run(&mut self, line: &str, index: usize) -> Result<String, Ack> {
+        let args = split(line).map_err(|ack| ack.at(index, ""))?;
+        let Some((name, args)) = args.split_first() else {
+            return Err(Ack::new(ACK_ERROR_UNKNOWN, "No command given").at(index, ""));
+        };
+        self.execute(name, args)
+            .await
+            .map_err(|ack| ack.at(index, name))
+    }
+
+    async fn execute(&mut self, name: &str, args: &[String]) -> Result<String, Ack> {
+        let (state, version, uptime) = {
+            let shared = self.shared.borrow();
+            (
+                shared.state.clone(),
+                shared.version,
+                shared.started.elapsed().as_secs(),
+            )
+        };
+        let len = state.queue.len();
+        let position = |arg: Option<&String>| -> Result<usize, Ack> {
+            let pos: usize = parse(arg, "song position")?;
+            if pos >= len {
+                return Err(Ack::arg("Bad song index"));
+            }
+            Ok(pos)
+        };
+        let id = |arg: Option<&String>| -> Result<usize, Ack> {
+            let id: usize = parse(arg, "song id")?;
+            if id == 0 || id > len {
+                return Err(Ack::new(ACK_ERROR_NO_EXIST, "No such song"));
+            }
+            Ok(id - 1)
+        };
+
+        let mut out = String::new();
+        match name {
+            "ping" | "password" | "clearerror" => {}
+            "commands" => {
+                for command in COMMANDS {
+                    writeln!(out, "command: {command}").ok();
+                }
+            }
+            "notcommands" | "decoders" | "listplaylists" | "lsinfo" | "list" => {}
+            "tagtypes" if args.is_empty() => {
+                for tag in TAG_TYPES {
+                    writeln!(out, "tagtype: {tag}").ok();
+                }
+            }
+            "tagtypes" => {}
+            "urlhandlers" => out.push_str("handler: http://\nhandler: https://\nhandler: yt:\n"),
+            "outputs" => out
+                .push_str("outputid: 0\noutputname: default\nplugin: default\noutputenabled: 1\n"),
+            "replay_gain_status" => out.push_str("replay_gain_mode: off\n"),
+            "stats" => {
+                writeln!(out, "uptime: {uptime}\nplaytime: {uptime}").ok();
+                out.push_str("artists: 0\nalbums: 0\nsongs: 0\ndb_playtime: 0\ndb_update: 0\n");
+            }
+
+            "status" => write_status(&mut out, &state, version),
+            "currentsong" => {
+                if let Some(pos) = state.current.filter(|&pos| pos < len) {
+                    write_song(&mut out, &state, pos);
+                }
+            }
+            "playlistinfo" => {
+                let range = match args.first() {
+                    Some(arg) => parse_range(arg, len)?,
+                    None => 0..len,
+                };
+                range.for_each(|pos| write_song(&mut out, &state, pos));
+            }
+            "playlistid" => match args.first() {
+                Some(_) => write_song(&mut out, &state, id(args.first())?),
+                None => (0..len).for_each(|pos| write_song(&mut out, &state, pos)),
+            },
+            "plchanges" => {
+                let since: u32 = parse(args.first(), "version")?;
+                if since != version {
+                    (0..len).for_each(|pos| write_song(&mut out, &state, pos));
+                }
+            }
+            "plchangesposid" => {
+                let since: u32 = parse(args.first(), "version")?;
+                if since != version {
+                    for pos in 0..len {
+                        writeln!(out, "cpos: {pos}\nId: {}", pos + 1).ok();
+                    }
+                }
+            }
+
+            "play" => match args.first() {
+                Some(arg) => self.send(Command::Play(position(Some(arg))?)).await?,
+                None if state.current.is_some() => self.send(Command::Resume).await?,
+                None if len > 0 => self.send(Command::Play(0)).await?,
+                None => {}
+            },
+            "playid" => match args.first() {
+                Some(arg) => self.send(Command::Play(id(Some(arg))?)).await?,
+                None => self.send(Command::Resume).await?,
+            },
+            "pause" => {
+                let pause = match args.first() {
+                    Some(_) => parse_bool(args.first())?,
+                    None => state.state == PlayState::Play,
+                };
+                self.call(if pause {
+                    Command::Pause
+                } else {
+                    Command::Resume
+                })
+                .await?;
+            }
+            "stop" => self.send(Command::Stop).await?,
+            "next" => self.send(Command::Next).await?,
+            "previous" => self.send(Command::Previous).await?,
+            "seek" | "seekid" => {
+                let pos = if name == "seek" {
+                    position(args.first())?
+                } else {
+                    id(args.first())?
+                };
+                let secs: f64 = parse(args.get(1), "time")?;
+                if state.current != Some(pos) {
+                    self.send(Command::Play(pos)).await?;
+                }
+                self.send(Command::Seek(secs.max(0.))).await?;
+            }
+            "seekcur" => {
+                if state.current.is_none() {
+                    return Err(Ack::new(ACK_ERROR_PLAYER_SYNC, "Not playing"));
+                }
+                let arg = args.first().map(String::as_str).unwrap_or_default();
+                let offset: f64 = parse(args.first(), "time")?;
+                let secs = if arg.starts_with(['+', '-']) {
+                    state.elapsed + offset
+                } else {
+                    offset
+                };
+                self.send(Command::Seek(secs.max(0.))).await?;
+            }
+
+            "setvol" => {
+                let volume: u8 = parse(args.first(), "volume")?;
+                if volume > 100 {
+                    return Err(Ack::arg("Invalid volume value"));
+                }
+                self.send(Command::SetVolume(volume)).await?;
+            }
+            "volume" => {
+                let change: i16 = parse(args.first(), "volume change")?;
+                let volume = (i16::from(state.volume) + change).clamp(0, 100) as u8;
+                self.send(Command::SetVolume(volume)).await?;
+            }
+            "getvol" => {
+                writeln!(out, "volume: {}", state.volume).ok();
+            }
+            "random" => {
+                self.send(Command::SetRandom(parse_bool(args.first())?))
+                    .await?
+            }
+            "repeat" => {
+                let repeat = match (parse_bool(args.first())?, state.repeat) {
+                    (false, _) => Repeat::Off,
+                    (true, Repeat::One) => Repeat::One,
+                    (true, _) => Repeat::All,
+                };
+                self.send(Command::SetRepeat(repeat)).await?;
+            }
+            "single" => {
+                let repeat = match (parse_bool(args.first())?, state.repeat) {
+                    (true, _) => Repeat::One,
+                    (false, Repeat::One) => Repeat::All,
+                    (false, repeat) => repeat,
+                };
+                self.send(Command::SetRepeat(repeat)).await?;
+            }
+            "consume" => {
+                if parse_bool(args.first())? {
+                    return Err(Ack::arg("consume mode is not supported"));
+                }
+            }
+
+            "add" | "addid" => {
+                let uri = args.first().ok_or_else(|| Ack::arg("missing URI"))?;
+                if args.len() > 1 {
+                    return Err(Ack::arg("adding at a position is not supported"));
+                }
+                self.send(Command::Add(uri.clone()))
+                    .await
+                    .map_err(|ack| Ack::new(ACK_ERROR_NO_EXIST, ack.message))?;
+                if name == "addid" {
+                    writeln!(out, "Id: {}", len + 1).ok();
+                }
+            }
+            "delete" => {
+                let range = parse_range(
+                    args.first().ok_or_else(|| Ack::arg("missing position"))?,
+                    len,
+                )?;
+                // Highest first, so the positions left to delete don't shift.
+                for pos in range.rev() {
+                    self.send(Command::Delete(pos)).await?;
+                }
+            }
+            "deleteid" => self.send(Command::Delete(id(args.first())?)).await?,
+            "clear" => self.send(Command::Clear).await?,
+            "move" | "moveid" => {
+                let from = if name == "move" {
+                    position(args.first())?
+                } else {
+                    id(args.first())?
+                };
+                let to = position(args.get(1))?;
+                self.send(Command::Move { from, to }).await?;
+            }
+
+            "search" | "find" => {
+                let query = search_query(args).ok_or_else(|| Ack::arg("nothing to search for"))?;
+                for song in self.call(Command::Search(query)).await? {
+                    write_tags(&mut out, &song, song.duration);
+                }
+            }
+
+            "idle"
+            | "noidle"
+            | "close"
+            | "command_list_begin"
+            | "command_list_ok_begin"
+            | "command_list_end" => {
+                return Err(Ack::arg(format!("\"{name}\" is not allowed here")));
+            }
+            _ => {
+                return Err(Ack::new(
+                    ACK_ERROR_UNKNOWN,
+                    format!("unknown command \"{name}\""),
+                ));
+            }
+        }
+        Ok(out)
+    }
+
+    /// Like [`Client::call`], for commands that return nothing.
+    async fn send(&self, command: Command) -> Result<(), Ack> {
+        self.call(command).await.map(drop)
+    }
+
+    /// Send `command` to the player and wait for its outcome.
+    async fn call(&self, command: Command) -> Result<Vec<Song>, Ack> {
+        let gone = || Ack::new(ACK_ERROR_SYSTEM, "player is not running");
+        let (reply, rx) = oneshot::channel();
+        self.tx
+            .send(Request { command, reply })
+            .map_err(|_| gone())?;
+        rx.await
+            .map_err(|_| gone())?
+            .map_err(|message| Ack::new(ACK_ERROR_SYSTEM, message))
+    }
+}
+
+fn write_status(out: &mut String, state: &State, version: u32) {
+    let flag = |on: bool| u8::from(on);
+    writeln!(
+        out,
+        "volume: {}\nrepeat: {}\nrandom: {}\nsingle: {}\nconsume: 0\nplaylist: {version}\nplaylistlength: {}\nstate: {}",
+        state.volume,
+        flag(state.repeat != Repeat::Off),
+        flag(state.random),
+        flag(state.repeat == Repeat::One),
+        state.queue.len(),
+        state.state.as_str(),
+    )
+    .ok();
+    let Some(pos) = state.current.filter(|&pos| pos < state.queue.len()) else {
+        return;
+    };
+    writeln!(out, "song: {pos}\nsongid: {}", pos + 1).ok();
+    let duration = state.duration.or(state.queue[pos].duration);
+    if state.state != PlayState::Stop {
+        writeln!(
+            out,
+            "time: {}:{}\nelapsed: {:.3}",
+            state.elapsed as u64,
+            duration.unwrap_or_default() as u64,
+            state.elapsed
+        )
+        .ok();
+        if let Some(duration) = duration {
+            writeln!(out, "duration: {duration:.3}").ok();
+        }
+    }
+    if pos + 1 < state.queue.len() {
+        writeln!(out, "nextsong: {}\nnextsongid: {}", pos + 1, pos + 2).ok();
+    }
+}
+
+/// The queue entry at `pos`, with its position and ID.
+fn write_song(out: &mut String, state: &State, pos: usize) {
+    let song = &state.queue[pos];
+    let duration = if state.current == Some(pos) {
+        state.duration.or(song.duration)
+    } else {
+        song.duration
+    };
+    write_tags(out, song, duration);
+    writeln!(out, "Pos: {pos}\nId: {}", pos + 1).ok();
+}
+
+fn write_tags(out: &mut String, song: &Song, duration: Option<f64>) {
+    // A value can't span lines in the protocol.
+    let clean = |s: &str| s.replace(['\n', '\r'], " ");
+    writeln!(out, "file: {}", clean(&song.file)).ok();
+    for (tag, value) in [
+        ("Title", &song.title),
+        ("Artist", &song.artist),
+        ("Album", &song.album),
+    ] {
+        if !value.is_empty() {
+            writeln!(out, "{tag}: {}", clean(value)).ok();
+        }
+    }
+    if let Some(duration) = duration {
+        writeln!(
+            out,
+            "Time: {}\nduration: {duration:.3}",
+            duration.round() as u64
+        )
+        .ok();
+    }
+}
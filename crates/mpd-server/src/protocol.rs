@@ -0,0 +1,209 @@
+//! Request parsing and error replies of the MPD text protocol.
+
+use std::fmt;
+use std::ops::Range;
+
+pub const ACK_ERROR_ARG: u8 = 2;
+pub const ACK_ERROR_UNKNOWN: u8 = 5;
+pub const ACK_ERROR_NO_EXIST: u8 = 50;
+pub const ACK_ERROR_SYSTEM: u8 = 52;
+pub const ACK_ERROR_PLAYER_SYNC: u8 = 55;
+
+/// A failed command, written as `ACK [code@index] {command} message`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ack {
+    pub code: u8,
+    pub message: String,
+    /// Position of the failed command in a command list.
+    pub index: usize,
+    pub command: String,
+}
+
+impl Ack {
+    pub fn new(code: u8, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            index: 0,
+            command: String::new(),
+        }
+    }
+
+    pub fn arg(message: impl Into<String>) -> Self {
+        Self::new(ACK_ERROR_ARG, message)
+    }
+
+    pub fn at(self, index: usize, command: &str) -> Self {
+        Self {
+            index,
+            command: command.to_owned(),
+            ..self
+        }
+    }
+}
+
+impl fmt::Display for Ack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "ACK [{}@{}] {{{}}} {}",
+            self.code, self.index, self.command, self.message
+        )
+    }
+}
+
+/// Split a request line into the command and its arguments. Arguments are
+/// separated by whitespace; double-quoted ones may contain it, with `\`
+/// escaping the next character.
+pub fn split(line: &str) -> Result<Vec<String>, Ack> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let Some(first) = chars.next() else {
+            return Ok(args);
+        };
+        let mut arg = String::new();
+        if first == '"' {
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => arg.extend(chars.next()),
+                    Some(c) => arg.push(c),
+                    None => return Err(Ack::arg("Missing closing '\"'")),
+                }
+            }
+            if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                return Err(Ack::arg("Space expected after closing '\"'"));
+            }
+        } else {
+            arg.push(first);
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                arg.push(c);
+            }
+        }
+        args.push(arg);
+    }
+}
+
+pub fn parse<T: std::str::FromStr>(arg: Option<&String>, what: &str) -> Result<T, Ack> {
+    let arg = arg.ok_or_else(|| Ack::arg(format!("missing {what}")))?;
+    arg.parse()
+        .map_err(|_| Ack::arg(format!("invalid {what} \"{arg}\"")))
+}
+
+pub fn parse_bool(arg: Option<&String>) -> Result<bool, Ack> {
+    match arg.map(String::as_str) {
+        Some("1") => Ok(true),
+        Some("0") => Ok(false),
+        _ => Err(Ack::arg("expected 0 or 1")),
+    }
+}
+
+/// `N` or `START:END` (`END` may be left out) as a range of positions.
+pub fn parse_range(arg: &str, len: usize) -> Result<Range<usize>, Ack> {
+    let bad = || Ack::arg(format!("invalid range \"{arg}\""));
+    let range = match arg.split_once(':') {
+        Some((start, "")) => start.parse().map_err(|_| bad())?..len,
+        Some((start, end)) => start.parse().map_err(|_| bad())?..end.parse().map_err(|_| bad())?,
+        None => {
+            let pos: usize = arg.parse().map_err(|_| bad())?;
+            pos..pos + 1
+        }
+    };
+    if range.start > range.end || range.end > len {
+        return Err(Ack::arg("Bad song index"));
+    }
+    Ok(range)
+}
+
+/// The text to search for in `search`/`find` arguments, which are either a
+/// filter expression such as `(any contains "foo")` or `TAG VALUE` pairs.
+/// Tags and operators are dropped: every value becomes part of one query.
+pub fn search_query(args: &[String]) -> Option<String> {
+    let values: Vec<String> = match args.first() {
+        Some(expr) if expr.starts_with('(') => quoted_values(expr),
+        _ => args
+            .chunks(2)
+            .filter_map(|pair| match pair {
+                [tag, value] if !matches!(tag.as_str(), "sort" | "window") => Some(value.clone()),
+                _ => None,
+            })
+            .collect(),
+    };
+    let query = values.join(" ").trim().to_owned();
+    (!query.is_empty()).then_some(query)
+}
+
+/// The quoted strings in a filter expression.
+fn quoted_values(expr: &str) -> Vec<String> {
+    let mut values = Vec::new();
+    let mut chars = expr.chars();
+    while let Some(c) = chars.next() {
+        if c != '"' && c != '\'' {
+            continue;
+        }
+        let mut value = String::new();
+        while let Some(d) = chars.next() {
+            match d {
+                _ if d == c => break,
+                '\\' => value.extend(chars.next()),
+                _ => value.push(d),
+            }
+        }
+        values.push(value);
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_quoted_arguments() {
+        assert_eq!(split("  play  3 ").unwrap(), ["play", "3"]);
+        assert_eq!(
+            split(r#"search "(any contains \"a b\")" window 0:5"#).unwrap(),
+            ["search", r#"(any contains "a b")"#, "window", "0:5"]
+        );
+        assert_eq!(split(r#"add "yt:x\\y""#).unwrap(), ["add", r"yt:x\y"]);
+        assert!(split(r#"add "unterminated"#).is_err());
+        assert!(split(r#"add "a"b"#).is_err());
+        assert!(split("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_range("2", 5).unwrap(), 2..3);
+        assert_eq!(parse_range("1:3", 5).unwrap(), 1..3);
+        assert_eq!(parse_range("1:", 5).unwrap(), 1..5);
+        assert!(parse_range("5", 5).is_err());
+        assert!(parse_range("3:1", 5).is_err());
+        assert!(parse_range("x", 5).is_err());
+    }
+
+    #[test]
+    fn search_queries() {
+        let args = |list: &[&str]| list.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            search_query(&args(&["any", "daft punk"])).as_deref(),
+            Some("daft punk")
+        );
+        assert_eq!(
+            search_query(&args(&["artist", "Daft Punk", "title", "One More Time"])).as_deref(),
+            Some("Daft Punk One More Time")
+        );
+        assert_eq!(
+            search_query(&args(&[
+                r#"((artist == 'Daft Punk') AND (title contains "it\'s"))"#,
+                "window",
+                "0:10"
+            ]))
+            .as_deref(),
+            Some("Daft Punk it's")
+        );
+        assert_eq!(search_query(&args(&["any", " "])), None);
+        assert_eq!(search_query(&[]), None);
+    }
+}
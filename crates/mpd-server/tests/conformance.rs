@@ -0,0 +1,421 @@
+//! Scripted TCP sessions against a server backed by a stand-in player that
+//! applies commands to its state the way a real one would.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use mpd_server::{Command, MpdServer, PlayState, Repeat, Request, Song, State};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::sync::mpsc::{UnboundedReceiver, unbounded_channel};
+
+fn song(id: &str, title: &str) -> Song {
+    Song {
+        file: format!("yt:{id}"),
+        title: title.into(),
+        artist: "Artist".into(),
+        album: String::new(),
+        duration: None,
+    }
+}
+
+/// Apply `command` to `state` like a player would; `Err` for unknown URIs.
+fn apply(state: &mut State, command: &Command) -> Result<Vec<Song>, String> {
+    match command {
+        Command::Play(pos) => {
+            state.current = Some(*pos);
+            state.state = PlayState::Play;
+            state.elapsed = 0.;
+            state.duration = Some(200.);
+        }
+        Command::Resume => state.state = PlayState::Play,
+        Command::Pause => state.state = PlayState::Pause,
+        Command::Stop => state.state = PlayState::Stop,
+        Command::Next => state.current = state.current.map(|pos| pos + 1),
+        Command::Previous => state.current = state.current.map(|pos| pos.saturating_sub(1)),
+        Command::Seek(secs) => state.elapsed = *secs,
+        Command::SetVolume(volume) => state.volume = *volume,
+        Command::SetRandom(random) => state.random = *random,
+        Command::SetRepeat(repeat) => state.repeat = *repeat,
+        Command::Add(uri) => {
+            let id = uri.strip_prefix("yt:").ok_or("no such song")?;
+            state.queue.push(song(id, &format!("Song {id}")));
+        }
+        Command::Delete(pos) => {
+            state.queue.remove(*pos);
+        }
+        Command::Move { from, to } => {
+            let song = state.queue.remove(*from);
+            state.queue.insert(*to, song);
+        }
+        Command::Clear => state.queue.clear(),
+        Command::Search(query) => return Ok(vec![song("found", query)]),
+    }
+    Ok(Vec::new())
+}
+
+struct Fixture {
+    server: Arc<MpdServer>,
+    /// Every command the player got, in order.
+    commands: UnboundedReceiver<Command>,
+}
+
+async fn fixture(initial: State) -> Fixture {
+    let (tx, mut requests) = unbounded_channel::<Request>();
+    let (log, commands) = unbounded_channel();
+    let server = Arc::new(MpdServer::bind("127.0.0.1:0", tx).await.unwrap());
+    server.update(initial.clone());
+    let player = Arc::clone(&server);
+    tokio::spawn(async move {
+        let mut state = initial;
+        while let Some(Request { command, reply }) = requests.recv().await {
+            let result = apply(&mut state, &command);
+            player.update(state.clone());
+            log.send(command).ok();
+            reply.send(result).ok();
+        }
+    });
+    Fixture { server, commands }
+}
+
+struct Client {
+    read: tokio::io::Lines<BufReader<OwnedReadHalf>>,
+    write: OwnedWriteHalf,
+}
+
+impl Client {
+    async fn connect(server: &MpdServer) -> Self {
+        let stream = TcpStream::connect(server.local_addr()).await.unwrap();
+        let (read, write) = stream.into_split();
+        let mut client = Self {
+            read: BufReader::new(read).lines(),
+            write,
+        };
+        assert_eq!(client.line().await, "OK MPD 0.23.5");
+        client
+    }
+
+    async fn line(&mut self) -> String {
+        tokio::time::timeout(Duration::from_secs(5), self.read.next_line())
+            .await
+            .expect("no response")
+            .unwrap()
+            .expect("connection closed")
+    }
+
+    async fn send(&mut self, request: &str) {
+        self.write
+            .write_all(format!("{request}\n").as_bytes())
+            .await
+            .unwrap();
+    }
+
+    /// The response up to and including its `OK` or `ACK` line.
+    async fn response(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        loop {
+            let line = self.line().await;
+            let done = line == "OK" || line.starts_with("ACK ");
+            lines.push(line);
+            if done {
+                return lines;
+            }
+        }
+    }
+
+    async fn expect(&mut self, request: &str, response: &[&str]) {
+        self.send(request).await;
+        assert_eq!(self.response().await, response, "response to {request:?}");
+    }
+
+    /// `key: value` pairs of a response.
+    async fn fields(&mut self, request: &str) -> Vec<(String, String)> {
+        self.send(request).await;
+        let mut response = self.response().await;
+        assert_eq!(response.pop().as_deref(), Some("OK"), "{request:?} failed");
+        response
+            .iter()
+            .map(|line| {
+                let (key, value) = line.split_once(": ").unwrap();
+                (key.to_owned(), value.to_owned())
+            })
+            .collect()
+    }
+}
+
+fn field<'a>(fields: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    fields
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.as_str())
+}
+
+fn queue_of(titles: &[&str]) -> State {
+    State {
+        queue: titles
+            .iter()
+            .enumerate()
+            .map(|(i, title)| song(&format!("id{i}"), title))
+            .collect(),
+        current: Some(0),
+        state: PlayState::Pause,
+        volume: 80,
+        ..State::default()
+    }
+}
+
+#[tokio::test]
+async fn basics_and_errors() {
+    let f = fixture(State::default()).await;
+    let mut c = Client::connect(&f.server).await;
+    c.expect("ping", &["OK"]).await;
+    c.expect(
+        "frobnicate 1",
+        &[r#"ACK [5@0] {frobnicate} unknown command "frobnicate""#],
+    )
+    .await;
+    c.expect(r#"add "yt:x"#, &[r#"ACK [2@0] {} Missing closing '"'"#])
+        .await;
+    c.expect("play 3", &["ACK [2@0] {play} Bad song index"])
+        .await;
+    c.expect("add nowhere", &["ACK [50@0] {add} no such song"])
+        .await;
+    c.expect("setvol 101", &["ACK [2@0] {setvol} Invalid volume value"])
+        .await;
+
+    let commands = c.fields("commands").await;
+    for name in [
+        "play",
+        "pause",
+        "next",
+        "seek",
+        "playlistinfo",
+        "add",
+        "status",
+        "currentsong",
+        "idle",
+        "search",
+    ] {
+        assert!(commands.iter().any(|(_, v)| v == name), "{name} not listed");
+    }
+    c.send("close").await;
+    assert!(c.read.next_line().await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn queue_and_status() {
+    let f = fixture(State {
+        volume: 50,
+        ..State::default()
+    })
+    .await;
+    let mut c = Client::connect(&f.server).await;
+
+    let status = c.fields("status").await;
+    assert_eq!(field(&status, "state"), Some("stop"));
+    assert_eq!(field(&status, "playlistlength"), Some("0"));
+    assert_eq!(field(&status, "volume"), Some("50"));
+    assert_eq!(field(&status, "song"), None);
+    let version = field(&status, "playlist").unwrap().to_owned();
+
+    c.expect("add yt:aaa", &["OK"]).await;
+    c.expect(r#"addid "yt:bbb""#, &["Id: 2", "OK"]).await;
+    c.expect(
+        "playlistinfo",
+        &[
+            "file: yt:aaa",
+            "Title: Song aaa",
+            "Artist: Artist",
+            "Pos: 0",
+            "Id: 1",
+            "file: yt:bbb",
+            "Title: Song bbb",
+            "Artist: Artist",
+            "Pos: 1",
+            "Id: 2",
+            "OK",
+        ],
+    )
+    .await;
+    c.expect(
+        "playlistinfo 1",
+        &[
+            "file: yt:bbb",
+            "Title: Song bbb",
+            "Artist: Artist",
+            "Pos: 1",
+            "Id: 2",
+            "OK",
+        ],
+    )
+    .await;
+    c.expect("currentsong", &["OK"]).await;
+
+    let changes = c.fields(&format!("plchanges {version}")).await;
+    assert_eq!(changes.iter().filter(|(k, _)| k == "file").count(), 2);
+    let version = field(&c.fields("status").await, "playlist")
+        .unwrap()
+        .to_owned();
+    c.expect(&format!("plchanges {version}"), &["OK"]).await;
+
+    c.expect("play 1", &["OK"]).await;
+    c.expect("seekcur 12.5", &["OK"]).await;
+    let status = c.fields("status").await;
+    assert_eq!(field(&status, "state"), Some("play"));
+    assert_eq!(field(&status, "song"), Some("1"));
+    assert_eq!(field(&status, "songid"), Some("2"));
+    assert_eq!(field(&status, "time"), Some("12:200"));
+    assert_eq!(field(&status, "elapsed"), Some("12.500"));
+    assert_eq!(field(&status, "duration"), Some("200.000"));
+    assert_eq!(field(&status, "nextsong"), None);
+    c.expect(
+        "currentsong",
+        &[
+            "file: yt:bbb",
+            "Title: Song bbb",
+            "Artist: Artist",
+            "Time: 200",
+            "duration: 200.000",
+            "Pos: 1",
+            "Id: 2",
+            "OK",
+        ],
+    )
+    .await;
+
+    c.expect("move 1 0", &["OK"]).await;
+    c.expect("deleteid 2", &["OK"]).await;
+    let files = c.fields("playlistinfo").await;
+    assert_eq!(field(&files, "file"), Some("yt:bbb"));
+    assert_eq!(files.iter().filter(|(k, _)| k == "file").count(), 1);
+}
+
+#[tokio::test]
+async fn playback_commands() {
+    let mut f = fixture(queue_of(&["One", "Two", "Three"])).await;
+    let mut c = Client::connect(&f.server).await;
+
+    let script = [
+        ("play", Command::Resume),
+        ("pause", Command::Pause),
+        ("pause 0", Command::Resume),
+        ("next", Command::Next),
+        ("previous", Command::Previous),
+        ("seek 0 42", Command::Seek(42.)),
+        ("seekcur +8", Command::Seek(50.)),
+        ("seekcur 10", Command::Seek(10.)),
+        ("playid 3", Command::Play(2)),
+        ("stop", Command::Stop),
+        ("setvol 30", Command::SetVolume(30)),
+        ("volume -5", Command::SetVolume(25)),
+        ("random 1", Command::SetRandom(true)),
+        ("repeat 1", Command::SetRepeat(Repeat::All)),
+        ("single 1", Command::SetRepeat(Repeat::One)),
+        ("single 0", Command::SetRepeat(Repeat::All)),
+        ("repeat 0", Command::SetRepeat(Repeat::Off)),
+        ("clear", Command::Clear),
+    ];
+    for (request, command) in script {
+        c.expect(request, &["OK"]).await;
+        assert_eq!(f.commands.recv().await.unwrap(), command, "for {request:?}");
+    }
+
+    // Seeking into another song starts it first; the player keeps the
+    // position until that song has loaded.
+    f.server.update(queue_of(&["One", "Two"]));
+    c.expect("seek 1 5", &["OK"]).await;
+    assert_eq!(f.commands.recv().await.unwrap(), Command::Play(1));
+    assert_eq!(f.commands.recv().await.unwrap(), Command::Seek(5.));
+
+    let status = c.fields("status").await;
+    assert_eq!(field(&status, "repeat"), Some("0"));
+    assert_eq!(field(&status, "random"), Some("1"));
+    assert_eq!(field(&status, "volume"), Some("25"));
+}
+
+#[tokio::test]
+async fn command_lists() {
+    let mut f = fixture(queue_of(&["One", "Two"])).await;
+    let mut c = Client::connect(&f.server).await;
+
+    for line in [
+        "command_list_ok_begin",
+        "status",
+        "next",
+        "command_list_end",
+    ] {
+        c.send(line).await;
+    }
+    let response = c.response().await;
+    assert_eq!(response.iter().filter(|l| *l == "list_OK").count(), 2);
+    assert!(response.iter().any(|l| l == "state: pause"));
+    assert_eq!(f.commands.recv().await.unwrap(), Command::Next);
+
+    for line in [
+        "command_list_begin",
+        "ping",
+        "play 9",
+        "next",
+        "command_list_end",
+    ] {
+        c.send(line).await;
+    }
+    assert_eq!(c.response().await, ["ACK [2@1] {play} Bad song index"]);
+    // The list stops at the failed command.
+    c.expect("ping", &["OK"]).await;
+    assert!(f.commands.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn idle_reports_changes() {
+    let f = fixture(queue_of(&["One", "Two"])).await;
+    let mut c = Client::connect(&f.server).await;
+    let mut other = Client::connect(&f.server).await;
+
+    c.send("idle").await;
+    other.expect("play 1", &["OK"]).await;
+    assert_eq!(c.response().await, ["changed: player", "OK"]);
+
+    c.send("idle mixer").await;
+    other.expect("add yt:ccc", &["OK"]).await;
+    other.expect("setvol 10", &["OK"]).await;
+    assert_eq!(c.response().await, ["changed: mixer", "OK"]);
+
+    // The queue change is still pending for this client.
+    c.expect("idle playlist options", &["changed: playlist", "OK"])
+        .await;
+
+    c.send("idle").await;
+    c.expect("noidle", &["OK"]).await;
+    c.expect("ping", &["OK"]).await;
+}
+
+#[tokio::test]
+async fn search() {
+    let mut f = fixture(State::default()).await;
+    let mut c = Client::connect(&f.server).await;
+
+    c.expect(
+        r#"search "(any contains \"daft punk\")""#,
+        &["file: yt:found", "Title: daft punk", "Artist: Artist", "OK"],
+    )
+    .await;
+    assert_eq!(
+        f.commands.recv().await.unwrap(),
+        Command::Search("daft punk".into())
+    );
+
+    c.expect(
+        r#"find artist "Daft Punk" title Aerodynamic"#,
+        &[
+            "file: yt:found",
+            "Title: Daft Punk Aerodynamic",
+            "Artist: Artist",
+            "OK",
+        ],
+    )
+    .await;
+    c.expect("search any", &["ACK [2@0] {search} nothing to search for"])
+        .await;
+}
@@ -0,0 +1,630 @@
+//! MPRIS2 service for a media player.
+//!
+//! Registers `org.mpris.MediaPlayer2.<name>` on the session bus with the
+//! `org.mpris.MediaPlayer2` and `org.mpris.MediaPlayer2.Player` interfaces,
+//! so media keys, `playerctl`, desktop media widgets and lock screens can
+//! show and control the player.
+//!
+//! The player pushes its state with [`Mpris::update`]; the service emits
+//! `PropertiesChanged` for whatever changed and `Seeked` when the position
+//! jumps. Calls from clients arrive as [`Command`]s on the channel given to
+//! [`MprisBuilder::serve`].
+//!
+//! ```no_run
+//! # async fn run() -> zbus::Result<()> {
+//! use mpris_bridge::{Mpris, PlaybackStatus, Status};
+//!
+//! let (tx, mut commands) = tokio::sync::mpsc::unbounded_channel();
+//! let mpris = Mpris::builder("example").identity("Example").serve(tx).await?;
+//! mpris
+//!     .update(Status { playback: PlaybackStatus::Playing, ..Status::default() })
+//!     .await?;
+//! while let Some(cmd) = commands.recv().await {
+//!     println!("{cmd:?}");
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc::UnboundedSender;
+use zbus::object_server::SignalEmitter;
+use zbus::zvariant::{ObjectPath, OwnedObjectPath, Value};
+use zbus::{Connection, connection, fdo, interface};
+
+pub use zbus::{Error, Result};
+
+/// Object path both interfaces are served at.
+const PATH: &str = "/org/mpris/MediaPlayer2";
+
+/// Track ID reported while nothing is loaded.
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+/// A position this far off from where playback should be counts as a seek.
+const SEEK_TOLERANCE: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PlaybackStatus {
+    Playing,
+    Paused,
+    #[default]
+    Stopped,
+}
+
+impl PlaybackStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Playing => "Playing",
+            Self::Paused => "Paused",
+            Self::Stopped => "Stopped",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LoopStatus {
+    #[default]
+    None,
+    /// Repeat the current track.
+    Track,
+    /// Start the playlist over after its last track.
+    Playlist,
+}
+
+impl LoopStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::None => "None",
+            Self::Track => "Track",
+            Self::Playlist => "Playlist",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "None" => Some(Self::None),
+            "Track" => Some(Self::Track),
+            "Playlist" => Some(Self::Playlist),
+            _ => None,
+        }
+    }
+}
+
+/// The track being played.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Track {
+    /// Player-side ID, turned into the `mpris:trackid` object path.
+    pub id: String,
+    pub title: String,
+    pub artists: Vec<String>,
+    pub album: String,
+    /// Cover art, as `mpris:artUrl`.
+    pub art_url: Option<String>,
+    /// `None` while the length is unknown.
+    pub length: Option<Duration>,
+}
+
+/// Everything the service mirrors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Status {
+    pub playback: PlaybackStatus,
+    pub loop_status: LoopStatus,
+    pub shuffle: bool,
+    /// `1.0` is full volume.
+    pub volume: f64,
+    /// Playback speed; `1.0` is normal.
+    pub rate: f64,
+    pub position: Duration,
+    pub track: Option<Track>,
+    pub can_go_next: bool,
+    pub can_go_previous: bool,
+}
+
+impl Default for Status {
+    fn default() -> Self {
+        Self {
+            playback: PlaybackStatus::default(),
+            loop_status: LoopStatus::default(),
+            shuffle: false,
+            volume: 1.,
+            rate: 1.,
+            position: Duration::ZERO,
+            track: None,
+            can_go_next: false,
+            can_go_previous: false,
+        }
+    }
+}
+
+/// What an MPRIS client asked for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Play,
+    Pause,
+    Stop,
+    Next,
+    Previous,
+    /// Jump to this position in the current track. Relative `Seek` calls
+    /// arrive here too, already added to the current position.
+    SetPosition(Duration),
+    SetVolume(f64),
+    SetRate(f64),
+    SetShuffle(bool),
+    SetLoopStatus(LoopStatus),
+}
+
+/// Escape `s` into one object path element: ASCII letters and digits are
+/// kept, every other byte becomes `_` and two hex digits.
+fn path_element(s: &str) -> String {
+    s.bytes()
+        .map(|b| {
+            if b.is_ascii_alphanumeric() {
+                char::from(b).to_string()
+            } else {
+                format!("_{b:02x}")
+            }
+        })
+        .collect()
+}
+
+struct Root {
+    identity: String,
+    desktop_entry: String,
+}
+
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    fn raise(&self) {}
+
+    fn quit(&self) {}
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> String {
+        self.identity.clone()
+    }
+
+    #[zbus(property)]
+    fn desktop_entry(&self) -> String {
+        self.desktop_entry.clone()
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+struct Player {
+    status: Status,
+    /// When `status.position` was current.
+    updated_at: Instant,
+    /// Path element tracks IDs are namespaced under.
+    name: String,
+    rate_range: (f64, f64),
+    commands: UnboundedSender<Command>,
+}
+
+impl Player {
+    fn send(&self, cmd: Command) {
+        tracing::debug!(?cmd, "MPRIS command");
+        self.commands.send(cmd).ok();
+    }
+
+    /// Where playback should be now, moving on from the last update while
+    /// playing.
+    fn position_now(&self) -> Duration {
+        let mut position = self.status.position;
+        if self.status.playback == PlaybackStatus::Playing {
+            position += self.updated_at.elapsed().mul_f64(self.status.rate.max(0.));
+        }
+        match self.status.track.as_ref().and_then(|t| t.length) {
+            Some(length) => position.min(length),
+            None => position,
+        }
+    }
+
+    fn track_path(&self) -> OwnedObjectPath {
+        let path = match &self.status.track {
+            Some(track) => format!("/{}/track/{}", self.name, path_element(&track.id)),
+            None => NO_TRACK.to_owned(),
+        };
+        ObjectPath::try_from(path)
+            .map(Into::into)
+            .unwrap_or_else(|_| ObjectPath::from_static_str_unchecked(NO_TRACK).into())
+    }
+
+    fn has_track(&self) -> bool {
+        self.status.track.is_some()
+    }
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn next(&self) {
+        self.send(Command::Next);
+    }
+
+    fn previous(&self) {
+        self.send(Command::Previous);
+    }
+
+    fn pause(&self) {
+        self.send(Command::Pause);
+    }
+
+    fn play_pause(&self) {
+        self.send(match self.status.playback {
+            PlaybackStatus::Playing => Command::Pause,
+            PlaybackStatus::Paused | PlaybackStatus::Stopped => Command::Play,
+        });
+    }
+
+    fn stop(&self) {
+        self.send(Command::Stop);
+    }
+
+    fn play(&self) {
+        self.send(Command::Play);
+    }
+
+    /// Seek by `offset` microseconds; past the end goes to the next track.
+    fn seek(&self, offset: i64) {
+        let Some(track) = &self.status.track else {
+            return;
+        };
+        let now = self.position_now();
+        let by = Duration::from_micros(offset.unsigned_abs());
+        let target = if offset < 0 {
+            now.saturating_sub(by)
+        } else {
+            now + by
+        };
+        match track.length {
+            Some(length) if target > length => self.send(Command::Next),
+            _ => self.send(Command::SetPosition(target)),
+        }
+    }
+
+    /// Jump to `position` microseconds, unless `track_id` is no longer the
+    /// current track.
+    fn set_position(&self, track_id: ObjectPath<'_>, position: i64) {
+        let Some(track) = &self.status.track else {
+            return;
+        };
+        let Ok(position) = u64::try_from(position).map(Duration::from_micros) else {
+            return;
+        };
+        if track_id.as_str() != self.track_path().as_str()
+            || track.length.is_some_and(|length| position > length)
+        {
+            return;
+        }
+        self.send(Command::SetPosition(position));
+    }
+
+    fn open_uri(&self, _uri: String) -> fdo::Result<()> {
+        Err(fdo::Error::NotSupported(
+            "opening URIs is not supported".into(),
+        ))
+    }
+
+    #[zbus(signal)]
+    async fn seeked(emitter: &SignalEmitter<'_>, position: i64) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> &str {
+        self.status.playback.as_str()
+    }
+
+    #[zbus(property)]
+    fn loop_status(&self) -> &str {
+        self.status.loop_status.as_str()
+    }
+
+    #[zbus(property)]
+    fn set_loop_status(&mut self, value: String) -> fdo::Result<()> {
+        let status = LoopStatus::parse(&value)
+            .ok_or_else(|| fdo::Error::InvalidArgs(format!("unknown loop status {value}")))?;
+        self.send(Command::SetLoopStatus(status));
+        Ok(())
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        self.status.rate
+    }
+
+    /// A rate of `0` pauses, as the specification asks.
+    #[zbus(property)]
+    fn set_rate(&mut self, value: f64) {
+        if value <= 0. {
+            self.send(Command::Pause);
+        } else {
+            let (min, max) = self.rate_range;
+            self.send(Command::SetRate(value.clamp(min, max)));
+        }
+    }
+
+    #[zbus(property)]
+    fn shuffle(&self) -> bool {
+        self.status.shuffle
+    }
+
+    #[zbus(property)]
+    fn set_shuffle(&mut self, value: bool) {
+        self.send(Command::SetShuffle(value));
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<&'static str, Value<'static>> {
+        let mut map = HashMap::new();
+        map.insert("mpris:trackid", Value::from(self.track_path()));
+        let Some(track) = &self.status.track else {
+            return map;
+        };
+        map.insert("xesam:title", Value::from(track.title.clone()));
+        if !track.artists.is_empty() {
+            map.insert("xesam:artist", Value::from(track.artists.clone()));
+        }
+        if !track.album.is_empty() {
+            map.insert("xesam:album", Value::from(track.album.clone()));
+        }
+        if let Some(url) = &track.art_url {
+            map.insert("mpris:artUrl", Value::from(url.clone()));
+        }
+        if let Some(length) = track.length {
+            let micros = i64::try_from(length.as_micros()).unwrap_or(i64::MAX);
+            map.insert("mpris:length", Value::from(micros));
+        }
+        map
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        self.status.volume
+    }
+
+    #[zbus(property)]
+    fn set_volume(&mut self, value: f64) {
+        self.send(Command::SetVolume(value.max(0.)));
+    }
+
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        i64::try_from(self.position_now().as_micros()).unwrap_or(i64::MAX)
+    }
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        self.rate_range.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        self.rate_range.1
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        self.status.can_go_next
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        self.status.can_go_previous
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        self.has_track()
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        self.has_track()
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        self.status
+            .track
+            .as_ref()
+            .is_some_and(|t| t.length.is_some())
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+/// Configures and starts an MPRIS service; see [`Mpris::builder`].
+pub struct MprisBuilder {
+    name: String,
+    identity: String,
+    desktop_entry: String,
+    rate_range: (f64, f64),
+    address: Option<String>,
+}
+
+impl MprisBuilder {
+    /// Name shown to the user, e.g. in a media widget. Defaults to `name`.
+    #[must_use]
+    pub fn identity(mut self, identity: impl Into<String>) -> Self {
+        self.identity = identity.into();
+        self
+    }
+
+    /// Basename of the player's `.desktop` file. Defaults to `name`.
+    #[must_use]
+    pub fn desktop_entry(mut self, desktop_entry: impl Into<String>) -> Self {
+        self.desktop_entry = desktop_entry.into();
+        self
+    }
+
+    /// Slowest and fastest playback rate the player supports; `1.0` for both
+    /// by default.
+    #[must_use]
+    pub fn rate_range(mut self, min: f64, max: f64) -> Self {
+        self.rate_range = (min, max);
+        self
+    }
+
+    /// Serve on the bus at `address` rather than the session bus, e.g. a
+    /// private bus in tests.
+    #[must_use]
+    pub fn address(mut self, address: impl Into<String>) -> Self {
+        self.address = Some(address.into());
+        self
+    }
+
+    /// Connect, serve both interfaces and take the bus name. Client calls
+    /// are sent to `commands`.
+    ///
+    /// # Errors
+    ///
+    /// Fails when the bus can't be reached or the name is taken.
+    pub async fn serve(self, commands: UnboundedSender<Command>) -> Result<Mpris> {
+        let builder = match &self.address {
+            Some(address) => connection::Builder::address(address.as_str())?,
+            None => connection::Builder::session()?,
+        };
+        let root = Root {
+            identity: self.identity,
+            desktop_entry: self.desktop_entry,
+        };
+        let player = Player {
+            status: Status::default(),
+            updated_at: Instant::now(),
+            name: path_element(&self.name),
+            rate_range: self.rate_range,
+            commands,
+        };
+        let conn = builder
+            .name(format!("org.mpris.MediaPlayer2.{}", self.name))?
+            .serve_at(PATH, root)?
+            .serve_at(PATH, player)?
+            .build()
+            .await?;
+        Ok(Mpris { conn })
+    }
+}
+
+/// A running MPRIS service. Dropping it leaves the bus.
+pub struct Mpris {
+    conn: Connection,
+}
+
+impl Mpris {
+    /// Service for the bus name `org.mpris.MediaPlayer2.<name>`.
+    pub fn builder(name: impl Into<String>) -> MprisBuilder {
+        let name = name.into();
+        MprisBuilder {
+            identity: name.clone(),
+            desktop_entry: name.clone(),
+            name,
+            rate_range: (1., 1.),
+            address: None,
+        }
+    }
+
+    /// Mirror `status`, signalling what changed since the last update.
+    ///
+    /// # Errors
+    ///
+    /// Fails when a signal can't be sent.
+    pub async fn update(&self, status: Status) -> Result<()> {
+        let iface = self
+            .conn
+            .object_server()
+            .interface::<_, Player>(PATH)
+            .await?;
+        let mut player = iface.get_mut().await;
+        if player.status == status {
+            return Ok(());
+        }
+
+        let expected = player.position_now();
+        let old = std::mem::replace(&mut player.status, status);
+        player.updated_at = Instant::now();
+        let new = &player.status;
+        let same_track = old.track.as_ref().map(|t| &t.id) == new.track.as_ref().map(|t| &t.id);
+        let seeked = same_track && expected.abs_diff(new.position) > SEEK_TOLERANCE;
+
+        let emitter = iface.signal_emitter();
+        if old.playback != new.playback {
+            player.playback_status_changed(emitter).await?;
+            player.can_play_changed(emitter).await?;
+            player.can_pause_changed(emitter).await?;
+        }
+        if old.loop_status != new.loop_status {
+            player.loop_status_changed(emitter).await?;
+        }
+        if old.shuffle != new.shuffle {
+            player.shuffle_changed(emitter).await?;
+        }
+        if old.volume != new.volume {
+            player.volume_changed(emitter).await?;
+        }
+        if old.rate != new.rate {
+            player.rate_changed(emitter).await?;
+        }
+        if old.track != new.track {
+            player.metadata_changed(emitter).await?;
+            player.can_seek_changed(emitter).await?;
+        }
+        if old.can_go_next != new.can_go_next {
+            player.can_go_next_changed(emitter).await?;
+        }
+        if old.can_go_previous != new.can_go_previous {
+            player.can_go_previous_changed(emitter).await?;
+        }
+        if seeked {
+            let micros = i64::try_from(new.position.as_micros()).unwrap_or(i64::MAX);
+            Player::seeked(emitter, micros).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn path_element_escapes_everything_but_alphanumerics() {
+        assert_eq!(path_element("dQw4w9WgXcQ"), "dQw4w9WgXcQ");
+        assert_eq!(path_element("a-b_c"), "a_2db_5fc");
+        assert!(ObjectPath::try_from(format!("/x/{}", path_element("-_ ü"))).is_ok());
+    }
+
+    #[test]
+    fn loop_status_round_trips() {
+        for status in [LoopStatus::None, LoopStatus::Track, LoopStatus::Playlist] {
+            assert_eq!(LoopStatus::parse(status.as_str()), Some(status));
+        }
+        assert_eq!(LoopStatus::parse("none"), None);
+    }
+}
@@ -0,0 +1,267 @@
+//! Runs the service on a private `dbus-daemon` and talks to it as a client
+//! would. Skipped when `dbus-daemon` is not installed.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, Command as Process, Stdio};
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use mpris_bridge::{Command, LoopStatus, Mpris, PlaybackStatus, Status, Track};
+use tokio::sync::mpsc::{UnboundedReceiver, unbounded_channel};
+use zbus::zvariant::{ObjectPath, OwnedValue};
+use zbus::{Connection, Proxy, connection};
+
+const DEST: &str = "org.mpris.MediaPlayer2.test";
+const PATH: &str = "/org/mpris/MediaPlayer2";
+const PLAYER: &str = "org.mpris.MediaPlayer2.Player";
+
+/// A session bus of its own, killed on drop.
+struct PrivateBus {
+    daemon: Child,
+    address: String,
+}
+
+impl PrivateBus {
+    fn start() -> Option<Self> {
+        let mut daemon = Process::new("dbus-daemon")
+            .args(["--session", "--nofork", "--print-address"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .inspect_err(|e| eprintln!("skipping: no dbus-daemon ({e})"))
+            .ok()?;
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take()?)
+            .read_line(&mut address)
+            .ok()?;
+        Some(Self {
+            daemon,
+            address: address.trim().to_owned(),
+        })
+    }
+}
+
+impl Drop for PrivateBus {
+    fn drop(&mut self) {
+        self.daemon.kill().ok();
+        self.daemon.wait().ok();
+    }
+}
+
+struct Fixture {
+    _bus: PrivateBus,
+    mpris: Mpris,
+    commands: UnboundedReceiver<Command>,
+    player: Proxy<'static>,
+    client: Connection,
+}
+
+async fn fixture() -> Option<Fixture> {
+    let bus = PrivateBus::start()?;
+    let (tx, commands) = unbounded_channel();
+    let mpris = Mpris::builder("test")
+        .identity("Test Player")
+        .rate_range(0.5, 2.)
+        .address(bus.address.as_str())
+        .serve(tx)
+        .await
+        .unwrap();
+    let client = connection::Builder::address(bus.address.as_str())
+        .unwrap()
+        .build()
+        .await
+        .unwrap();
+    let player = Proxy::new(&client, DEST, PATH, PLAYER).await.unwrap();
+    Some(Fixture {
+        _bus: bus,
+        mpris,
+        commands,
+        player,
+        client,
+    })
+}
+
+fn playing() -> Status {
+    Status {
+        playback: PlaybackStatus::Playing,
+        loop_status: LoopStatus::Playlist,
+        volume: 0.5,
+        position: Duration::from_secs(30),
+        track: Some(Track {
+            id: "dQw4w9-WgXcQ".into(),
+            title: "Song".into(),
+            artists: vec!["Artist".into()],
+            album: "Album".into(),
+            art_url: Some("https://example.com/cover.jpg".into()),
+            length: Some(Duration::from_secs(200)),
+        }),
+        can_go_next: true,
+        can_go_previous: true,
+        ..Status::default()
+    }
+}
+
+async fn next_command(commands: &mut UnboundedReceiver<Command>) -> Command {
+    tokio::time::timeout(Duration::from_secs(5), commands.recv())
+        .await
+        .expect("no command")
+        .unwrap()
+}
+
+#[tokio::test]
+async fn mirrors_state_and_metadata() {
+    let Some(f) = fixture().await else { return };
+    f.mpris.update(playing()).await.unwrap();
+
+    let root = Proxy::new(&f.client, DEST, PATH, "org.mpris.MediaPlayer2")
+        .await
+        .unwrap();
+    let identity: String = root.get_property("Identity").await.unwrap();
+    assert_eq!(identity, "Test Player");
+
+    let status: String = f.player.get_property("PlaybackStatus").await.unwrap();
+    assert_eq!(status, "Playing");
+    let loop_status: String = f.player.get_property("LoopStatus").await.unwrap();
+    assert_eq!(loop_status, "Playlist");
+    let volume: f64 = f.player.get_property("Volume").await.unwrap();
+    assert!((volume - 0.5).abs() < f64::EPSILON);
+    let position: i64 = f.player.get_property("Position").await.unwrap();
+    assert!((30_000_000..31_000_000).contains(&position), "{position}");
+    let can_seek: bool = f.player.get_property("CanSeek").await.unwrap();
+    assert!(can_seek);
+
+    let metadata: HashMap<String, OwnedValue> = f.player.get_property("Metadata").await.unwrap();
+    let text = |key: &str| String::try_from(metadata[key].try_clone().unwrap()).unwrap();
+    assert_eq!(text("xesam:title"), "Song");
+    assert_eq!(text("xesam:album"), "Album");
+    assert_eq!(text("mpris:artUrl"), "https://example.com/cover.jpg");
+    let artists = Vec::<String>::try_from(metadata["xesam:artist"].try_clone().unwrap()).unwrap();
+    assert_eq!(artists, ["Artist"]);
+    let length = i64::try_from(metadata["mpris:length"].try_clone().unwrap()).unwrap();
+    assert_eq!(length, 200_000_000);
+    let track_id = ObjectPath::try_from(metadata["mpris:trackid"].try_clone().unwrap()).unwrap();
+    assert_eq!(track_id.as_str(), "/test/track/dQw4w9_2dWgXcQ");
+}
+
+#[tokio::test]
+async fn calls_become_commands() {
+    let Some(mut f) = fixture().await else { return };
+    f.mpris.update(playing()).await.unwrap();
+
+    f.player.call_method("PlayPause", &()).await.unwrap();
+    assert_eq!(next_command(&mut f.commands).await, Command::Pause);
+    f.player.call_method("Next", &()).await.unwrap();
+    assert_eq!(next_command(&mut f.commands).await, Command::Next);
+
+    f.player
+        .call_method("Seek", &(-40_000_000i64))
+        .await
+        .unwrap();
+    assert_eq!(
+        next_command(&mut f.commands).await,
+        Command::SetPosition(Duration::ZERO)
+    );
+    f.player
+        .call_method("Seek", &(500_000_000i64))
+        .await
+        .unwrap();
+    assert_eq!(next_command(&mut f.commands).await, Command::Next);
+
+    // A stale track ID is ignored.
+    let stale = ObjectPath::try_from("/test/track/other").unwrap();
+    f.player
+        .call_method("SetPosition", &(stale, 1_000_000i64))
+        .await
+        .unwrap();
+    let current = ObjectPath::try_from("/test/track/dQw4w9_2dWgXcQ").unwrap();
+    f.player
+        .call_method("SetPosition", &(current, 90_000_000i64))
+        .await
+        .unwrap();
+    assert_eq!(
+        next_command(&mut f.commands).await,
+        Command::SetPosition(Duration::from_secs(90))
+    );
+
+    f.player.set_property("Volume", 0.25f64).await.unwrap();
+    assert_eq!(
+        next_command(&mut f.commands).await,
+        Command::SetVolume(0.25)
+    );
+    f.player.set_property("LoopStatus", "Track").await.unwrap();
+    assert_eq!(
+        next_command(&mut f.commands).await,
+        Command::SetLoopStatus(LoopStatus::Track)
+    );
+    f.player.set_property("Shuffle", true).await.unwrap();
+    assert_eq!(
+        next_command(&mut f.commands).await,
+        Command::SetShuffle(true)
+    );
+    f.player.set_property("Rate", 4f64).await.unwrap();
+    assert_eq!(next_command(&mut f.commands).await, Command::SetRate(2.));
+
+    assert!(
+        f.player
+            .set_property("LoopStatus", "Sometimes")
+            .await
+            .is_err()
+    );
+    assert!(
+        f.player
+            .call_method("OpenUri", &("file:///x",))
+            .await
+            .is_err()
+    );
+    assert!(f.commands.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn updates_are_signalled() {
+    let Some(f) = fixture().await else { return };
+    f.mpris.update(playing()).await.unwrap();
+
+    let properties = zbus::fdo::PropertiesProxy::builder(&f.client)
+        .destination(DEST)
+        .unwrap()
+        .path(PATH)
+        .unwrap()
+        .build()
+        .await
+        .unwrap();
+    let mut changes = properties.receive_properties_changed().await.unwrap();
+    let mut seeks = f.player.receive_signal("Seeked").await.unwrap();
+
+    let paused = Status {
+        playback: PlaybackStatus::Paused,
+        ..playing()
+    };
+    f.mpris.update(paused.clone()).await.unwrap();
+    let change = tokio::time::timeout(Duration::from_secs(5), changes.next())
+        .await
+        .unwrap()
+        .unwrap();
+    let args = change.args().unwrap();
+    assert_eq!(args.interface_name.as_str(), PLAYER);
+    let status = String::try_from(
+        args.changed_properties["PlaybackStatus"]
+            .try_clone()
+            .unwrap(),
+    );
+    assert_eq!(status.unwrap(), "Paused");
+
+    f.mpris
+        .update(Status {
+            position: Duration::from_secs(120),
+            ..paused
+        })
+        .await
+        .unwrap();
+    let seeked = tokio::time::timeout(Duration::from_secs(5), seeks.next())
+        .await
+        .unwrap()
+        .unwrap();
+    let position: i64 = seeked.body().deserialize().unwrap();
+    assert_eq!(position, 120_000_000);
+}
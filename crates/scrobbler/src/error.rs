@@ -0,0 +1,23 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("HTTP request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    #[error("service answered {status}: {message}")]
+    Status { status: u16, message: String },
+
+    /// The service will never take these listens; retrying is pointless.
+    #[error("listen rejected: {0}")]
+    Rejected(String),
+}
+
+impl Error {
+    /// Whether the same submission may succeed later.
+    pub fn is_transient(&self) -> bool {
+        !matches!(self, Self::Rejected(_))
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
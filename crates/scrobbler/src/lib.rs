@@ -0,0 +1,308 @@
+//! Scrobbling: telling listening-history services what is playing and what
+//! was listened to.
+//!
+//! A [`Tracker`] watches the player and says when a track started and when
+//! enough of it was heard to count; a [`Scrobbler`] sends both to every
+//! configured [`Service`] and keeps listens that failed to go through in a
+//! [`RetryQueue`] on disk until a later attempt succeeds.
+
+mod error;
+mod queue;
+mod service;
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use serde_json::{Value, json};
+
+pub use error::{Error, Result};
+pub use queue::{Pending, RetryQueue};
+pub use service::{Kind, LASTFM_URL, LISTENBRAINZ_URL, Service};
+
+/// Tracks shorter than this are never scrobbled.
+pub const MIN_DURATION: f64 = 30.;
+
+/// Listening time that always counts, however long the track.
+pub const MAX_THRESHOLD: f64 = 240.;
+
+/// Longest gap between two [`Tracker::observe`] calls counted as listening,
+/// so a suspended machine does not scrobble on waking up.
+const MAX_STEP: f64 = 5.;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// A listen, as services see it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    pub artist: String,
+    pub title: String,
+    /// Empty when unknown.
+    pub album: String,
+    /// Whole seconds.
+    pub duration: Option<u32>,
+    /// Where the track can be played, for services that link to it.
+    pub url: Option<String>,
+    /// Unix time the track started playing.
+    pub started_at: u64,
+}
+
+impl Track {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "artist": self.artist,
+            "title": self.title,
+            "album": self.album,
+            "duration": self.duration,
+            "url": self.url,
+            "started_at": self.started_at,
+        })
+    }
+
+    /// Parse what [`Track::to_json`] wrote.
+    pub fn from_json(v: &Value) -> Option<Self> {
+        Some(Self {
+            artist: v["artist"].as_str()?.to_owned(),
+            title: v["title"].as_str()?.to_owned(),
+            album: v["album"].as_str().unwrap_or_default().to_owned(),
+            duration: v["duration"].as_u64().and_then(|d| u32::try_from(d).ok()),
+            url: v["url"].as_str().map(str::to_owned),
+            started_at: v["started_at"].as_u64()?,
+        })
+    }
+}
+
+/// Seconds of a track that must be heard before it is scrobbled: half of
+/// it, or four minutes for long ones. `None` for tracks too short to count.
+pub fn threshold(duration: f64) -> Option<f64> {
+    (duration >= MIN_DURATION).then(|| (duration / 2.).min(MAX_THRESHOLD))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    /// The track began playing; time for a "now playing" update.
+    Started,
+    /// Enough of the track was heard; time to scrobble it.
+    Reached,
+}
+
+/// Follows one track at a time, counting the time it was actually heard, so
+/// seeking ahead or leaving it paused does not make a listen.
+#[derive(Debug, Default)]
+pub struct Tracker {
+    key: Option<String>,
+    position: f64,
+    heard: f64,
+    started: bool,
+    reached: bool,
+}
+
+impl Tracker {
+    /// Feed one look at the player: which track is current (any key that
+    /// tells tracks apart), where it is, how long it is (`0` if unknown),
+    /// whether it is audibly playing and the seconds since the last call.
+    pub fn observe(
+        &mut self,
+        key: Option<&str>,
+        position: f64,
+        duration: f64,
+        playing: bool,
+        elapsed: f64,
+    ) -> Option<Event> {
+        // Back at the top of a track already scrobbled: repeated, so a new listen.
+        let replayed = self.reached && position + 1. < self.position && position < MAX_STEP;
+        if key != self.key.as_deref() || replayed {
+            *self = Self {
+                key: key.map(str::to_owned),
+                ..Self::default()
+            };
+        }
+        self.key.as_ref()?;
+        self.position = position;
+        if !playing {
+            return None;
+        }
+        if !self.started {
+            self.started = true;
+            return Some(Event::Started);
+        }
+        self.heard += elapsed.clamp(0., MAX_STEP);
+        if !self.reached && threshold(duration).is_some_and(|t| self.heard >= t) {
+            self.reached = true;
+            return Some(Event::Reached);
+        }
+        None
+    }
+}
+
+/// Sends listens to services, queueing those that fail.
+pub struct Scrobbler {
+    services: Vec<Service>,
+    queue: RetryQueue,
+    http: reqwest::Client,
+}
+
+impl Scrobbler {
+    /// Scrobble to `services`, keeping failed listens in the file at
+    /// `queue_path`.
+    pub fn new(services: Vec<Service>, queue_path: impl Into<PathBuf>) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self {
+            services,
+            queue: RetryQueue::load(queue_path),
+            http,
+        }
+    }
+
+    pub fn queue(&self) -> &RetryQueue {
+        &self.queue
+    }
+
+    /// Tell every service `track` is playing. Not retried: it is stale by then.
+    pub async fn now_playing(&self, track: &Track) {
+        for service in &self.services {
+            if let Err(e) = service.now_playing(&self.http, track).await {
+                tracing::warn!(service = service.name, error = %e, "now playing update failed");
+            }
+        }
+    }
+
+    /// Submit a listen everywhere, after any older ones still waiting.
+    pub async fn scrobble(&mut self, track: Track) {
+        for service in &self.services {
+            // Listens go in order, so this one waits behind any still queued.
+            if !flush(&self.http, service, &mut self.queue).await {
+                self.queue.push(&service.name, track.clone());
+                continue;
+            }
+            let result = service
+                .scrobble(&self.http, std::slice::from_ref(&track))
+                .await;
+            match result {
+                Ok(()) => tracing::debug!(service = service.name, "scrobbled"),
+                Err(e) if e.is_transient() => {
+                    tracing::warn!(service = service.name, error = %e, "scrobble queued for retry");
+                    self.queue.push(&service.name, track.clone());
+                }
+                Err(e) => tracing::warn!(service = service.name, error = %e, "scrobble rejected"),
+            }
+        }
+    }
+
+    /// Try the queued listens again.
+    pub async fn retry(&mut self) {
+        for service in &self.services {
+            flush(&self.http, service, &mut self.queue).await;
+        }
+    }
+}
+
+/// Submit `service`'s queued listens in order; `false` if some are left.
+async fn flush(http: &reqwest::Client, service: &Service, queue: &mut RetryQueue) -> bool {
+    loop {
+        let batch = queue.peek(&service.name, service.batch_size());
+        if batch.is_empty() {
+            return true;
+        }
+        match service.scrobble(http, &batch).await {
+            Ok(()) => {
+                tracing::info!(
+                    service = service.name,
+                    count = batch.len(),
+                    "queued scrobbles sent"
+                );
+            }
+            Err(e) if e.is_transient() => {
+                tracing::debug!(service = service.name, error = %e, "queued scrobbles still failing");
+                return false;
+            }
+            Err(e) => {
+                tracing::warn!(service = service.name, error = %e, "dropping rejected scrobbles");
+            }
+        }
+        queue.remove(&service.name, batch.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thresholds() {
+        assert_eq!(threshold(0.), None);
+        assert_eq!(threshold(29.), None);
+        assert_eq!(threshold(30.), Some(15.));
+        assert_eq!(threshold(200.), Some(100.));
+        assert_eq!(threshold(3600.), Some(MAX_THRESHOLD));
+    }
+
+    #[test]
+    fn counts_heard_time() {
+        let mut t = Tracker::default();
+        assert_eq!(t.observe(None, 0., 0., false, 1.), None);
+        // Loading: not audible yet.
+        assert_eq!(t.observe(Some("a"), 0., 0., false, 1.), None);
+        assert_eq!(
+            t.observe(Some("a"), 0., 100., true, 1.),
+            Some(Event::Started)
+        );
+        for i in 1..50 {
+            assert_eq!(t.observe(Some("a"), f64::from(i), 100., true, 1.), None);
+        }
+        // Paused time does not count.
+        assert_eq!(t.observe(Some("a"), 49., 100., false, 60.), None);
+        assert_eq!(
+            t.observe(Some("a"), 50., 100., true, 1.),
+            Some(Event::Reached)
+        );
+        assert_eq!(t.observe(Some("a"), 51., 100., true, 1.), None);
+    }
+
+    #[test]
+    fn seeking_does_not_count() {
+        let mut t = Tracker::default();
+        t.observe(Some("a"), 0., 100., true, 0.);
+        // Jumping near the end, or a long gap between looks, adds little.
+        assert_eq!(t.observe(Some("a"), 95., 100., true, 1.), None);
+        assert_eq!(t.observe(Some("a"), 96., 100., true, 3600.), None);
+        assert_eq!(t.heard, 6.);
+    }
+
+    #[test]
+    fn new_and_repeated_tracks_start_over() {
+        let mut t = Tracker::default();
+        t.observe(Some("a"), 0., 40., true, 0.);
+        assert_eq!(t.observe(Some("a"), 20., 40., true, 5.), None);
+        assert_eq!(
+            t.observe(Some("b"), 0., 40., true, 5.),
+            Some(Event::Started)
+        );
+        for _ in 0..4 {
+            t.observe(Some("b"), 20., 40., true, 5.);
+        }
+        assert!(t.reached);
+        // Repeat-one: the same track from the top.
+        assert_eq!(
+            t.observe(Some("b"), 0.5, 40., true, 1.),
+            Some(Event::Started)
+        );
+        assert!(!t.reached);
+    }
+
+    #[test]
+    fn track_round_trip() {
+        let track = Track {
+            artist: "Artist".into(),
+            title: "Title".into(),
+            album: String::new(),
+            duration: Some(180),
+            url: None,
+            started_at: 1_700_000_000,
+        };
+        assert_eq!(Track::from_json(&track.to_json()), Some(track));
+        assert_eq!(Track::from_json(&json!({ "title": "No artist" })), None);
+    }
+}
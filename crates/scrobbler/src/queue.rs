@@ -0,0 +1,108 @@
+//! Listens no service took yet, kept on disk so they survive restarts.
+
+use std::path::PathBuf;
+
+use serde_json::{Value, json};
+
+use crate::Track;
+
+/// Listens kept at most; the oldest go first past this.
+const MAX_PENDING: usize = 5000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pending {
+    /// [`Service::name`](crate::Service::name) of the service it is for.
+    pub service: String,
+    pub track: Track,
+}
+
+/// A JSON file of [`Pending`] listens, oldest first, rewritten on each change.
+#[derive(Debug)]
+pub struct RetryQueue {
+    path: PathBuf,
+    entries: Vec<Pending>,
+}
+
+impl RetryQueue {
+    /// Open the queue at `path`; missing or unreadable files start it empty.
+    pub fn load(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        let entries = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|s| serde_json::from_str::<Value>(&s).ok())
+            .and_then(|v| v.as_array().cloned())
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|entry| {
+                Some(Pending {
+                    service: entry["service"].as_str()?.to_owned(),
+                    track: Track::from_json(&entry["track"])?,
+                })
+            })
+            .collect();
+        Self { path, entries }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn entries(&self) -> &[Pending] {
+        &self.entries
+    }
+
+    /// The oldest `limit` listens waiting for `service`.
+    pub fn peek(&self, service: &str, limit: usize) -> Vec<Track> {
+        self.entries
+            .iter()
+            .filter(|p| p.service == service)
+            .take(limit)
+            .map(|p| p.track.clone())
+            .collect()
+    }
+
+    pub fn push(&mut self, service: &str, track: Track) {
+        self.entries.push(Pending {
+            service: service.to_owned(),
+            track,
+        });
+        if self.entries.len() > MAX_PENDING {
+            let dropped = self.entries.len() - MAX_PENDING;
+            tracing::warn!(dropped, "scrobble retry queue full, dropping oldest");
+            self.entries.drain(..dropped);
+        }
+        self.save();
+    }
+
+    /// Forget the oldest `count` listens for `service`.
+    pub fn remove(&mut self, service: &str, count: usize) {
+        let mut left = count;
+        self.entries.retain(|p| {
+            if left > 0 && p.service == service {
+                left -= 1;
+                return false;
+            }
+            true
+        });
+        self.save();
+    }
+
+    fn save(&self) {
+        let entries: Vec<Value> = self
+            .entries
+            .iter()
+            .map(|p| json!({ "service": p.service, "track": p.track.to_json() }))
+            .collect();
+        // Written aside and renamed so a crash never leaves half a file.
+        let tmp = self.path.with_extension("tmp");
+        let result = std::fs::write(&tmp, Value::Array(entries).to_string())
+            .and_then(|()| std::fs::rename(&tmp, &self.path));
+        if let Err(e) = result {
+            tracing::warn!(error = %e, path = %self.path.display(), "cannot save scrobble queue");
+        }
+    }
+}
@@ -0,0 +1,345 @@
+//! The services listens go to, and their submission APIs.
+
+use std::collections::BTreeMap;
+
+use reqwest::Client;
+use serde_json::{Value, json};
+
+use crate::Track;
+use crate::error::{Error, Result};
+
+pub const LISTENBRAINZ_URL: &str = "https://api.listenbrainz.org";
+pub const LASTFM_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+
+/// Name sent to services that ask which player submitted a listen.
+const CLIENT_NAME: &str = "weviwavo";
+
+/// Most listens ListenBrainz takes in one import.
+const LISTENBRAINZ_BATCH: usize = 100;
+/// Most scrobbles Last.fm takes in one request.
+const LASTFM_BATCH: usize = 50;
+
+/// Last.fm error codes for requests that can never succeed as sent.
+const LASTFM_INVALID_PARAMETERS: i64 = 6;
+const LASTFM_INVALID_METHOD: i64 = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Kind {
+    /// ListenBrainz or a server implementing its API.
+    ListenBrainz { token: String },
+    /// Last.fm's scrobbling API, also spoken by Libre.fm and others.
+    LastFm {
+        api_key: String,
+        secret: String,
+        session_key: String,
+    },
+}
+
+/// A configured service. Its `name` keys its entries in the retry queue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Service {
+    pub name: String,
+    pub url: String,
+    pub kind: Kind,
+}
+
+impl Service {
+    /// Parse one entry of the configuration object:
+    ///
+    /// ```json
+    /// { "type": "listenbrainz", "token": "...", "url": "optional" }
+    /// { "type": "lastfm", "api_key": "...", "secret": "...",
+    ///   "session_key": "...", "url": "optional" }
+    /// ```
+    pub fn from_json(name: &str, v: &Value) -> Option<Self> {
+        let field = |key: &str| {
+            v[key]
+                .as_str()
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::to_owned)
+        };
+        let (kind, default_url) = match v["type"].as_str()? {
+            "listenbrainz" => (
+                Kind::ListenBrainz {
+                    token: field("token")?,
+                },
+                LISTENBRAINZ_URL,
+            ),
+            "lastfm" => (
+                Kind::LastFm {
+                    api_key: field("api_key")?,
+                    secret: field("secret")?,
+                    session_key: field("session_key")?,
+                },
+                LASTFM_URL,
+            ),
+            _ => return None,
+        };
+        Some(Self {
+            name: name.to_owned(),
+            url: field("url").unwrap_or_else(|| default_url.to_owned()),
+            kind,
+        })
+    }
+
+    /// Every valid service in a configuration object keyed by name.
+    pub fn all_from_json(v: &Value) -> Vec<Self> {
+        let Some(entries) = v.as_object() else {
+            return Vec::new();
+        };
+        entries
+            .iter()
+            .filter_map(|(name, entry)| {
+                let service = Self::from_json(name, entry);
+                if service.is_none() {
+                    tracing::warn!(name, "ignoring invalid scrobbling service");
+                }
+                service
+            })
+            .collect()
+    }
+
+    /// Most listens [`Service::scrobble`] should be given at once.
+    pub fn batch_size(&self) -> usize {
+        match self.kind {
+            Kind::ListenBrainz { .. } => LISTENBRAINZ_BATCH,
+            Kind::LastFm { .. } => LASTFM_BATCH,
+        }
+    }
+
+    pub async fn now_playing(&self, http: &Client, track: &Track) -> Result<()> {
+        match &self.kind {
+            Kind::ListenBrainz { token } => {
+                let body = json!({
+                    "listen_type": "playing_now",
+                    "payload": [{ "track_metadata": track_metadata(track) }],
+                });
+                self.listenbrainz(http, token, &body).await
+            }
+            Kind::LastFm {
+                api_key,
+                secret,
+                session_key,
+            } => {
+                let mut params = vec![("method".to_owned(), "track.updateNowPlaying".to_owned())];
+                params.extend(lastfm_track(track, None));
+                params.push(("api_key".to_owned(), api_key.clone()));
+                params.push(("sk".to_owned(), session_key.clone()));
+                self.lastfm(http, secret, params).await
+            }
+        }
+    }
+
+    /// Submit finished listens, oldest first.
+    pub async fn scrobble(&self, http: &Client, tracks: &[Track]) -> Result<()> {
+        match &self.kind {
+            Kind::ListenBrainz { token } => {
+                let payload: Vec<Value> = tracks
+                    .iter()
+                    .map(|track| {
+                        json!({
+                            "listened_at": track.started_at,
+                            "track_metadata": track_metadata(track),
+                        })
+                    })
+                    .collect();
+                let listen_type = if tracks.len() == 1 {
+                    "single"
+                } else {
+                    "import"
+                };
+                let body = json!({ "listen_type": listen_type, "payload": payload });
+                self.listenbrainz(http, token, &body).await
+            }
+            Kind::LastFm {
+                api_key,
+                secret,
+                session_key,
+            } => {
+                let mut params = vec![("method".to_owned(), "track.scrobble".to_owned())];
+                for (i, track) in tracks.iter().enumerate() {
+                    params.extend(lastfm_track(track, Some(i)));
+                }
+                params.push(("api_key".to_owned(), api_key.clone()));
+                params.push(("sk".to_owned(), session_key.clone()));
+                self.lastfm(http, secret, params).await
+            }
+        }
+    }
+
+    async fn listenbrainz(&self, http: &Client, token: &str, body: &Value) -> Result<()> {
+        let url = format!("{}/1/submit-listens", self.url.trim_end_matches('/'));
+        let response = http
+            .post(url)
+            .header("Authorization", format!("Token {token}"))
+            .json(body)
+            .send()
+            .await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let body: Value = response.json().await.unwrap_or_default();
+        let message = body["error"].as_str().unwrap_or_default().to_owned();
+        // Bad listens; an expired token is 401 and worth another try.
+        if status == reqwest::StatusCode::BAD_REQUEST {
+            return Err(Error::Rejected(message));
+        }
+        Err(Error::Status {
+            status: status.as_u16(),
+            message,
+        })
+    }
+
+    /// Sign and send a Last.fm API call.
+    async fn lastfm(
+        &self,
+        http: &Client,
+        secret: &str,
+        mut params: Vec<(String, String)>,
+    ) -> Result<()> {
+        let signature = lastfm_signature(&params, secret);
+        // `format` is left out of the signature, as Last.fm expects.
+        params.push(("api_sig".to_owned(), signature));
+        params.push(("format".to_owned(), "json".to_owned()));
+
+        let response = http.post(&self.url).form(&params).send().await?;
+        let status = response.status();
+        let body: Value = response.json().await.unwrap_or_default();
+        let Some(code) = body["error"].as_i64() else {
+            if status.is_success() {
+                return Ok(());
+            }
+            return Err(Error::Status {
+                status: status.as_u16(),
+                message: String::new(),
+            });
+        };
+        let message = body["message"].as_str().unwrap_or_default().to_owned();
+        if matches!(code, LASTFM_INVALID_PARAMETERS | LASTFM_INVALID_METHOD) {
+            return Err(Error::Rejected(message));
+        }
+        Err(Error::Status {
+            status: status.as_u16(),
+            message: format!("error {code}: {message}"),
+        })
+    }
+}
+
+fn track_metadata(track: &Track) -> Value {
+    let mut info = json!({
+        "media_player": CLIENT_NAME,
+        "submission_client": CLIENT_NAME,
+    });
+    if let Some(duration) = track.duration {
+        info["duration"] = json!(duration);
+    }
+    if let Some(url) = &track.url {
+        info["origin_url"] = json!(url);
+    }
+    let mut metadata = json!({
+        "artist_name": track.artist,
+        "track_name": track.title,
+        "additional_info": info,
+    });
+    if !track.album.is_empty() {
+        metadata["release_name"] = json!(track.album);
+    }
+    metadata
+}
+
+/// A track's Last.fm parameters, keyed `name[index]` in batches.
+fn lastfm_track(track: &Track, index: Option<usize>) -> Vec<(String, String)> {
+    let mut params = vec![
+        ("artist", track.artist.clone()),
+        ("track", track.title.clone()),
+    ];
+    if !track.album.is_empty() {
+        params.push(("album", track.album.clone()));
+    }
+    if let Some(duration) = track.duration {
+        params.push(("duration", duration.to_string()));
+    }
+    if index.is_some() {
+        params.push(("timestamp", track.started_at.to_string()));
+    }
+    params
+        .into_iter()
+        .map(|(key, value)| match index {
+            Some(i) => (format!("{key}[{i}]"), value),
+            None => (key.to_owned(), value),
+        })
+        .collect()
+}
+
+/// `api_sig`: the MD5 of every parameter as `keyvalue`, sorted by key, then
+/// the shared secret.
+fn lastfm_signature(params: &[(String, String)], secret: &str) -> String {
+    let sorted: BTreeMap<&str, &str> = params
+        .iter()
+        .map(|(k, v)| (k.as_str(), v.as_str()))
+        .collect();
+    let mut text: String = sorted.iter().map(|(k, v)| format!("{k}{v}")).collect();
+    text.push_str(secret);
+    format!("{:x}", md5::compute(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_configuration() {
+        let config = json!({
+            "listenbrainz": { "type": "listenbrainz", "token": "abc" },
+            "librefm": {
+                "type": "lastfm",
+                "url": "https://libre.fm/2.0/",
+                "api_key": "k",
+                "secret": "s",
+                "session_key": "sk",
+            },
+            "broken": { "type": "lastfm", "api_key": "k" },
+        });
+        let services = Service::all_from_json(&config);
+        assert_eq!(
+            services,
+            [
+                Service {
+                    name: "librefm".into(),
+                    url: "https://libre.fm/2.0/".into(),
+                    kind: Kind::LastFm {
+                        api_key: "k".into(),
+                        secret: "s".into(),
+                        session_key: "sk".into(),
+                    },
+                },
+                Service {
+                    name: "listenbrainz".into(),
+                    url: LISTENBRAINZ_URL.into(),
+                    kind: Kind::ListenBrainz {
+                        token: "abc".into()
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn signs_sorted_parameters() {
+        let params = [
+            ("track", "B"),
+            ("artist", "A"),
+            ("method", "track.scrobble"),
+        ]
+        .map(|(k, v)| (k.to_owned(), v.to_owned()));
+        assert_eq!(
+            lastfm_signature(&params, "secret"),
+            format!(
+                "{:x}",
+                md5::compute("artistAmethodtrack.scrobbletrackBsecret")
+            )
+        );
+    }
+}
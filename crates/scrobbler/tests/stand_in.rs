@@ -0,0 +1,289 @@
+//! Submissions against a local stand-in for the services, which records what
+//! it was sent and answers as scripted.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use scrobbler::{Kind, Scrobbler, Service, Track};
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::{UnboundedReceiver, unbounded_channel};
+
+#[derive(Debug)]
+struct Received {
+    path: String,
+    headers: BTreeMap<String, String>,
+    body: String,
+}
+
+struct StandIn {
+    url: String,
+    received: UnboundedReceiver<Received>,
+    /// Status and body of the next replies; `200 {}` once empty.
+    replies: Arc<Mutex<VecDeque<(u16, Value)>>>,
+}
+
+impl StandIn {
+    async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, received) = unbounded_channel();
+        let replies = Arc::new(Mutex::new(VecDeque::<(u16, Value)>::new()));
+        let script = Arc::clone(&replies);
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let (read, mut write) = stream.into_split();
+                let mut read = BufReader::new(read);
+                let mut line = String::new();
+                read.read_line(&mut line).await.unwrap();
+                let path = line.split(' ').nth(1).unwrap_or_default().to_owned();
+                let mut headers = BTreeMap::new();
+                loop {
+                    line.clear();
+                    read.read_line(&mut line).await.unwrap();
+                    let Some((name, value)) = line.trim_end().split_once(": ") else {
+                        break;
+                    };
+                    headers.insert(name.to_ascii_lowercase(), value.to_owned());
+                }
+                let length = headers
+                    .get("content-length")
+                    .and_then(|l| l.parse().ok())
+                    .unwrap_or(0);
+                let mut body = vec![0; length];
+                read.read_exact(&mut body).await.unwrap();
+                let (status, reply) = script
+                    .lock()
+                    .unwrap()
+                    .pop_front()
+                    .unwrap_or((200, json!({})));
+                let reply = reply.to_string();
+                let response = format!(
+                    "HTTP/1.1 {status} Scripted\r\nContent-Type: application/json\r\n\
+                     Content-Length: {}\r\nConnection: close\r\n\r\n{reply}",
+                    reply.len()
+                );
+                write.write_all(response.as_bytes()).await.unwrap();
+                let body = String::from_utf8(body).unwrap();
+                tx.send(Received {
+                    path,
+                    headers,
+                    body,
+                })
+                .ok();
+            }
+        });
+        Self {
+            url,
+            received,
+            replies,
+        }
+    }
+
+    fn reply(&self, status: u16, body: Value) {
+        self.replies.lock().unwrap().push_back((status, body));
+    }
+
+    async fn next(&mut self) -> Received {
+        self.received.recv().await.unwrap()
+    }
+}
+
+fn listenbrainz(url: &str) -> Service {
+    Service {
+        name: "listenbrainz".into(),
+        url: url.into(),
+        kind: Kind::ListenBrainz {
+            token: "lb-token".into(),
+        },
+    }
+}
+
+fn lastfm(url: &str) -> Service {
+    Service {
+        name: "lastfm".into(),
+        url: format!("{url}/2.0/"),
+        kind: Kind::LastFm {
+            api_key: "key".into(),
+            secret: "secret".into(),
+            session_key: "session".into(),
+        },
+    }
+}
+
+fn track(title: &str, started_at: u64) -> Track {
+    Track {
+        artist: "Daft Punk".into(),
+        title: title.into(),
+        album: "Discovery".into(),
+        duration: Some(320),
+        url: Some("https://music.youtube.com/watch?v=FGBhQbmPwH8".into()),
+        started_at,
+    }
+}
+
+/// A fresh queue file for one test.
+fn queue_path(test: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("scrobbler-{test}-{}.json", std::process::id()));
+    std::fs::remove_file(&path).ok();
+    path
+}
+
+fn decode(s: &str) -> String {
+    let s = s.replace('+', " ");
+    let mut out = Vec::new();
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex = [bytes.next().unwrap(), bytes.next().unwrap()];
+            let hex = std::str::from_utf8(&hex).unwrap();
+            out.push(u8::from_str_radix(hex, 16).unwrap());
+        } else {
+            out.push(b);
+        }
+    }
+    String::from_utf8(out).unwrap()
+}
+
+fn form(body: &str) -> BTreeMap<String, String> {
+    body.split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(k, v)| (decode(k), decode(v)))
+        .collect()
+}
+
+#[tokio::test]
+async fn listenbrainz_submissions() {
+    let mut server = StandIn::start().await;
+    let mut scrobbler = Scrobbler::new(vec![listenbrainz(&server.url)], queue_path("listenbrainz"));
+
+    scrobbler.now_playing(&track("One More Time", 100)).await;
+    let request = server.next().await;
+    assert_eq!(request.path, "/1/submit-listens");
+    assert_eq!(request.headers["authorization"], "Token lb-token");
+    let body: Value = serde_json::from_str(&request.body).unwrap();
+    assert_eq!(body["listen_type"], "playing_now");
+    assert_eq!(body["payload"][0].get("listened_at"), None);
+
+    scrobbler.scrobble(track("One More Time", 100)).await;
+    let body: Value = serde_json::from_str(&server.next().await.body).unwrap();
+    assert_eq!(
+        body,
+        json!({
+            "listen_type": "single",
+            "payload": [{
+                "listened_at": 100,
+                "track_metadata": {
+                    "artist_name": "Daft Punk",
+                    "track_name": "One More Time",
+                    "release_name": "Discovery",
+                    "additional_info": {
+                        "duration": 320,
+                        "origin_url": "https://music.youtube.com/watch?v=FGBhQbmPwH8",
+                        "media_player": "weviwavo",
+                        "submission_client": "weviwavo",
+                    },
+                },
+            }],
+        })
+    );
+    assert!(scrobbler.queue().is_empty());
+}
+
+#[tokio::test]
+async fn lastfm_requests_are_signed() {
+    let mut server = StandIn::start().await;
+    let mut scrobbler = Scrobbler::new(vec![lastfm(&server.url)], queue_path("lastfm"));
+
+    scrobbler.scrobble(track("Aerodynamic", 200)).await;
+    let request = server.next().await;
+    assert_eq!(request.path, "/2.0/");
+    let params = form(&request.body);
+    assert_eq!(params["method"], "track.scrobble");
+    assert_eq!(params["artist[0]"], "Daft Punk");
+    assert_eq!(params["track[0]"], "Aerodynamic");
+    assert_eq!(params["album[0]"], "Discovery");
+    assert_eq!(params["timestamp[0]"], "200");
+    assert_eq!(params["sk"], "session");
+    assert_eq!(params["format"], "json");
+
+    // Every other parameter, sorted by name, then the secret.
+    let signed: String = params
+        .iter()
+        .filter(|(k, _)| !matches!(k.as_str(), "api_sig" | "format"))
+        .map(|(k, v)| format!("{k}{v}"))
+        .collect();
+    let expected = format!("{:x}", md5::compute(format!("{signed}secret")));
+    assert_eq!(params["api_sig"], expected);
+}
+
+#[tokio::test]
+async fn failed_listens_are_kept_and_sent_in_order() {
+    let mut server = StandIn::start().await;
+    let path = queue_path("retry");
+    let mut scrobbler = Scrobbler::new(vec![listenbrainz(&server.url)], &path);
+
+    server.reply(503, json!({ "code": 503, "error": "down for maintenance" }));
+    scrobbler.scrobble(track("Digital Love", 300)).await;
+    server.next().await;
+    assert_eq!(scrobbler.queue().len(), 1);
+
+    // Still down: the queued listen is tried first, and the new one waits.
+    server.reply(503, json!({}));
+    scrobbler
+        .scrobble(track("Harder, Better, Faster, Stronger", 600))
+        .await;
+    assert_eq!(
+        serde_json::from_str::<Value>(&server.next().await.body).unwrap()["listen_type"],
+        "single"
+    );
+    assert!(server.received.try_recv().is_err());
+    assert_eq!(scrobbler.queue().len(), 2);
+    drop(scrobbler);
+
+    // After a restart the queue is still there and goes out in one import.
+    let mut scrobbler = Scrobbler::new(vec![listenbrainz(&server.url)], &path);
+    assert_eq!(scrobbler.queue().len(), 2);
+    scrobbler.retry().await;
+    let body: Value = serde_json::from_str(&server.next().await.body).unwrap();
+    assert_eq!(body["listen_type"], "import");
+    let titles: Vec<&str> = body["payload"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|listen| listen["track_metadata"]["track_name"].as_str().unwrap())
+        .collect();
+    assert_eq!(titles, ["Digital Love", "Harder, Better, Faster, Stronger"]);
+    assert!(scrobbler.queue().is_empty());
+    assert!(Scrobbler::new(Vec::new(), &path).queue().is_empty());
+}
+
+#[tokio::test]
+async fn rejected_listens_are_dropped() {
+    let mut server = StandIn::start().await;
+    let mut scrobbler = Scrobbler::new(
+        vec![listenbrainz(&server.url), lastfm(&server.url)],
+        queue_path("rejected"),
+    );
+
+    server.reply(400, json!({ "code": 400, "error": "bad listen" }));
+    server.reply(200, json!({ "error": 6, "message": "Invalid parameters" }));
+    scrobbler.scrobble(track("Voyager", 400)).await;
+    server.next().await;
+    server.next().await;
+    assert!(scrobbler.queue().is_empty());
+
+    // Last.fm errors other than bad parameters are worth retrying.
+    server.reply(200, json!({}));
+    server.reply(
+        200,
+        json!({ "error": 16, "message": "Temporarily unavailable" }),
+    );
+    scrobbler.scrobble(track("Veridis Quo", 500)).await;
+    let queued = scrobbler.queue().entries();
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].service, "lastfm");
+}
@@ -0,0 +1,17 @@
+//! Where a `weviwavo --daemon` listens, shared by the daemon and this
+//! client so both always agree.
+
+use std::path::PathBuf;
+
+/// The control socket: `$WEVIWAVO_SOCKET`, else `weviwavo.sock` in
+/// `$XDG_RUNTIME_DIR`, else a per-user socket in the temp directory.
+pub fn socket_path() -> PathBuf {
+    if let Some(path) = std::env::var_os("WEVIWAVO_SOCKET") {
+        return PathBuf::from(path);
+    }
+    if let Some(dir) = std::env::var_os("XDG_RUNTIME_DIR") {
+        return PathBuf::from(dir).join("weviwavo.sock");
+    }
+    let user = std::env::var("USER").unwrap_or_default();
+    std::env::temp_dir().join(format!("weviwavo-{user}.sock"))
+}
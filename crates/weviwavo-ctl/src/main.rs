@@ -0,0 +1,232 @@
+//! Control a `weviwavo --daemon` over its Unix socket.
+//!
+//! Each invocation sends one JSON-lines request and prints the reply, either
+//! as text or, with `--json`, as the raw reply line.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use serde_json::{Value, json};
+use weviwavo_ctl::socket_path;
+
+const USAGE: &str = "\
+usage: weviwavo-ctl [--socket PATH] [--json] <command>
+
+commands:
+  status                 current track, position and queue
+  play <video-id>        play a song now
+  enqueue <video-id>     add a song to the end of the queue
+  seek <secs | m:ss>     jump within the current track
+  volume <0-100>         set the volume
+  pause | resume | next | previous | stop
+  search <query...>      find songs to play";
+
+/// `90`, `90.5` or `1:30`.
+fn parse_secs(s: &str) -> Option<f64> {
+    match s.split_once(':') {
+        Some((m, s)) => Some(m.parse::<u32>().ok()? as f64 * 60. + s.parse::<f64>().ok()?),
+        None => s.parse().ok(),
+    }
+    .filter(|secs: &f64| secs.is_finite() && *secs >= 0.)
+}
+
+fn format_secs(secs: f64) -> String {
+    let secs = secs.max(0.) as u64;
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+/// The request for a command line, or a usage error.
+fn request(args: &[String]) -> Result<Value, String> {
+    let (cmd, rest) = args.split_first().ok_or("missing command")?;
+    let one = || match rest {
+        [arg] => Ok(arg.as_str()),
+        _ => Err(format!("`{cmd}` takes one argument")),
+    };
+    Ok(match cmd.as_str() {
+        "status" | "pause" | "resume" | "next" | "previous" | "stop" if rest.is_empty() => {
+            json!({ "cmd": cmd })
+        }
+        "play" | "enqueue" => json!({ "cmd": cmd, "id": one()? }),
+        "seek" => {
+            let secs = parse_secs(one()?).ok_or("seek takes seconds or m:ss")?;
+            json!({ "cmd": "seek", "secs": secs })
+        }
+        "volume" => {
+            let level = one()?
+                .trim_end_matches('%')
+                .parse::<u8>()
+                .ok()
+                .filter(|v| *v <= 100)
+                .ok_or("volume takes a number from 0 to 100")?;
+            json!({ "cmd": "volume", "level": f64::from(level) / 100. })
+        }
+        "search" if !rest.is_empty() => json!({ "cmd": "search", "query": rest.join(" ") }),
+        "status" | "pause" | "resume" | "next" | "previous" | "stop" => {
+            return Err(format!("`{cmd}` takes no arguments"));
+        }
+        "search" => return Err("search needs a query".into()),
+        other => return Err(format!("unknown command `{other}`")),
+    })
+}
+
+#[cfg(unix)]
+fn send(socket: &PathBuf, request: &Value) -> std::io::Result<Value> {
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixStream;
+
+    let mut stream = UnixStream::connect(socket)?;
+    writeln!(stream, "{request}")?;
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    serde_json::from_str(&line).map_err(std::io::Error::other)
+}
+
+#[cfg(not(unix))]
+fn send(_socket: &PathBuf, _request: &Value) -> std::io::Result<Value> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "the daemon only runs on Unix",
+    ))
+}
+
+fn song_line(song: &Value) -> String {
+    let text = |key: &str| song[key].as_str().unwrap_or_default().to_owned();
+    let (title, artist) = (text("title"), text("artist"));
+    if artist.is_empty() {
+        title
+    } else {
+        format!("{title} — {artist}")
+    }
+}
+
+/// Human-readable form of a successful reply.
+fn render(cmd: &str, reply: &Value) -> String {
+    match cmd {
+        "status" => {
+            let status = &reply["status"];
+            let state = status["state"].as_str().unwrap_or("stopped");
+            if state == "stopped" {
+                return "stopped".into();
+            }
+            let mut out = format!(
+                "{state}: {}\n{} / {}  volume {:.0}%",
+                song_line(status),
+                format_secs(status["position"].as_f64().unwrap_or_default()),
+                format_secs(status["duration"].as_f64().unwrap_or_default()),
+                status["volume"].as_f64().unwrap_or_default() * 100.,
+            );
+            let current = status["queue_index"].as_u64().unwrap_or_default() as usize;
+            for (i, song) in status["queue"].as_array().into_iter().flatten().enumerate() {
+                let marker = if i == current { '>' } else { ' ' };
+                out.push_str(&format!("\n{marker} {:>3}. {}", i + 1, song_line(song)));
+            }
+            out
+        }
+        "play" => format!("playing {}", song_line(&reply["song"])),
+        "enqueue" => format!("queued {}", song_line(&reply["song"])),
+        "search" => reply["results"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|song| {
+                let mut line = format!(
+                    "{}  {}",
+                    song["video_id"].as_str().unwrap_or_default(),
+                    song_line(song)
+                );
+                if let Some(secs) = song["duration"].as_f64() {
+                    line.push_str(&format!(" ({})", format_secs(secs)));
+                }
+                line
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
+    }
+}
+
+fn main() -> ExitCode {
+    let mut socket = socket_path();
+    let mut raw = false;
+    let mut args = Vec::new();
+    let mut argv = std::env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        match arg.as_str() {
+            "--socket" => match argv.next() {
+                Some(path) => socket = PathBuf::from(path),
+                None => {
+                    eprintln!("--socket needs a path\n\n{USAGE}");
+                    return ExitCode::from(2);
+                }
+            },
+            "--json" => raw = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return ExitCode::SUCCESS;
+            }
+            _ => args.push(arg),
+        }
+    }
+
+    let request = match request(&args) {
+        Ok(request) => request,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return ExitCode::from(2);
+        }
+    };
+    let reply = match send(&socket, &request) {
+        Ok(reply) => reply,
+        Err(e) => {
+            eprintln!("cannot reach weviwavo at {}: {e}", socket.display());
+            return ExitCode::from(2);
+        }
+    };
+
+    if raw {
+        println!("{reply}");
+    } else if reply["ok"].as_bool() == Some(true) {
+        let text = render(request["cmd"].as_str().unwrap_or_default(), &reply);
+        if !text.is_empty() {
+            println!("{text}");
+        }
+    } else {
+        eprintln!("{}", reply["error"].as_str().unwrap_or("request failed"));
+    }
+    if reply["ok"].as_bool() == Some(true) {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(str::to_owned).collect()
+    }
+
+    #[test]
+    fn builds_requests() {
+        assert_eq!(request(&args("next")).unwrap(), json!({ "cmd": "next" }));
+        assert_eq!(
+            request(&args("seek 1:30")).unwrap(),
+            json!({ "cmd": "seek", "secs": 90. })
+        );
+        assert_eq!(
+            request(&args("volume 40%")).unwrap(),
+            json!({ "cmd": "volume", "level": 0.4 })
+        );
+        assert_eq!(
+            request(&args("search daft punk")).unwrap(),
+            json!({ "cmd": "search", "query": "daft punk" })
+        );
+        assert!(request(&args("seek -5")).is_err());
+        assert!(request(&args("volume 150")).is_err());
+        assert!(request(&args("pause now")).is_err());
+        assert!(request(&args("play")).is_err());
+        assert!(request(&[]).is_err());
+    }
+}
@@ -0,0 +1,103 @@
+//! Runs the binary against a stand-in daemon that records the request and
+//! answers with a canned reply.
+#![cfg(unix)]
+
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+use std::thread;
+
+use serde_json::{Value, json};
+
+/// Run `weviwavo-ctl args` against a daemon replying `reply`; returns the
+/// request it got and the command's output.
+fn run(name: &str, args: &[&str], reply: Value) -> (Value, Output) {
+    let socket =
+        std::env::temp_dir().join(format!("weviwavo-ctl-{}-{name}.sock", std::process::id()));
+    std::fs::remove_file(&socket).ok();
+    let listener = UnixListener::bind(&socket).unwrap();
+    let daemon = thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut line = String::new();
+        BufReader::new(&stream).read_line(&mut line).unwrap();
+        writeln!(&stream, "{reply}").unwrap();
+        serde_json::from_str::<Value>(&line).unwrap()
+    });
+    let output = Command::new(env!("CARGO_BIN_EXE_weviwavo-ctl"))
+        .args(args)
+        .env("WEVIWAVO_SOCKET", &socket)
+        .output()
+        .unwrap();
+    let request = daemon.join().unwrap();
+    std::fs::remove_file(&socket).ok();
+    (request, output)
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn prints_status() {
+    let reply = json!({
+        "ok": true,
+        "status": {
+            "state": "playing",
+            "title": "Song",
+            "artist": "Artist",
+            "position": 83.2,
+            "duration": 225.0,
+            "volume": 0.5,
+            "queue_index": 1,
+            "queue": [
+                { "video_id": "a", "title": "First", "artist": "Artist" },
+                { "video_id": "b", "title": "Song", "artist": "Artist" },
+            ],
+        },
+    });
+    let (request, output) = run("status", &["status"], reply);
+    assert_eq!(request, json!({ "cmd": "status" }));
+    assert!(output.status.success());
+    assert_eq!(
+        stdout(&output),
+        "playing: Song — Artist\n1:23 / 3:45  volume 50%\n    1. First — Artist\n>   2. Song — Artist\n"
+    );
+}
+
+#[test]
+fn lists_search_results() {
+    let reply = json!({
+        "ok": true,
+        "results": [{ "video_id": "dQw4w9WgXcQ", "title": "Song", "artist": "Artist", "duration": 213 }],
+    });
+    let (request, output) = run("search", &["search", "never", "gonna"], reply);
+    assert_eq!(request, json!({ "cmd": "search", "query": "never gonna" }));
+    assert_eq!(stdout(&output), "dQw4w9WgXcQ  Song — Artist (3:33)\n");
+}
+
+#[test]
+fn daemon_errors_fail() {
+    let reply = json!({ "ok": false, "error": "no video `x`" });
+    let (request, output) = run("error", &["play", "x"], reply.clone());
+    assert_eq!(request, json!({ "cmd": "play", "id": "x" }));
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "no video `x`\n");
+
+    let (_, output) = run("raw", &["--json", "play", "x"], reply.clone());
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        serde_json::from_str::<Value>(&stdout(&output)).unwrap(),
+        reply
+    );
+}
+
+#[test]
+fn unreachable_daemon() {
+    let socket: PathBuf = std::env::temp_dir().join("weviwavo-ctl-missing.sock");
+    let output = Command::new(env!("CARGO_BIN_EXE_weviwavo-ctl"))
+        .args(["--socket", socket.to_str().unwrap(), "status"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+}
@@ -1,10 +1,6 @@
 use std::collections::HashMap;
 use std::time::{SystemTime, UNIX_EPOCH};
 
-/// All Innertube requests go to music.youtube.com regardless of client type;
-/// SAPISIDHASH must be computed against this origin (Metrolist: hardcoded).
-const ORIGIN: &str = "https://music.youtube.com";
-
 /// Parse a browser cookie header string into a name→value map.
 pub(crate) fn parse_cookies(cookie_str: &str) -> HashMap<String, String> {
     cookie_str
@@ -17,10 +13,14 @@ pub(crate) fn parse_cookies(cookie_str: &str) -> HashMap<String, String> {
         .collect()
 }
 
-/// Compute `SAPISIDHASH {ts}_{sha1}` for `music.youtube.com`.
+/// Compute `SAPISIDHASH {ts}_{sha1}` for `origin`.
+///
+/// All Innertube requests go to music.youtube.com regardless of client type, so
+/// `origin` is that host unless the client was pointed at a different
+/// [`crate::client::ApiEndpoint`].
 ///
 /// Returns `None` when neither SAPISID nor `__Secure-3PAPISID` is present.
-pub(crate) fn sapisidhash(cookies: &HashMap<String, String>) -> Option<String> {
+pub(crate) fn sapisidhash(cookies: &HashMap<String, String>, origin: &str) -> Option<String> {
     let sapisid = cookies
         .get("SAPISID")
         .or_else(|| cookies.get("__Secure-3PAPISID"))?;
@@ -30,7 +30,7 @@ pub(crate) fn sapisidhash(cookies: &HashMap<String, String>) -> Option<String> {
         .ok()?
         .as_secs();
 
-    let payload = format!("{ts} {sapisid} {ORIGIN}");
+    let payload = format!("{ts} {sapisid} {origin}");
     let hash = sha1_smol::Sha1::from(payload.as_bytes()).digest();
     Some(format!("SAPISIDHASH {ts}_{hash}"))
 }
@@ -474,3 +474,41 @@ impl Default for Locale {
 pub(crate) const MUSIC_ORIGIN: &str = "https://music.youtube.com";
 pub(crate) const MUSIC_REFERER: &str = "https://music.youtube.com/";
 pub(crate) const MUSIC_API_BASE: &str = "https://music.youtube.com/youtubei/v1/";
+
+/// Where Innertube requests are sent and which origin they claim to come from.
+///
+/// Defaults to `music.youtube.com`. Override it to point the client at a local
+/// stand-in server (see [`crate::transport`] for swapping the HTTP layer itself).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiEndpoint {
+    /// Prefix every endpoint path is appended to; must end with `/`.
+    pub api_base: String,
+    /// Sent as `X-Origin` and used for the SAPISIDHASH computation.
+    pub origin: String,
+    /// Sent as `Referer`.
+    pub referer: String,
+}
+
+impl ApiEndpoint {
+    /// Endpoint rooted at `base` (e.g. `"http://127.0.0.1:8080"`), using the
+    /// Innertube path layout: `{base}/youtubei/v1/`.
+    #[must_use]
+    pub fn at(base: &str) -> Self {
+        let base = base.trim_end_matches('/');
+        Self {
+            api_base: format!("{base}/youtubei/v1/"),
+            origin: base.to_owned(),
+            referer: format!("{base}/"),
+        }
+    }
+}
+
+impl Default for ApiEndpoint {
+    fn default() -> Self {
+        Self {
+            api_base: MUSIC_API_BASE.to_owned(),
+            origin: MUSIC_ORIGIN.to_owned(),
+            referer: MUSIC_REFERER.to_owned(),
+        }
+    }
+}
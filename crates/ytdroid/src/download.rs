@@ -0,0 +1,401 @@
+//! Chunked range downloads from googlevideo.
+//!
+//! googlevideo throttles long single `GET`s to roughly real-time speed, but
+//! serves bounded `range=` requests at full speed. [`ChunkedDownloader`] splits
+//! a byte range into fixed-size chunks, keeps a few of them in flight at once
+//! and retries each chunk on its own, while still yielding the bytes in order so
+//! callers can write them straight to a buffer or file.
+//!
+//! ```no_run
+//! use ytdroid::download::ChunkedDownloader;
+//!
+//! # async fn example(url: &str) -> ytdroid::Result<()> {
+//! let dl = ChunkedDownloader::new(reqwest::Client::new())
+//!     .chunk_size(512 * 1024)
+//!     .parallelism(4);
+//! let len = dl.content_length(url).await?;
+//! let mut chunks = dl.download(url, 0..len);
+//! while let Some(chunk) = chunks.next().await {
+//!     let chunk = chunk?;
+//!     println!("{} bytes at {}", chunk.data.len(), chunk.offset);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::VecDeque;
+use std::future::{Future, poll_fn};
+use std::ops::Range;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::Poll;
+use std::time::Duration;
+
+use bytes::Bytes;
+use reqwest::{Client, StatusCode, Url};
+use tracing::{debug, warn};
+
+use crate::error::{Error, Result};
+
+/// How a chunk's byte range is put on the request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RangeStyle {
+    /// `&range=start-end` query parameter, which googlevideo serves unthrottled.
+    #[default]
+    Query,
+    /// Standard `Range: bytes=start-end` header, for any other HTTP server.
+    Header,
+}
+
+/// One downloaded piece of the requested range.
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    /// Absolute offset of `data` in the file.
+    pub offset: u64,
+    pub data: Bytes,
+}
+
+/// Downloads byte ranges as parallel, individually retried chunks.
+#[derive(Debug, Clone)]
+pub struct ChunkedDownloader {
+    http: Client,
+    chunk_size: u64,
+    parallelism: usize,
+    retries: u32,
+    retry_delay: Duration,
+    range_style: RangeStyle,
+    headers: Arc<Vec<(String, String)>>,
+}
+
+impl ChunkedDownloader {
+    /// 1 MiB chunks, 4 in flight, 3 retries per chunk.
+    #[must_use]
+    pub fn new(http: Client) -> Self {
+        Self {
+            http,
+            chunk_size: 1024 * 1024,
+            parallelism: 4,
+            retries: 3,
+            retry_delay: Duration::from_millis(500),
+            range_style: RangeStyle::default(),
+            headers: Arc::new(Vec::new()),
+        }
+    }
+
+    /// Bytes per request. Clamped to at least 1.
+    #[must_use]
+    pub fn chunk_size(mut self, bytes: u64) -> Self {
+        self.chunk_size = bytes.max(1);
+        self
+    }
+
+    /// Chunks downloaded concurrently. Clamped to at least 1.
+    #[must_use]
+    pub fn parallelism(mut self, n: usize) -> Self {
+        self.parallelism = n.max(1);
+        self
+    }
+
+    /// Extra attempts per chunk after the first one fails.
+    #[must_use]
+    pub fn retries(mut self, n: u32) -> Self {
+        self.retries = n;
+        self
+    }
+
+    /// Base delay between attempts; attempt `n` waits `n × delay`.
+    #[must_use]
+    pub fn retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    #[must_use]
+    pub fn range_style(mut self, style: RangeStyle) -> Self {
+        self.range_style = style;
+        self
+    }
+
+    /// Header sent with every request (user agent, cookies, …).
+    #[must_use]
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.headers).push((name.into(), value.into()));
+        self
+    }
+
+    /// Split `range` into the chunk ranges this downloader would request.
+    #[must_use]
+    pub fn plan(&self, range: Range<u64>) -> Vec<Range<u64>> {
+        let mut chunks = Vec::new();
+        let mut start = range.start;
+        while start < range.end {
+            let end = (start + self.chunk_size).min(range.end);
+            chunks.push(start..end);
+            start = end;
+        }
+        chunks
+    }
+
+    /// Total size of the resource at `url`.
+    ///
+    /// Uses the `clen` parameter that googlevideo URLs carry (the same value as
+    /// `StreamingFormat::content_length`), and otherwise asks the server for the
+    /// first byte and reads the total from `Content-Range`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the request fails or the server reports no length.
+    pub async fn content_length(&self, url: &str) -> Result<u64> {
+        if let Some(clen) = Url::parse(url).ok().and_then(|u| {
+            u.query_pairs()
+                .find(|(k, _)| k == "clen")
+                .and_then(|(_, v)| v.parse().ok())
+        }) {
+            return Ok(clen);
+        }
+
+        let mut req = self.http.get(url).header("Range", "bytes=0-0");
+        for (k, v) in self.headers.iter() {
+            req = req.header(k, v);
+        }
+        let resp = req.send().await?;
+        if !resp.status().is_success() {
+            return Err(Error::HttpStatus {
+                status: resp.status().as_u16(),
+            });
+        }
+        resp.headers()
+            .get(reqwest::header::CONTENT_RANGE)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit('/').next())
+            .and_then(|total| total.parse().ok())
+            .or_else(|| (resp.status() == StatusCode::OK).then(|| resp.content_length()).flatten())
+            .ok_or(Error::MissingField {
+                field: "content length",
+            })
+    }
+
+    /// Download `range` of `url`, yielding chunks in file order.
+    ///
+    /// Up to `parallelism` chunks are requested ahead of the one being
+    /// consumed. Dropping the stream cancels everything still in flight.
+    #[must_use]
+    pub fn download(&self, url: &str, range: Range<u64>) -> ChunkStream {
+        ChunkStream {
+            downloader: self.clone(),
+            url: url.into(),
+            planned: self.plan(range).into(),
+            in_flight: VecDeque::new(),
+        }
+    }
+
+    /// Download a single chunk, retrying transient failures.
+    ///
+    /// # Errors
+    ///
+    /// [`Error::HttpStatus`] for a non-retryable status (e.g. `403` on an
+    /// expired URL), or [`Error::Chunk`] once the retries are used up.
+    pub async fn fetch_chunk(&self, url: &str, range: Range<u64>) -> Result<Bytes> {
+        let mut attempt = 0;
+        loop {
+            let reason = match self.try_chunk(url, range.clone()).await {
+                Ok(data) => return Ok(data),
+                Err(Error::HttpStatus { status }) if !is_retryable(status) => {
+                    return Err(Error::HttpStatus { status });
+                }
+                Err(e) => e.to_string(),
+            };
+            if attempt >= self.retries {
+                return Err(Error::Chunk {
+                    start: range.start,
+                    end: range.end,
+                    reason,
+                });
+            }
+            attempt += 1;
+            warn!(start = range.start, attempt, reason, "chunk failed, retrying");
+            tokio::time::sleep(self.retry_delay * attempt).await;
+        }
+    }
+
+    async fn try_chunk(&self, url: &str, range: Range<u64>) -> Result<Bytes> {
+        // Both forms take an inclusive end.
+        let spec = format!("{}-{}", range.start, range.end - 1);
+        let mut req = match self.range_style {
+            RangeStyle::Query => self.http.get(url).query(&[("range", &spec)]),
+            RangeStyle::Header => self.http.get(url).header("Range", format!("bytes={spec}")),
+        };
+        for (k, v) in self.headers.iter() {
+            req = req.header(k, v);
+        }
+
+        let resp = req.send().await?;
+        if !resp.status().is_success() {
+            return Err(Error::HttpStatus {
+                status: resp.status().as_u16(),
+            });
+        }
+        let data = resp.bytes().await?;
+        let expected = range.end - range.start;
+        if data.len() as u64 != expected {
+            return Err(Error::Chunk {
+                start: range.start,
+                end: range.end,
+                reason: format!("got {} of {expected} bytes", data.len()),
+            });
+        }
+        debug!(start = range.start, len = expected, "chunk downloaded");
+        Ok(data)
+    }
+}
+
+fn is_retryable(status: u16) -> bool {
+    status >= 500 || status == 408 || status == 429
+}
+
+type ChunkFuture = Pin<Box<dyn Future<Output = Result<Bytes>> + Send>>;
+
+enum Slot {
+    Running(ChunkFuture),
+    Done(Result<Bytes>),
+}
+
+/// Ordered chunks of one [`ChunkedDownloader::download`] call.
+pub struct ChunkStream {
+    downloader: ChunkedDownloader,
+    url: String,
+    planned: VecDeque<Range<u64>>,
+    in_flight: VecDeque<(u64, Slot)>,
+}
+
+impl ChunkStream {
+    /// Next chunk in file order, or `None` when the range is complete.
+    ///
+    /// After an error the stream stops; remaining chunks are not requested.
+    pub async fn next(&mut self) -> Option<Result<Chunk>> {
+        while self.in_flight.len() < self.downloader.parallelism {
+            let Some(range) = self.planned.pop_front() else {
+                break;
+            };
+            let dl = self.downloader.clone();
+            let url = self.url.clone();
+            let offset = range.start;
+            let fut: ChunkFuture = Box::pin(async move { dl.fetch_chunk(&url, range).await });
+            self.in_flight.push_back((offset, Slot::Running(fut)));
+        }
+
+        let result = poll_fn(|cx| {
+            for (_, slot) in &mut self.in_flight {
+                if let Slot::Running(fut) = slot
+                    && let Poll::Ready(res) = fut.as_mut().poll(cx)
+                {
+                    *slot = Slot::Done(res);
+                }
+            }
+            match self.in_flight.front() {
+                None => Poll::Ready(None),
+                Some((_, Slot::Done(_))) => Poll::Ready(self.in_flight.pop_front()),
+                Some((_, Slot::Running(_))) => Poll::Pending,
+            }
+        })
+        .await?;
+
+        let (offset, Slot::Done(res)) = result else {
+            unreachable!("only finished slots are popped");
+        };
+        if res.is_err() {
+            self.planned.clear();
+            self.in_flight.clear();
+        }
+        Some(res.map(|data| Chunk { offset, data }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    fn body() -> Vec<u8> {
+        (0..100_000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    /// Minimal `range=` server; the first request for each chunk fails with 503.
+    async fn serve() -> (String, Arc<Mutex<Vec<u64>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/videoplayback?clen=100000", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&requests);
+        tokio::spawn(async move {
+            let seen = Arc::new(Mutex::new(HashSet::new()));
+            loop {
+                let (mut sock, _) = listener.accept().await.unwrap();
+                let (log, seen) = (Arc::clone(&log), Arc::clone(&seen));
+                tokio::spawn(async move {
+                    let mut buf = vec![0; 4096];
+                    let n = sock.read(&mut buf).await.unwrap();
+                    let head = String::from_utf8_lossy(&buf[..n]).into_owned();
+                    let spec = head.split("range=").nth(1).unwrap().split(' ').next().unwrap();
+                    let (a, b) = spec.split_once('-').unwrap();
+                    let (a, b): (usize, usize) = (a.parse().unwrap(), b.parse().unwrap());
+                    log.lock().unwrap().push(a as u64);
+                    let resp = if seen.lock().unwrap().insert(a) {
+                        b"HTTP/1.1 503 Busy\r\ncontent-length: 0\r\n\r\n".to_vec()
+                    } else {
+                        let mut r = format!("HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n", b + 1 - a)
+                            .into_bytes();
+                        r.extend_from_slice(&body()[a..=b]);
+                        r
+                    };
+                    sock.write_all(&resp).await.unwrap();
+                });
+            }
+        });
+        (url, requests)
+    }
+
+    #[test]
+    fn plans_bounded_chunks() {
+        let dl = ChunkedDownloader::new(Client::new()).chunk_size(400);
+        assert_eq!(dl.plan(100..1000), vec![100..500, 500..900, 900..1000]);
+        assert!(dl.plan(10..10).is_empty());
+    }
+
+    #[tokio::test]
+    async fn downloads_in_order_with_retries() {
+        let (url, requests) = serve().await;
+        let dl = ChunkedDownloader::new(Client::new())
+            .chunk_size(16_384)
+            .parallelism(3)
+            .retry_delay(Duration::from_millis(1));
+
+        let len = dl.content_length(&url).await.unwrap();
+        assert_eq!(len, 100_000);
+
+        let mut out = Vec::new();
+        let mut chunks = dl.download(&url, 0..len);
+        while let Some(chunk) = chunks.next().await {
+            let chunk = chunk.unwrap();
+            assert_eq!(chunk.offset, out.len() as u64);
+            out.extend_from_slice(&chunk.data);
+        }
+        assert!(out == body());
+        // Seven chunks, each failing once.
+        assert_eq!(requests.lock().unwrap().len(), 14);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_retries() {
+        let (url, _) = serve().await;
+        let dl = ChunkedDownloader::new(Client::new())
+            .retries(0)
+            .retry_delay(Duration::from_millis(1));
+        let err = dl.fetch_chunk(&url, 0..10).await.unwrap_err();
+        assert!(matches!(err, Error::Chunk { start: 0, end: 10, .. }), "{err}");
+    }
+}
@@ -25,6 +25,21 @@ pub enum Error {
 
     #[error("HTTP {status} from Innertube API")]
     HttpStatus { status: u16 },
+
+    #[error("chunk {start}-{end} failed: {reason}")]
+    Chunk { start: u64, end: u64, reason: String },
+
+    #[error("fixture {key} unavailable: {reason}")]
+    Fixture { key: String, reason: String },
+
+    #[error("could not resolve {id} for download: {reason}")]
+    Resolve { id: String, reason: String },
+
+    #[error("could not tag audio file: {reason}")]
+    Tagging { reason: String },
+
+    #[error("I/O failed: {0}")]
+    Io(#[from] std::io::Error),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
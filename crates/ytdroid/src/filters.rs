@@ -62,4 +62,10 @@ pub mod browse_id {
     pub fn playlist(id: &str) -> String {
         format!("VL{id}")
     }
+
+    /// Whether a browse ID opens a podcast show (`"MPSP" + playlist_id`).
+    #[must_use]
+    pub fn is_podcast_show(id: &str) -> bool {
+        id.starts_with("MPSP")
+    }
 }
@@ -0,0 +1,247 @@
+//! Record/replay [`Transport`]s for offline tests.
+//!
+//! [`RecordingTransport`] forwards every request to a real transport and stores
+//! the raw JSON response under a fixtures directory. [`ReplayTransport`] serves
+//! those files back without touching the network, so page parsers can be
+//! exercised against captured responses and schema drift shows up as a failing
+//! test instead of silently empty sections.
+//!
+//! Files are named by [`fixture_key`]: the endpoint, the request's primary ID
+//! (browse ID, video ID, query, …) and a short hash of the rest of the body.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde_json::Value;
+use tracing::{debug, warn};
+
+use crate::error::{Error, Result};
+use crate::transport::{Transport, TransportFuture, TransportRequest, TransportResponse};
+
+/// Body fields tried in order to give fixture files a readable name.
+const PRIMARY_FIELDS: &[&str] = &[
+    "browseId",
+    "videoId",
+    "playlistId",
+    "query",
+    "input",
+    "continuation",
+];
+
+/// Longest readable ID kept in a fixture file name.
+const MAX_PRIMARY_LEN: usize = 48;
+
+/// Stable file stem (without `.json`) identifying `request`.
+///
+/// Only the endpoint and body take part — headers (cookies, visitor ID) vary
+/// between sessions and are ignored. From the `context` object only the client
+/// name is kept, since locale and version bumps should not invalidate fixtures.
+#[must_use]
+pub fn fixture_key(request: &TransportRequest) -> String {
+    let body = &request.body;
+
+    let mut ident = body.clone();
+    if let Some(obj) = ident.as_object_mut() {
+        obj.remove("context");
+        if let Some(name) = body["context"]["client"]["clientName"].as_str() {
+            obj.insert("clientName".into(), Value::from(name));
+        }
+    }
+    let mut canonical = String::new();
+    write_canonical(&ident, &mut canonical);
+    let digest = sha1_smol::Sha1::from(canonical).digest().to_string();
+
+    let mut key = sanitize(&request.endpoint);
+    if let Some(primary) = PRIMARY_FIELDS
+        .iter()
+        .find_map(|f| body[*f].as_str().filter(|s| !s.is_empty()))
+    {
+        let primary: String = sanitize(primary).chars().take(MAX_PRIMARY_LEN).collect();
+        key.push('-');
+        key.push_str(&primary);
+    }
+    key.push('-');
+    key.push_str(&digest[..10]);
+    key
+}
+
+/// Serialize with object keys sorted, independent of `serde_json` map ordering.
+fn write_canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (i, (k, v)) in entries.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                let _ = write!(out, "{}:", Value::from(k.as_str()));
+                write_canonical(v, out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, v) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write_canonical(v, out);
+            }
+            out.push(']');
+        }
+        other => {
+            let _ = write!(out, "{other}");
+        }
+    }
+}
+
+fn sanitize(s: &str) -> String {
+    s.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+        .collect()
+}
+
+fn fixture_path(dir: &Path, request: &TransportRequest) -> (String, PathBuf) {
+    let key = fixture_key(request);
+    let path = dir.join(format!("{key}.json"));
+    (key, path)
+}
+
+// ── Recording ────────────────────────────────────────────────────────────────
+
+/// Forwards to an inner transport and saves successful JSON responses.
+///
+/// Responses are pretty-printed so fixtures diff cleanly in review. Non-2xx
+/// responses are passed through but not recorded.
+#[derive(Clone)]
+pub struct RecordingTransport {
+    inner: Arc<dyn Transport>,
+    dir: PathBuf,
+}
+
+impl RecordingTransport {
+    #[must_use]
+    pub fn new(inner: Arc<dyn Transport>, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            dir: dir.into(),
+        }
+    }
+
+    async fn record(&self, request: TransportRequest) -> Result<TransportResponse> {
+        let (key, path) = fixture_path(&self.dir, &request);
+        let resp = self.inner.post(request).await?;
+        if !resp.is_success() {
+            return Ok(resp);
+        }
+        match serde_json::from_slice::<Value>(&resp.body) {
+            Ok(json) => {
+                fs::create_dir_all(&self.dir)
+                    .and_then(|()| fs::write(&path, serde_json::to_string_pretty(&json)?))
+                    .map_err(|e| Error::Fixture {
+                        key: key.clone(),
+                        reason: e.to_string(),
+                    })?;
+                debug!(key, "recorded fixture");
+            }
+            Err(e) => warn!(key, error = %e, "response is not JSON — not recorded"),
+        }
+        Ok(resp)
+    }
+}
+
+impl Transport for RecordingTransport {
+    fn post(&self, request: TransportRequest) -> TransportFuture<'_> {
+        Box::pin(self.record(request))
+    }
+}
+
+// ── Replay ───────────────────────────────────────────────────────────────────
+
+/// Serves responses previously saved by [`RecordingTransport`].
+///
+/// A request without a matching file fails with [`Error::Fixture`] naming the
+/// expected key, so a new fixture can be recorded for it.
+#[derive(Debug, Clone)]
+pub struct ReplayTransport {
+    dir: PathBuf,
+}
+
+impl ReplayTransport {
+    #[must_use]
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn replay(&self, request: &TransportRequest) -> Result<TransportResponse> {
+        let (key, path) = fixture_path(&self.dir, request);
+        let body = fs::read(&path).map_err(|e| Error::Fixture {
+            key,
+            reason: e.to_string(),
+        })?;
+        Ok(TransportResponse { status: 200, body })
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn post(&self, request: TransportRequest) -> TransportFuture<'_> {
+        let result = self.replay(&request);
+        Box::pin(async move { result })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    struct Canned;
+
+    impl Transport for Canned {
+        fn post(&self, _request: TransportRequest) -> TransportFuture<'_> {
+            Box::pin(async { Ok(TransportResponse::json(&json!({ "ok": true }))) })
+        }
+    }
+
+    fn request(client: &str, hl: &str) -> TransportRequest {
+        TransportRequest {
+            url: "https://example.invalid/youtubei/v1/browse".into(),
+            endpoint: "browse".into(),
+            headers: vec![],
+            body: json!({
+                "context": { "client": { "clientName": client, "hl": hl } },
+                "browseId": "FEmusic_home",
+            }),
+        }
+    }
+
+    #[test]
+    fn key_ignores_locale_but_not_client() {
+        let a = fixture_key(&request("WEB_REMIX", "en"));
+        assert!(a.starts_with("browse-FEmusic_home-"), "{a}");
+        assert_eq!(a, fixture_key(&request("WEB_REMIX", "es")));
+        assert_ne!(a, fixture_key(&request("ANDROID_MUSIC", "en")));
+    }
+
+    #[tokio::test]
+    async fn replays_what_was_recorded() {
+        let dir = std::env::temp_dir().join(format!("ytdroid-fixtures-{}", std::process::id()));
+        let recorder = RecordingTransport::new(Arc::new(Canned), &dir);
+        recorder.post(request("WEB_REMIX", "en")).await.unwrap();
+
+        let replay = ReplayTransport::new(&dir);
+        let resp = replay.post(request("WEB_REMIX", "en")).await.unwrap();
+        let body: Value = serde_json::from_slice(&resp.body).unwrap();
+        assert_eq!(body, json!({ "ok": true }));
+
+        let missing = replay.post(request("IOS", "en")).await;
+        assert!(matches!(missing, Err(Error::Fixture { .. })));
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,239 @@
+//! Audio format selection for [`crate::YouTube::audio_stream`].
+//!
+//! A [`FormatPreference`] ranks the audio-only `adaptiveFormats` of a player
+//! response. Every constraint is soft: when nothing satisfies the bitrate window
+//! the closest format is used instead, so playback never fails just because the
+//! preferred quality is unavailable.
+
+use std::cmp::Reverse;
+
+use crate::response::StreamingFormat;
+
+/// Audio codec families served by `YouTube`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudioCodec {
+    /// Opus in a WebM container (itags 249/250/251/774).
+    Opus,
+    /// AAC in an MP4 container (itags 139/140/141).
+    Aac,
+}
+
+impl AudioCodec {
+    /// MIME type prefix of the container carrying this codec.
+    #[must_use]
+    pub fn mime_prefix(self) -> &'static str {
+        match self {
+            Self::Opus => "audio/webm",
+            Self::Aac => "audio/mp4",
+        }
+    }
+
+    fn matches(self, format: &StreamingFormat) -> bool {
+        format
+            .mime_type
+            .as_deref()
+            .is_some_and(|m| m.starts_with(self.mime_prefix()))
+    }
+}
+
+/// The `audioQuality` bucket Innertube assigns to each audio format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QualityBucket {
+    Low,
+    Medium,
+    High,
+}
+
+impl QualityBucket {
+    /// Value of the `audioQuality` field for this bucket.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Low => "AUDIO_QUALITY_LOW",
+            Self::Medium => "AUDIO_QUALITY_MEDIUM",
+            Self::High => "AUDIO_QUALITY_HIGH",
+        }
+    }
+}
+
+/// Container priority used when no codec is preferred: mp4 → mpeg → ogg → webm.
+const DEFAULT_MIME_PRIORITY: &[&str] = &["audio/mp4", "audio/mpeg", "audio/ogg", "audio/webm"];
+
+/// How to pick one audio format out of a player response.
+///
+/// The default prefers `audio/mp4` at the highest bitrate, matching the
+/// behaviour before preferences existed.
+///
+/// ```
+/// use ytdroid::format::{AudioCodec, FormatPreference, QualityBucket};
+///
+/// // Opus at up to 160 kbps.
+/// let pref = FormatPreference::default()
+///     .codec(AudioCodec::Opus)
+///     .quality(QualityBucket::Medium)
+///     .max_bitrate(160_000);
+/// # let _ = pref;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FormatPreference {
+    /// Preferred codec; other codecs are still used when it is unavailable.
+    pub codec: Option<AudioCodec>,
+    /// Preferred `audioQuality` bucket.
+    pub quality: Option<QualityBucket>,
+    /// Bitrate floor in bits per second.
+    pub min_bitrate: Option<u64>,
+    /// Bitrate ceiling in bits per second.
+    pub max_bitrate: Option<u64>,
+    /// Only this format, e.g. to continue a stream with another client.
+    /// Nothing is picked when it is missing.
+    pub itag: Option<u32>,
+}
+
+impl FormatPreference {
+    #[must_use]
+    pub fn codec(mut self, codec: AudioCodec) -> Self {
+        self.codec = Some(codec);
+        self
+    }
+
+    #[must_use]
+    pub fn quality(mut self, quality: QualityBucket) -> Self {
+        self.quality = Some(quality);
+        self
+    }
+
+    #[must_use]
+    pub fn min_bitrate(mut self, bps: u64) -> Self {
+        self.min_bitrate = Some(bps);
+        self
+    }
+
+    #[must_use]
+    pub fn max_bitrate(mut self, bps: u64) -> Self {
+        self.max_bitrate = Some(bps);
+        self
+    }
+
+    #[must_use]
+    pub fn itag(mut self, itag: u32) -> Self {
+        self.itag = Some(itag);
+        self
+    }
+
+    /// Pick the best format among `formats` according to this preference.
+    ///
+    /// Ranking, most significant first: inside the bitrate window (or, when
+    /// nothing is, nearest to it), preferred codec (or the default container
+    /// priority), matching quality bucket, then the highest bitrate.
+    pub fn select<'a>(
+        &self,
+        formats: impl IntoIterator<Item = &'a StreamingFormat>,
+    ) -> Option<&'a StreamingFormat> {
+        formats
+            .into_iter()
+            .filter(|f| self.itag.is_none_or(|itag| f.itag == Some(itag)))
+            .max_by_key(|f| self.rank(f))
+    }
+
+    fn rank(&self, f: &StreamingFormat) -> (Reverse<u64>, usize, bool, u64) {
+        let bitrate = effective_bitrate(f);
+        let below = self.min_bitrate.map_or(0, |min| min.saturating_sub(bitrate));
+        let above = self.max_bitrate.map_or(0, |max| bitrate.saturating_sub(max));
+
+        let codec_score = match self.codec {
+            Some(codec) => usize::from(codec.matches(f)),
+            None => f.mime_type.as_deref().map_or(0, |m| {
+                DEFAULT_MIME_PRIORITY
+                    .iter()
+                    .position(|p| m.starts_with(p))
+                    .map_or(0, |i| DEFAULT_MIME_PRIORITY.len() - i)
+            }),
+        };
+
+        let bucket_match = self
+            .quality
+            .is_none_or(|q| f.audio_quality.as_deref() == Some(q.as_str()));
+
+        (Reverse(below + above), codec_score, bucket_match, bitrate)
+    }
+}
+
+/// `averageBitrate` when reported (what listeners actually get), else the peak `bitrate`.
+fn effective_bitrate(f: &StreamingFormat) -> u64 {
+    f.average_bitrate.or(f.bitrate).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fmt(itag: u32, mime: &str, avg: u64, quality: &str) -> StreamingFormat {
+        StreamingFormat {
+            itag: Some(itag),
+            url: Some(format!("https://cdn.invalid/{itag}")),
+            bitrate: Some(avg + avg / 8),
+            average_bitrate: Some(avg),
+            mime_type: Some(format!("{mime}; codecs=\"x\"")),
+            audio_quality: Some(quality.to_owned()),
+            ..Default::default()
+        }
+    }
+
+    fn formats() -> Vec<StreamingFormat> {
+        vec![
+            fmt(139, "audio/mp4", 48_000, "AUDIO_QUALITY_LOW"),
+            fmt(140, "audio/mp4", 129_000, "AUDIO_QUALITY_MEDIUM"),
+            fmt(249, "audio/webm", 50_000, "AUDIO_QUALITY_LOW"),
+            fmt(250, "audio/webm", 66_000, "AUDIO_QUALITY_LOW"),
+            fmt(251, "audio/webm", 135_000, "AUDIO_QUALITY_MEDIUM"),
+        ]
+    }
+
+    fn pick(pref: &FormatPreference) -> Option<u32> {
+        let formats = formats();
+        pref.select(&formats).and_then(|f| f.itag)
+    }
+
+    #[test]
+    fn default_prefers_highest_mp4() {
+        assert_eq!(pick(&FormatPreference::default()), Some(140));
+    }
+
+    #[test]
+    fn opus_under_ceiling() {
+        let pref = FormatPreference::default()
+            .codec(AudioCodec::Opus)
+            .quality(QualityBucket::Medium)
+            .max_bitrate(160_000);
+        assert_eq!(pick(&pref), Some(251));
+    }
+
+    #[test]
+    fn low_bucket_with_ceiling() {
+        let pref = FormatPreference::default()
+            .codec(AudioCodec::Opus)
+            .quality(QualityBucket::Low)
+            .max_bitrate(64_000);
+        assert_eq!(pick(&pref), Some(249));
+    }
+
+    #[test]
+    fn unreachable_window_falls_back_to_nearest() {
+        assert_eq!(pick(&FormatPreference::default().max_bitrate(10_000)), Some(139));
+        assert_eq!(pick(&FormatPreference::default().min_bitrate(500_000)), Some(251));
+    }
+
+    #[test]
+    fn missing_codec_uses_other() {
+        let formats: Vec<_> = formats().into_iter().filter(|f| f.itag < Some(200)).collect();
+        let pref = FormatPreference::default().codec(AudioCodec::Opus);
+        assert_eq!(pref.select(&formats).and_then(|f| f.itag), Some(140));
+    }
+
+    #[test]
+    fn itag_restricts_choice() {
+        let pref = FormatPreference::default().codec(AudioCodec::Opus).itag(140);
+        assert_eq!(pick(&pref), Some(140));
+        assert_eq!(pick(&FormatPreference::default().itag(22)), None);
+    }
+}
@@ -4,25 +4,31 @@
 //! higher-level [`crate::YouTube`] API unless they need raw JSON access.
 
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
 
-use reqwest::{Client, StatusCode};
 use serde_json::{Value, json};
 use tracing::{debug, instrument, warn};
 
 use crate::auth::{parse_cookies, sapisidhash};
-use crate::client::{Locale, YouTubeClient, MUSIC_API_BASE, MUSIC_ORIGIN, MUSIC_REFERER};
+use crate::client::{ApiEndpoint, Locale, YouTubeClient};
 use crate::error::{Error, Result};
+use crate::fixtures::{RecordingTransport, ReplayTransport};
 use crate::response::{
     AccountMenuResponse, BrowseResponse, GetQueueResponse, GetSearchSuggestionsResponse,
     GetTranscriptResponse, NextResponse, PlayerResponse, SearchResponse,
 };
+use crate::transport::{ReqwestTransport, Transport, TransportRequest};
 
 /// Low-level Innertube HTTP client.
 ///
-/// Construct via [`InnerTube::new`]. Handles auth, retry on 429, and JSON parsing.
+/// Construct via [`InnerTube::new`]. Handles auth and JSON parsing; the actual
+/// HTTP exchange is delegated to a [`Transport`] (by default [`ReqwestTransport`],
+/// which also retries on 429).
 #[derive(Clone)]
 pub struct InnerTube {
-    http: Client,
+    transport: Arc<dyn Transport>,
+    endpoint: ApiEndpoint,
     locale: Locale,
     cookies: HashMap<String, String>,
     /// Visitor ID sent as `X-Goog-Visitor-Id` (optional).
@@ -39,22 +45,53 @@ impl InnerTube {
     ///
     /// Returns an error if the underlying HTTP client cannot be constructed.
     pub fn new(cookie_header: Option<&str>, locale: Locale) -> Result<Self> {
-        let http = Client::builder()
-            .gzip(true)
-            .deflate(true)
-            .user_agent("Mozilla/5.0")
-            .build()?;
         let cookies = cookie_header
             .map(parse_cookies)
             .unwrap_or_default();
         Ok(Self {
-            http,
+            transport: Arc::new(ReqwestTransport::new()?),
+            endpoint: ApiEndpoint::default(),
             locale,
             cookies,
             visitor_id: None,
         })
     }
 
+    /// Replace the HTTP layer (e.g. with an in-process fake).
+    #[must_use]
+    pub fn with_transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transport = Arc::new(transport);
+        self
+    }
+
+    /// Save every successful response under `dir` while still hitting the
+    /// current transport (see [`crate::fixtures`]).
+    #[must_use]
+    pub fn with_recording(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.transport = Arc::new(RecordingTransport::new(Arc::clone(&self.transport), dir));
+        self
+    }
+
+    /// Serve responses from fixtures previously recorded under `dir`.
+    #[must_use]
+    pub fn with_replay(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.transport = Arc::new(ReplayTransport::new(dir));
+        self
+    }
+
+    /// Send requests to a different base URL / origin / referer.
+    #[must_use]
+    pub fn with_endpoint(mut self, endpoint: ApiEndpoint) -> Self {
+        self.endpoint = endpoint;
+        self
+    }
+
+    /// The endpoint requests are currently sent to.
+    #[must_use]
+    pub fn endpoint(&self) -> &ApiEndpoint {
+        &self.endpoint
+    }
+
     /// True when a `SAPISID` or `__Secure-3PAPISID` cookie is present (login capable).
     #[must_use]
     pub fn is_logged_in(&self) -> bool {
@@ -119,66 +156,49 @@ impl InnerTube {
         client: &YouTubeClient,
         body: Value,
     ) -> Result<Value> {
-        let url = format!("{MUSIC_API_BASE}{endpoint}?prettyPrint=false");
+        let url = format!("{}{endpoint}?prettyPrint=false", self.endpoint.api_base);
         let cookie_str = self.cookie_header();
-        let mut req = self
-            .http
-            .post(&url)
-            .header("Content-Type", "application/json")
-            .header("X-Goog-Api-Format-Version", "1")
-            .header("X-YouTube-Client-Name", client.client_id)
-            .header("X-YouTube-Client-Version", client.client_version)
+        let mut headers: Vec<(String, String)> = vec![
+            ("Content-Type".into(), "application/json".into()),
+            ("X-Goog-Api-Format-Version".into(), "1".into()),
+            ("X-YouTube-Client-Name".into(), client.client_id.into()),
+            ("X-YouTube-Client-Version".into(), client.client_version.into()),
             // X-Origin and Referer are sent for all clients (matches Metrolist's ytClient).
             // Note: we use X-Origin (not Origin) — Origin is a CORS browser header and causes
             // INVALID_ARGUMENT 400 on Android client endpoints when sent unconditionally.
-            .header("X-Origin", MUSIC_ORIGIN)
-            .header("Referer", MUSIC_REFERER)
-            .header("User-Agent", client.user_agent);
+            ("X-Origin".into(), self.endpoint.origin.clone()),
+            ("Referer".into(), self.endpoint.referer.clone()),
+            ("User-Agent".into(), client.user_agent.into()),
+        ];
 
         if client.login_supported && !cookie_str.is_empty() {
-            req = req.header("Cookie", &cookie_str);
-            if let Some(auth) = sapisidhash(&self.cookies) {
-                req = req.header("Authorization", auth);
+            headers.push(("Cookie".into(), cookie_str));
+            if let Some(auth) = sapisidhash(&self.cookies, &self.endpoint.origin) {
+                headers.push(("Authorization".into(), auth));
             }
         }
         if let Some(vid) = &self.visitor_id {
-            req = req.header("X-Goog-Visitor-Id", vid);
+            headers.push(("X-Goog-Visitor-Id".into(), vid.clone()));
         }
 
-        req = req.json(&body);
-
-        let resp = self.with_retry(req).await?;
-        let status = resp.status();
-        if !status.is_success() {
-            let body = resp.text().await.unwrap_or_default();
-            warn!(endpoint, client = client.client_name, %status, body = %body, "API non-success");
-            return Err(Error::HttpStatus { status: status.as_u16() });
+        let resp = self
+            .transport
+            .post(TransportRequest {
+                url,
+                endpoint: endpoint.to_owned(),
+                headers,
+                body,
+            })
+            .await?;
+        if !resp.is_success() {
+            let body = String::from_utf8_lossy(&resp.body);
+            warn!(endpoint, client = client.client_name, status = resp.status, body = %body, "API non-success");
+            return Err(Error::HttpStatus { status: resp.status });
         }
-        let value = resp.json::<Value>().await.map_err(Error::from)?;
+        let value = serde_json::from_slice::<Value>(&resp.body)?;
         Ok(value)
     }
 
-    async fn with_retry(
-        &self,
-        req: reqwest::RequestBuilder,
-    ) -> Result<reqwest::Response> {
-        let mut attempts = 0u32;
-        loop {
-            // `try_clone` is available because we only use json bodies (not streams).
-            let r = req.try_clone().ok_or(Error::MissingField {
-                field: "request clone",
-            })?;
-            let resp = r.send().await?;
-            if resp.status() == StatusCode::TOO_MANY_REQUESTS && attempts < 3 {
-                attempts += 1;
-                warn!("429 Too Many Requests — waiting before retry {attempts}");
-                tokio::time::sleep(std::time::Duration::from_secs(u64::from(attempts) * 2)).await;
-                continue;
-            }
-            return Ok(resp);
-        }
-    }
-
     fn body(&self, client: &YouTubeClient, extra: &Value) -> Value {
         let mut obj = json!({ "context": self.build_context(client) });
         if let (Some(base), Some(ext)) = (obj.as_object_mut(), extra.as_object()) {
@@ -651,3 +671,105 @@ impl AddDedupOption {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::transport::{TransportFuture, TransportResponse};
+
+    #[derive(Default)]
+    struct Recorder {
+        seen: Arc<Mutex<Vec<TransportRequest>>>,
+    }
+
+    impl Transport for Recorder {
+        fn post(&self, request: TransportRequest) -> TransportFuture<'_> {
+            self.seen.lock().unwrap().push(request);
+            Box::pin(async { Ok(TransportResponse::json(&json!({}))) })
+        }
+    }
+
+    #[tokio::test]
+    async fn uses_configured_endpoint() {
+        let recorder = Recorder::default();
+        let seen = Arc::clone(&recorder.seen);
+        let yt = InnerTube::new(Some("SAPISID=abc; HSID=x"), Locale::default())
+            .unwrap()
+            .with_endpoint(ApiEndpoint::at("http://127.0.0.1:9000/"))
+            .with_transport(recorder);
+
+        yt.browse(&YouTubeClient::WEB_REMIX, "FEmusic_home", None, None)
+            .await
+            .unwrap();
+
+        let seen = seen.lock().unwrap();
+        let req = &seen[0];
+        assert_eq!(req.url, "http://127.0.0.1:9000/youtubei/v1/browse?prettyPrint=false");
+        assert_eq!(req.endpoint, "browse");
+        assert_eq!(req.header("x-origin"), Some("http://127.0.0.1:9000"));
+        assert_eq!(req.header("referer"), Some("http://127.0.0.1:9000/"));
+        assert!(req.header("authorization").is_some_and(|a| a.starts_with("SAPISIDHASH ")));
+        assert_eq!(req.body["browseId"], "FEmusic_home");
+    }
+
+    #[tokio::test]
+    async fn non_success_status_is_an_error() {
+        struct Forbidden;
+        impl Transport for Forbidden {
+            fn post(&self, _: TransportRequest) -> TransportFuture<'_> {
+                Box::pin(async { Ok(TransportResponse { status: 403, body: Vec::new() }) })
+            }
+        }
+
+        let yt = InnerTube::new(None, Locale::default())
+            .unwrap()
+            .with_transport(Forbidden);
+        let err = yt
+            .search(&YouTubeClient::WEB_REMIX, "q", None, None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::HttpStatus { status: 403 }));
+    }
+
+    #[tokio::test]
+    async fn player_exposes_loudness() {
+        struct Player;
+        impl Transport for Player {
+            fn post(&self, _: TransportRequest) -> TransportFuture<'_> {
+                Box::pin(async {
+                    Ok(TransportResponse::json(&json!({
+                        "playabilityStatus": { "status": "OK" },
+                        "playerConfig": {
+                            "audioConfig": {
+                                "loudnessDb": 4.53,
+                                "perceptualLoudnessDb": -9.47,
+                                "enablePerFormatLoudness": true
+                            }
+                        },
+                        "streamingData": {
+                            "adaptiveFormats": [
+                                { "itag": 251, "mimeType": "audio/webm", "loudnessDb": 4.4 }
+                            ]
+                        }
+                    })))
+                })
+            }
+        }
+
+        let yt = InnerTube::new(None, Locale::default())
+            .unwrap()
+            .with_transport(Player);
+        let resp = yt
+            .player(&YouTubeClient::WEB_REMIX, "x", None, None, None)
+            .await
+            .unwrap();
+        let config = resp.audio_config().unwrap();
+        assert_eq!(config.loudness_db, Some(4.53));
+        assert_eq!(config.perceptual_loudness_db, Some(-9.47));
+        assert!(config.enable_per_format_loudness);
+        let fmt = &resp.streaming_data.unwrap().adaptive_formats[0];
+        assert_eq!(fmt.loudness_db, Some(4.4));
+    }
+}
@@ -24,22 +24,30 @@
 
 pub mod auth;
 pub mod client;
+pub mod download;
 pub mod error;
 pub mod filters;
+pub mod fixtures;
+pub mod format;
 pub mod http;
 pub mod models;
+pub mod offline;
 pub mod pages;
 pub mod response;
+pub mod tags;
+pub mod transport;
 pub mod youtube;
 
 // ── Flat re-exports for ergonomic use ────────────────────────────────────────
 
-pub use client::Locale;
+pub use client::{ApiEndpoint, Locale};
 pub use error::{Error, Result};
 pub use filters::{LibraryFilter, SearchFilter};
+pub use format::{AudioCodec, FormatPreference, QualityBucket};
 pub use models::{
     AlbumItem, AlbumRef, Artist, ArtistItem, EpisodeItem, LikeStatus, PlaylistItem, PodcastItem,
     PodcastRef, SongItem, WatchEndpoint, WatchPlaylistEndpoint, YTItem,
 };
 pub use http::PlaylistPrivacy;
+pub use transport::{Transport, TransportRequest, TransportResponse};
 pub use youtube::{AudioStream, ContentHints, SearchResult, YouTube};
@@ -0,0 +1,662 @@
+//! Whole-file downloads for offline playback.
+//!
+//! A [`DownloadManager`] works through a queue of jobs, a few at a time, each
+//! saving one resource to a file with a [`ChunkedDownloader`]. Bytes are
+//! appended to `<dest>.part` in order, next to a `<dest>.part.meta` naming the
+//! resolved format, so a job stopped by a pause, an error or a crash carries
+//! on where its partial file ends as long as the same format resolves again.
+//! Finished files are renamed into place. A job's file is only written by
+//! one task at a time: a job paused or removed mid-chunk is not started again
+//! until the task writing its file has stopped.
+//!
+//! Stream URLs expire, so each job asks the caller's [`Resolver`] for one
+//! right before it starts.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//!
+//! use ytdroid::download::ChunkedDownloader;
+//! use ytdroid::offline::{DownloadManager, Resolved, Resolver};
+//!
+//! # async fn example() {
+//! let resolver: Resolver = Arc::new(|id: &str| {
+//!     let url = format!("https://example.com/{id}");
+//!     Box::pin(async move { Ok(Resolved { url, len: None, format: "mp3".into() }) })
+//! });
+//! let (manager, mut events) =
+//!     DownloadManager::new(ChunkedDownloader::new(reqwest::Client::new()), resolver, 2);
+//! manager.enqueue("song", "/tmp/song.mp3");
+//! while let Some(progress) = events.recv().await {
+//!     println!("{}: {:?} {}/{:?}", progress.id, progress.status, progress.done, progress.total);
+//! }
+//! # }
+//! ```
+
+use std::ffi::OsString;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use crate::download::ChunkedDownloader;
+use crate::error::{Error, Result};
+
+/// Where and what to download for a job.
+#[derive(Debug, Clone)]
+pub struct Resolved {
+    pub url: String,
+    /// Size in bytes, when known up front; otherwise asked from the server.
+    pub len: Option<u64>,
+    /// Identifies the bytes behind `url` (e.g. an itag). A partial file is
+    /// only continued when it was started for the same format.
+    pub format: String,
+}
+
+pub type ResolveFuture =
+    Pin<Box<dyn Future<Output = std::result::Result<Resolved, String>> + Send>>;
+
+/// Turns a job ID into a fresh download URL, or the reason it cannot.
+pub type Resolver = Arc<dyn Fn(&str) -> ResolveFuture + Send + Sync>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Paused,
+    Done,
+    Failed(String),
+}
+
+/// State of one job, sent on every change.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JobProgress {
+    pub id: String,
+    pub status: JobStatus,
+    /// Bytes saved so far, including those of a resumed partial file.
+    pub done: u64,
+    pub total: Option<u64>,
+}
+
+struct Job {
+    id: String,
+    dest: PathBuf,
+    status: JobStatus,
+    done: u64,
+    total: Option<u64>,
+    /// Token of the task working on this job; cleared once the job is
+    /// paused, even if that task is still finishing a chunk.
+    task: Option<u64>,
+}
+
+impl Job {
+    fn progress(&self) -> JobProgress {
+        JobProgress {
+            id: self.id.clone(),
+            status: self.status.clone(),
+            done: self.done,
+            total: self.total,
+        }
+    }
+}
+
+/// A spawned download, from its start until it has returned.
+struct Task {
+    token: u64,
+    dest: PathBuf,
+    /// Asks the task to stop after its current chunk.
+    stop: Arc<AtomicBool>,
+    /// Its job was removed: delete the partial file once stopped.
+    discard: bool,
+}
+
+#[derive(Default)]
+struct Queue {
+    jobs: Vec<Job>,
+    tasks: Vec<Task>,
+    next_token: u64,
+}
+
+impl Queue {
+    /// Stop the tasks writing to `dest`; `discard` has them delete the
+    /// partial file too. `false` when none are running.
+    fn stop_tasks(&mut self, dest: &Path, discard: bool) -> bool {
+        let mut any = false;
+        for task in self.tasks.iter_mut().filter(|t| t.dest == dest) {
+            task.stop.store(true, Ordering::Relaxed);
+            task.discard |= discard;
+            any = true;
+        }
+        any
+    }
+}
+
+struct Shared {
+    downloader: ChunkedDownloader,
+    resolver: Resolver,
+    concurrency: usize,
+    queue: Mutex<Queue>,
+    events: mpsc::UnboundedSender<JobProgress>,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Apply `f` to job `id` and report its new state.
+    fn update(&self, id: &str, f: impl FnOnce(&mut Job)) {
+        self.update_where(|job| job.id == id, f);
+    }
+
+    /// Apply `f` to the job task `token` works on, if it still does.
+    fn update_task(&self, token: u64, f: impl FnOnce(&mut Job)) {
+        self.update_where(|job| job.task == Some(token), f);
+    }
+
+    fn update_where(&self, pred: impl Fn(&Job) -> bool, f: impl FnOnce(&mut Job)) {
+        let mut queue = self.lock();
+        if let Some(job) = queue.jobs.iter_mut().find(|j| pred(j)) {
+            f(job);
+            self.events.send(job.progress()).ok();
+        }
+    }
+}
+
+/// Queue of download jobs with a limit on how many run at once.
+///
+/// Cloning gives another handle to the same queue. Jobs are started on the
+/// current Tokio runtime.
+#[derive(Clone)]
+pub struct DownloadManager {
+    shared: Arc<Shared>,
+}
+
+impl DownloadManager {
+    /// A manager running up to `concurrency` jobs at once (at least one), and
+    /// the receiver of its progress reports.
+    #[must_use]
+    pub fn new(
+        downloader: ChunkedDownloader,
+        resolver: Resolver,
+        concurrency: usize,
+    ) -> (Self, mpsc::UnboundedReceiver<JobProgress>) {
+        let (events, rx) = mpsc::unbounded_channel();
+        let shared = Arc::new(Shared {
+            downloader,
+            resolver,
+            concurrency: concurrency.max(1),
+            queue: Mutex::default(),
+            events,
+        });
+        (Self { shared }, rx)
+    }
+
+    /// Queue `id` for download to `dest`. A job already known under `id` is
+    /// left alone, unless it failed, in which case it is queued again.
+    pub fn enqueue(&self, id: impl Into<String>, dest: impl Into<PathBuf>) {
+        let id = id.into();
+        {
+            let mut queue = self.shared.lock();
+            match queue.jobs.iter_mut().find(|j| j.id == id) {
+                Some(job) if matches!(job.status, JobStatus::Failed(_)) => {
+                    job.status = JobStatus::Queued;
+                    self.shared.events.send(job.progress()).ok();
+                }
+                Some(_) => return,
+                None => {
+                    let job = Job {
+                        id,
+                        dest: dest.into(),
+                        status: JobStatus::Queued,
+                        done: 0,
+                        total: None,
+                        task: None,
+                    };
+                    self.shared.events.send(job.progress()).ok();
+                    queue.jobs.push(job);
+                }
+            }
+        }
+        pump(&self.shared);
+    }
+
+    /// Stop job `id` after its current chunk, keeping the partial file.
+    pub fn pause(&self, id: &str) {
+        {
+            let mut queue = self.shared.lock();
+            let Some(at) = queue.jobs.iter().position(|j| j.id == id) else { return };
+            let job = &mut queue.jobs[at];
+            if !matches!(job.status, JobStatus::Queued | JobStatus::Running) {
+                return;
+            }
+            job.status = JobStatus::Paused;
+            job.task = None;
+            self.shared.events.send(job.progress()).ok();
+            let dest = job.dest.clone();
+            queue.stop_tasks(&dest, false);
+        }
+        pump(&self.shared);
+    }
+
+    /// Queue a paused or failed job again. It starts once the task it was
+    /// paused in has stopped.
+    pub fn resume(&self, id: &str) {
+        self.shared.update(id, |job| {
+            if matches!(job.status, JobStatus::Paused | JobStatus::Failed(_)) {
+                job.status = JobStatus::Queued;
+            }
+        });
+        pump(&self.shared);
+    }
+
+    /// Forget job `id`, stopping it and deleting its partial file. A finished
+    /// file is left for the caller to delete.
+    pub fn remove(&self, id: &str) {
+        {
+            let mut queue = self.shared.lock();
+            let Some(at) = queue.jobs.iter().position(|j| j.id == id) else { return };
+            let job = queue.jobs.remove(at);
+            // A task still writing the file deletes it itself once stopped;
+            // until then, the same file is not downloaded again.
+            if !queue.stop_tasks(&job.dest, true) {
+                std::fs::remove_file(with_suffix(&job.dest, ".part")).ok();
+                std::fs::remove_file(with_suffix(&job.dest, ".part.meta")).ok();
+            }
+        }
+        pump(&self.shared);
+    }
+
+    /// Current state of every job, in queue order.
+    #[must_use]
+    pub fn jobs(&self) -> Vec<JobProgress> {
+        self.shared.lock().jobs.iter().map(Job::progress).collect()
+    }
+}
+
+/// Start queued jobs while fewer than the limit of tasks are running,
+/// stopping ones included. A job waits while a task still writes its file.
+fn pump(shared: &Arc<Shared>) {
+    let mut guard = shared.lock();
+    let queue = &mut *guard;
+    for job in &mut queue.jobs {
+        if queue.tasks.len() >= shared.concurrency {
+            break;
+        }
+        if job.status != JobStatus::Queued || queue.tasks.iter().any(|t| t.dest == job.dest) {
+            continue;
+        }
+        let token = queue.next_token;
+        queue.next_token += 1;
+        let stop = Arc::new(AtomicBool::new(false));
+        queue.tasks.push(Task {
+            token,
+            dest: job.dest.clone(),
+            stop: Arc::clone(&stop),
+            discard: false,
+        });
+        job.status = JobStatus::Running;
+        job.task = Some(token);
+        shared.events.send(job.progress()).ok();
+        tokio::spawn(run(Arc::clone(shared), token, job.id.clone(), job.dest.clone(), stop));
+    }
+}
+
+async fn run(shared: Arc<Shared>, token: u64, id: String, dest: PathBuf, stop: Arc<AtomicBool>) {
+    let result = download(&shared, token, &id, &dest, &stop).await;
+    match result {
+        Ok(true) => {
+            debug!(id, "download finished");
+            // Also when the job was paused during its last chunk: the file
+            // is in place either way.
+            shared.update_where(
+                |job| job.dest == dest && job.task.is_none_or(|t| t == token),
+                |job| job.status = JobStatus::Done,
+            );
+        }
+        // Paused or removed; `pause` set the status.
+        Ok(false) => {}
+        Err(e) => {
+            warn!(id, error = %e, "download failed");
+            shared.update_task(token, |job| job.status = JobStatus::Failed(e.to_string()));
+        }
+    }
+    let discard = shared.lock().tasks.iter().any(|t| t.token == token && t.discard);
+    if discard {
+        delete_partial(&dest).await;
+    }
+    {
+        let mut queue = shared.lock();
+        queue.tasks.retain(|t| t.token != token);
+        if let Some(job) = queue.jobs.iter_mut().find(|j| j.task == Some(token)) {
+            job.task = None;
+        }
+    }
+    pump(&shared);
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    name.into()
+}
+
+async fn delete_partial(dest: &Path) {
+    fs::remove_file(with_suffix(dest, ".part")).await.ok();
+    fs::remove_file(with_suffix(dest, ".part.meta")).await.ok();
+}
+
+/// Download job `id` into `dest`, reporting progress on the job task `token`
+/// works on. `Ok(false)` when stopped early.
+async fn download(
+    shared: &Shared,
+    token: u64,
+    id: &str,
+    dest: &Path,
+    stop: &AtomicBool,
+) -> Result<bool> {
+    let resolved = (shared.resolver)(id).await.map_err(|reason| Error::Resolve {
+        id: id.to_owned(),
+        reason,
+    })?;
+    if stop.load(Ordering::Relaxed) {
+        return Ok(false);
+    }
+    let total = match resolved.len {
+        Some(len) => len,
+        None => shared.downloader.content_length(&resolved.url).await?,
+    };
+
+    let part = with_suffix(dest, ".part");
+    let meta = with_suffix(dest, ".part.meta");
+    let meta_text = format!("{}\n{total}", resolved.format);
+    let offset = match fs::read_to_string(&meta).await {
+        Ok(m) if m == meta_text => fs::metadata(&part).await.map_or(0, |m| m.len().min(total)),
+        _ => 0,
+    };
+    if let Some(dir) = dest.parent() {
+        fs::create_dir_all(dir).await?;
+    }
+    if offset == 0 {
+        fs::write(&meta, &meta_text).await?;
+    } else {
+        debug!(id, offset, total, "resuming partial download");
+    }
+
+    let file = fs::OpenOptions::new().create(true).append(true).open(&part).await?;
+    // Drop anything past the offset, e.g. from a write cut short by a crash.
+    file.set_len(offset).await?;
+    let mut file = tokio::io::BufWriter::new(file);
+    shared.update_task(token, |job| {
+        job.done = offset;
+        job.total = Some(total);
+    });
+
+    let mut done = offset;
+    let mut chunks = shared.downloader.download(&resolved.url, offset..total);
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk?;
+        file.write_all(&chunk.data).await?;
+        done += chunk.data.len() as u64;
+        shared.update_task(token, |job| job.done = done);
+        if stop.load(Ordering::Relaxed) {
+            file.flush().await?;
+            return Ok(false);
+        }
+    }
+    file.flush().await?;
+    file.into_inner().sync_all().await?;
+
+    fs::rename(&part, dest).await?;
+    fs::remove_file(&meta).await.ok();
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicU64;
+    use std::time::Duration;
+
+    use reqwest::Client;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::download::RangeStyle;
+
+    const LEN: usize = 100_000;
+
+    fn body() -> Vec<u8> {
+        (0..LEN as u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    /// `Range:` header server answering each request after `delay`; logs the
+    /// start offset of every request.
+    async fn serve(delay: Duration) -> (String, Arc<Mutex<Vec<u64>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&requests);
+        tokio::spawn(async move {
+            loop {
+                let (mut sock, _) = listener.accept().await.unwrap();
+                let log = Arc::clone(&log);
+                tokio::spawn(async move {
+                    let mut buf = vec![0; 4096];
+                    let n = sock.read(&mut buf).await.unwrap();
+                    let head = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+                    let spec = head.split("range: bytes=").nth(1).unwrap().lines().next().unwrap();
+                    let (a, b) = spec.trim().split_once('-').unwrap();
+                    let (a, b): (usize, usize) = (a.parse().unwrap(), b.parse().unwrap());
+                    log.lock().unwrap().push(a as u64);
+                    tokio::time::sleep(delay).await;
+                    let mut resp = format!(
+                        "HTTP/1.1 206 Partial Content\r\ncontent-length: {}\r\n\r\n",
+                        b + 1 - a
+                    )
+                    .into_bytes();
+                    resp.extend_from_slice(&body()[a..=b]);
+                    sock.write_all(&resp).await.ok();
+                });
+            }
+        });
+        (url, requests)
+    }
+
+    fn manager(url: &str, concurrency: usize) -> (DownloadManager, mpsc::UnboundedReceiver<JobProgress>) {
+        let url = url.to_owned();
+        let resolver: Resolver = Arc::new(move |id: &str| {
+            let url = format!("{url}/{id}");
+            Box::pin(async move {
+                Ok(Resolved {
+                    url,
+                    len: Some(LEN as u64),
+                    format: "251".into(),
+                })
+            })
+        });
+        let dl = ChunkedDownloader::new(Client::new())
+            .chunk_size(16_384)
+            .parallelism(1)
+            .range_style(RangeStyle::Header);
+        DownloadManager::new(dl, resolver, concurrency)
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        static N: AtomicU64 = AtomicU64::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "ytdroid-offline-{}-{name}-{}",
+            std::process::id(),
+            N.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Wait for a report on `id` matching `pred`.
+    async fn wait_for(
+        rx: &mut mpsc::UnboundedReceiver<JobProgress>,
+        id: &str,
+        pred: impl Fn(&JobProgress) -> bool,
+    ) -> JobProgress {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            loop {
+                let p = rx.recv().await.unwrap();
+                if p.id == id && pred(&p) {
+                    return p;
+                }
+            }
+        })
+        .await
+        .expect("timed out waiting for download progress")
+    }
+
+    #[tokio::test]
+    async fn runs_queue_within_concurrency_limit() {
+        let (url, _) = serve(Duration::from_millis(2)).await;
+        let (manager, mut rx) = manager(&url, 1);
+        let dir = temp_dir("queue");
+        for id in ["a", "b", "c"] {
+            manager.enqueue(id, dir.join(id));
+        }
+
+        let mut finished = 0;
+        let mut running = std::collections::HashSet::new();
+        while finished < 3 {
+            let p = rx.recv().await.unwrap();
+            match p.status {
+                JobStatus::Running => {
+                    running.insert(p.id);
+                    assert_eq!(running.len(), 1, "more jobs running than allowed");
+                }
+                JobStatus::Done => {
+                    running.remove(&p.id);
+                    finished += 1;
+                }
+                ref s => assert_eq!(*s, JobStatus::Queued),
+            }
+        }
+        for id in ["a", "b", "c"] {
+            assert!(std::fs::read(dir.join(id)).unwrap() == body());
+            assert!(!dir.join(format!("{id}.part")).exists());
+        }
+    }
+
+    #[tokio::test]
+    async fn resumes_partial_file() {
+        let (url, requests) = serve(Duration::ZERO).await;
+        let (manager, mut rx) = manager(&url, 1);
+        let dir = temp_dir("resume");
+        // Left behind by a crash: two whole chunks and part of a third.
+        std::fs::write(dir.join("a.part"), &body()[..40_000]).unwrap();
+        std::fs::write(dir.join("a.part.meta"), format!("251\n{LEN}")).unwrap();
+
+        manager.enqueue("a", dir.join("a"));
+        wait_for(&mut rx, "a", |p| p.status == JobStatus::Done).await;
+
+        assert!(std::fs::read(dir.join("a")).unwrap() == body());
+        assert!(!dir.join("a.part.meta").exists());
+        assert_eq!(requests.lock().unwrap()[0], 40_000);
+    }
+
+    #[tokio::test]
+    async fn restarts_partial_file_of_another_format() {
+        let (url, requests) = serve(Duration::ZERO).await;
+        let (manager, mut rx) = manager(&url, 1);
+        let dir = temp_dir("format");
+        std::fs::write(dir.join("a.part"), [7u8; 40_000]).unwrap();
+        std::fs::write(dir.join("a.part.meta"), format!("140\n{LEN}")).unwrap();
+
+        manager.enqueue("a", dir.join("a"));
+        wait_for(&mut rx, "a", |p| p.status == JobStatus::Done).await;
+
+        assert!(std::fs::read(dir.join("a")).unwrap() == body());
+        assert_eq!(requests.lock().unwrap()[0], 0);
+    }
+
+    #[tokio::test]
+    async fn pauses_and_resumes() {
+        let (url, requests) = serve(Duration::from_millis(20)).await;
+        let (manager, mut rx) = manager(&url, 1);
+        let dir = temp_dir("pause");
+
+        manager.enqueue("a", dir.join("a"));
+        wait_for(&mut rx, "a", |p| p.done > 0).await;
+        manager.pause("a");
+        assert_eq!(manager.jobs()[0].status, JobStatus::Paused);
+        // Let the running chunk land; nothing may follow it.
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let paused_at = requests.lock().unwrap().len();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(requests.lock().unwrap().len(), paused_at);
+        assert!(!dir.join("a").exists());
+
+        manager.resume("a");
+        wait_for(&mut rx, "a", |p| p.status == JobStatus::Done).await;
+        assert!(std::fs::read(dir.join("a")).unwrap() == body());
+        // Continued from the partial file instead of starting over.
+        assert!(requests.lock().unwrap()[paused_at] > 0);
+    }
+
+    #[tokio::test]
+    async fn resume_waits_for_the_paused_task() {
+        let (url, requests) = serve(Duration::from_millis(20)).await;
+        let (manager, mut rx) = manager(&url, 1);
+        let dir = temp_dir("pause-resume");
+
+        manager.enqueue("a", dir.join("a"));
+        wait_for(&mut rx, "a", |p| p.done > 0).await;
+        manager.pause("a");
+        manager.resume("a");
+        // The paused task is still fetching its chunk.
+        assert_eq!(manager.jobs()[0].status, JobStatus::Queued);
+
+        wait_for(&mut rx, "a", |p| p.status == JobStatus::Done).await;
+        assert!(std::fs::read(dir.join("a")).unwrap() == body());
+        assert!(!dir.join("a.part").exists());
+        // One writer at a time: every chunk was asked for once, in order.
+        let requests = requests.lock().unwrap().clone();
+        assert!(requests.windows(2).all(|w| w[0] < w[1]), "{requests:?}");
+    }
+
+    #[tokio::test]
+    async fn remove_then_enqueue_starts_over() {
+        let (url, requests) = serve(Duration::from_millis(20)).await;
+        let (manager, mut rx) = manager(&url, 1);
+        let dir = temp_dir("remove-enqueue");
+
+        manager.enqueue("a", dir.join("a"));
+        wait_for(&mut rx, "a", |p| p.done > 0).await;
+        manager.remove("a");
+        manager.enqueue("a", dir.join("a"));
+        assert_eq!(manager.jobs()[0].status, JobStatus::Queued);
+
+        let done = wait_for(&mut rx, "a", |p| p.status == JobStatus::Done).await;
+        assert_eq!(done.done, LEN as u64);
+        assert!(std::fs::read(dir.join("a")).unwrap() == body());
+        assert!(!dir.join("a.part").exists());
+        // The old task deleted its partial file before the new one began.
+        let restarts = requests.lock().unwrap().iter().filter(|&&at| at == 0).count();
+        assert_eq!(restarts, 2);
+    }
+
+    #[tokio::test]
+    async fn remove_deletes_partial_file() {
+        let (url, _) = serve(Duration::from_millis(20)).await;
+        let (manager, mut rx) = manager(&url, 1);
+        let dir = temp_dir("remove");
+
+        manager.enqueue("a", dir.join("a"));
+        wait_for(&mut rx, "a", |p| p.done > 0).await;
+        manager.remove("a");
+        assert!(manager.jobs().is_empty());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(!dir.join("a.part").exists());
+        assert!(!dir.join("a").exists());
+    }
+}
@@ -71,6 +71,7 @@ impl AlbumPage {
             } else if let Some(rh) = response
                 .single_col()
                 .and_then(|sc| sc.first_section_list())
+                .or_else(|| response.two_col()?.first_tab_section_list())
                 .and_then(|sl| {
                     sl.contents
                         .iter()
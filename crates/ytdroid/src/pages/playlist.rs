@@ -3,7 +3,7 @@
 use crate::error::{Error, Result};
 use crate::models::{PlaylistItem, SongItem, YTItem};
 use crate::response::common::{get_continuation, Runs};
-use crate::response::BrowseResponse;
+use crate::response::{BrowseResponse, SectionListRenderer};
 use super::from_responsive;
 
 #[derive(Debug, Clone)]
@@ -106,8 +106,7 @@ impl PlaylistPage {
 
         // ── Song list ────────────────────────────────────────
         // The song list is in two_col secondary OR single_col section list.
-        let (songs, songs_continuation) =
-            extract_songs_from_response(response).unwrap_or_default();
+        let (songs, songs_continuation) = extract_songs_from_response(response);
 
         // Build a browse ID from the URL canonical (microformat) or fall back to empty.
         let browse_id = response
@@ -139,25 +138,24 @@ impl PlaylistPage {
     /// Parse a playlist songs continuation.
     #[must_use]
     pub fn from_continuation(response: &BrowseResponse) -> PlaylistContinuationPage {
-        let (songs, continuation) = extract_songs_from_response(response).unwrap_or_default();
+        let (songs, continuation) = extract_songs_from_response(response);
         PlaylistContinuationPage { songs, continuation }
     }
 }
 
-fn extract_songs_from_response(
-    response: &BrowseResponse,
-) -> Option<(Vec<SongItem>, Option<String>)> {
-    // Try two-column layout (playlist/album page).
+fn extract_songs_from_response(response: &BrowseResponse) -> (Vec<SongItem>, Option<String>) {
+    // Try two-column layout (playlist/album page). Continuation responses have no
+    // section list at all, so its absence is not an error.
     let section_list = response
         .two_col()
         .and_then(|tc| tc.secondary_section_list())
         .or_else(|| response.single_col()?.first_section_list())
-        .or_else(|| response.two_col()?.first_tab_section_list())?;
+        .or_else(|| response.two_col()?.first_tab_section_list());
 
     let mut songs = Vec::new();
-    let mut continuation = section_list.continuation();
+    let mut continuation = section_list.and_then(SectionListRenderer::continuation);
 
-    for content in &section_list.contents {
+    for content in section_list.iter().flat_map(|sl| &sl.contents) {
         let shelf = content
             .music_playlist_shelf_renderer
             .as_ref()
@@ -193,6 +191,24 @@ fn extract_songs_from_response(
         }
     }
 
+    // Song-list continuation pages.
+    if let Some(pc) = response
+        .continuation_contents
+        .as_ref()
+        .and_then(|cc| cc.music_playlist_shelf_continuation.as_ref())
+    {
+        for c in &pc.contents {
+            if let Some(YTItem::Song(song)) =
+                c.music_responsive_list_item_renderer.as_ref().and_then(from_responsive)
+            {
+                songs.push(*song);
+            }
+        }
+        if continuation.is_none() {
+            continuation = get_continuation(&pc.continuations);
+        }
+    }
+
     // Also check on_response_received_actions (used by some continuation forms).
     for item in response.appended_items() {
         if let Some(r) = &item.music_responsive_list_item_renderer
@@ -205,5 +221,5 @@ fn extract_songs_from_response(
             }
     }
 
-    Some((songs, continuation))
+    (songs, continuation)
 }
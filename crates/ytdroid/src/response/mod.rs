@@ -4,6 +4,8 @@ pub mod common;
 
 use serde::Deserialize;
 
+use crate::format::FormatPreference;
+
 use common::{
     ChipCloudRenderer, ContinuationItem, GridRenderer,
     MusicCarouselShelfRenderer, MusicDetailHeaderRenderer, MusicEditablePlaylistDetailHeaderRenderer,
@@ -448,8 +450,12 @@ pub struct PlayabilityStatus {
 
 #[derive(Debug, Clone, Deserialize, Default)]
 pub struct StreamingFormat {
+    pub itag: Option<u32>,
     pub url: Option<String>,
+    /// Peak bitrate in bits per second.
     pub bitrate: Option<u64>,
+    #[serde(rename = "averageBitrate")]
+    pub average_bitrate: Option<u64>,
     /// Present only for video streams; absent for audio-only.
     pub width: Option<u32>,
     pub height: Option<u32>,
@@ -461,6 +467,9 @@ pub struct StreamingFormat {
     pub audio_quality: Option<String>,
     #[serde(rename = "signatureCipher")]
     pub signature_cipher: Option<String>,
+    /// Per-format loudness, reported when `enablePerFormatLoudness` is set.
+    #[serde(rename = "loudnessDb")]
+    pub loudness_db: Option<f32>,
 }
 
 impl StreamingFormat {
@@ -487,33 +496,27 @@ pub struct StreamingData {
 }
 
 impl StreamingData {
-    /// Best audio-only format with a direct URL.
+    /// Audio-only format with a direct URL that best matches `pref`.
     ///
-    /// Priority: `audio/mp4` (AAC) → `audio/mpeg` (MP3) → `audio/ogg` (Vorbis) → `audio/webm` (Opus) → any.
+    /// With the default preference: `audio/mp4` (AAC) → `audio/mpeg` (MP3) →
+    /// `audio/ogg` (Vorbis) → `audio/webm` (Opus) → any, highest bitrate first.
     #[must_use]
-    pub fn best_audio_format(&self) -> Option<&StreamingFormat> {
-        self.best_supported_audio(|f| f.is_audio_only() && f.has_direct_url())
+    pub fn best_audio_format(&self, pref: &FormatPreference) -> Option<&StreamingFormat> {
+        pref.select(
+            self.adaptive_formats
+                .iter()
+                .filter(|f| f.is_audio_only() && f.has_direct_url()),
+        )
     }
 
-    /// Best audio-only format that uses `signatureCipher` (WEB clients).
-    ///
-    /// Same MIME priority as [`best_audio_format`]: mp4 → mpeg → ogg → webm → any.
+    /// Audio-only format using `signatureCipher` (WEB clients) that best matches `pref`.
     #[must_use]
-    pub fn best_cipher_audio_format(&self) -> Option<&StreamingFormat> {
-        self.best_supported_audio(|f| f.is_audio_only() && f.signature_cipher.is_some())
-    }
-
-    fn best_supported_audio(&self, base: impl Fn(&StreamingFormat) -> bool) -> Option<&StreamingFormat> {
-        let audio = || self.adaptive_formats.iter().filter(|f| base(f));
-        for prefix in &["audio/mp4", "audio/mpeg", "audio/ogg", "audio/webm"] {
-            let best = audio()
-                .filter(|f| f.mime_type.as_deref().map_or(false, |m| m.starts_with(prefix)))
-                .max_by_key(|f| f.bitrate.unwrap_or(0));
-            if best.is_some() {
-                return best;
-            }
-        }
-        audio().max_by_key(|f| f.bitrate.unwrap_or(0))
+    pub fn best_cipher_audio_format(&self, pref: &FormatPreference) -> Option<&StreamingFormat> {
+        pref.select(
+            self.adaptive_formats
+                .iter()
+                .filter(|f| f.is_audio_only() && f.signature_cipher.is_some()),
+        )
     }
 }
 
@@ -524,6 +527,34 @@ pub struct PlayerResponse {
     pub playability_status: PlayabilityStatus,
     pub streaming_data: Option<StreamingData>,
     pub video_details: Option<VideoDetails>,
+    pub player_config: Option<PlayerConfig>,
+}
+
+impl PlayerResponse {
+    #[must_use]
+    pub fn audio_config(&self) -> Option<&AudioConfig> {
+        self.player_config.as_ref()?.audio_config.as_ref()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PlayerConfig {
+    pub audio_config: Option<AudioConfig>,
+}
+
+/// Loudness measurements `YouTube` uses for its own volume normalization.
+///
+/// `loudness_db` is how far the track sits above (positive) or below
+/// (negative) `YouTube`'s reference level; playing it at `-loudness_db` dB
+/// matches the web player's normalized volume.
+#[derive(Debug, Clone, Copy, Deserialize, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AudioConfig {
+    pub loudness_db: Option<f32>,
+    pub perceptual_loudness_db: Option<f32>,
+    #[serde(default)]
+    pub enable_per_format_loudness: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, Default)]
@@ -0,0 +1,975 @@
+//! Metadata tags for downloaded audio files.
+//!
+//! Streams from googlevideo carry no tags. [`embed`] adds title, artists,
+//! album, year, track number and cover art, so saved files show up properly
+//! in other players:
+//!
+//! - AAC in MP4 gets an iTunes-style `moov/udta/meta/ilst` atom list. Chunk
+//!   offsets (`stco`/`co64`, `tfhd` base offsets) behind the grown `moov` are
+//!   moved along with the data they point at.
+//! - Opus gets Vorbis comments in its `OpusTags` header, with the cover as a
+//!   `METADATA_BLOCK_PICTURE`. WebM has no place for those, so Opus in WebM
+//!   is remuxed into Ogg on the way; Ogg input only has its tags replaced.
+//!
+//! The container is recognized from the data itself, so tagging a file twice
+//! replaces the first tags.
+
+use base64::Engine as _;
+
+use crate::error::{Error, Result};
+use crate::models::{AlbumItem, SongItem};
+use crate::response::VideoDetails;
+
+/// Cover art to embed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Picture {
+    /// e.g. `image/jpeg`. MP4 only takes JPEG and PNG.
+    pub mime_type: String,
+    pub data: Vec<u8>,
+}
+
+impl Picture {
+    /// Picture from image bytes, recognizing JPEG, PNG and WebP.
+    #[must_use]
+    pub fn from_bytes(data: Vec<u8>) -> Option<Self> {
+        let mime_type = if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            "image/jpeg"
+        } else if data.starts_with(b"\x89PNG") {
+            "image/png"
+        } else if data.len() > 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+            "image/webp"
+        } else {
+            return None;
+        };
+        Some(Self {
+            mime_type: mime_type.to_owned(),
+            data,
+        })
+    }
+}
+
+/// What to write into a file. Empty fields are left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Tags {
+    pub title: Option<String>,
+    pub artists: Vec<String>,
+    pub album: Option<String>,
+    /// Release year, e.g. `"2024"`.
+    pub year: Option<String>,
+    /// Position on the album, from 1.
+    pub track: Option<u32>,
+    pub track_total: Option<u32>,
+    pub cover: Option<Picture>,
+}
+
+impl Tags {
+    /// Title, artists and album name of a song.
+    #[must_use]
+    pub fn from_song(song: &SongItem) -> Self {
+        Self {
+            title: Some(song.title.clone()),
+            artists: song.artists.iter().map(|a| a.name.clone()).collect(),
+            album: song.album.as_ref().map(|a| a.name.clone()),
+            ..Self::default()
+        }
+    }
+
+    /// Add album details, for track `track` of `total`.
+    #[must_use]
+    pub fn with_album(mut self, album: &AlbumItem, track: u32, total: u32) -> Self {
+        self.album = Some(album.title.clone());
+        self.year.clone_from(&album.year);
+        if self.artists.is_empty() {
+            self.artists = album.artists.iter().map(|a| a.name.clone()).collect();
+        }
+        self.track = Some(track);
+        self.track_total = Some(total);
+        self
+    }
+
+    /// Fill in title and artist from the player response where missing.
+    #[must_use]
+    pub fn with_video_details(mut self, details: &VideoDetails) -> Self {
+        if self.title.as_deref().is_none_or(str::is_empty) {
+            self.title.clone_from(&details.title);
+        }
+        if self.artists.is_empty() {
+            self.artists.extend(details.author.clone());
+        }
+        self
+    }
+
+    #[must_use]
+    pub fn with_cover(mut self, cover: Picture) -> Self {
+        self.cover = Some(cover);
+        self
+    }
+
+    fn artist(&self) -> Option<String> {
+        (!self.artists.is_empty()).then(|| self.artists.join(", "))
+    }
+}
+
+/// A tagged file.
+#[derive(Debug)]
+pub struct Tagged {
+    pub data: Vec<u8>,
+    /// MIME type of the result, which differs from the input's after a remux.
+    pub mime_type: &'static str,
+    /// File extension usual for the result, without the dot.
+    pub extension: &'static str,
+}
+
+fn invalid(reason: impl Into<String>) -> Error {
+    Error::Tagging {
+        reason: reason.into(),
+    }
+}
+
+/// Embed `tags` into an MP4, WebM or Ogg audio file.
+///
+/// # Errors
+///
+/// [`Error::Tagging`] for another container or a file that cannot be parsed.
+pub fn embed(data: &[u8], tags: &Tags) -> Result<Tagged> {
+    if data.get(4..8) == Some(b"ftyp") {
+        Ok(Tagged {
+            data: mp4::embed(data, tags)?,
+            mime_type: "audio/mp4",
+            extension: "m4a",
+        })
+    } else if data.starts_with(&webm::EBML_MAGIC) || data.starts_with(b"OggS") {
+        let (head, packets) = if data.starts_with(b"OggS") {
+            let mut packets = ogg::read_packets(data)?.into_iter();
+            let head = packets
+                .next()
+                .ok_or_else(|| invalid("Ogg file without header"))?;
+            // Drop the old OpusTags.
+            packets.next();
+            (head, packets.collect())
+        } else {
+            webm::opus_packets(data)?
+        };
+        if !head.starts_with(b"OpusHead") {
+            return Err(invalid("not an Opus stream"));
+        }
+        Ok(Tagged {
+            data: ogg::write_opus(&head, &opus_tags(tags), &packets),
+            mime_type: "audio/ogg; codecs=\"opus\"",
+            extension: "opus",
+        })
+    } else {
+        Err(invalid("unknown container"))
+    }
+}
+
+/// `OpusTags` header packet with Vorbis comments for `tags`.
+fn opus_tags(tags: &Tags) -> Vec<u8> {
+    let mut comments: Vec<String> = Vec::new();
+    if let Some(title) = &tags.title {
+        comments.push(format!("TITLE={title}"));
+    }
+    comments.extend(tags.artists.iter().map(|a| format!("ARTIST={a}")));
+    if let Some(album) = &tags.album {
+        comments.push(format!("ALBUM={album}"));
+    }
+    if let Some(year) = &tags.year {
+        comments.push(format!("DATE={year}"));
+    }
+    if let Some(track) = tags.track {
+        comments.push(format!("TRACKNUMBER={track}"));
+    }
+    if let Some(total) = tags.track_total {
+        comments.push(format!("TRACKTOTAL={total}"));
+    }
+    if let Some(cover) = &tags.cover {
+        // FLAC picture block: front cover, no description, size unknown.
+        let mut block = Vec::with_capacity(cover.data.len() + 64);
+        block.extend_from_slice(&3u32.to_be_bytes());
+        push_len_be(&mut block, cover.mime_type.as_bytes());
+        push_len_be(&mut block, b"");
+        block.extend_from_slice(&[0; 16]);
+        push_len_be(&mut block, &cover.data);
+        comments.push(format!(
+            "METADATA_BLOCK_PICTURE={}",
+            base64::engine::general_purpose::STANDARD.encode(block)
+        ));
+    }
+
+    let mut out = b"OpusTags".to_vec();
+    push_len_le(
+        &mut out,
+        concat!("ytdroid ", env!("CARGO_PKG_VERSION")).as_bytes(),
+    );
+    out.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+    for comment in &comments {
+        push_len_le(&mut out, comment.as_bytes());
+    }
+    out
+}
+
+fn push_len_be(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_be_bytes());
+    out.extend_from_slice(bytes);
+}
+
+fn push_len_le(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+/// Samples at 48 kHz in an Opus packet, from its TOC byte (RFC 6716 §3.1).
+fn opus_samples(packet: &[u8]) -> u64 {
+    let Some(&toc) = packet.first() else { return 0 };
+    let config = toc >> 3;
+    let frame = match config {
+        0..=11 => [480, 960, 1920, 2880][usize::from(config % 4)],
+        12..=15 => [480, 960][usize::from(config % 2)],
+        _ => [120, 240, 480, 960][usize::from(config % 4)],
+    };
+    let frames = match toc & 3 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => packet.get(1).map_or(0, |b| u64::from(b & 0x3F)),
+    };
+    frame * frames
+}
+
+mod mp4 {
+    use super::{Result, Tags, invalid};
+
+    /// Boxes whose content is more boxes, on the way to chunk offsets.
+    const CONTAINERS: [&[u8; 4]; 7] = [
+        b"moov", b"trak", b"mdia", b"minf", b"stbl", b"moof", b"traf",
+    ];
+
+    struct Atom {
+        kind: [u8; 4],
+        start: usize,
+        body: usize,
+        end: usize,
+    }
+
+    fn atoms(data: &[u8]) -> Result<Vec<Atom>> {
+        let mut atoms = Vec::new();
+        let mut pos = 0;
+        while pos + 8 <= data.len() {
+            let size = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap_or_default());
+            let kind: [u8; 4] = data[pos + 4..pos + 8].try_into().unwrap_or_default();
+            let (body, end) = match size {
+                0 => (pos + 8, data.len()),
+                1 => {
+                    let large = data
+                        .get(pos + 8..pos + 16)
+                        .ok_or_else(|| invalid("truncated MP4 box"))?;
+                    let large = u64::from_be_bytes(large.try_into().unwrap_or_default());
+                    (
+                        pos + 16,
+                        pos.saturating_add(usize::try_from(large).unwrap_or(usize::MAX)),
+                    )
+                }
+                n => (pos + 8, pos + n as usize),
+            };
+            if end > data.len() || end < body {
+                return Err(invalid("MP4 box overruns the file"));
+            }
+            atoms.push(Atom {
+                kind,
+                start: pos,
+                body,
+                end,
+            });
+            pos = end;
+        }
+        Ok(atoms)
+    }
+
+    pub(super) fn atom(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(body.len() + 8);
+        out.extend_from_slice(&(body.len() as u32 + 8).to_be_bytes());
+        out.extend_from_slice(kind);
+        out.extend_from_slice(body);
+        out
+    }
+
+    fn data_atom(kind: &[u8; 4], type_code: u32, payload: &[u8]) -> Vec<u8> {
+        let mut body = type_code.to_be_bytes().to_vec();
+        body.extend_from_slice(&[0; 4]);
+        body.extend_from_slice(payload);
+        atom(kind, &atom(b"data", &body))
+    }
+
+    fn udta(tags: &Tags) -> Vec<u8> {
+        let mut ilst = Vec::new();
+        let text = [
+            (b"\xA9nam", tags.title.clone()),
+            (b"\xA9ART", tags.artist()),
+            (b"\xA9alb", tags.album.clone()),
+            (b"\xA9day", tags.year.clone()),
+        ];
+        for (kind, value) in text {
+            if let Some(value) = value {
+                ilst.extend(data_atom(kind, 1, value.as_bytes()));
+            }
+        }
+        if let Some(track) = tags.track {
+            let mut trkn = vec![0, 0];
+            trkn.extend_from_slice(&u16::try_from(track).unwrap_or(u16::MAX).to_be_bytes());
+            let total = tags.track_total.unwrap_or_default();
+            trkn.extend_from_slice(&u16::try_from(total).unwrap_or(u16::MAX).to_be_bytes());
+            trkn.extend_from_slice(&[0, 0]);
+            ilst.extend(data_atom(b"trkn", 0, &trkn));
+        }
+        if let Some(cover) = &tags.cover {
+            match cover.mime_type.as_str() {
+                "image/jpeg" => ilst.extend(data_atom(b"covr", 13, &cover.data)),
+                "image/png" => ilst.extend(data_atom(b"covr", 14, &cover.data)),
+                _ => {}
+            }
+        }
+
+        let mut hdlr = vec![0; 8];
+        hdlr.extend_from_slice(b"mdirappl");
+        hdlr.extend_from_slice(&[0; 9]);
+        let mut meta = vec![0; 4];
+        meta.extend(atom(b"hdlr", &hdlr));
+        meta.extend(atom(b"ilst", &ilst));
+        atom(b"udta", &atom(b"meta", &meta))
+    }
+
+    /// Move absolute file offsets at or past `from` by `delta`.
+    fn shift_offsets(data: &mut [u8], from: u64, delta: i64) -> Result<()> {
+        let shift = |offset: u64| -> Result<u64> {
+            if offset < from {
+                return Ok(offset);
+            }
+            offset
+                .checked_add_signed(delta)
+                .ok_or_else(|| invalid("chunk offset out of range"))
+        };
+        for atom in atoms(data)? {
+            let body = &mut data[atom.body..atom.end];
+            match &atom.kind {
+                kind if CONTAINERS.contains(&kind) => shift_offsets(body, from, delta)?,
+                b"stco" | b"co64" => {
+                    let wide = &atom.kind == b"co64";
+                    let count = body
+                        .get(4..8)
+                        .map_or(0, |c| u32::from_be_bytes(c.try_into().unwrap_or_default()));
+                    let width = if wide { 8 } else { 4 };
+                    for i in 0..count as usize {
+                        let at = 8 + i * width;
+                        let entry = body
+                            .get_mut(at..at + width)
+                            .ok_or_else(|| invalid("truncated chunk offset table"))?;
+                        if wide {
+                            let offset =
+                                shift(u64::from_be_bytes(entry.try_into().unwrap_or_default()))?;
+                            entry.copy_from_slice(&offset.to_be_bytes());
+                        } else {
+                            let offset = shift(u64::from(u32::from_be_bytes(
+                                entry.try_into().unwrap_or_default(),
+                            )))?;
+                            let offset = u32::try_from(offset)
+                                .map_err(|_| invalid("chunk offset out of range"))?;
+                            entry.copy_from_slice(&offset.to_be_bytes());
+                        }
+                    }
+                }
+                b"tfhd" if body.get(3).is_some_and(|flags| flags & 1 != 0) => {
+                    let entry = body
+                        .get_mut(8..16)
+                        .ok_or_else(|| invalid("truncated tfhd"))?;
+                    let offset = shift(u64::from_be_bytes(entry.try_into().unwrap_or_default()))?;
+                    entry.copy_from_slice(&offset.to_be_bytes());
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn embed(data: &[u8], tags: &Tags) -> Result<Vec<u8>> {
+        let top = atoms(data)?;
+        let moov = top
+            .iter()
+            .find(|a| &a.kind == b"moov")
+            .ok_or_else(|| invalid("MP4 file without moov"))?;
+
+        let mut body = Vec::with_capacity(moov.end - moov.body);
+        for child in atoms(&data[moov.body..moov.end])? {
+            if &child.kind != b"udta" {
+                body.extend_from_slice(&data[moov.body + child.start..moov.body + child.end]);
+            }
+        }
+        body.extend(udta(tags));
+        let mut new_moov = atom(b"moov", &body);
+
+        let delta = new_moov.len() as i64 - (moov.end - moov.start) as i64;
+        let mut tail = data[moov.end..].to_vec();
+        shift_offsets(&mut new_moov, moov.end as u64, delta)?;
+        shift_offsets(&mut tail, moov.end as u64, delta)?;
+
+        let mut out = Vec::with_capacity(data.len() + body.len());
+        out.extend_from_slice(&data[..moov.start]);
+        out.extend(new_moov);
+        out.extend(tail);
+        Ok(out)
+    }
+}
+
+mod webm {
+    use super::{Result, invalid};
+
+    pub(super) const EBML_MAGIC: [u8; 4] = [0x1A, 0x45, 0xDF, 0xA3];
+
+    const SEGMENT: u32 = 0x1853_8067;
+    const TRACKS: u32 = 0x1654_AE6B;
+    const TRACK_ENTRY: u32 = 0xAE;
+    const TRACK_NUMBER: u32 = 0xD7;
+    const CODEC_ID: u32 = 0x86;
+    const CODEC_PRIVATE: u32 = 0x63A2;
+    const CLUSTER: u32 = 0x1F43_B675;
+    const BLOCK_GROUP: u32 = 0xA0;
+    const BLOCK: u32 = 0xA1;
+    const SIMPLE_BLOCK: u32 = 0xA3;
+
+    /// Elements walked into rather than skipped. Their sizes are ignored, so
+    /// unknown sizes (live-style WebM) need no special handling.
+    const MASTERS: [u32; 5] = [SEGMENT, TRACKS, TRACK_ENTRY, CLUSTER, BLOCK_GROUP];
+
+    /// EBML variable-length integer at `pos`: value with the length marker
+    /// kept (for IDs) or removed, and its length. `None` value for an
+    /// unknown size.
+    fn vint(data: &[u8], pos: usize, keep_marker: bool) -> Result<(Option<u64>, usize)> {
+        let first = *data
+            .get(pos)
+            .ok_or_else(|| invalid("truncated WebM element"))?;
+        if first == 0 {
+            return Err(invalid("invalid EBML number"));
+        }
+        let len = first.leading_zeros() as usize + 1;
+        let bytes = data
+            .get(pos..pos + len)
+            .ok_or_else(|| invalid("truncated WebM element"))?;
+        let mask = if keep_marker {
+            0xFF
+        } else {
+            (0xFF_u16 >> len) as u8
+        };
+        let value = bytes[1..]
+            .iter()
+            .fold(u64::from(first & mask), |v, &b| (v << 8) | u64::from(b));
+        let unknown = !keep_marker && value == (1 << (7 * len)) - 1;
+        Ok(((!unknown).then_some(value), len))
+    }
+
+    /// `OpusHead` and the audio packets of an Opus track in WebM.
+    pub(super) fn opus_packets(data: &[u8]) -> Result<(Vec<u8>, Vec<Vec<u8>>)> {
+        let mut codec = None;
+        let mut head = None;
+        let mut track = None;
+        let mut packets = Vec::new();
+
+        let mut pos = 0;
+        while pos < data.len() {
+            let (id, id_len) = vint(data, pos, true)?;
+            let (size, size_len) = vint(data, pos + id_len, false)?;
+            let id =
+                u32::try_from(id.unwrap_or_default()).map_err(|_| invalid("invalid EBML ID"))?;
+            pos += id_len + size_len;
+            if MASTERS.contains(&id) {
+                continue;
+            }
+            let size = size.ok_or_else(|| invalid("unknown size on a WebM leaf element"))?;
+            let end = usize::try_from(size)
+                .ok()
+                .and_then(|size| pos.checked_add(size))
+                .filter(|&end| end <= data.len())
+                .ok_or_else(|| invalid("WebM element overruns the file"))?;
+            let body = &data[pos..end];
+            match id {
+                CODEC_ID => codec = Some(body),
+                CODEC_PRIVATE => head = Some(body.to_vec()),
+                TRACK_NUMBER => {
+                    track = Some(body.iter().fold(0u64, |v, &b| (v << 8) | u64::from(b)));
+                }
+                SIMPLE_BLOCK | BLOCK => {
+                    let (number, len) = vint(body, 0, false)?;
+                    let flags = *body
+                        .get(len + 2)
+                        .ok_or_else(|| invalid("truncated WebM block"))?;
+                    if track.is_some_and(|t| number != Some(t)) {
+                        pos = end;
+                        continue;
+                    }
+                    if flags & 0x06 != 0 {
+                        return Err(invalid("laced WebM blocks are not supported"));
+                    }
+                    packets.push(body[len + 3..].to_vec());
+                }
+                _ => {}
+            }
+            pos = end;
+        }
+
+        if codec != Some(b"A_OPUS".as_slice()) {
+            return Err(invalid("WebM file without an Opus track"));
+        }
+        let head = head.ok_or_else(|| invalid("Opus track without OpusHead"))?;
+        Ok((head, packets))
+    }
+}
+
+mod ogg {
+    use super::{Result, invalid, opus_samples};
+
+    /// Pages are closed once they hold about this many bytes.
+    const PAGE_TARGET: usize = 4096;
+
+    /// Any value works for a single-stream file.
+    const SERIAL: u32 = 0x7974_6472;
+
+    const CRC_TABLE: [u32; 256] = {
+        let mut table = [0u32; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = (i as u32) << 24;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 0x8000_0000 != 0 {
+                    (crc << 1) ^ 0x04C1_1DB7
+                } else {
+                    crc << 1
+                };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+
+    pub(super) fn crc(data: &[u8]) -> u32 {
+        data.iter().fold(0, |crc, &b| {
+            (crc << 8) ^ CRC_TABLE[usize::from((crc >> 24) as u8 ^ b)]
+        })
+    }
+
+    struct Writer {
+        out: Vec<u8>,
+        seq: u32,
+        lacing: Vec<u8>,
+        body: Vec<u8>,
+        /// Granule position of the last packet completed on this page.
+        granule: Option<u64>,
+        /// This page starts with the rest of a packet.
+        continued: bool,
+    }
+
+    impl Writer {
+        fn packet(&mut self, packet: &[u8], granule: u64) {
+            let mut rest = packet;
+            let mut started = false;
+            loop {
+                if self.lacing.len() == 255 {
+                    self.flush(false, started);
+                }
+                let n = rest.len().min(255);
+                self.lacing.push(n as u8);
+                self.body.extend_from_slice(&rest[..n]);
+                rest = &rest[n..];
+                started = true;
+                if n < 255 {
+                    break;
+                }
+            }
+            self.granule = Some(granule);
+        }
+
+        /// Close the current page; `next_continued` when it ends inside a packet.
+        fn flush(&mut self, eos: bool, next_continued: bool) {
+            let mut header_type = 0;
+            if self.continued {
+                header_type |= 1;
+            }
+            if self.seq == 0 {
+                header_type |= 2;
+            }
+            if eos {
+                header_type |= 4;
+            }
+            let granule = self.granule.map_or(-1, |g| g as i64);
+
+            let start = self.out.len();
+            self.out.extend_from_slice(b"OggS\0");
+            self.out.push(header_type);
+            self.out.extend_from_slice(&granule.to_le_bytes());
+            self.out.extend_from_slice(&SERIAL.to_le_bytes());
+            self.out.extend_from_slice(&self.seq.to_le_bytes());
+            self.out.extend_from_slice(&[0; 4]);
+            self.out.push(self.lacing.len() as u8);
+            self.out.append(&mut self.lacing);
+            self.out.append(&mut self.body);
+            let crc = crc(&self.out[start..]);
+            self.out[start + 22..start + 26].copy_from_slice(&crc.to_le_bytes());
+
+            self.seq += 1;
+            self.granule = None;
+            self.continued = next_continued;
+        }
+    }
+
+    /// Ogg Opus file of a header, tags and audio packets.
+    pub(super) fn write_opus(head: &[u8], tags: &[u8], packets: &[Vec<u8>]) -> Vec<u8> {
+        let mut w = Writer {
+            out: Vec::new(),
+            seq: 0,
+            lacing: Vec::new(),
+            body: Vec::new(),
+            granule: None,
+            continued: false,
+        };
+        // Both headers end their pages; audio starts on a fresh one.
+        w.packet(head, 0);
+        w.flush(false, false);
+        w.packet(tags, 0);
+        w.flush(false, false);
+
+        let mut granule = 0;
+        for packet in packets {
+            if w.body.len() >= PAGE_TARGET {
+                w.flush(false, false);
+            }
+            granule += opus_samples(packet);
+            w.packet(packet, granule);
+        }
+        w.flush(true, false);
+        w.out
+    }
+
+    /// Packets of a single-stream Ogg file.
+    pub(super) fn read_packets(data: &[u8]) -> Result<Vec<Vec<u8>>> {
+        let mut packets = Vec::new();
+        let mut packet = Vec::new();
+        let mut pos = 0;
+        while pos < data.len() {
+            let header = data
+                .get(pos..pos + 27)
+                .filter(|h| h.starts_with(b"OggS"))
+                .ok_or_else(|| invalid("invalid Ogg page"))?;
+            let segments = usize::from(header[26]);
+            let lacing = data
+                .get(pos + 27..pos + 27 + segments)
+                .ok_or_else(|| invalid("truncated Ogg page"))?;
+            let mut at = pos + 27 + segments;
+            for &len in lacing {
+                let len = usize::from(len);
+                let bytes = data
+                    .get(at..at + len)
+                    .ok_or_else(|| invalid("truncated Ogg page"))?;
+                packet.extend_from_slice(bytes);
+                at += len;
+                if len < 255 {
+                    packets.push(std::mem::take(&mut packet));
+                }
+            }
+            pos = at;
+        }
+        Ok(packets)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mp4::atom;
+    use super::*;
+
+    const AUDIO: &[u8] = b"audio payload";
+
+    fn tags() -> Tags {
+        Tags {
+            title: Some("Song".into()),
+            artists: vec!["A".into(), "B".into()],
+            album: Some("Album".into()),
+            year: Some("2024".into()),
+            track: Some(3),
+            track_total: Some(12),
+            cover: Picture::from_bytes([&[0xFF, 0xD8, 0xFF][..], &[7; 70_000]].concat()),
+        }
+    }
+
+    /// Body of the first box named `path[0]`, then of its child `path[1]`...
+    fn find<'a>(mut data: &'a [u8], path: &[&[u8; 4]]) -> Option<&'a [u8]> {
+        for kind in path {
+            let mut pos = 0;
+            let mut found = None;
+            while pos + 8 <= data.len() {
+                let size = u32::from_be_bytes(data[pos..pos + 4].try_into().unwrap()) as usize;
+                if &data[pos + 4..pos + 8] == *kind {
+                    found = Some(&data[pos + 8..pos + size]);
+                    break;
+                }
+                pos += size;
+            }
+            data = found?;
+        }
+        Some(data)
+    }
+
+    fn count(data: &[u8], needle: &[u8]) -> usize {
+        data.windows(needle.len()).filter(|w| *w == needle).count()
+    }
+
+    /// `ftyp`, `moov` with one `stco` entry pointing into the `mdat`
+    /// following it, then the `mdat`.
+    fn plain_mp4() -> Vec<u8> {
+        let ftyp = atom(b"ftyp", b"M4A \0\0\0\0");
+        let stco_body =
+            |offset: u32| [&[0; 4][..], &1u32.to_be_bytes(), &offset.to_be_bytes()].concat();
+        let moov = |offset| {
+            atom(
+                b"moov",
+                &atom(
+                    b"trak",
+                    &atom(
+                        b"mdia",
+                        &atom(b"minf", &atom(b"stbl", &atom(b"stco", &stco_body(offset)))),
+                    ),
+                ),
+            )
+        };
+        let offset = (ftyp.len() + moov(0).len() + 8) as u32;
+        [ftyp, moov(offset), atom(b"mdat", AUDIO)].concat()
+    }
+
+    fn stco_target(file: &[u8]) -> &[u8] {
+        let stco = find(
+            file,
+            &[b"moov", b"trak", b"mdia", b"minf", b"stbl", b"stco"],
+        )
+        .unwrap();
+        let offset = u32::from_be_bytes(stco[8..12].try_into().unwrap()) as usize;
+        &file[offset..offset + AUDIO.len()]
+    }
+
+    #[test]
+    fn mp4_gets_ilst_and_keeps_chunk_offsets() {
+        let tagged = embed(&plain_mp4(), &tags()).unwrap();
+        assert_eq!(tagged.mime_type, "audio/mp4");
+        let ilst = find(&tagged.data, &[b"moov", b"udta", b"meta"])
+            .and_then(|meta| find(&meta[4..], &[b"ilst"]))
+            .unwrap();
+
+        let text = |kind: &[u8; 4]| {
+            let data = find(ilst, &[kind, b"data"]).unwrap();
+            String::from_utf8(data[8..].to_vec()).unwrap()
+        };
+        assert_eq!(text(b"\xA9nam"), "Song");
+        assert_eq!(text(b"\xA9ART"), "A, B");
+        assert_eq!(text(b"\xA9alb"), "Album");
+        assert_eq!(text(b"\xA9day"), "2024");
+        let trkn = find(ilst, &[b"trkn", b"data"]).unwrap();
+        assert_eq!(&trkn[8..], &[0, 0, 0, 3, 0, 12, 0, 0]);
+        let covr = find(ilst, &[b"covr", b"data"]).unwrap();
+        assert_eq!(&covr[..4], &13u32.to_be_bytes());
+        assert_eq!(covr.len() - 8, 70_003);
+
+        assert_eq!(stco_target(&tagged.data), AUDIO);
+    }
+
+    #[test]
+    fn mp4_retag_replaces_tags() {
+        let first = embed(&plain_mp4(), &tags()).unwrap();
+        let retag = Tags {
+            title: Some("Other".into()),
+            ..Tags::default()
+        };
+        let second = embed(&first.data, &retag).unwrap();
+        assert_eq!(count(&second.data, b"udta"), 1);
+        assert_eq!(count(&second.data, b"covr"), 0);
+        assert_eq!(count(&second.data, b"Other"), 1);
+        assert_eq!(stco_target(&second.data), AUDIO);
+    }
+
+    #[test]
+    fn mp4_fragment_base_offsets_move() {
+        let ftyp = atom(b"ftyp", b"dash\0\0\0\0");
+        let moov = atom(b"moov", &atom(b"mvhd", &[0; 100]));
+        let mdat_at = (ftyp.len() + moov.len()) as u64;
+        // tfhd with base-data-offset-present, pointing past itself at the mdat.
+        let moof_len = 8 + 8 + 8 + 16;
+        let tfhd = [
+            &[0, 0, 0, 1][..],
+            &1u32.to_be_bytes(),
+            &(mdat_at + moof_len + 8).to_be_bytes(),
+        ]
+        .concat();
+        let moof = atom(b"moof", &atom(b"traf", &atom(b"tfhd", &tfhd)));
+        assert_eq!(moof.len() as u64, moof_len);
+        let file = [ftyp, moov, moof, atom(b"mdat", AUDIO)].concat();
+
+        let tagged = embed(&file, &tags()).unwrap().data;
+        let tfhd = find(&tagged, &[b"moof", b"traf", b"tfhd"]).unwrap();
+        let base = u64::from_be_bytes(tfhd[8..16].try_into().unwrap()) as usize;
+        assert_eq!(&tagged[base..base + AUDIO.len()], AUDIO);
+    }
+
+    fn ebml(id: &[u8], body: &[u8]) -> Vec<u8> {
+        // Eight-byte sizes keep the test independent of body lengths.
+        [id, &[0x01], &(body.len() as u64).to_be_bytes()[1..], body].concat()
+    }
+
+    fn opus_head() -> Vec<u8> {
+        [
+            &b"OpusHead"[..],
+            &[1, 2, 0x38, 0x01, 0x80, 0xBB, 0, 0, 0, 0, 0],
+        ]
+        .concat()
+    }
+
+    /// 20 ms CELT packet (config 31, one frame).
+    fn packet(i: u8) -> Vec<u8> {
+        [&[31 << 3][..], &vec![i; 150 + usize::from(i) * 3]].concat()
+    }
+
+    fn webm(packets: &[Vec<u8>]) -> Vec<u8> {
+        let header = ebml(&webm::EBML_MAGIC, &ebml(&[0x42, 0x82], b"webm"));
+        let track = ebml(
+            &[0xAE],
+            &[
+                ebml(&[0xD7], &[1]),
+                ebml(&[0x86], b"A_OPUS"),
+                ebml(&[0x63, 0xA2], &opus_head()),
+            ]
+            .concat(),
+        );
+        let blocks: Vec<u8> = packets
+            .iter()
+            .flat_map(|p| ebml(&[0xA3], &[&[0x81, 0, 0, 0x80][..], p].concat()))
+            .collect();
+        // Segment and cluster of unknown size, as in live WebM.
+        let unknown = [0x01, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+        [
+            header,
+            vec![0x18, 0x53, 0x80, 0x67],
+            unknown.to_vec(),
+            ebml(&[0x16, 0x54, 0xAE, 0x6B], &track),
+            vec![0x1F, 0x43, 0xB6, 0x75],
+            unknown.to_vec(),
+            ebml(&[0xE7], &[0]),
+            blocks,
+        ]
+        .concat()
+    }
+
+    /// Check every page and return the packets, the last granule position and
+    /// header flags of the first and last page.
+    fn read_ogg(data: &[u8]) -> (Vec<Vec<u8>>, i64, u8, u8) {
+        let mut pos = 0;
+        let mut flags = Vec::new();
+        let mut granule = 0;
+        while pos < data.len() {
+            let segments = usize::from(data[pos + 26]);
+            let len = 27
+                + segments
+                + data[pos + 27..pos + 27 + segments]
+                    .iter()
+                    .map(|&l| usize::from(l))
+                    .sum::<usize>();
+            let mut page = data[pos..pos + len].to_vec();
+            let stored = u32::from_le_bytes(page[22..26].try_into().unwrap());
+            page[22..26].fill(0);
+            assert_eq!(ogg::crc(&page), stored, "bad CRC on page at {pos}");
+            flags.push(page[5]);
+            granule = i64::from_le_bytes(page[6..14].try_into().unwrap());
+            pos += len;
+        }
+        let packets = ogg::read_packets(data).unwrap();
+        (packets, granule, flags[0], *flags.last().unwrap())
+    }
+
+    fn comments(opus_tags: &[u8]) -> Vec<String> {
+        let mut pos = 8;
+        let next = |pos: &mut usize| {
+            let len = u32::from_le_bytes(opus_tags[*pos..*pos + 4].try_into().unwrap()) as usize;
+            *pos += 4 + len;
+            &opus_tags[*pos - len..*pos]
+        };
+        next(&mut pos);
+        let count = u32::from_le_bytes(opus_tags[pos..pos + 4].try_into().unwrap());
+        pos += 4;
+        (0..count)
+            .map(|_| String::from_utf8(next(&mut pos).to_vec()).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn webm_opus_is_remuxed_to_tagged_ogg() {
+        let packets: Vec<_> = (0..100).map(packet).collect();
+        let tagged = embed(&webm(&packets), &tags()).unwrap();
+        assert_eq!(tagged.extension, "opus");
+
+        let (read, granule, first, last) = read_ogg(&tagged.data);
+        assert_eq!(first, 2, "first page must be BOS");
+        assert_eq!(last & 4, 4, "last page must be EOS");
+        assert_eq!(granule, 100 * 960);
+        assert_eq!(read[0], opus_head());
+        assert_eq!(&read[2..], &packets[..]);
+
+        let comments = comments(&read[1]);
+        assert_eq!(
+            &comments[..7],
+            [
+                "TITLE=Song",
+                "ARTIST=A",
+                "ARTIST=B",
+                "ALBUM=Album",
+                "DATE=2024",
+                "TRACKNUMBER=3",
+                "TRACKTOTAL=12"
+            ]
+        );
+        let picture = comments[7].strip_prefix("METADATA_BLOCK_PICTURE=").unwrap();
+        let block = base64::engine::general_purpose::STANDARD
+            .decode(picture)
+            .unwrap();
+        assert_eq!(&block[..4], &3u32.to_be_bytes());
+        assert_eq!(&block[8..18], b"image/jpeg");
+        assert_eq!(block.len(), 4 + 4 + 10 + 4 + 16 + 4 + 70_003);
+    }
+
+    #[test]
+    fn ogg_retag_keeps_audio() {
+        let packets: Vec<_> = (0..20).map(packet).collect();
+        let first = embed(&webm(&packets), &tags()).unwrap();
+        let second = embed(
+            &first.data,
+            &Tags {
+                title: Some("Other".into()),
+                ..Tags::default()
+            },
+        )
+        .unwrap();
+
+        let (read, granule, _, _) = read_ogg(&second.data);
+        assert_eq!(comments(&read[1]), ["TITLE=Other"]);
+        assert_eq!(&read[2..], &packets[..]);
+        assert_eq!(granule, 20 * 960);
+    }
+
+    #[test]
+    fn unknown_container_is_an_error() {
+        assert!(matches!(
+            embed(b"ID3\x04 not audio", &tags()),
+            Err(Error::Tagging { .. })
+        ));
+    }
+
+    #[test]
+    fn opus_sample_counts() {
+        assert_eq!(opus_samples(&[31 << 3]), 960);
+        assert_eq!(opus_samples(&[(16 << 3) | 1]), 240);
+        assert_eq!(opus_samples(&[(1 << 3) | 3, 3]), 2880);
+    }
+}
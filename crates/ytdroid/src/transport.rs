@@ -0,0 +1,134 @@
+//! Pluggable HTTP layer underneath [`crate::http::InnerTube`].
+//!
+//! Every Innertube call is a JSON `POST`; [`InnerTube`](crate::http::InnerTube)
+//! builds the URL, headers and body and hands them to a [`Transport`]. The
+//! default [`ReqwestTransport`] talks to the network. Tests and offline tools can
+//! provide their own implementation to serve canned responses in-process.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+use reqwest::{Client, StatusCode};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::error::{Error, Result};
+
+/// Boxed future returned by [`Transport::post`].
+pub type TransportFuture<'a> = Pin<Box<dyn Future<Output = Result<TransportResponse>> + Send + 'a>>;
+
+/// A fully-built Innertube request.
+#[derive(Debug, Clone)]
+pub struct TransportRequest {
+    /// Absolute URL, including the `?prettyPrint=false` query.
+    pub url: String,
+    /// Endpoint path relative to the API base (e.g. `"browse"`, `"music/get_queue"`).
+    pub endpoint: String,
+    /// Header name/value pairs, in the order they were added.
+    pub headers: Vec<(String, String)>,
+    /// JSON request body.
+    pub body: Value,
+}
+
+impl TransportRequest {
+    /// First value of header `name` (case-insensitive).
+    #[must_use]
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Raw response handed back by a [`Transport`].
+#[derive(Debug, Clone)]
+pub struct TransportResponse {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl TransportResponse {
+    /// A `200 OK` response carrying `body` serialized as JSON.
+    #[must_use]
+    pub fn json(body: &Value) -> Self {
+        Self {
+            status: 200,
+            body: body.to_string().into_bytes(),
+        }
+    }
+
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// Sends Innertube requests.
+///
+/// Implementations must be cheap to share: [`InnerTube`](crate::http::InnerTube)
+/// stores one behind an `Arc` and clones it with the client.
+pub trait Transport: Send + Sync {
+    fn post(&self, request: TransportRequest) -> TransportFuture<'_>;
+}
+
+/// Default [`Transport`] backed by `reqwest`, with retry on `429 Too Many Requests`.
+#[derive(Clone)]
+pub struct ReqwestTransport {
+    http: Client,
+}
+
+impl ReqwestTransport {
+    /// Build a transport with gzip/deflate enabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the underlying HTTP client cannot be constructed.
+    pub fn new() -> Result<Self> {
+        let http = Client::builder()
+            .gzip(true)
+            .deflate(true)
+            .user_agent("Mozilla/5.0")
+            .build()?;
+        Ok(Self { http })
+    }
+
+    /// Wrap an existing `reqwest` client (proxies, custom TLS, timeouts, …).
+    #[must_use]
+    pub fn with_client(http: Client) -> Self {
+        Self { http }
+    }
+
+    async fn send(&self, request: TransportRequest) -> Result<TransportResponse> {
+        let mut req = self.http.post(&request.url);
+        for (k, v) in &request.headers {
+            req = req.header(k, v);
+        }
+        req = req.json(&request.body);
+
+        let mut attempts = 0u32;
+        loop {
+            // `try_clone` is available because we only use json bodies (not streams).
+            let r = req.try_clone().ok_or(Error::MissingField {
+                field: "request clone",
+            })?;
+            let resp = r.send().await?;
+            if resp.status() == StatusCode::TOO_MANY_REQUESTS && attempts < 3 {
+                attempts += 1;
+                warn!("429 Too Many Requests — waiting before retry {attempts}");
+                tokio::time::sleep(Duration::from_secs(u64::from(attempts) * 2)).await;
+                continue;
+            }
+            let status = resp.status().as_u16();
+            let body = resp.bytes().await?.to_vec();
+            return Ok(TransportResponse { status, body });
+        }
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn post(&self, request: TransportRequest) -> TransportFuture<'_> {
+        Box::pin(self.send(request))
+    }
+}
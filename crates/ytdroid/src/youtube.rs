@@ -1,11 +1,16 @@
 //! High-level `YouTube` Music API — the primary entry point for consumers.
 
+use std::path::PathBuf;
+use std::time::Duration;
+
 use tracing::instrument;
 
-use crate::client::{Locale, YouTubeClient};
+use crate::client::{ApiEndpoint, Locale, YouTubeClient};
 use crate::error::{Error, Result};
 use crate::filters::{LibraryFilter, SearchFilter};
+use crate::format::FormatPreference;
 use crate::http::{AddDedupOption, InnerTube, LikeAction};
+use crate::transport::Transport;
 pub use crate::http::PlaylistPrivacy;
 
 // ─────────────────────────────────────────────
@@ -24,6 +29,9 @@ pub struct ContentHints {
     pub is_kids_content: bool,
     /// True for live streams.
     pub is_live: bool,
+    /// Clients (by `client_name`) whose stream already failed for this
+    /// video, e.g. with a 403 from the CDN. They are skipped.
+    pub failed_clients: Vec<&'static str>,
 }
 
 /// Resolved audio stream from [`YouTube::audio_stream`].
@@ -35,6 +43,60 @@ pub struct AudioStream {
     /// Streaming PoToken to append as `pot=` to the final CDN URL (WEB clients only).
     /// The caller must append this AFTER sig decryption and nsig transform.
     pub streaming_pot: Option<String>,
+    /// `itag` of the selected format.
+    pub itag: Option<u32>,
+    /// MIME type including codecs, e.g. `audio/webm; codecs="opus"`.
+    pub mime_type: Option<String>,
+    /// Average (or, when unknown, peak) bitrate in bits per second.
+    pub bitrate: Option<u64>,
+    /// Stream size in bytes, when the player response reports it.
+    pub content_length: Option<u64>,
+    /// Loudness relative to `YouTube`'s reference level; see
+    /// [`AudioConfig`](crate::response::AudioConfig).
+    /// Taken from the selected format when it reports its own.
+    pub loudness_db: Option<f32>,
+    /// Perceptual loudness from `playerConfig.audioConfig`.
+    pub perceptual_loudness_db: Option<f32>,
+    /// `videoDetails` of the player response, e.g. for tagging downloads.
+    pub video_details: Option<VideoDetails>,
+    /// `client_name` of the client that resolved the stream.
+    pub client: &'static str,
+    /// How long the URL stays valid, from `streamingData.expiresInSeconds`.
+    pub expires_in: Option<Duration>,
+}
+
+impl AudioStream {
+    fn new(
+        data: String,
+        is_cipher: bool,
+        streaming_pot: Option<String>,
+        fmt: &StreamingFormat,
+        resp: &PlayerResponse,
+        client: &YouTubeClient,
+    ) -> Self {
+        let audio_config = resp.audio_config();
+        let expires_in = resp
+            .streaming_data
+            .as_ref()
+            .and_then(|sd| sd.expires_in_seconds.as_deref()?.parse().ok())
+            .map(Duration::from_secs);
+        Self {
+            data,
+            is_cipher,
+            streaming_pot,
+            itag: fmt.itag,
+            mime_type: fmt.mime_type.clone(),
+            bitrate: fmt.average_bitrate.or(fmt.bitrate),
+            content_length: fmt.content_length.as_deref().and_then(|l| l.parse().ok()),
+            loudness_db: fmt
+                .loudness_db
+                .or_else(|| audio_config.and_then(|c| c.loudness_db)),
+            perceptual_loudness_db: audio_config.and_then(|c| c.perceptual_loudness_db),
+            video_details: resp.video_details.clone(),
+            client: client.client_name,
+            expires_in,
+        }
+    }
 }
 
 fn clients_for_hints(hints: &ContentHints) -> Vec<YouTubeClient> {
@@ -90,6 +152,7 @@ use crate::pages::{
 };
 use crate::response::{
     AccountMenuResponse, GetQueueResponse, GetTranscriptResponse, PlayerResponse,
+    StreamingFormat, VideoDetails,
 };
 
 /// The primary `YouTube` Music client.
@@ -129,6 +192,35 @@ impl YouTube {
         self
     }
 
+    /// Route all Innertube requests through `transport` instead of the network.
+    #[must_use]
+    pub fn with_transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.inner = self.inner.with_transport(transport);
+        self
+    }
+
+    /// Record raw responses under `dir` while talking to the network.
+    #[must_use]
+    pub fn with_recording(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.inner = self.inner.with_recording(dir);
+        self
+    }
+
+    /// Answer every request from fixtures recorded under `dir` (no network).
+    #[must_use]
+    pub fn with_replay(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.inner = self.inner.with_replay(dir);
+        self
+    }
+
+    /// Point the client at a different base URL / origin / referer
+    /// (e.g. `ApiEndpoint::at("http://127.0.0.1:8080")` for a local stand-in).
+    #[must_use]
+    pub fn with_endpoint(mut self, endpoint: ApiEndpoint) -> Self {
+        self.inner = self.inner.with_endpoint(endpoint);
+        self
+    }
+
     /// The visitor/session ID set via [`with_visitor_id`], if any.
     #[must_use]
     pub fn visitor_id(&self) -> Option<&str> {
@@ -519,17 +611,19 @@ impl YouTube {
             .await
     }
 
-    /// Fetch the best audio-only stream for a video.
+    /// Fetch the audio-only stream for a video that best matches `pref`.
     ///
     /// Mirrors Metrolist's `ContentAwareFallbackStrategy` + `YTPlayerUtils.playerResponseForPlayback`.
     ///
     /// - `hints` — content type hints that select the optimal client chain.
+    /// - `pref` — codec / bitrate / quality-bucket preference (see [`FormatPreference`]).
     /// - `po_token` — player-request PoToken (sent in the `/player` body for WEB clients).
     /// - `streaming_pot` — streaming PoToken to append as `pot=` to the CDN URL after resolution.
     /// - `sig_ts` — `signatureTimestamp` from the player JS, required by WEB/TVHTML5 clients.
     ///
     /// Clients that `require_po_token` are skipped when `po_token` is `None`.
     /// Clients that `login_required` are skipped when not authenticated.
+    /// Clients in `hints.failed_clients` are skipped.
     ///
     /// # Errors
     ///
@@ -539,6 +633,7 @@ impl YouTube {
         &self,
         video_id: &str,
         hints: &ContentHints,
+        pref: &FormatPreference,
         po_token: Option<&str>,
         streaming_pot: Option<&str>,
         sig_ts: Option<u32>,
@@ -551,6 +646,10 @@ impl YouTube {
         };
 
         for client in &clients {
+            if hints.failed_clients.contains(&client.client_name) {
+                tracing::debug!(client = client.client_name, "skipping: failed before");
+                continue;
+            }
             if client.require_po_token && po_token.is_none() {
                 tracing::debug!(client = client.client_name, "skipping: requires PoToken");
                 continue;
@@ -587,23 +686,23 @@ impl YouTube {
                     tracing::debug!(client = client.client_name, audio_only, direct, cipher, "player OK");
 
                     // Prefer direct URL (no sig/nsig needed — VISIONOS/VR path).
-                    if let Some(fmt) = sd.and_then(|s| s.best_audio_format()) {
-                        tracing::debug!(client = client.client_name, bitrate = fmt.bitrate, mime = fmt.mime_type.as_deref().unwrap_or("?"), "direct audio format");
+                    if let Some(fmt) = sd.and_then(|s| s.best_audio_format(pref)) {
+                        tracing::debug!(client = client.client_name, itag = fmt.itag, bitrate = fmt.bitrate, mime = fmt.mime_type.as_deref().unwrap_or("?"), "direct audio format");
                         let url = fmt.url.clone().ok_or(Error::NoAudioFormat {
                             video_id: video_id.to_owned(),
                         })?;
-                        let spot = client.use_web_po_tokens.then(|| streaming_pot).flatten().map(str::to_owned);
-                        return Ok(AudioStream { data: url, is_cipher: false, streaming_pot: spot });
+                        let spot = client.use_web_po_tokens.then_some(streaming_pot).flatten().map(str::to_owned);
+                        return Ok(AudioStream::new(url, false, spot, fmt, &resp, client));
                     }
 
                     // Cipher URL (WEB clients — sig decryption + nsig needed by caller).
-                    if let Some(fmt) = sd.and_then(|s| s.best_cipher_audio_format()) {
-                        tracing::debug!(client = client.client_name, bitrate = fmt.bitrate, "cipher audio format");
+                    if let Some(fmt) = sd.and_then(|s| s.best_cipher_audio_format(pref)) {
+                        tracing::debug!(client = client.client_name, itag = fmt.itag, bitrate = fmt.bitrate, "cipher audio format");
                         let cipher_str = fmt.signature_cipher.clone().ok_or(Error::NoAudioFormat {
                             video_id: video_id.to_owned(),
                         })?;
-                        let spot = client.use_web_po_tokens.then(|| streaming_pot).flatten().map(str::to_owned);
-                        return Ok(AudioStream { data: cipher_str, is_cipher: true, streaming_pot: spot });
+                        let spot = client.use_web_po_tokens.then_some(streaming_pot).flatten().map(str::to_owned);
+                        return Ok(AudioStream::new(cipher_str, true, spot, fmt, &resp, client));
                     }
 
                     last_err = Error::NoAudioFormat { video_id: video_id.to_owned() };
@@ -624,7 +723,8 @@ impl YouTube {
     ///
     /// Returns [`Error::AllClientsFailed`], [`Error::NotPlayable`], or [`Error::NoAudioFormat`].
     pub async fn audio_url(&self, video_id: &str) -> Result<String> {
-        let stream = self.audio_stream(video_id, &ContentHints::default(), None, None, None).await?;
+        let stream = self
+            .audio_stream(video_id, &ContentHints::default(), &FormatPreference::default(), None, None, None).await?;
         if stream.is_cipher {
             Err(Error::NoAudioFormat { video_id: video_id.to_owned() })
         } else {
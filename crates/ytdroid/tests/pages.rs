@@ -0,0 +1,223 @@
+//! Snapshot tests for every page parser, driven by recorded Innertube responses.
+//!
+//! Each test calls the public [`YouTube`] API with a [`ReplayTransport`] serving
+//! the JSON files under `tests/fixtures/` (named by
+//! [`ytdroid::fixtures::fixture_key`]) and compares the parsed page's `Debug`
+//! output with `tests/snapshots/<name>.snap`.
+//!
+//! - `YTDROID_RECORD=1` re-records the fixtures from the live API (set
+//!   `YTDROID_COOKIE` to a logged-in `Cookie:` header for the library tests).
+//! - `YTDROID_UPDATE_SNAPSHOTS=1` rewrites the snapshots after an intended
+//!   parser change.
+//!
+//! [`ReplayTransport`]: ytdroid::fixtures::ReplayTransport
+
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+
+use ytdroid::filters::browse_id;
+use ytdroid::{Locale, SearchFilter, SearchResult, YouTube};
+
+fn test_dir(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join(name)
+}
+
+fn client() -> YouTube {
+    let cookie = std::env::var("YTDROID_COOKIE").unwrap_or_else(|_| "SAPISID=fixture".into());
+    let yt = YouTube::new(Some(&cookie), Locale::default()).expect("client");
+    if std::env::var_os("YTDROID_RECORD").is_some() {
+        yt.with_recording(test_dir("fixtures"))
+    } else {
+        yt.with_replay(test_dir("fixtures"))
+    }
+}
+
+fn assert_snapshot(name: &str, value: &impl Debug) {
+    let path = test_dir("snapshots").join(format!("{name}.snap"));
+    let actual = format!("{value:#?}\n");
+    if std::env::var_os("YTDROID_UPDATE_SNAPSHOTS").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&path).unwrap_or_else(|_| {
+        panic!("missing snapshot {}; run with YTDROID_UPDATE_SNAPSHOTS=1", path.display())
+    });
+    assert!(
+        expected == actual,
+        "snapshot {name} changed; run with YTDROID_UPDATE_SNAPSHOTS=1 if intended\n\
+         --- expected\n{expected}\n+++ actual\n{actual}"
+    );
+}
+
+#[tokio::test]
+async fn home() {
+    let page = client().home(None).await.unwrap();
+    assert!(!page.sections.is_empty());
+    assert_snapshot("home", &page);
+}
+
+#[tokio::test]
+async fn album() {
+    let page = client().album("MPREb_nlBWQROfvjo").await.unwrap();
+    assert!(!page.songs.is_empty());
+    assert_snapshot("album", &page);
+}
+
+#[tokio::test]
+async fn artist() {
+    let page = client().artist("UCq19-LqvG35A-30oyAiPiqA").await.unwrap();
+    assert!(!page.sections.is_empty());
+    assert_snapshot("artist", &page);
+}
+
+#[tokio::test]
+async fn artist_items() {
+    let page = client()
+        .artist_items("MPADUCq19-LqvG35A-30oyAiPiqA", "ggMIegYIARoCAQI%3D")
+        .await
+        .unwrap();
+    assert!(!page.items.is_empty());
+    assert_snapshot("artist_items", &page);
+}
+
+#[tokio::test]
+async fn playlist() {
+    let page = client()
+        .playlist(&browse_id::playlist("PL4fGSI1pDJn6O1LS0XSdF3RyO0Rq_LDeI"))
+        .await
+        .unwrap();
+    assert!(!page.songs.is_empty());
+    assert_snapshot("playlist", &page);
+}
+
+#[tokio::test]
+async fn playlist_continuation() {
+    let page = client()
+        .playlist_continuation("4qmFsgJbEiRWTFBMNGZHU0kxcERKbjZPMUxTMFhTZEYzUnlPMFJxX0xEZUkaM")
+        .await
+        .unwrap();
+    assert!(!page.songs.is_empty());
+    assert_snapshot("playlist_continuation", &page);
+}
+
+#[tokio::test]
+async fn search_summary() {
+    let SearchResult::Summary(page) = client().search("radiohead", None).await.unwrap() else {
+        panic!("expected a summary page");
+    };
+    assert!(!page.sections.is_empty());
+    assert_snapshot("search_summary", &page);
+}
+
+#[tokio::test]
+async fn search_filtered() {
+    let result = client()
+        .search("radiohead", Some(&SearchFilter::SONGS))
+        .await
+        .unwrap();
+    let SearchResult::Filtered(page) = result else {
+        panic!("expected a filtered page");
+    };
+    assert!(!page.items.is_empty());
+    assert_snapshot("search_songs", &page);
+}
+
+#[tokio::test]
+async fn search_continuation() {
+    let page = client()
+        .search_continuation("EpIDEglyYWRpb2hlYWQaiANFZ1dLQVFJSUFVZ")
+        .await
+        .unwrap();
+    assert!(!page.items.is_empty());
+    assert_snapshot("search_continuation", &page);
+}
+
+#[tokio::test]
+async fn next() {
+    let page = client()
+        .next(Some("ZVtATUR-sVY"), Some("RDAMVMZVtATUR-sVY"), None, None, None)
+        .await
+        .unwrap();
+    assert!(!page.items.is_empty());
+    assert_snapshot("next", &page);
+}
+
+#[tokio::test]
+async fn next_continuation() {
+    let page = client()
+        .next_continuation("CBkSExILWlZ0QVRVUi1zVlnAAQHIAQE")
+        .await
+        .unwrap();
+    assert!(!page.items.is_empty());
+    assert_snapshot("next_continuation", &page);
+}
+
+#[tokio::test]
+async fn related() {
+    let page = client().related("MPTRt_wrKjTn9hmry").await.unwrap();
+    assert!(!page.playlists.is_empty());
+    assert_snapshot("related", &page);
+}
+
+#[tokio::test]
+async fn library() {
+    let page = client()
+        .library(browse_id::LIBRARY_PLAYLISTS, None)
+        .await
+        .unwrap();
+    assert!(!page.items.is_empty());
+    assert_snapshot("library_playlists", &page);
+}
+
+#[tokio::test]
+async fn library_continuation() {
+    let page = client()
+        .library_continuation("4qmFsgJBEhNGRW11c2ljX2xpa2VkX3ZpZGVvcxoqZWdad1lXZGxPa")
+        .await
+        .unwrap();
+    assert!(!page.items.is_empty());
+    assert_snapshot("library_songs_continuation", &page);
+}
+
+#[tokio::test]
+async fn history() {
+    let page = client().history().await.unwrap();
+    assert!(!page.items.is_empty());
+    assert_snapshot("history", &page);
+}
+
+#[tokio::test]
+async fn explore() {
+    let page = client().explore().await.unwrap();
+    assert!(!page.sections.is_empty());
+    assert_snapshot("explore", &page);
+}
+
+#[tokio::test]
+async fn new_releases() {
+    let page = client().new_releases().await.unwrap();
+    assert!(!page.sections.is_empty());
+    assert_snapshot("new_releases", &page);
+}
+
+#[tokio::test]
+async fn moods_and_genres() {
+    let page = client().moods_and_genres().await.unwrap();
+    assert!(!page.sections.is_empty());
+    assert_snapshot("moods_and_genres", &page);
+}
+
+#[tokio::test]
+async fn charts() {
+    let page = client().charts(None).await.unwrap();
+    assert!(!page.sections.is_empty());
+    assert_snapshot("charts", &page);
+}
+
+#[tokio::test]
+async fn podcast_discover() {
+    let page = client().podcast_discover().await.unwrap();
+    assert!(!page.sections.is_empty());
+    assert_snapshot("podcast_discover", &page);
+}
@@ -6,12 +6,14 @@ use crate::components::{LoadingBar, PlayerBar};
 mod album;
 mod artist;
 mod data;
+mod downloads;
 mod home;
 mod playlist;
 
 pub use data::*;
 use album::Album;
 use artist::Artist;
+use downloads::Downloads;
 use home::Home;
 pub use playlist::Playlist;
 
@@ -27,6 +29,8 @@ pub enum Route {
         Artist { id: String },
         #[route("/album/:id")]
         Album { id: String },
+        #[route("/downloads")]
+        Downloads,
 }
 
 #[derive(Clone)]
@@ -1,6 +1,10 @@
+use freya::icons::lucide::download;
 use freya::prelude::*;
+use freya::radio::use_radio;
 use freya::router::RouterContext;
 
+use crate::app::{Data, DataChannel, DownloadCommand};
+
 /// Stub page for `/album/:id`.
 #[derive(Clone, PartialEq)]
 pub struct Album {
@@ -10,6 +14,9 @@ pub struct Album {
 impl Component for Album {
     fn render(&self) -> impl IntoElement {
         let router = RouterContext::get();
+        let downloads_radio = use_radio::<Data, DataChannel>(DataChannel::Downloads);
+        let download_cmd = downloads_radio.read().download_cmd.clone();
+        let id = self.id.clone();
 
         rect()
             .vertical()
@@ -43,5 +50,33 @@ impl Component for Album {
                     .color(Color::WHITE)
                     .font_size(20.),
             )
+            .child(
+                rect()
+                    .horizontal()
+                    .spacing(8.)
+                    .cross_align(Alignment::Center)
+                    .padding(Gaps::new_symmetric(8., 12.))
+                    .corner_radius(8.)
+                    .background(Color::WHITE.with_a(30))
+                    .on_press(move |_| {
+                        if let Some(ref tx) = download_cmd {
+                            tx.try_send(DownloadCommand::Album(id.clone())).ok();
+                        }
+                    })
+                    .on_pointer_enter(|_| Cursor::set(CursorIcon::Pointer))
+                    .on_pointer_leave(|_| Cursor::set(CursorIcon::Default))
+                    .child(
+                        SvgViewer::new(download())
+                            .color(Color::WHITE)
+                            .width(Size::px(16.))
+                            .height(Size::px(16.)),
+                    )
+                    .child(
+                        label()
+                            .text("Descargar álbum")
+                            .color(Color::WHITE)
+                            .font_size(14.),
+                    ),
+            )
     }
 }
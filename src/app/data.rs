@@ -1,10 +1,15 @@
 use freya::radio::RadioChannel;
-use tokio::sync::mpsc;
+use serde_json::{Value, json};
+use tokio::sync::{mpsc, watch};
 use ytdroid::models::{PlaylistItem, SongItem};
+use ytdroid::offline::JobStatus;
 use ytdroid::pages::home::HomePage;
 use ytdroid::YouTube;
 
-use crate::audio::AudioCommand;
+use crate::audio::{
+    AudioCommand, AudioQuality, DEFAULT_CACHE_LIMIT, EqSettings, NormalizationMode, OutputDevice,
+    RepeatMode, SleepMode,
+};
 
 /// A song entry in the playback queue.
 #[derive(Debug, Clone, Default, PartialEq)]
@@ -14,6 +19,47 @@ pub struct QueueSong {
     pub artist: String,
     pub album: String,
     pub thumbnail_url: String,
+    /// Browse ID of the show, for podcast episodes.
+    pub podcast: Option<String>,
+}
+
+impl QueueSong {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "video_id": self.video_id,
+            "title": self.title,
+            "artist": self.artist,
+            "album": self.album,
+            "thumbnail_url": self.thumbnail_url,
+            "podcast": self.podcast,
+        })
+    }
+
+    /// Parse what [`QueueSong::to_json`] wrote; `None` without a video ID.
+    pub fn from_json(v: &Value) -> Option<Self> {
+        let text = |key: &str| v[key].as_str().unwrap_or_default().to_owned();
+        Some(Self {
+            video_id: v["video_id"].as_str()?.to_owned(),
+            title: text("title"),
+            artist: text("artist"),
+            album: text("album"),
+            thumbnail_url: text("thumbnail_url"),
+            podcast: v["podcast"].as_str().map(str::to_owned),
+        })
+    }
+}
+
+impl From<&SongItem> for QueueSong {
+    fn from(song: &SongItem) -> Self {
+        Self {
+            video_id: song.id.clone(),
+            title: song.title.clone(),
+            artist: song.artists.first().map(|a| a.name.clone()).unwrap_or_default(),
+            album: song.album.as_ref().map(|a| a.name.clone()).unwrap_or_default(),
+            thumbnail_url: song.thumbnail.clone().unwrap_or_default(),
+            podcast: None,
+        }
+    }
 }
 
 /// Commands for the background data-loading task.
@@ -29,9 +75,36 @@ pub enum NavCommand {
     ClearPending,
 }
 
+/// Commands for the download task.
+#[derive(Debug)]
+pub enum DownloadCommand {
+    Song(QueueSong),
+    /// Download every song of the playlist with this browse ID.
+    Playlist(String),
+    /// Download every song of the album with this browse ID.
+    Album(String),
+    /// Pause the download of a video ID, keeping what was saved so far.
+    Pause(String),
+    Resume(String),
+    /// Cancel a download or delete a downloaded song.
+    Remove(String),
+}
+
+/// A song in the downloads view.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DownloadItem {
+    pub song: QueueSong,
+    pub status: JobStatus,
+    /// Bytes saved so far.
+    pub done: u64,
+    pub total: Option<u64>,
+}
+
 #[derive(Clone)]
 pub struct PlaylistViewData {
     pub playlist: PlaylistItem,
+    /// Browse ID of the show when the page is a podcast; its songs are episodes.
+    pub podcast: Option<String>,
     pub songs: Vec<SongItem>,
     pub suggestions: Vec<SongItem>,
     pub related_playlists: Vec<PlaylistItem>,
@@ -40,6 +113,11 @@ pub struct PlaylistViewData {
 #[derive(Clone)]
 pub struct PlayerState {
     pub is_playing: bool,
+    /// Playback is stalled waiting for the stream to download.
+    pub is_buffering: bool,
+    /// Where the stretch of the current stream downloaded without a gap from
+    /// the playback position ends, as a share of the stream in `0.0..=1.0`.
+    pub buffered_until: f32,
     pub current_secs: f32,
     pub total_secs: f32,
     pub title: String,
@@ -47,15 +125,44 @@ pub struct PlayerState {
     pub album: String,
     pub year: Option<String>,
     pub thumbnail_url: String,
+    /// Show of the current episode; `None` for songs.
+    pub podcast: Option<String>,
     pub volume: f32,
+    /// Crossfade between tracks in seconds; `0` disables it.
+    pub crossfade_secs: f32,
+    pub normalization: NormalizationMode,
+    /// Stream quality chosen for playback.
+    pub quality: AudioQuality,
+    /// Playback speed of the current track, pitch preserved.
+    pub speed: f32,
+    /// Equalizer of the current output device.
+    pub equalizer: EqSettings,
+    /// Output devices found on the last refresh.
+    pub outputs: Vec<OutputDevice>,
+    /// ID of the chosen output device; `None` follows the system default.
+    pub output: Option<String>,
+    /// Queue in its original order, whatever the shuffle state.
     pub queue: Vec<QueueSong>,
     pub queue_index: usize,
+    pub shuffle: bool,
+    pub repeat: RepeatMode,
+    /// Continue with radio tracks when the queue runs out.
+    pub autoplay: bool,
+    /// Size limit of the audio cache in bytes; `0` disables it.
+    pub cache_limit: u64,
+    /// Running sleep timer, if any.
+    pub sleep_timer: Option<SleepMode>,
+    /// Seconds until the sleep timer pauses playback; `None` while that
+    /// depends on tracks not playing yet.
+    pub sleep_remaining: Option<f32>,
 }
 
 impl Default for PlayerState {
     fn default() -> Self {
         Self {
             is_playing: false,
+            is_buffering: false,
+            buffered_until: 0.0,
             current_secs: 0.0,
             total_secs: 0.0,
             title: String::new(),
@@ -63,9 +170,23 @@ impl Default for PlayerState {
             album: String::new(),
             year: None,
             thumbnail_url: String::new(),
+            podcast: None,
             volume: 1.0,
+            crossfade_secs: 0.0,
+            normalization: NormalizationMode::default(),
+            quality: AudioQuality::default(),
+            speed: 1.0,
+            equalizer: EqSettings::default(),
+            outputs: Vec::new(),
+            output: None,
             queue: Vec::new(),
             queue_index: 0,
+            shuffle: false,
+            repeat: RepeatMode::default(),
+            autoplay: true,
+            cache_limit: DEFAULT_CACHE_LIMIT,
+            sleep_timer: None,
+            sleep_remaining: None,
         }
     }
 }
@@ -78,12 +199,17 @@ pub struct Data {
     pub feed: HomePage,
     pub player: PlayerState,
     pub audio_cmd: Option<mpsc::Sender<AudioCommand>>,
+    /// Ticks each time the audio engine has written the playback position.
+    pub progress_tick: Option<watch::Sender<()>>,
     pub nav_cmd: Option<mpsc::Sender<NavCommand>>,
     pub playlist_view: Option<PlaylistViewData>,
     /// True while a playlist fetch is in progress.
     pub is_loading: bool,
     /// Browse ID of the playlist navigation that's pending data.
     pub pending_playlist_id: Option<String>,
+    pub download_cmd: Option<mpsc::Sender<DownloadCommand>>,
+    /// Queued, running and finished downloads, in the order they were added.
+    pub downloads: Vec<DownloadItem>,
 }
 
 #[derive(Default, PartialEq, Eq, Clone, Debug, Copy, Hash, PartialOrd, Ord)]
@@ -94,6 +220,8 @@ pub enum DataChannel {
     Player,
     /// Owns `nav_cmd`, `playlist_view`, `is_loading`, `pending_playlist_id`.
     Navigation,
+    /// Owns `download_cmd` and `downloads`.
+    Downloads,
 }
 
 impl RadioChannel<Data> for DataChannel {}
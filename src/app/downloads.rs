@@ -0,0 +1,198 @@
+use std::str::FromStr;
+
+use bytes::Bytes;
+use freya::icons::lucide::{pause, play, trash_2};
+use freya::prelude::*;
+use freya::radio::use_radio;
+use tokio::sync::mpsc::Sender;
+use ytdroid::offline::JobStatus;
+
+use crate::app::{Data, DataChannel, DownloadCommand, DownloadItem};
+use crate::components::TopBar;
+
+/// Page component for `/downloads`: songs saved or being saved for offline
+/// playback.
+#[derive(PartialEq)]
+pub struct Downloads;
+
+impl Component for Downloads {
+    fn render(&self) -> impl IntoElement {
+        let downloads_radio = use_radio::<Data, DataChannel>(DataChannel::Downloads);
+        let state = downloads_radio.read();
+        let items = state.downloads.clone();
+        let download_cmd = state.download_cmd.clone();
+        drop(state);
+
+        rect()
+            .vertical()
+            .expanded()
+            .content(Content::Flex)
+            .child(TopBar)
+            .child(
+                rect().width(Size::Fill).height(Size::flex(1.0)).child(
+                    ScrollView::new()
+                        .expanded()
+                        .direction(Direction::Vertical)
+                        .child(
+                            rect()
+                                .vertical()
+                                .padding(Gaps::new_all(24.))
+                                .width(Size::Fill)
+                                .spacing(2.)
+                                .child(
+                                    label()
+                                        .text("Descargas")
+                                        .font_weight(FontWeight::BOLD)
+                                        .font_size(20.)
+                                        .color(Color::WHITE),
+                                )
+                                .child(rect().height(Size::px(12.)))
+                                .maybe_child(items.is_empty().then_some(
+                                    label()
+                                        .text("No hay descargas")
+                                        .color(Color::WHITE.with_a(180)),
+                                ))
+                                .children(
+                                    items
+                                        .into_iter()
+                                        .map(|item| download_row(item, download_cmd.clone()))
+                                        .map(IntoElement::into_element),
+                                ),
+                        ),
+                ),
+            )
+    }
+}
+
+fn status_label(item: &DownloadItem) -> String {
+    let percent = item
+        .total
+        .filter(|&t| t > 0)
+        .map(|t| item.done * 100 / t);
+    match (&item.status, percent) {
+        (JobStatus::Queued, _) => "En cola".into(),
+        (JobStatus::Running, Some(p)) => format!("Descargando · {p} %"),
+        (JobStatus::Running, None) => "Descargando".into(),
+        (JobStatus::Paused, Some(p)) => format!("En pausa · {p} %"),
+        (JobStatus::Paused, None) => "En pausa".into(),
+        (JobStatus::Done, _) => {
+            format!("Descargada · {:.1} MB", item.done as f64 / 1_000_000.)
+        }
+        (JobStatus::Failed(e), _) => format!("Error: {e}"),
+    }
+}
+
+fn action_btn(icon: Bytes, on_press: impl Into<EventHandler<Event<PressEventData>>>) -> Rect {
+    let handler = on_press.into();
+    rect()
+        .center()
+        .padding(Gaps::new_all(8.))
+        .rounded_full()
+        .on_press(move |e: Event<PressEventData>| handler.call(e))
+        .on_pointer_enter(|_| Cursor::set(CursorIcon::Pointer))
+        .on_pointer_leave(|_| Cursor::set(CursorIcon::Default))
+        .child(
+            SvgViewer::new(icon)
+                .color(Color::WHITE)
+                .width(Size::px(16.))
+                .height(Size::px(16.)),
+        )
+}
+
+fn download_row(item: DownloadItem, download_cmd: Option<Sender<DownloadCommand>>) -> Rect {
+    let thumbnail_url = Url::from_str(&item.song.thumbnail_url).ok();
+    let status = status_label(&item);
+    let progress = match item.total {
+        Some(total) if total > 0 && item.status != JobStatus::Done => {
+            Some(item.done as f32 / total as f32 * 100.)
+        }
+        _ => None,
+    };
+    let video_id = item.song.video_id.clone();
+    let paused = matches!(item.status, JobStatus::Paused | JobStatus::Failed(_));
+
+    let send = move |cmd: DownloadCommand| {
+        if let Some(ref tx) = download_cmd {
+            tx.try_send(cmd).ok();
+        }
+    };
+
+    rect()
+        .horizontal()
+        .spacing(12.)
+        .padding(Gaps::new_symmetric(6., 8.))
+        .width(Size::Fill)
+        .content(Content::Flex)
+        .cross_align(Alignment::Center)
+        .child(
+            rect()
+                .width(Size::px(40.))
+                .height(Size::px(40.))
+                .corner_radius(4.)
+                .overflow(Overflow::Clip)
+                .background(Color::from_hex("#333333").unwrap())
+                .maybe_child(thumbnail_url.map(|url| {
+                    ImageViewer::new(url)
+                        .expanded()
+                        .image_cover(ImageCover::Center)
+                })),
+        )
+        .child(
+            rect()
+                .vertical()
+                .width(Size::flex(1.))
+                .spacing(2.)
+                .child(
+                    label()
+                        .text(item.song.title.clone())
+                        .font_size(14.)
+                        .max_lines(1)
+                        .text_overflow(TextOverflow::Ellipsis)
+                        .color(Color::WHITE),
+                )
+                .child(
+                    label()
+                        .text(item.song.artist.clone())
+                        .font_size(12.)
+                        .max_lines(1)
+                        .text_overflow(TextOverflow::Ellipsis)
+                        .color(Color::from_hex("#B3B3B3").unwrap()),
+                )
+                .child(
+                    label()
+                        .text(status)
+                        .font_size(11.)
+                        .max_lines(1)
+                        .text_overflow(TextOverflow::Ellipsis)
+                        .color(Color::from_hex("#888888").unwrap()),
+                )
+                .maybe_child(progress.map(|percent| {
+                    rect()
+                        .width(Size::Fill)
+                        .height(Size::px(3.))
+                        .corner_radius(2.)
+                        .background(Color::WHITE.with_a(40))
+                        .child(
+                            rect()
+                                .width(Size::percent(percent))
+                                .height(Size::Fill)
+                                .corner_radius(2.)
+                                .background(Color::WHITE),
+                        )
+                })),
+        )
+        .maybe_child((item.status != JobStatus::Done).then(|| {
+            let send = send.clone();
+            let video_id = video_id.clone();
+            action_btn(if paused { play() } else { pause() }, move |_| {
+                send(if paused {
+                    DownloadCommand::Resume(video_id.clone())
+                } else {
+                    DownloadCommand::Pause(video_id.clone())
+                });
+            })
+        }))
+        .child(action_btn(trash_2(), move |_| {
+            send(DownloadCommand::Remove(video_id.clone()));
+        }))
+}
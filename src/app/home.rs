@@ -1,7 +1,9 @@
+use freya::icons::lucide::download;
 use freya::prelude::*;
 use freya::radio::use_radio;
+use freya::router::RouterContext;
 
-use crate::app::{Data, DataChannel};
+use crate::app::{Data, DataChannel, Route};
 use crate::components::Section;
 
 #[derive(PartialEq)]
@@ -11,6 +13,7 @@ impl Component for Home {
     fn render(&self) -> impl IntoElement {
         let feed_radio = use_radio::<Data, DataChannel>(DataChannel::Feed);
         let radio = feed_radio.read();
+        let router = RouterContext::get();
 
         rect()
             .vertical()
@@ -28,7 +31,28 @@ impl Component for Home {
                             .background(Color::GRAY)
                             .child(label().text(c.title.clone()))
                             .into_element()
-                    })),
+                    }))
+                    .child(
+                        rect()
+                            .horizontal()
+                            .spacing(6.)
+                            .cross_align(Alignment::Center)
+                            .padding((10., 15.))
+                            .corner_radius(8.)
+                            .background(Color::GRAY)
+                            .on_press(move |_| {
+                                router.push(Route::Downloads).ok();
+                            })
+                            .on_pointer_enter(|_| Cursor::set(CursorIcon::Pointer))
+                            .on_pointer_leave(|_| Cursor::set(CursorIcon::Default))
+                            .child(
+                                SvgViewer::new(download())
+                                    .color(Color::WHITE)
+                                    .width(Size::px(14.))
+                                    .height(Size::px(14.)),
+                            )
+                            .child(label().text("Descargas")),
+                    ),
             )
             .child(
                 rect()
@@ -1,11 +1,11 @@
 use std::str::FromStr;
 
 use freya::animation::*;
-use freya::icons::lucide::play;
+use freya::icons::lucide::{download, play};
 use freya::prelude::*;
 use freya::radio::use_radio;
 
-use crate::app::{Data, DataChannel, NavCommand, PlaylistViewData};
+use crate::app::{Data, DataChannel, DownloadCommand, NavCommand, PlaylistViewData};
 use crate::audio::{AudioCommand, AudioQuality};
 use crate::components::{PlaylistSongRow, SongInfo, TopBar};
 
@@ -70,7 +70,12 @@ impl Component for Playlist {
         let playlist_view = state.playlist_view.clone();
         drop(state);
 
-        let audio_cmd = audio_radio.read().audio_cmd.clone();
+        // Playing starts at the quality chosen in the player menu.
+        let audio_cmd = {
+            let data = audio_radio.read();
+            data.audio_cmd.clone().map(|tx| (tx, data.player.quality))
+        };
+        let download_cmd = audio_radio.read().download_cmd.clone();
 
         if *last_requested.read() != self.id {
             last_requested.set(self.id.clone());
@@ -119,7 +124,12 @@ impl Component for Playlist {
                                 .padding(Gaps::new_all(24.))
                                 .width(Size::Fill)
                                 .center()
-                                .child(render_content(pv, audio_cmd, max_width as f32)),
+                                .child(render_content(
+                                    pv,
+                                    audio_cmd,
+                                    download_cmd,
+                                    max_width as f32,
+                                )),
                         ),
                 ),
             )
@@ -128,7 +138,8 @@ impl Component for Playlist {
 
 fn render_content(
     pv: PlaylistViewData,
-    audio_cmd: Option<tokio::sync::mpsc::Sender<AudioCommand>>,
+    audio_cmd: Option<(tokio::sync::mpsc::Sender<AudioCommand>, AudioQuality)>,
+    download_cmd: Option<tokio::sync::mpsc::Sender<DownloadCommand>>,
     max_width: f32,
 ) -> Rect {
     let thumbnail_url = pv
@@ -136,6 +147,7 @@ fn render_content(
         .thumbnail
         .as_deref()
         .and_then(|u| Url::from_str(u).ok());
+    let browse_id = pv.playlist.id.clone();
     let title = pv.playlist.title.clone();
     let author = pv.playlist.author.clone().unwrap_or_default();
     let song_count = pv.playlist.song_count_text.clone().unwrap_or_default();
@@ -169,6 +181,7 @@ fn render_content(
             song_count,
             songs.clone(),
             audio_cmd.clone(),
+            download_cmd.map(|tx| (tx, browse_id)),
         ))
         // ── Songs + extras ────────────────────────────────────────────────────
         .child(
@@ -243,7 +256,8 @@ fn render_header(
     author: String,
     song_count: String,
     songs: Vec<ytdroid::models::SongItem>,
-    audio_cmd: Option<tokio::sync::mpsc::Sender<AudioCommand>>,
+    audio_cmd: Option<(tokio::sync::mpsc::Sender<AudioCommand>, AudioQuality)>,
+    download: Option<(tokio::sync::mpsc::Sender<DownloadCommand>, String)>,
 ) -> Rect {
     // Wide: column with thumbnail on top, info + play centred below.
     // Narrow: row with thumbnail on the left, info + play on the right.
@@ -298,26 +312,49 @@ fn render_header(
         ))
         .child(
             rect()
-                .rounded_full()
-                .padding(Gaps::new_all(12.))
-                .background(Color::WHITE)
-                .on_press({
-                    let audio_cmd = audio_cmd.clone();
-                    move |_| {
-                        if let Some(first) = songs.first() {
-                            send_play(&audio_cmd, first);
-                        }
-                    }
-                })
-                .on_pointer_enter(|_| Cursor::set(CursorIcon::Pointer))
-                .on_pointer_leave(|_| Cursor::set(CursorIcon::Default))
+                .horizontal()
+                .spacing(12.)
+                .cross_align(Alignment::Center)
                 .child(
-                    SvgViewer::new(play())
-                        .fill(Color::BLACK)
-                        .color(Color::BLACK)
-                        .width(Size::px(20.))
-                        .height(Size::px(20.)),
-                ),
+                    rect()
+                        .rounded_full()
+                        .padding(Gaps::new_all(12.))
+                        .background(Color::WHITE)
+                        .on_press({
+                            let audio_cmd = audio_cmd.clone();
+                            move |_| {
+                                if let Some(first) = songs.first() {
+                                    send_play(&audio_cmd, first);
+                                }
+                            }
+                        })
+                        .on_pointer_enter(|_| Cursor::set(CursorIcon::Pointer))
+                        .on_pointer_leave(|_| Cursor::set(CursorIcon::Default))
+                        .child(
+                            SvgViewer::new(play())
+                                .fill(Color::BLACK)
+                                .color(Color::BLACK)
+                                .width(Size::px(20.))
+                                .height(Size::px(20.)),
+                        ),
+                )
+                .maybe_child(download.map(|(tx, browse_id)| {
+                    rect()
+                        .rounded_full()
+                        .padding(Gaps::new_all(12.))
+                        .background(Color::WHITE.with_a(30))
+                        .on_press(move |_| {
+                            tx.try_send(DownloadCommand::Playlist(browse_id.clone())).ok();
+                        })
+                        .on_pointer_enter(|_| Cursor::set(CursorIcon::Pointer))
+                        .on_pointer_leave(|_| Cursor::set(CursorIcon::Default))
+                        .child(
+                            SvgViewer::new(download())
+                                .color(Color::WHITE)
+                                .width(Size::px(20.))
+                                .height(Size::px(20.)),
+                        )
+                })),
         );
 
     if is_wide {
@@ -344,13 +381,13 @@ fn render_header(
 }
 
 fn send_play(
-    audio_cmd: &Option<tokio::sync::mpsc::Sender<AudioCommand>>,
+    audio_cmd: &Option<(tokio::sync::mpsc::Sender<AudioCommand>, AudioQuality)>,
     song: &ytdroid::models::SongItem,
 ) {
-    if let Some(tx) = audio_cmd {
+    if let Some((tx, quality)) = audio_cmd {
         tx.try_send(AudioCommand::Play {
             video_id: song.id.clone(),
-            quality: AudioQuality::Medium,
+            quality: *quality,
             title: song.title.clone(),
             artist: song
                 .artists
@@ -363,6 +400,7 @@ fn send_play(
                 .map(|a| a.name.clone())
                 .unwrap_or_default(),
             thumbnail_url: song.thumbnail.clone().unwrap_or_default(),
+            podcast: None,
         })
         .ok();
     }
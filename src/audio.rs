@@ -1,8 +1,30 @@
+mod autoplay;
+mod cache;
 mod engine;
+mod eq;
+mod loudness;
 pub(crate) mod nsig;
+mod order;
+mod output;
+mod session;
+mod sleep;
+mod stream;
+mod stretch;
 mod youtube;
 
+pub use cache::{CachedAudio, DEFAULT_LIMIT as DEFAULT_CACHE_LIMIT};
 pub use engine::{AudioCommand, AudioEngine, run_audio_engine};
+pub use eq::{EqPreset, EqSettings};
+pub use loudness::NormalizationMode;
+pub use order::RepeatMode;
+pub use output::OutputDevice;
+pub use session::Session;
+pub use sleep::SleepMode;
+pub use stream::downloader as stream_downloader;
+pub use stretch::{MAX_SPEED, MIN_SPEED};
+pub use youtube::{ResolvedAudio, resolve_audio};
+
+use ytdroid::{AudioCodec, FormatPreference, QualityBucket};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum AudioQuality {
@@ -11,3 +33,37 @@ pub enum AudioQuality {
     Medium,
     High,
 }
+
+impl AudioQuality {
+    /// Stream selection for this quality level.
+    ///
+    /// All levels prefer Opus, which sounds better than AAC at the same bitrate.
+    /// `Low` stays under 64 kbps for metered links, `Medium` is the usual
+    /// 160 kbps desktop stream and `High` takes whatever is best.
+    pub fn format_preference(self) -> FormatPreference {
+        let pref = FormatPreference::default().codec(AudioCodec::Opus);
+        match self {
+            Self::Low => pref.quality(QualityBucket::Low).max_bitrate(64_000),
+            Self::Medium => pref.quality(QualityBucket::Medium).max_bitrate(160_000),
+            Self::High => pref.quality(QualityBucket::High),
+        }
+    }
+
+    /// Name stored in prefs.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Medium => "medium",
+            Self::High => "high",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "low" => Some(Self::Low),
+            "medium" => Some(Self::Medium),
+            "high" => Some(Self::High),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,258 @@
+//! Endless playback: before the queue runs dry, more tracks are appended from
+//! the radio (`RDAMVM` playlist) of the track playing at that point, the way
+//! YouTube Music's autoplay continues.
+//!
+//! A radio is followed through its continuations for as long as it has any;
+//! only then is a new one seeded from the track playing at that time.
+//! Requests and their batches carry the generation of the [`Autoplay`] that
+//! made them, so a batch still on its way when the user picks something else
+//! to play is dropped instead of being appended to the new queue.
+
+use std::collections::{HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use ytdroid::YouTube;
+
+use crate::app::QueueSong;
+
+/// Fetch more tracks once this many queue entries or fewer are left to play.
+pub const LOW_WATER: usize = 2;
+
+/// Video IDs of recently played tracks a radio may not bring back.
+const RECENT_LEN: usize = 200;
+
+/// Wait before asking again after a request failed.
+const RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// What to ask YouTube for next.
+pub struct RadioRequest {
+    /// Video ID the radio is seeded from.
+    pub seed: String,
+    /// Continuation of the radio; `None` starts it.
+    pub token: Option<String>,
+    generation: u64,
+}
+
+/// Tracks returned by one radio request.
+pub struct RadioBatch {
+    /// Video ID the radio was seeded from.
+    pub seed: String,
+    pub songs: Vec<QueueSong>,
+    pub continuation: Option<String>,
+    generation: u64,
+}
+
+/// Why a radio request failed.
+pub struct RadioError {
+    pub message: String,
+    generation: u64,
+}
+
+#[derive(Default)]
+pub struct Autoplay {
+    pub enabled: bool,
+    /// Seed of the radio feeding the queue, with its continuation token;
+    /// `None` as token once the radio ran out.
+    radio: Option<(String, Option<String>)>,
+    /// A request is in flight.
+    pub fetching: bool,
+    /// Playback stopped at the end of the queue while waiting for a batch;
+    /// the first new track starts when it lands.
+    pub resume: bool,
+    /// No request before this instant, after a failed one.
+    retry_at: Option<Instant>,
+    recent: VecDeque<String>,
+    /// Bumped by [`Autoplay::reset`]; answers to older requests are ignored.
+    generation: u64,
+}
+
+impl Autoplay {
+    /// Remember `video_id` as played.
+    pub fn played(&mut self, video_id: &str) {
+        if self.recent.back().is_some_and(|id| id == video_id) {
+            return;
+        }
+        self.recent.push_back(video_id.to_owned());
+        if self.recent.len() > RECENT_LEN {
+            self.recent.pop_front();
+        }
+    }
+
+    /// Forget the radio after something else was picked to play, along with
+    /// any request still in flight.
+    pub fn reset(&mut self) {
+        self.radio = None;
+        self.resume = false;
+        self.fetching = false;
+        self.generation += 1;
+    }
+
+    /// Request for more tracks while `current` plays; `None` while one is in
+    /// flight or when the radio seeded from `current` has nothing left.
+    pub fn request(&self, current: &str) -> Option<RadioRequest> {
+        if self.fetching || self.retry_at.is_some_and(|at| Instant::now() < at) {
+            return None;
+        }
+        let (seed, token) = match &self.radio {
+            Some((seed, Some(token))) => (seed.clone(), Some(token.clone())),
+            Some((seed, None)) if seed == current => return None,
+            _ => (current.to_owned(), None),
+        };
+        Some(RadioRequest {
+            seed,
+            token,
+            generation: self.generation,
+        })
+    }
+
+    /// Back off after a request failed.
+    pub fn failed(&mut self, error: &RadioError) {
+        if error.generation != self.generation {
+            return;
+        }
+        self.fetching = false;
+        self.retry_at = Some(Instant::now() + RETRY_AFTER);
+    }
+
+    /// Take in a fetched batch, keeping only tracks neither in `queue` nor
+    /// played recently. A batch requested before the last reset gives none.
+    pub fn accept(&mut self, batch: RadioBatch, queue: &[QueueSong]) -> Vec<QueueSong> {
+        if batch.generation != self.generation {
+            return Vec::new();
+        }
+        self.fetching = false;
+        self.retry_at = None;
+        self.radio = Some((batch.seed, batch.continuation));
+
+        let mut seen: HashSet<String> = queue.iter().map(|s| s.video_id.clone()).collect();
+        seen.extend(self.recent.iter().cloned());
+        batch
+            .songs
+            .into_iter()
+            .filter(|s| seen.insert(s.video_id.clone()))
+            .collect()
+    }
+}
+
+/// Fetch the tracks `request` asks for.
+pub async fn fetch(yt: YouTube, request: RadioRequest) -> Result<RadioBatch, RadioError> {
+    let RadioRequest {
+        seed,
+        token,
+        generation,
+    } = request;
+    let error = |e: ytdroid::Error| RadioError {
+        message: e.to_string(),
+        generation,
+    };
+    let (items, continuation) = match &token {
+        None => {
+            let playlist_id = format!("RDAMVM{seed}");
+            let page = yt
+                .next(Some(&seed), Some(&playlist_id), None, None, None)
+                .await
+                .map_err(error)?;
+            (page.items, page.continuation)
+        }
+        Some(token) => {
+            let page = yt.next_continuation(token).await.map_err(error)?;
+            (page.items, page.continuation)
+        }
+    };
+    Ok(RadioBatch {
+        seed,
+        songs: items.iter().map(|i| QueueSong::from(&i.song)).collect(),
+        continuation,
+        generation,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(id: &str) -> QueueSong {
+        QueueSong {
+            video_id: id.to_owned(),
+            title: id.to_uppercase(),
+            artist: String::new(),
+            album: String::new(),
+            thumbnail_url: String::new(),
+            podcast: None,
+        }
+    }
+
+    /// The answer to `request`: tracks `ids`, then `continuation`.
+    fn batch(request: &RadioRequest, ids: &[&str], continuation: Option<&str>) -> RadioBatch {
+        RadioBatch {
+            seed: request.seed.clone(),
+            songs: ids.iter().map(|id| song(id)).collect(),
+            continuation: continuation.map(str::to_owned),
+            generation: request.generation,
+        }
+    }
+
+    fn ids(songs: &[QueueSong]) -> Vec<&str> {
+        songs.iter().map(|s| s.video_id.as_str()).collect()
+    }
+
+    #[test]
+    fn follows_the_radio_then_reseeds() {
+        let mut autoplay = Autoplay::default();
+        let request = autoplay.request("a").unwrap();
+        assert_eq!((request.seed.as_str(), request.token.as_deref()), ("a", None));
+
+        autoplay.fetching = true;
+        assert!(autoplay.request("a").is_none());
+        autoplay.accept(batch(&request, &["b"], Some("more")), &[]);
+        let request = autoplay.request("b").unwrap();
+        assert_eq!((request.seed.as_str(), request.token.as_deref()), ("a", Some("more")));
+
+        // The radio ran out: nothing more for its seed, a new one for others.
+        autoplay.accept(batch(&request, &["c"], None), &[]);
+        assert!(autoplay.request("a").is_none());
+        let request = autoplay.request("c").unwrap();
+        assert_eq!((request.seed.as_str(), request.token.as_deref()), ("c", None));
+    }
+
+    #[test]
+    fn skips_queued_played_and_repeated_tracks() {
+        let mut autoplay = Autoplay::default();
+        autoplay.played("x");
+        autoplay.played("y");
+        let request = autoplay.request("a").unwrap();
+        let queue = [song("a"), song("b")];
+        let added = autoplay.accept(batch(&request, &["a", "c", "x", "c", "d", "y"], None), &queue);
+        assert_eq!(ids(&added), ["c", "d"]);
+    }
+
+    #[test]
+    fn drops_batches_from_before_a_reset() {
+        let mut autoplay = Autoplay::default();
+        let stale = autoplay.request("old").unwrap();
+        autoplay.fetching = true;
+
+        // A new song is picked while the old radio is loading.
+        autoplay.reset();
+        assert!(!autoplay.fetching);
+        let request = autoplay.request("new").unwrap();
+        assert_eq!(request.seed, "new");
+        autoplay.fetching = true;
+
+        let late = batch(&stale, &["o1", "o2"], Some("old-more"));
+        assert!(autoplay.accept(late, &[song("new")]).is_empty());
+        assert!(autoplay.fetching);
+        // A stale failure does not back off the request in flight either.
+        autoplay.failed(&RadioError {
+            message: "timeout".into(),
+            generation: stale.generation,
+        });
+        assert!(autoplay.fetching && autoplay.retry_at.is_none());
+
+        let added = autoplay.accept(batch(&request, &["n1"], Some("new-more")), &[song("new")]);
+        assert_eq!(ids(&added), ["n1"]);
+        assert!(!autoplay.fetching);
+        let next = autoplay.request("n1").unwrap();
+        assert_eq!((next.seed.as_str(), next.token.as_deref()), ("new", Some("new-more")));
+    }
+}
@@ -0,0 +1,389 @@
+//! On-disk cache of fully downloaded audio streams.
+//!
+//! Entries are keyed by video ID and itag and live under
+//! `data_dir(["cache", "audio"])`, one file each, next to an `index.json`
+//! holding their format, size, SHA-1 and last use. Lookups happen before the
+//! player request: a cached stream skips the client fallback chain, PoToken,
+//! signature decryption and the download. The least recently used entries
+//! are evicted once the cache grows past its size limit, counting any files the
+//! index lost track of.
+//!
+//! All functions block on the file system; call them off the runtime.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde_json::{Map, Value, json};
+use tracing::{debug, warn};
+
+use crate::audio::AudioQuality;
+use crate::utils::data_dir;
+
+/// Size limit used until [`set_limit`] is called.
+pub const DEFAULT_LIMIT: u64 = 1024 * 1024 * 1024;
+
+const INDEX_FILE: &str = "index.json";
+
+/// Name prefix of entries still being written.
+const PARTIAL_PREFIX: &str = ".partial-";
+
+/// Partial files older than this were left behind by a crash.
+const PARTIAL_MAX_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+static LIMIT: AtomicU64 = AtomicU64::new(DEFAULT_LIMIT);
+
+/// Serializes read-modify-write cycles of the index.
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+/// A cached stream, as returned by [`lookup`].
+pub struct CachedAudio {
+    pub itag: u32,
+    /// The opened entry, positioned at its start.
+    pub file: File,
+    pub len: u64,
+    pub mime_type: Option<String>,
+    pub loudness_db: Option<f32>,
+}
+
+/// Format details stored with a new entry.
+pub struct CacheEntry {
+    pub video_id: String,
+    pub itag: u32,
+    pub mime_type: Option<String>,
+    pub bitrate: Option<u64>,
+    pub loudness_db: Option<f32>,
+}
+
+/// Limit the cache to `bytes`; `0` turns it off. Takes effect on the next
+/// insert.
+pub fn set_limit(bytes: u64) {
+    LIMIT.store(bytes, Ordering::Relaxed);
+}
+
+fn dir() -> PathBuf {
+    data_dir(&["cache", "audio"])
+}
+
+fn file_name(video_id: &str, itag: u32) -> String {
+    format!("{video_id}-{itag}")
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+fn lock() -> MutexGuard<'static, ()> {
+    INDEX_LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn load_index(dir: &Path) -> Map<String, Value> {
+    std::fs::read_to_string(dir.join(INDEX_FILE))
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+fn save_index(dir: &Path, index: &Map<String, Value>) {
+    if let Err(e) = std::fs::write(dir.join(INDEX_FILE), Value::Object(index.clone()).to_string()) {
+        warn!(error = %e, "failed to write audio cache index");
+    }
+}
+
+fn remove(dir: &Path, index: &mut Map<String, Value>, key: &str) {
+    index.remove(key);
+    std::fs::remove_file(dir.join(key)).ok();
+}
+
+/// Best cached stream of `video_id` for `quality`: the highest bitrate within
+/// the quality's ceiling. Entries whose file no longer matches its recorded
+/// size and SHA-1 are dropped.
+pub fn lookup(video_id: &str, quality: AudioQuality) -> Option<CachedAudio> {
+    if LIMIT.load(Ordering::Relaxed) == 0 {
+        return None;
+    }
+    let max_bitrate = quality.format_preference().max_bitrate.unwrap_or(u64::MAX);
+    lookup_in(&dir(), video_id, max_bitrate)
+}
+
+fn lookup_in(dir: &Path, video_id: &str, max_bitrate: u64) -> Option<CachedAudio> {
+    let (key, entry) = {
+        let _guard = lock();
+        load_index(dir)
+            .into_iter()
+            .filter(|(_, e)| e["video_id"].as_str() == Some(video_id))
+            .filter(|(_, e)| e["bitrate"].as_u64().is_none_or(|b| b <= max_bitrate))
+            .max_by_key(|(_, e)| e["bitrate"].as_u64().unwrap_or_default())?
+    };
+
+    // Hashing takes a while for long tracks; the index stays unlocked until
+    // the verdict is in. An open file survives its eviction in the meantime.
+    let file = File::open(dir.join(&key))
+        .ok()
+        .filter(|file| {
+            let len = file.metadata().map(|m| m.len()).ok();
+            len.is_some() && entry["size"].as_u64() == len
+        })
+        .and_then(|mut file| {
+            let (_, sha1) = copy_hashed(&mut file, &mut io::sink()).ok()?;
+            file.seek(SeekFrom::Start(0)).ok()?;
+            (entry["sha1"].as_str() == Some(sha1.as_str())).then_some(file)
+        });
+
+    let _guard = lock();
+    let mut index = load_index(dir);
+    // Leave the entry alone if it was replaced while we were hashing.
+    let current = index.get(&key).is_some_and(|e| e["sha1"] == entry["sha1"]);
+    let Some(file) = file else {
+        if current {
+            warn!(key, "cached audio is missing or corrupt, dropping it");
+            remove(dir, &mut index, &key);
+            save_index(dir, &index);
+        }
+        return None;
+    };
+
+    if current && let Some(e) = index.get_mut(&key) {
+        e["last_used"] = json!(now());
+        save_index(dir, &index);
+    }
+    debug!(key, "audio cache hit");
+    Some(CachedAudio {
+        itag: entry["itag"].as_u64().and_then(|i| u32::try_from(i).ok())?,
+        file,
+        len: entry["size"].as_u64()?,
+        mime_type: entry["mime_type"].as_str().map(str::to_owned),
+        loudness_db: entry["loudness_db"].as_f64().map(|db| db as f32),
+    })
+}
+
+/// Copy `reader` into `writer`; returns the byte count and the SHA-1 of the
+/// bytes, in hex.
+fn copy_hashed(reader: &mut impl Read, writer: &mut impl Write) -> io::Result<(u64, String)> {
+    let mut sha1 = sha1_smol::Sha1::new();
+    let mut buf = vec![0; 64 * 1024];
+    let mut len = 0;
+    loop {
+        match reader.read(&mut buf)? {
+            0 => return Ok((len, sha1.digest().to_string())),
+            n => {
+                sha1.update(&buf[..n]);
+                writer.write_all(&buf[..n])?;
+                len += n as u64;
+            }
+        }
+    }
+}
+
+/// Store a fully downloaded stream of `len` bytes, then evict the least
+/// recently used entries until the cache fits its limit again.
+pub fn insert(entry: CacheEntry, data: impl Read, len: u64) {
+    insert_in(&dir(), LIMIT.load(Ordering::Relaxed), entry, data, len);
+}
+
+fn insert_in(dir: &Path, limit: u64, entry: CacheEntry, mut data: impl Read, len: u64) {
+    if limit == 0 || len > limit {
+        return;
+    }
+    let key = file_name(&entry.video_id, entry.itag);
+
+    // Copy outside the lock, into a partial file that only becomes the entry
+    // once it is complete.
+    let written = std::fs::create_dir_all(dir)
+        .and_then(|()| tempfile::Builder::new().prefix(PARTIAL_PREFIX).tempfile_in(dir))
+        .and_then(|mut partial| {
+            let (copied, sha1) = copy_hashed(&mut data, &mut partial)?;
+            if copied != len {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("got {copied} of {len} bytes"),
+                ));
+            }
+            Ok((partial, sha1))
+        });
+    let (partial, sha1) = match written {
+        Ok(written) => written,
+        Err(e) => {
+            warn!(key, error = %e, "failed to write audio cache entry");
+            return;
+        }
+    };
+
+    let _guard = lock();
+    if let Err(e) = partial.persist(dir.join(&key)) {
+        warn!(key, error = %e, "failed to write audio cache entry");
+        return;
+    }
+    let mut index = load_index(dir);
+    index.insert(
+        key.clone(),
+        json!({
+            "video_id": entry.video_id,
+            "itag": entry.itag,
+            "mime_type": entry.mime_type,
+            "bitrate": entry.bitrate,
+            "loudness_db": entry.loudness_db,
+            "size": len,
+            "sha1": sha1,
+            "last_used": now(),
+        }),
+    );
+    let total = evict(dir, &mut index, limit, &key);
+    save_index(dir, &index);
+    debug!(key, size = len, total, "audio cached");
+}
+
+/// Evict the least recently used files other than `keep` until the directory
+/// fits `limit`; returns what it holds afterwards.
+///
+/// Sizes are taken from the directory, not the index: files the index lost
+/// track of (say, after a crash before it was written) count too, and go
+/// first. Index entries without a file are dropped.
+fn evict(dir: &Path, index: &mut Map<String, Value>, limit: u64, keep: &str) -> u64 {
+    let mut files = Vec::new();
+    for dir_entry in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+        let Ok(meta) = dir_entry.metadata() else {
+            continue;
+        };
+        let Ok(name) = dir_entry.file_name().into_string() else {
+            continue;
+        };
+        if !meta.is_file() || name == INDEX_FILE {
+            continue;
+        }
+        if name.starts_with(PARTIAL_PREFIX) {
+            // Being written by another insert, unless it is long abandoned.
+            let age = meta.modified().ok().and_then(|at| at.elapsed().ok());
+            if age.is_some_and(|age| age > PARTIAL_MAX_AGE) {
+                std::fs::remove_file(dir_entry.path()).ok();
+            }
+            continue;
+        }
+        let last_used = index.get(&name).map(|e| e["last_used"].as_u64().unwrap_or_default());
+        if last_used.is_none() {
+            debug!(key = name, "found audio missing from the cache index");
+        }
+        files.push((last_used, meta.len(), name));
+    }
+    index.retain(|key, _| files.iter().any(|(_, _, name)| name == key));
+
+    let mut total: u64 = files.iter().map(|(_, size, _)| size).sum();
+    // `None` (not indexed) sorts before any time of use.
+    files.sort_unstable();
+    for (_, size, name) in files.into_iter().filter(|(_, _, name)| name != keep) {
+        if total <= limit {
+            break;
+        }
+        debug!(key = name, size, "evicting cached audio");
+        remove(dir, index, &name);
+        total -= size;
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(video_id: &str, bitrate: u64) -> CacheEntry {
+        CacheEntry {
+            video_id: video_id.to_owned(),
+            itag: 251,
+            mime_type: Some("audio/webm".to_owned()),
+            bitrate: Some(bitrate),
+            loudness_db: None,
+        }
+    }
+
+    fn put(dir: &Path, limit: u64, video_id: &str, len: usize) {
+        let data = vec![video_id.as_bytes()[0]; len];
+        insert_in(dir, limit, entry(video_id, 128_000), data.as_slice(), len as u64);
+    }
+
+    fn touch(dir: &Path, video_id: &str, last_used: u64) {
+        let mut index = load_index(dir);
+        index[&file_name(video_id, 251)]["last_used"] = json!(last_used);
+        save_index(dir, &index);
+    }
+
+    fn cached(dir: &Path) -> Vec<String> {
+        let mut keys: Vec<_> = load_index(dir).keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn reads_back_what_was_stored() {
+        let dir = tempfile::tempdir().unwrap();
+        put(dir.path(), 1000, "a", 100);
+
+        let mut hit = lookup_in(dir.path(), "a", u64::MAX).unwrap();
+        let mut data = Vec::new();
+        hit.file.read_to_end(&mut data).unwrap();
+        assert_eq!((hit.itag, hit.len, data), (251, 100, vec![b'a'; 100]));
+        assert!(lookup_in(dir.path(), "a", 64_000).is_none());
+        assert!(lookup_in(dir.path(), "b", u64::MAX).is_none());
+    }
+
+    #[test]
+    fn drops_corrupt_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        put(dir.path(), 1000, "a", 100);
+        std::fs::write(dir.path().join(file_name("a", 251)), [b'x'; 100]).unwrap();
+
+        assert!(lookup_in(dir.path(), "a", u64::MAX).is_none());
+        assert!(cached(dir.path()).is_empty());
+        assert!(!dir.path().join(file_name("a", 251)).exists());
+    }
+
+    #[test]
+    fn skips_short_downloads() {
+        let dir = tempfile::tempdir().unwrap();
+        insert_in(dir.path(), 1000, entry("a", 1), [0; 10].as_slice(), 20);
+        assert!(cached(dir.path()).is_empty());
+        let files = std::fs::read_dir(dir.path()).unwrap().count();
+        assert_eq!(files, 0);
+    }
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let dir = tempfile::tempdir().unwrap();
+        put(dir.path(), 300, "a", 100);
+        put(dir.path(), 300, "b", 100);
+        put(dir.path(), 300, "c", 100);
+        touch(dir.path(), "a", 30);
+        touch(dir.path(), "b", 10);
+        touch(dir.path(), "c", 20);
+
+        put(dir.path(), 300, "d", 150);
+        assert_eq!(cached(dir.path()), ["a-251", "d-251"]);
+        assert!(!dir.path().join("b-251").exists());
+        assert!(!dir.path().join("c-251").exists());
+
+        // Larger than the whole cache: not stored, nothing evicted.
+        put(dir.path(), 300, "e", 301);
+        assert_eq!(cached(dir.path()), ["a-251", "d-251"]);
+    }
+
+    #[test]
+    fn counts_files_missing_from_the_index() {
+        let dir = tempfile::tempdir().unwrap();
+        put(dir.path(), 300, "a", 100);
+        std::fs::write(dir.path().join("lost-251"), [0; 150]).unwrap();
+        touch(dir.path(), "a", 0);
+
+        put(dir.path(), 300, "b", 100);
+        assert_eq!(cached(dir.path()), ["a-251", "b-251"]);
+        assert!(!dir.path().join("lost-251").exists());
+
+        // An index entry whose file is gone does not hold space either.
+        std::fs::remove_file(dir.path().join("a-251")).unwrap();
+        put(dir.path(), 300, "c", 200);
+        assert_eq!(cached(dir.path()), ["b-251", "c-251"]);
+    }
+}
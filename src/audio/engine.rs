@@ -1,5 +1,5 @@
-use std::io::Cursor;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
@@ -7,13 +7,37 @@ use freya::radio::RadioStation;
 use rodio::Decoder;
 use rodio::source::{SeekError, Source};
 use tokio::sync::mpsc;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
-use crate::app::{Data, DataChannel, QueueSong};
-use crate::audio::{AudioQuality, youtube::fetch_audio_bytes};
+use crate::app::{Data, DataChannel, PlayerState, QueueSong};
+use crate::audio::autoplay::{self, Autoplay, RadioBatch, RadioError};
+use crate::audio::cache;
+use crate::audio::eq::{EqBand, EqControl, EqPreset, EqSettings, Equalizer};
+use crate::audio::loudness::{self, NormalizationMode, Normalizer};
+use crate::audio::order::{PlayOrder, QueueEdit, RepeatMode};
+use crate::audio::output;
+use crate::audio::session::{Session, SessionSaver};
+use crate::audio::sleep::{self, SleepMode, SleepTimer};
+use crate::audio::stream::{StreamBuffer, StreamReader};
+use crate::audio::stretch::{
+    MAX_SPEED, MIN_SPEED, SpeedHandle, TimeStretch, load_speed, new_speed,
+};
+use crate::audio::{AudioQuality, youtube::fetch_audio_stream};
 
 const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
 
+/// Output the equalizer settings are stored under when no device is chosen.
+const DEFAULT_OUTPUT: &str = "default";
+
+/// How often the rodio thread checks whether its output device failed.
+const OUTPUT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Wait between attempts to open an output while none is available.
+const REOPEN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often a sink on the default device checks whether the chosen one is back.
+const PREFERRED_OUTPUT_INTERVAL: Duration = Duration::from_secs(5);
+
 #[derive(Debug)]
 pub enum AudioCommand {
     Play {
@@ -23,12 +47,27 @@ pub enum AudioCommand {
         artist: String,
         album: String,
         thumbnail_url: String,
+        /// Show browse ID when playing a podcast episode.
+        podcast: Option<String>,
     },
     PlayFromQueue {
         songs: Vec<QueueSong>,
         index: usize,
         quality: AudioQuality,
     },
+    /// Append a song to the queue, starting it when the queue is empty.
+    Enqueue(QueueSong),
+    /// Insert a song right after the current one (also with shuffle on).
+    PlayNext(QueueSong),
+    /// Remove a queue entry; removing the current one skips to the next.
+    RemoveAt(usize),
+    /// Move the queue entry at `from` so it ends up at index `to`.
+    Move {
+        from: usize,
+        to: usize,
+    },
+    /// Remove everything but the current song from the queue.
+    ClearQueue,
     Next,
     Previous,
     Pause,
@@ -36,19 +75,273 @@ pub enum AudioCommand {
     Stop,
     Seek(f32),
     SetVolume(f32),
+    /// Overlap consecutive tracks by this many seconds; `0` disables crossfade.
+    SetCrossfade(f32),
+    SetNormalization(NormalizationMode),
+    /// Stream quality for every track loaded from now on.
+    SetQuality(AudioQuality),
+    SetShuffle(bool),
+    SetRepeat(RepeatMode),
+    /// Keep playing the radio of the last track when the queue runs out.
+    SetAutoplay(bool),
+    /// Cap the on-disk audio cache at this many bytes; `0` turns it off.
+    SetCacheLimit(u64),
+    /// Pause playback later, fading it out first; `None` cancels the timer.
+    SetSleepTimer(Option<SleepMode>),
+    /// Show the session of the last run, paused. Its current track is only
+    /// resolved once playback resumes.
+    Restore(Session),
+    /// Play faster or slower without changing pitch. Remembered per podcast
+    /// show, so each episode of a show starts at that show's speed.
+    SetSpeed(f32),
+    SetEqEnabled(bool),
+    /// Load a preset's bands; `Custom` keeps the current ones.
+    SetEqPreset(EqPreset),
+    /// Change the gain of one band, switching to the `Custom` preset.
+    SetEqBand {
+        index: usize,
+        gain_db: f32,
+    },
+    /// Replace all bands with user-defined ones (`Custom` preset).
+    SetEqBands(Vec<EqBand>),
+    SetEqPreamp(f32),
+    /// Re-read the list of output devices into `PlayerState::outputs`.
+    RefreshOutputs,
+    /// Play through the output device with this ID; `None` follows the
+    /// system default.
+    SetOutput(Option<String>),
 }
 
 enum RodioCmd {
-    Play(Vec<u8>, Arc<AtomicBool>),
+    /// Replace whatever is playing with this track.
+    Play(PlayerTrack),
+    /// Append a track to play right after the current one, without a gap.
+    Enqueue(PlayerTrack),
+    /// Start a track on top of the current one: the current track fades out
+    /// while the new one fades in over the given duration.
+    Crossfade(PlayerTrack, Duration),
+    /// Drop the current track and continue with the enqueued one.
+    Skip,
+    /// Cut the enqueued track short so it is skipped when reached.
+    DropQueued,
     Pause,
     Resume,
     Stop,
     Seek(Duration),
     SetVolume(f32),
+    /// Close the current sink and open one on this device.
+    SetOutput(Option<String>),
+}
+
+/// Sent by the rodio thread to the engine task.
+enum RodioEvent {
+    /// A new sink was opened after the previous one failed or was switched;
+    /// whatever was playing on the old one is gone.
+    OutputReopened,
+}
+
+/// Linear gain as `f32` bits, shared with a playing track's [`TrackGain`].
+type GainHandle = Arc<AtomicU32>;
+
+fn new_gain() -> GainHandle {
+    Arc::new(AtomicU32::new(1f32.to_bits()))
+}
+
+/// Everything the rodio thread needs to play one track.
+struct PlayerTrack {
+    source: Decoder<StreamReader>,
+    /// Set by the track's [`CompletionNotifier`] when it runs out.
+    done: Arc<AtomicBool>,
+    gain: GainHandle,
+}
+
+/// A resolved track whose decoder is already built, ready for the player.
+struct LoadedTrack {
+    video_id: String,
+    source: Decoder<StreamReader>,
+    buffer: Arc<StreamBuffer>,
+    loudness_db: Option<f32>,
+}
+
+/// Loudness of a track handed to rodio, kept to recompute its gain.
+struct TrackLevel {
+    album: String,
+    loudness_db: Option<f32>,
+    gain: GainHandle,
+}
+
+impl TrackLevel {
+    fn apply(&self, normalizer: &mut Normalizer) {
+        let gain = normalizer.gain(&self.album, self.loudness_db);
+        self.gain.store(gain.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// The next queue entry, loaded while the current track plays.
+struct NextTrack {
+    index: usize,
+    /// Held back for a crossfade; `None` once appended to the player.
+    pending: Option<PlayerTrack>,
+    done: Arc<AtomicBool>,
+    buffer: Arc<StreamBuffer>,
+    level: TrackLevel,
+}
+
+/// Engine-side view of what the rodio thread is playing.
+struct Playback {
+    /// Set by the current track's [`CompletionNotifier`] when it runs out.
+    song_done: Arc<AtomicBool>,
+    /// Buffer of the current stream, polled for buffering state. `None`
+    /// while the current track is still loading.
+    stream: Option<Arc<StreamBuffer>>,
+    next: Option<NextTrack>,
+    /// Queue index of the preload in flight, if any.
+    preloading: Option<usize>,
+    /// Crossfade length in seconds; `0` plays tracks back to back.
+    crossfade: f32,
+    normalizer: Normalizer,
+    /// Level of the current track, `None` while it is loading.
+    level: Option<TrackLevel>,
+    /// Equalizer shared with every track the rodio thread plays.
+    eq: Arc<EqControl>,
+    /// Output device the equalizer settings belong to.
+    output: String,
+    /// Speed shared with every track the rodio thread plays.
+    speed: SpeedHandle,
+    /// Speed per podcast show browse ID; `""` is the one for songs.
+    speeds: HashMap<String, f32>,
+    /// Stream quality of the tracks loaded, as last chosen.
+    quality: AudioQuality,
+    /// Position to seek the next loaded track to, in seconds.
+    resume_at: Option<f32>,
+    /// Position to load the current track at once playback resumes: that of
+    /// a restored session, or where the track's stream failed.
+    restored: Option<f32>,
+    order: PlayOrder,
+    autoplay: Autoplay,
+    sleep: Option<SleepTimer>,
+}
+
+impl Playback {
+    fn new(
+        equalizer: EqSettings,
+        output: String,
+        speeds: HashMap<String, f32>,
+        quality: AudioQuality,
+    ) -> Self {
+        Self {
+            song_done: Arc::new(AtomicBool::new(false)),
+            stream: None,
+            next: None,
+            preloading: None,
+            crossfade: 0.,
+            normalizer: Normalizer::default(),
+            level: None,
+            eq: EqControl::new(equalizer),
+            output,
+            speed: new_speed(1.),
+            speeds,
+            quality,
+            resume_at: None,
+            restored: None,
+            order: PlayOrder::default(),
+            autoplay: Autoplay::default(),
+            sleep: None,
+        }
+    }
+
+    /// Make the loaded next track the current one.
+    fn advance(&mut self, next: NextTrack) {
+        self.song_done = next.done;
+        self.stream = Some(next.buffer);
+        self.level = Some(next.level);
+    }
+
+    /// Hand a freshly loaded track to rodio as the current one.
+    fn play(&mut self, track: LoadedTrack, album: String) -> PlayerTrack {
+        // `Play` drops anything enqueued behind the old track.
+        self.song_done = Arc::new(AtomicBool::new(false));
+        self.next = None;
+        self.stream = Some(track.buffer);
+        self.normalizer.reset();
+        let level = TrackLevel {
+            album,
+            loudness_db: track.loudness_db,
+            gain: new_gain(),
+        };
+        level.apply(&mut self.normalizer);
+        let player_track = PlayerTrack {
+            source: track.source,
+            done: Arc::clone(&self.song_done),
+            gain: Arc::clone(&level.gain),
+        };
+        self.level = Some(level);
+        player_track
+    }
+
+    /// Recompute the gain of the current and next track after a mode change.
+    fn set_normalization(&mut self, mode: NormalizationMode) {
+        self.normalizer = Normalizer::new(mode);
+        for level in [self.level.as_ref(), self.next.as_ref().map(|n| &n.level)]
+            .into_iter()
+            .flatten()
+        {
+            level.apply(&mut self.normalizer);
+        }
+    }
+
+    /// Queue index that follows the current entry; `auto` when the current
+    /// one ends by itself rather than being skipped.
+    fn upcoming(&mut self, player: &PlayerState, auto: bool) -> Option<usize> {
+        self.order.peek_next(player.queue.len(), player.queue_index, auto)
+    }
+
+    /// Switch to the speed remembered for `podcast`'s show (or for songs).
+    fn speed_for(&self, podcast: Option<&str>) -> f32 {
+        let speed = self.speeds.get(podcast.unwrap_or_default()).copied();
+        speed.unwrap_or(1.).clamp(MIN_SPEED, MAX_SPEED)
+    }
+
+    /// Forget the current and next tracks before switching to another song.
+    ///
+    /// The fresh `song_done` flag keeps the outgoing track, which plays on
+    /// until the new one is loaded, from triggering an auto-advance.
+    fn reset(&mut self) {
+        self.song_done = Arc::new(AtomicBool::new(false));
+        self.stream = None;
+        self.level = None;
+        self.next = None;
+        self.preloading = None;
+        self.resume_at = None;
+        self.restored = None;
+    }
+
+    /// Move the current track to `secs`: the seek to hand rodio, or `None`
+    /// when the track is not playing yet. While it loads, the track before it
+    /// may still be playing, so the position is kept for [`Self::start_at`].
+    fn seek(&mut self, secs: f32) -> Option<Duration> {
+        if let Some(restored) = &mut self.restored {
+            *restored = secs;
+            None
+        } else if self.stream.is_none() {
+            self.resume_at = Some(secs);
+            None
+        } else {
+            Some(Duration::from_secs_f32(secs))
+        }
+    }
+
+    /// Where the current track starts once loaded, if not at the beginning.
+    fn start_at(&mut self) -> Option<Duration> {
+        self.resume_at.take().map(Duration::from_secs_f32)
+    }
 }
 
 /// Shared progress state written by the rodio thread, read by the engine task.
 struct ProgressState {
+    /// Track that owns the fields below. A track only reports while no newer
+    /// one has, so a track fading out cannot overwrite its successor.
+    track: u64,
     current_secs: f32,
     total_secs: f32,
     /// `false` when the decoder could not determine total duration (e.g. some WebM/Opus files).
@@ -59,7 +352,7 @@ struct ProgressState {
 
 impl Default for ProgressState {
     fn default() -> Self {
-        Self { current_secs: 0.0, total_secs: 0.0, total_known: false }
+        Self { track: 0, current_secs: 0.0, total_secs: 0.0, total_known: false }
     }
 }
 
@@ -110,6 +403,110 @@ impl<S: Source> Source for CompletionNotifier<S> {
     }
 }
 
+/// Multiplies a [`Source`] by a gain that can change while it plays. A boost
+/// goes through [`loudness::limit`] so that loud masters do not clip.
+struct TrackGain<S: Source> {
+    inner: S,
+    gain: GainHandle,
+}
+
+impl<S: Source> Iterator for TrackGain<S> {
+    type Item = rodio::Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let gain = f32::from_bits(self.gain.load(Ordering::Relaxed));
+        let sample = self.inner.next()?;
+        Some(if gain > 1. {
+            loudness::limit(sample * gain)
+        } else {
+            sample * gain
+        })
+    }
+}
+
+impl<S: Source> Source for TrackGain<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.inner.current_span_len()
+    }
+
+    fn channels(&self) -> rodio::ChannelCount {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
+
+/// Trigger shared between the rodio thread and a track's [`FadeOut`].
+///
+/// Holds the fade length in milliseconds; `0` until a fade is requested.
+type FadeTrigger = Arc<AtomicU32>;
+
+/// Wraps a [`Source`] and, once its trigger is set, ramps it linearly to
+/// silence and then ends it.
+struct FadeOut<S: Source> {
+    inner: S,
+    trigger: FadeTrigger,
+    /// `(samples left, fade length in samples)` once the fade has started.
+    fade: Option<(u64, u64)>,
+}
+
+impl<S: Source> Iterator for FadeOut<S> {
+    type Item = rodio::Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.fade.is_none() {
+            let ms = self.trigger.load(Ordering::Relaxed);
+            if ms > 0 {
+                let per_sec =
+                    u64::from(self.inner.sample_rate().get()) * u64::from(self.inner.channels().get());
+                let len = (per_sec * u64::from(ms) / 1000).max(1);
+                self.fade = Some((len, len));
+            }
+        }
+        let sample = self.inner.next()?;
+        match &mut self.fade {
+            None => Some(sample),
+            Some((0, _)) => None,
+            Some((left, len)) => {
+                *left -= 1;
+                Some(sample * (*left as f32 / *len as f32))
+            }
+        }
+    }
+}
+
+impl<S: Source> Source for FadeOut<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.inner.current_span_len()
+    }
+
+    fn channels(&self) -> rodio::ChannelCount {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
+
 /// Handle for sending commands to the audio engine.
 pub struct AudioEngine {
     tx: mpsc::Sender<AudioCommand>,
@@ -138,35 +535,103 @@ pub async fn run_audio_engine(
     mut radio: RadioStation<Data, DataChannel>,
 ) {
     let (rodio_tx, rodio_rx) = std::sync::mpsc::channel::<RodioCmd>();
-    let (fetch_tx, mut fetch_rx) = mpsc::channel::<Result<Vec<u8>, String>>(4);
+    let (fetch_tx, mut fetch_rx) = mpsc::channel::<Result<LoadedTrack, String>>(4);
+    let (preload_tx, mut preload_rx) = mpsc::channel::<(usize, Result<LoadedTrack, String>)>(2);
+    let (radio_tx, mut radio_rx) = mpsc::channel::<Result<RadioBatch, RadioError>>(1);
 
     let progress = Arc::new(Mutex::new(ProgressState::default()));
     let progress_for_rodio = Arc::clone(&progress);
 
-    // Shared flag: the currently-playing source sets this to `true` when it
-    // runs out of samples.  The engine detects it during the progress tick
-    // and advances the queue.
-    let song_done = Arc::new(AtomicBool::new(false));
+    let (events_tx, mut events_rx) = mpsc::unbounded_channel::<RodioEvent>();
+
+    let output = crate::prefs::load_output();
+    let output_key = output.clone().unwrap_or_else(|| DEFAULT_OUTPUT.to_owned());
+    let equalizer = crate::prefs::load_equalizer(&output_key);
+    let quality = crate::prefs::load_quality();
+    {
+        let mut state = radio.write_channel(DataChannel::Player);
+        state.player.output = output.clone();
+        state.player.equalizer = equalizer.clone();
+        state.player.quality = quality;
+    }
+
+    let mut playback = Playback::new(equalizer, output_key, crate::prefs::load_speeds(), quality);
+    let eq_for_rodio = Arc::clone(&playback.eq);
+    let speed_for_rodio = Arc::clone(&playback.speed);
 
     // The rodio I/O thread is blocking and does not touch Freya state — safe
     // to dispatch to the thread pool.
     tokio::task::spawn_blocking(move || {
-        rodio_thread(rodio_rx, progress_for_rodio);
+        rodio_thread(
+            rodio_rx,
+            events_tx,
+            output,
+            progress_for_rodio,
+            eq_for_rodio,
+            speed_for_rodio,
+        );
     });
+    refresh_outputs(&mut radio).await;
 
     let mut progress_ticker = tokio::time::interval(PROGRESS_INTERVAL);
+    let mut session = SessionSaver::default();
 
     loop {
         tokio::select! {
             maybe_cmd = rx.recv() => {
                 let Some(cmd) = maybe_cmd else { break };
-                handle_command(cmd, &rodio_tx, &mut radio, &fetch_tx, &song_done).await;
+                handle_command(cmd, &rodio_tx, &mut radio, &fetch_tx, &mut playback).await;
+                schedule_preload(&radio, &mut playback, &preload_tx);
+                extend_queue(&radio, &mut playback, &radio_tx);
+                session.touch();
+            }
+            Some(result) = radio_rx.recv() => {
+                let batch = match result {
+                    Ok(batch) => batch,
+                    Err(e) => {
+                        warn!(error = %e.message, "fetching autoplay radio failed");
+                        playback.autoplay.failed(&e);
+                        continue;
+                    }
+                };
+                let songs = playback.autoplay.accept(batch, &radio.read().player.queue);
+                info!(count = songs.len(), "autoplay appending radio tracks");
+                for song in songs {
+                    let index = {
+                        let mut state = radio.write_channel(DataChannel::Player);
+                        state.player.queue.push(song);
+                        state.player.queue.len() - 1
+                    };
+                    playback.order.enqueue(index);
+                }
+                session.touch();
+                drop_stale_preload(&rodio_tx, &radio, &mut playback);
+                // Playback stopped at the end of the queue while this was loading.
+                if playback.autoplay.resume
+                    && let Some(next) = playback.upcoming(&radio.read().player, true)
+                {
+                    let vol = radio.read().player.volume;
+                    rodio_tx.send(RodioCmd::SetVolume(vol)).ok();
+                    skip_to(&rodio_tx, &mut radio, &fetch_tx, &mut playback, next);
+                }
+                schedule_preload(&radio, &mut playback, &preload_tx);
             }
             Some(result) = fetch_rx.recv() => {
                 match result {
-                    Ok(bytes) => {
-                        song_done.store(false, Ordering::Relaxed);
-                        rodio_tx.send(RodioCmd::Play(bytes, Arc::clone(&song_done))).ok();
+                    Ok(track) => {
+                        let (album, is_playing) = {
+                            let p = &radio.read().player;
+                            (p.album.clone(), p.is_playing)
+                        };
+                        let start_at = playback.start_at();
+                        rodio_tx.send(RodioCmd::Play(playback.play(track, album))).ok();
+                        if let Some(at) = start_at {
+                            rodio_tx.send(RodioCmd::Seek(at)).ok();
+                        }
+                        if !is_playing {
+                            rodio_tx.send(RodioCmd::Pause).ok();
+                        }
+                        schedule_preload(&radio, &mut playback, &preload_tx);
                     }
                     Err(e) => {
                         error!(error = %e, "failed to fetch audio");
@@ -177,82 +642,416 @@ pub async fn run_audio_engine(
                     }
                 }
             }
+            Some((index, result)) = preload_rx.recv() => {
+                // Stale when the queue moved on while the preload was running.
+                let expected = if playback.preloading == Some(index) {
+                    playback.preloading = None;
+                    let p = &radio.read().player;
+                    (playback.upcoming(p, true) == Some(index))
+                        .then(|| p.queue.get(index).map(|s| s.video_id.clone()))
+                        .flatten()
+                } else {
+                    None
+                };
+                match result {
+                    Ok(track) if expected.as_deref() == Some(track.video_id.as_str()) => {
+                        let fade = playback.crossfade > 0.
+                            && track.source.total_duration().is_some()
+                            && !same_album(&radio.read().player, index);
+                        info!(index, video_id = track.video_id, fade, "next track pre-buffered");
+                        let level = TrackLevel {
+                            album: radio.read().player.queue[index].album.clone(),
+                            loudness_db: track.loudness_db,
+                            gain: new_gain(),
+                        };
+                        level.apply(&mut playback.normalizer);
+                        let done = Arc::new(AtomicBool::new(false));
+                        let player_track = PlayerTrack {
+                            source: track.source,
+                            done: Arc::clone(&done),
+                            gain: Arc::clone(&level.gain),
+                        };
+                        let pending = if fade {
+                            Some(player_track)
+                        } else {
+                            rodio_tx.send(RodioCmd::Enqueue(player_track)).ok();
+                            None
+                        };
+                        playback.next = Some(NextTrack {
+                            index,
+                            pending,
+                            done,
+                            buffer: track.buffer,
+                            level,
+                        });
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!(index, error = %e, "preloading next track failed"),
+                }
+            }
+            Some(event) = events_rx.recv() => match event {
+                RodioEvent::OutputReopened => {
+                    // Reload the track that was playing and continue where
+                    // it stopped. Nothing to do while it is still loading.
+                    let current = {
+                        let p = &radio.read().player;
+                        p.queue.get(p.queue_index).cloned().map(|s| (s, p.current_secs))
+                    };
+                    if playback.stream.is_some()
+                        && let Some((song, secs)) = current
+                    {
+                        info!(video_id = song.video_id, secs, "output reopened, resuming");
+                        playback.reset();
+                        playback.resume_at = Some(secs);
+                        let vol = radio.read().player.volume;
+                        rodio_tx.send(RodioCmd::SetVolume(vol)).ok();
+                        spawn_load(&radio, &fetch_tx, &song, playback.quality);
+                    }
+                }
+            },
             _ = progress_ticker.tick() => {
-                let (current, total) = progress
+                let (current, total, total_known) = progress
                     .lock()
-                    .map(|p| (p.current_secs, p.total_secs))
-                    .unwrap_or((0., 0.));
-                {
+                    .map(|p| (p.current_secs, p.total_secs, p.total_known))
+                    .unwrap_or((0., 0., false));
+                let (buffering, buffered_until) = playback
+                    .stream
+                    .as_ref()
+                    .map(|b| (b.is_buffering(), b.buffered_until()))
+                    .unwrap_or((false, 0.));
+                // A restored session shows its saved position until loaded.
+                if playback.restored.is_none() {
                     let mut state = radio.write_channel(DataChannel::Player);
                     state.player.current_secs = current;
                     state.player.total_secs = total;
+                    state.player.is_buffering = buffering;
+                    state.player.buffered_until = buffered_until;
+                    if state.player.is_playing {
+                        session.touch();
+                    }
                 }
+                if let Some(tick) = &radio.read().progress_tick {
+                    tick.send_replace(());
+                }
+                let speed = radio.read().player.speed;
+                let track_left = total_known.then(|| (total - current).max(0.) / speed);
+                tick_sleep(&rodio_tx, &mut radio, &mut playback, track_left);
 
-                // Auto-advance when the current source signals completion.
-                if song_done.load(Ordering::Relaxed) {
-                    song_done.store(false, Ordering::Relaxed);
+                // Start the crossfade once the current track is within its
+                // length of the end. The now-playing state moves to the
+                // incoming track right away; the outgoing one fades out.
+                let upcoming = {
+                    let p = &radio.read().player;
+                    playback
+                        .upcoming(p, true)
+                        .filter(|_| p.is_playing)
+                        .and_then(|index| Some((index, p.queue.get(index)?.clone())))
+                };
+                // No crossfade into a track the sleep timer would pause.
+                let fade_due = playback.crossfade > 0.
+                    && track_left.is_some_and(|left| left <= playback.crossfade)
+                    && !playback.sleep.as_ref().is_some_and(|s| s.ends_with_track(false));
+                if fade_due
+                    && let Some((index, song)) = upcoming
+                    && let Some(mut next) = playback
+                        .next
+                        .take_if(|n| n.index == index && n.pending.is_some())
+                    && let Some(track) = next.pending.take()
+                {
+                    info!(index, video_id = song.video_id, "crossfading to next song");
+                    let fade = Duration::from_secs_f32(playback.crossfade);
+                    rodio_tx.send(RodioCmd::Crossfade(track, fade)).ok();
+                    let current = radio.read().player.queue_index;
+                    playback.order.advance(current, index);
+                    set_now_playing(&mut radio, &mut playback, &song, index);
+                    playback.advance(next);
+                    schedule_preload(&radio, &mut playback, &preload_tx);
+                }
+
+                // A stream no client could resolve again ends its track
+                // early. Stop there; resuming loads it again at that spot.
+                let failed = playback.stream.as_ref().and_then(|b| b.error());
+                if let Some(e) = failed
+                    && playback.song_done.swap(false, Ordering::Relaxed)
+                {
+                    error!(error = %e, "audio stream failed");
+                    let secs = radio.read().player.current_secs;
+                    // Keep the next track from starting in its place.
+                    rodio_tx.send(RodioCmd::Stop).ok();
+                    playback.reset();
+                    playback.restored = Some(secs);
+                    radio.write_channel(DataChannel::Player).player.is_playing = false;
+                }
 
-                    let (queue, idx) = {
+                // Auto-advance when the current source signals completion.
+                if playback.song_done.swap(false, Ordering::Relaxed) {
+                    let (queue, idx, upcoming) = {
                         let p = &radio.read().player;
-                        (p.queue.clone(), p.queue_index)
+                        (p.queue.clone(), p.queue_index, playback.upcoming(p, true))
                     };
+                    // Reached when the track's length was unknown; otherwise
+                    // the timer paused just before its end.
+                    let sleeping = playback
+                        .sleep
+                        .as_ref()
+                        .is_some_and(|s| s.ends_with_track(upcoming.is_none()));
 
-                    let next_idx = idx + 1;
-                    if next_idx < queue.len() {
+                    if let Some(next_idx) = upcoming {
                         let next = queue[next_idx].clone();
-                        let cookies = radio.read().cookie_header.clone();
-                        let vol = radio.read().player.volume;
-
+                        playback.order.advance(idx, next_idx);
                         info!(
                             next_idx,
                             queue_len = queue.len(),
                             video_id = next.video_id,
                             "auto-advancing to next song"
                         );
+                        set_now_playing(&mut radio, &mut playback, &next, next_idx);
 
-                        {
-                            let mut s = radio.write_channel(DataChannel::Player);
-                            let p = &mut s.player;
-                            p.queue_index = next_idx;
-                            p.title = next.title.clone();
-                            p.artist = next.artist.clone();
-                            p.album = next.album.clone();
-                            p.thumbnail_url = next.thumbnail_url.clone();
-                            p.current_secs = 0.;
-                            p.is_playing = true;
+                        match playback.next.take() {
+                            // Already playing when it was appended; otherwise
+                            // it was held for a crossfade that never started.
+                            Some(mut next) if next.index == next_idx => {
+                                if let Some(track) = next.pending.take() {
+                                    rodio_tx.send(RodioCmd::Play(track)).ok();
+                                }
+                                playback.advance(next);
+                            }
+                            _ => {
+                                playback.reset();
+                                let vol = radio.read().player.volume;
+                                rodio_tx.send(RodioCmd::SetVolume(vol)).ok();
+                                spawn_load(&radio, &fetch_tx, &next, playback.quality);
+                            }
                         }
-
-                        rodio_tx.send(RodioCmd::SetVolume(vol)).ok();
-
-                        let tx = fetch_tx.clone();
-                        let video_id = next.video_id.clone();
-                        tokio::spawn(async move {
-                            let result = fetch_audio_bytes(
-                                &video_id,
-                                AudioQuality::Medium,
-                                cookies,
-                            )
-                            .await
-                            .map_err(|e| e.to_string());
-                            tx.send(result).await.ok();
-                        });
+                        schedule_preload(&radio, &mut playback, &preload_tx);
                     } else {
-                        // End of queue — mark as stopped.
+                        // End of queue — mark as stopped. Autoplay picks up
+                        // again once its next radio tracks arrive.
                         info!(idx, "queue exhausted, stopping playback");
+                        playback.autoplay.resume = playback.autoplay.enabled;
                         radio.write_channel(DataChannel::Player).player.is_playing = false;
                     }
+                    if sleeping {
+                        sleep_now(&rodio_tx, &mut radio, &mut playback);
+                        // The next track may already have started.
+                        if upcoming.is_some() {
+                            rodio_tx.send(RodioCmd::Seek(Duration::ZERO)).ok();
+                        }
+                    }
+                }
+                extend_queue(&radio, &mut playback, &radio_tx);
+
+                if session.due() {
+                    let snapshot = Session::from_player(&radio.read().player);
+                    tokio::task::spawn_blocking(move || crate::prefs::save_session(&snapshot));
                 }
             }
         }
     }
+
+    // Keep the latest position on the way out.
+    crate::prefs::save_session(&Session::from_player(&radio.read().player));
+}
+
+/// Resolve, start streaming and build the decoder for `video_id`.
+async fn load_track(
+    video_id: String,
+    quality: AudioQuality,
+    cookies: Option<String>,
+) -> Result<LoadedTrack, String> {
+    let stream = fetch_audio_stream(&video_id, quality, cookies)
+        .await
+        .map_err(|e| e.to_string())?;
+    let buffer = stream.reader.buffer();
+    // Probing the container reads from the network — keep it off the runtime.
+    let source = tokio::task::spawn_blocking(move || decode(stream.reader))
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+    Ok(LoadedTrack {
+        video_id,
+        source,
+        buffer,
+        loudness_db: stream.loudness_db,
+    })
+}
+
+fn decode(reader: StreamReader) -> Result<Decoder<StreamReader>, rodio::decoder::DecoderError> {
+    let byte_len = reader.byte_len();
+    let mime_type = reader.mime_type().map(str::to_owned);
+    let mut builder = Decoder::builder()
+        .with_data(reader)
+        .with_byte_len(byte_len)
+        .with_seekable(true);
+    if let Some(mime) = &mime_type {
+        builder = builder.with_mime_type(mime);
+    }
+    builder.build()
+}
+
+/// Load `song` in the background and hand it to the fetch channel.
+fn spawn_load(
+    radio: &RadioStation<Data, DataChannel>,
+    fetch_tx: &mpsc::Sender<Result<LoadedTrack, String>>,
+    song: &QueueSong,
+    quality: AudioQuality,
+) {
+    let cookies = radio.read().cookie_header.clone();
+    let tx = fetch_tx.clone();
+    let video_id = song.video_id.clone();
+    tokio::spawn(async move {
+        tx.send(load_track(video_id, quality, cookies).await).await.ok();
+    });
+}
+
+/// Start loading the upcoming queue entry unless it is loaded or loading already.
+fn schedule_preload(
+    radio: &RadioStation<Data, DataChannel>,
+    playback: &mut Playback,
+    preload_tx: &mpsc::Sender<(usize, Result<LoadedTrack, String>)>,
+) {
+    let upcoming = {
+        let p = &radio.read().player;
+        playback
+            .upcoming(p, true)
+            .and_then(|index| Some((p.queue.get(index)?.clone(), index)))
+    };
+    let Some((song, index)) = upcoming else { return };
+    // Wait for the current track: `Play` would drop anything enqueued before it.
+    if playback.stream.is_none()
+        || playback.preloading == Some(index)
+        || playback.next.as_ref().is_some_and(|n| n.index == index)
+    {
+        return;
+    }
+
+    playback.preloading = Some(index);
+    let cookies = radio.read().cookie_header.clone();
+    let tx = preload_tx.clone();
+    let quality = playback.quality;
+    tokio::spawn(async move {
+        let result = load_track(song.video_id, quality, cookies).await;
+        tx.send((index, result)).await.ok();
+    });
+}
+
+/// Ask for more radio tracks when autoplay is on and the queue is about to
+/// run out. Repeating queues never run out.
+fn extend_queue(
+    radio: &RadioStation<Data, DataChannel>,
+    playback: &mut Playback,
+    radio_tx: &mpsc::Sender<Result<RadioBatch, RadioError>>,
+) {
+    if !playback.autoplay.enabled
+        || playback.order.repeat != RepeatMode::Off
+        || playback.sleep.as_ref().is_some_and(|s| s.mode == SleepMode::EndOfQueue)
+    {
+        return;
+    }
+    let (request, yt) = {
+        let data = radio.read();
+        let p = &data.player;
+        let Some(current) = p.queue.get(p.queue_index) else { return };
+        if playback.order.remaining(p.queue.len(), p.queue_index) > autoplay::LOW_WATER {
+            return;
+        }
+        (playback.autoplay.request(&current.video_id), data.yt_session.clone())
+    };
+    let (Some(request), Some(yt)) = (request, yt) else { return };
+
+    playback.autoplay.fetching = true;
+    let tx = radio_tx.clone();
+    tokio::spawn(async move {
+        tx.send(autoplay::fetch(yt, request).await).await.ok();
+    });
+}
+
+/// Fade out and pause once the sleep timer runs out; `track_left` is the
+/// time left of the current track in seconds, if known.
+fn tick_sleep(
+    rodio_tx: &std::sync::mpsc::Sender<RodioCmd>,
+    radio: &mut RadioStation<Data, DataChannel>,
+    playback: &mut Playback,
+    track_left: Option<f32>,
+) {
+    let last_track = playback.upcoming(&radio.read().player, true).is_none();
+    let (volume, is_playing) = {
+        let p = &radio.read().player;
+        (p.volume, p.is_playing)
+    };
+    let Some(timer) = &mut playback.sleep else { return };
+    let remaining = timer.remaining(track_left, last_track);
+    match remaining {
+        // Within a tick of the end: nothing left to hear after the fade.
+        Some(left) if left <= PROGRESS_INTERVAL.as_secs_f32() => {
+            sleep_now(rodio_tx, radio, playback);
+            return;
+        }
+        Some(left) if left < sleep::FADE_SECS && is_playing => {
+            timer.fading = true;
+            rodio_tx
+                .send(RodioCmd::SetVolume(volume * sleep::fade_factor(left)))
+                .ok();
+        }
+        // Seeked back or paused out of the fade.
+        _ if timer.fading => {
+            timer.fading = false;
+            rodio_tx.send(RodioCmd::SetVolume(volume)).ok();
+        }
+        _ => {}
+    }
+    radio.write_channel(DataChannel::Player).player.sleep_remaining = remaining;
+}
+
+/// Pause for the sleep timer and put the volume back for the next time.
+fn sleep_now(
+    rodio_tx: &std::sync::mpsc::Sender<RodioCmd>,
+    radio: &mut RadioStation<Data, DataChannel>,
+    playback: &mut Playback,
+) {
+    info!("sleep timer ran out, pausing");
+    playback.sleep = None;
+    playback.autoplay.resume = false;
+    rodio_tx.send(RodioCmd::Pause).ok();
+    let mut state = radio.write_channel(DataChannel::Player);
+    let p = &mut state.player;
+    rodio_tx.send(RodioCmd::SetVolume(p.volume)).ok();
+    p.is_playing = false;
+    p.sleep_timer = None;
+    p.sleep_remaining = None;
+}
+
+/// Show `song` as the current track at `index` in the player bar and switch
+/// to its show's speed.
+fn set_now_playing(
+    radio: &mut RadioStation<Data, DataChannel>,
+    playback: &mut Playback,
+    song: &QueueSong,
+    index: usize,
+) {
+    let speed = playback.speed_for(song.podcast.as_deref());
+    playback.speed.store(speed.to_bits(), Ordering::Relaxed);
+    playback.autoplay.played(&song.video_id);
+
+    let mut state = radio.write_channel(DataChannel::Player);
+    let p = &mut state.player;
+    p.queue_index = index;
+    p.title = song.title.clone();
+    p.artist = song.artist.clone();
+    p.album = song.album.clone();
+    p.thumbnail_url = song.thumbnail_url.clone();
+    p.podcast = song.podcast.clone();
+    p.speed = speed;
+    p.current_secs = 0.;
+    p.is_playing = true;
 }
 
 async fn handle_command(
     cmd: AudioCommand,
     rodio_tx: &std::sync::mpsc::Sender<RodioCmd>,
     radio: &mut RadioStation<Data, DataChannel>,
-    fetch_tx: &mpsc::Sender<Result<Vec<u8>, String>>,
-    song_done: &Arc<AtomicBool>,
+    fetch_tx: &mpsc::Sender<Result<LoadedTrack, String>>,
+    playback: &mut Playback,
 ) {
     match cmd {
         AudioCommand::Play {
@@ -262,31 +1061,18 @@ async fn handle_command(
             artist,
             album,
             thumbnail_url,
+            podcast,
         } => {
-            let cookies = radio.read().cookie_header.clone();
-            song_done.store(false, Ordering::Relaxed);
-
-            {
-                let mut state = radio.write_channel(DataChannel::Player);
-                let p = &mut state.player;
-                p.title = title;
-                p.artist = artist;
-                p.album = album;
-                p.thumbnail_url = thumbnail_url;
-                p.current_secs = 0.;
-                p.is_playing = true;
-                // Single-song play clears the queue so auto-advance is a no-op.
-                p.queue.clear();
-                p.queue_index = 0;
-            }
-
-            let tx = fetch_tx.clone();
-            tokio::spawn(async move {
-                let result = fetch_audio_bytes(&video_id, quality, cookies)
-                    .await
-                    .map_err(|e| e.to_string());
-                tx.send(result).await.ok();
-            });
+            let song = QueueSong {
+                video_id,
+                title,
+                artist,
+                album,
+                thumbnail_url,
+                podcast,
+            };
+            playback.quality = quality;
+            play_single(radio, fetch_tx, playback, song);
         }
 
         AudioCommand::PlayFromQueue { songs, index, quality } => {
@@ -295,64 +1081,83 @@ async fn handle_command(
             }
 
             let song = songs[index].clone();
-            let cookies = radio.read().cookie_header.clone();
-            song_done.store(false, Ordering::Relaxed);
+            playback.reset();
+            playback.quality = quality;
+            playback.order.reset(songs.len(), index);
+            playback.autoplay.reset();
+            radio.write_channel(DataChannel::Player).player.queue = songs;
+            set_now_playing(radio, playback, &song, index);
+            spawn_load(radio, fetch_tx, &song, quality);
+        }
 
-            {
-                let mut state = radio.write_channel(DataChannel::Player);
-                let p = &mut state.player;
-                p.queue = songs;
-                p.queue_index = index;
-                p.title = song.title.clone();
-                p.artist = song.artist.clone();
-                p.album = song.album.clone();
-                p.thumbnail_url = song.thumbnail_url.clone();
-                p.current_secs = 0.;
-                p.is_playing = true;
-            }
-
-            let tx = fetch_tx.clone();
-            let video_id = song.video_id.clone();
-            tokio::spawn(async move {
-                let result = fetch_audio_bytes(&video_id, quality, cookies)
-                    .await
-                    .map_err(|e| e.to_string());
-                tx.send(result).await.ok();
-            });
+        AudioCommand::Enqueue(song) => {
+            let len = radio.read().player.queue.len();
+            if len == 0 {
+                play_single(radio, fetch_tx, playback, song);
+                return;
+            }
+            radio.write_channel(DataChannel::Player).player.queue.push(song);
+            playback.order.enqueue(len);
+            drop_stale_preload(rodio_tx, radio, playback);
         }
 
-        AudioCommand::Next => {
-            let (queue, idx) = {
+        AudioCommand::PlayNext(song) => {
+            let (len, current) = {
                 let p = &radio.read().player;
-                (p.queue.clone(), p.queue_index)
+                (p.queue.len(), p.queue_index)
             };
-            let next_idx = idx + 1;
-            if next_idx < queue.len() {
-                let song = queue[next_idx].clone();
-                let cookies = radio.read().cookie_header.clone();
-                song_done.store(false, Ordering::Relaxed);
+            if len == 0 {
+                play_single(radio, fetch_tx, playback, song);
+                return;
+            }
+            let at = current + 1;
+            edit_queue(rodio_tx, radio, playback, QueueEdit::Insert { at, entry: song });
+            playback.order.play_next(at);
+            drop_stale_preload(rodio_tx, radio, playback);
+        }
 
-                {
-                    let mut state = radio.write_channel(DataChannel::Player);
-                    let p = &mut state.player;
-                    p.queue_index = next_idx;
-                    p.title = song.title.clone();
-                    p.artist = song.artist.clone();
-                    p.album = song.album.clone();
-                    p.thumbnail_url = song.thumbnail_url.clone();
-                    p.current_secs = 0.;
-                    p.is_playing = true;
+        AudioCommand::RemoveAt(index) => {
+            let (len, current) = {
+                let p = &radio.read().player;
+                (p.queue.len(), p.queue_index)
+            };
+            if index >= len {
+                return;
+            }
+            if index == current {
+                let next = playback
+                    .upcoming(&radio.read().player, false)
+                    .filter(|&next| next != index);
+                match next {
+                    Some(next) => skip_to(rodio_tx, radio, fetch_tx, playback, next),
+                    None => stop(rodio_tx, radio, playback),
                 }
+            }
+            edit_queue(rodio_tx, radio, playback, QueueEdit::Remove(index));
+            drop_stale_preload(rodio_tx, radio, playback);
+        }
 
-                let tx = fetch_tx.clone();
-                let video_id = song.video_id.clone();
-                tokio::spawn(async move {
-                    let result =
-                        fetch_audio_bytes(&video_id, AudioQuality::Medium, cookies)
-                            .await
-                            .map_err(|e| e.to_string());
-                    tx.send(result).await.ok();
-                });
+        AudioCommand::Move { from, to } => {
+            let len = radio.read().player.queue.len();
+            if from >= len || to >= len || from == to {
+                return;
+            }
+            edit_queue(rodio_tx, radio, playback, QueueEdit::Move { from, to });
+            drop_stale_preload(rodio_tx, radio, playback);
+        }
+
+        AudioCommand::ClearQueue => {
+            let current = radio.read().player.queue_index;
+            if radio.read().player.queue.get(current).is_none() {
+                return;
+            }
+            edit_queue(rodio_tx, radio, playback, QueueEdit::KeepOnly(current));
+        }
+
+        AudioCommand::Next => {
+            let upcoming = playback.upcoming(&radio.read().player, false);
+            if let Some(next) = upcoming {
+                skip_to(rodio_tx, radio, fetch_tx, playback, next);
             }
         }
 
@@ -361,33 +1166,11 @@ async fn handle_command(
                 let p = &radio.read().player;
                 (p.queue.clone(), p.queue_index)
             };
-            if idx > 0 {
-                let prev_idx = idx - 1;
+            if let Some(prev_idx) = playback.order.previous(queue.len(), idx) {
                 let song = queue[prev_idx].clone();
-                let cookies = radio.read().cookie_header.clone();
-                song_done.store(false, Ordering::Relaxed);
-
-                {
-                    let mut state = radio.write_channel(DataChannel::Player);
-                    let p = &mut state.player;
-                    p.queue_index = prev_idx;
-                    p.title = song.title.clone();
-                    p.artist = song.artist.clone();
-                    p.album = song.album.clone();
-                    p.thumbnail_url = song.thumbnail_url.clone();
-                    p.current_secs = 0.;
-                    p.is_playing = true;
-                }
-
-                let tx = fetch_tx.clone();
-                let video_id = song.video_id.clone();
-                tokio::spawn(async move {
-                    let result =
-                        fetch_audio_bytes(&video_id, AudioQuality::Medium, cookies)
-                            .await
-                            .map_err(|e| e.to_string());
-                    tx.send(result).await.ok();
-                });
+                playback.reset();
+                set_now_playing(radio, playback, &song, prev_idx);
+                spawn_load(radio, fetch_tx, &song, playback.quality);
             }
         }
 
@@ -396,20 +1179,28 @@ async fn handle_command(
             radio.write_channel(DataChannel::Player).player.is_playing = false;
         }
         AudioCommand::Resume => {
+            // The restored track is resolved only now, then seeked to where
+            // the last run stopped.
+            if let Some(secs) = playback.restored.take() {
+                let current = {
+                    let p = &radio.read().player;
+                    p.queue.get(p.queue_index).cloned()
+                };
+                if let Some(song) = current {
+                    playback.resume_at = Some(secs);
+                    spawn_load(radio, fetch_tx, &song, playback.quality);
+                }
+            }
             rodio_tx.send(RodioCmd::Resume).ok();
             radio.write_channel(DataChannel::Player).player.is_playing = true;
         }
-        AudioCommand::Stop => {
-            rodio_tx.send(RodioCmd::Stop).ok();
-            let mut state = radio.write_channel(DataChannel::Player);
-            state.player.is_playing = false;
-            state.player.current_secs = 0.;
-        }
-        AudioCommand::Seek(secs) => {
-            rodio_tx
-                .send(RodioCmd::Seek(Duration::from_secs_f32(secs)))
-                .ok();
-        }
+        AudioCommand::Stop => stop(rodio_tx, radio, playback),
+        AudioCommand::Seek(secs) => match playback.seek(secs) {
+            Some(at) => {
+                rodio_tx.send(RodioCmd::Seek(at)).ok();
+            }
+            None => radio.write_channel(DataChannel::Player).player.current_secs = secs,
+        },
         AudioCommand::SetVolume(vol) => {
             rodio_tx.send(RodioCmd::SetVolume(vol)).ok();
             if vol > 0.0 {
@@ -417,70 +1208,576 @@ async fn handle_command(
                 tokio::task::spawn_blocking(move || crate::prefs::save_volume(vol));
             }
         }
+        AudioCommand::SetCrossfade(secs) => {
+            let secs = secs.max(0.);
+            playback.crossfade = secs;
+            radio.write_channel(DataChannel::Player).player.crossfade_secs = secs;
+            tokio::task::spawn_blocking(move || crate::prefs::save_crossfade(secs));
+        }
+        AudioCommand::SetNormalization(mode) => {
+            playback.set_normalization(mode);
+            radio.write_channel(DataChannel::Player).player.normalization = mode;
+            tokio::task::spawn_blocking(move || crate::prefs::save_normalization(mode));
+        }
+        AudioCommand::SetQuality(quality) => {
+            playback.quality = quality;
+            radio.write_channel(DataChannel::Player).player.quality = quality;
+            tokio::task::spawn_blocking(move || crate::prefs::save_quality(quality));
+        }
+        AudioCommand::SetSpeed(speed) => {
+            let speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+            playback.speed.store(speed.to_bits(), Ordering::Relaxed);
+            let show = {
+                let mut state = radio.write_channel(DataChannel::Player);
+                state.player.speed = speed;
+                state.player.podcast.clone().unwrap_or_default()
+            };
+            playback.speeds.insert(show, speed);
+            let speeds = playback.speeds.clone();
+            tokio::task::spawn_blocking(move || crate::prefs::save_speeds(&speeds));
+        }
+        AudioCommand::SetShuffle(shuffle) => {
+            {
+                let p = &radio.read().player;
+                playback.order.set_shuffle(shuffle, p.queue.len(), p.queue_index);
+            }
+            // A preload follows the old order; drop it unless it still fits.
+            drop_stale_preload(rodio_tx, radio, playback);
+            radio.write_channel(DataChannel::Player).player.shuffle = shuffle;
+            tokio::task::spawn_blocking(move || crate::prefs::save_shuffle(shuffle));
+        }
+        AudioCommand::SetRepeat(repeat) => {
+            playback.order.repeat = repeat;
+            drop_stale_preload(rodio_tx, radio, playback);
+            radio.write_channel(DataChannel::Player).player.repeat = repeat;
+            tokio::task::spawn_blocking(move || crate::prefs::save_repeat(repeat));
+        }
+        AudioCommand::SetCacheLimit(bytes) => {
+            cache::set_limit(bytes);
+            radio.write_channel(DataChannel::Player).player.cache_limit = bytes;
+            tokio::task::spawn_blocking(move || crate::prefs::save_cache_limit(bytes));
+        }
+        AudioCommand::SetSleepTimer(mode) => {
+            // Undo a fade already under way.
+            if playback.sleep.take().is_some_and(|s| s.fading) {
+                let vol = radio.read().player.volume;
+                rodio_tx.send(RodioCmd::SetVolume(vol)).ok();
+            }
+            info!(?mode, "sleep timer set");
+            playback.sleep = mode.map(SleepTimer::start);
+            let mut state = radio.write_channel(DataChannel::Player);
+            state.player.sleep_timer = mode;
+            state.player.sleep_remaining = None;
+        }
+        AudioCommand::Restore(session) => {
+            let Some(song) = session.current().cloned() else { return };
+            info!(
+                video_id = song.video_id,
+                queue_len = session.queue.len(),
+                position = session.position,
+                "restoring last session"
+            );
+            playback.reset();
+            playback.order.reset(session.queue.len(), session.queue_index);
+            radio.write_channel(DataChannel::Player).player.queue = session.queue;
+            set_now_playing(radio, playback, &song, session.queue_index);
+            playback.restored = Some(session.position);
+            let mut state = radio.write_channel(DataChannel::Player);
+            state.player.is_playing = false;
+            state.player.current_secs = session.position;
+            state.player.total_secs = session.duration;
+        }
+        AudioCommand::SetAutoplay(enabled) => {
+            playback.autoplay.enabled = enabled;
+            if !enabled {
+                playback.autoplay.resume = false;
+            }
+            radio.write_channel(DataChannel::Player).player.autoplay = enabled;
+            tokio::task::spawn_blocking(move || crate::prefs::save_autoplay(enabled));
+        }
+        AudioCommand::RefreshOutputs => refresh_outputs(radio).await,
+        AudioCommand::SetOutput(id) => {
+            rodio_tx.send(RodioCmd::SetOutput(id.clone())).ok();
+            // Each output keeps its own equalizer.
+            playback.output = id.clone().unwrap_or_else(|| DEFAULT_OUTPUT.to_owned());
+            let output = playback.output.clone();
+            let equalizer = tokio::task::spawn_blocking(move || crate::prefs::load_equalizer(&output))
+                .await
+                .unwrap_or_default();
+            let equalizer = playback.eq.update(|s| *s = equalizer);
+            {
+                let mut state = radio.write_channel(DataChannel::Player);
+                state.player.output = id.clone();
+                state.player.equalizer = equalizer;
+            }
+            tokio::task::spawn_blocking(move || crate::prefs::save_output(id.as_deref()));
+        }
+        AudioCommand::SetEqEnabled(enabled) => {
+            update_equalizer(radio, playback, |s| s.enabled = enabled);
+        }
+        AudioCommand::SetEqPreset(preset) => {
+            update_equalizer(radio, playback, |s| s.apply_preset(preset));
+        }
+        AudioCommand::SetEqBand { index, gain_db } => {
+            update_equalizer(radio, playback, |s| {
+                if let Some(band) = s.bands.get_mut(index) {
+                    band.gain_db = gain_db.clamp(-12., 12.);
+                    s.preset = EqPreset::Custom;
+                }
+            });
+        }
+        AudioCommand::SetEqBands(bands) => {
+            update_equalizer(radio, playback, |s| {
+                s.bands = bands;
+                s.preset = EqPreset::Custom;
+            });
+        }
+        AudioCommand::SetEqPreamp(db) => {
+            update_equalizer(radio, playback, |s| s.preamp_db = db.clamp(-12., 12.));
+        }
     }
 }
 
-fn rodio_thread(rx: std::sync::mpsc::Receiver<RodioCmd>, progress: Arc<Mutex<ProgressState>>) {
-    let sink_handle: rodio::MixerDeviceSink = match rodio::DeviceSinkBuilder::open_default_sink() {
-        Ok(mut h) => {
-            h.log_on_drop(false);
-            h
+/// Start `song` as the only queue entry.
+fn play_single(
+    radio: &mut RadioStation<Data, DataChannel>,
+    fetch_tx: &mpsc::Sender<Result<LoadedTrack, String>>,
+    playback: &mut Playback,
+    song: QueueSong,
+) {
+    // Single-song play replaces the queue with just this song, so playback
+    // stops after it unless repeating.
+    playback.reset();
+    radio.write_channel(DataChannel::Player).player.queue = vec![song.clone()];
+    playback.order.reset(1, 0);
+    playback.autoplay.reset();
+    set_now_playing(radio, playback, &song, 0);
+    spawn_load(radio, fetch_tx, &song, playback.quality);
+}
+
+/// Jump from the current queue entry to `index`, using the preloaded track
+/// when it is that entry.
+fn skip_to(
+    rodio_tx: &std::sync::mpsc::Sender<RodioCmd>,
+    radio: &mut RadioStation<Data, DataChannel>,
+    fetch_tx: &mpsc::Sender<Result<LoadedTrack, String>>,
+    playback: &mut Playback,
+    index: usize,
+) {
+    let (song, current) = {
+        let p = &radio.read().player;
+        (p.queue[index].clone(), p.queue_index)
+    };
+    playback.order.advance(current, index);
+    playback.autoplay.resume = false;
+    set_now_playing(radio, playback, &song, index);
+
+    match playback.next.take() {
+        Some(mut next) if next.index == index => {
+            match next.pending.take() {
+                Some(track) => {
+                    rodio_tx.send(RodioCmd::Play(track)).ok();
+                }
+                None => {
+                    rodio_tx.send(RodioCmd::Skip).ok();
+                    rodio_tx.send(RodioCmd::Resume).ok();
+                }
+            }
+            playback.advance(next);
+        }
+        _ => {
+            playback.reset();
+            spawn_load(radio, fetch_tx, &song, playback.quality);
         }
-        Err(e) => {
-            error!(error = %e, "failed to open audio output stream");
+    }
+}
+
+/// Stop playback, keeping the queue.
+fn stop(
+    rodio_tx: &std::sync::mpsc::Sender<RodioCmd>,
+    radio: &mut RadioStation<Data, DataChannel>,
+    playback: &mut Playback,
+) {
+    playback.reset();
+    playback.autoplay.resume = false;
+    rodio_tx.send(RodioCmd::Stop).ok();
+    let mut state = radio.write_channel(DataChannel::Player);
+    state.player.is_playing = false;
+    state.player.current_secs = 0.;
+}
+
+/// Apply `edit` to the queue and move every remembered index along with its
+/// entry; a removed current entry leaves the one now at its position current.
+fn edit_queue(
+    rodio_tx: &std::sync::mpsc::Sender<RodioCmd>,
+    radio: &mut RadioStation<Data, DataChannel>,
+    playback: &mut Playback,
+    edit: QueueEdit<QueueSong>,
+) {
+    let remap = {
+        let mut state = radio.write_channel(DataChannel::Player);
+        let p = &mut state.player;
+        let remap = edit.apply(&mut p.queue);
+        p.queue_index = remap.current(p.queue_index, p.queue.len());
+        remap
+    };
+    playback.order.remap(remap);
+    // A preload still in flight is recognised by its index when it lands.
+    playback.preloading = playback.preloading.and_then(|i| remap.index(i));
+    if let Some(next) = playback.next.as_mut() {
+        match remap.index(next.index) {
+            Some(index) => next.index = index,
+            None => drop_preload(rodio_tx, playback),
+        }
+    }
+}
+
+/// Drop the preloaded track when it is no longer the entry that plays next.
+fn drop_stale_preload(
+    rodio_tx: &std::sync::mpsc::Sender<RodioCmd>,
+    radio: &RadioStation<Data, DataChannel>,
+    playback: &mut Playback,
+) {
+    let upcoming = playback.upcoming(&radio.read().player, true);
+    if playback.next.as_ref().is_some_and(|n| Some(n.index) != upcoming) {
+        drop_preload(rodio_tx, playback);
+    }
+}
+
+/// Re-read the available output devices into the player state.
+async fn refresh_outputs(radio: &mut RadioStation<Data, DataChannel>) {
+    let outputs = tokio::task::spawn_blocking(output::list_outputs)
+        .await
+        .unwrap_or_default();
+    radio.write_channel(DataChannel::Player).player.outputs = outputs;
+}
+
+/// Forget the preloaded next track after the play order changed. One already
+/// appended to the player is cut short so rodio skips over it.
+fn drop_preload(rodio_tx: &std::sync::mpsc::Sender<RodioCmd>, playback: &mut Playback) {
+    if let Some(next) = playback.next.take()
+        && next.pending.is_none()
+    {
+        rodio_tx.send(RodioCmd::DropQueued).ok();
+    }
+    playback.preloading = None;
+}
+
+/// Change the equalizer of the current output. Playing tracks pick it up on
+/// their next sample.
+fn update_equalizer(
+    radio: &mut RadioStation<Data, DataChannel>,
+    playback: &Playback,
+    f: impl FnOnce(&mut EqSettings),
+) {
+    let settings = playback.eq.update(f);
+    radio.write_channel(DataChannel::Player).player.equalizer = settings.clone();
+    let output = playback.output.clone();
+    tokio::task::spawn_blocking(move || crate::prefs::save_equalizer(&output, &settings));
+}
+
+/// The current queue entry and entry `next` come from the same album, where a
+/// crossfade would break segues the artist intended.
+fn same_album(player: &PlayerState, next: usize) -> bool {
+    match (player.queue.get(player.queue_index), player.queue.get(next)) {
+        (Some(prev), Some(next)) => !next.album.is_empty() && prev.album == next.album,
+        _ => false,
+    }
+}
+
+/// Wrap a decoded track with the equalizer, its gain, fade-out, completion,
+/// progress reporting and time-stretch.
+///
+/// `id` orders the tracks handed to rodio. Each one carries its own total
+/// and takes over the progress state once it starts playing, which covers both
+/// gapless appends and the overlap of a crossfade.
+fn track_source(
+    track: PlayerTrack,
+    fade: FadeTrigger,
+    id: u64,
+    progress: &Arc<Mutex<ProgressState>>,
+    eq: &Arc<EqControl>,
+    speed: &SpeedHandle,
+) -> impl Source + Send + 'static {
+    let total = track.source.total_duration().map(|d| d.as_secs_f32());
+    let progress = Arc::clone(progress);
+    let tracked = CompletionNotifier {
+        inner: FadeOut {
+            inner: TrackGain {
+                inner: Equalizer::new(track.source, Arc::clone(eq)),
+                gain: track.gain,
+            },
+            trigger: fade,
+            fade: None,
+        },
+        flag: track.done,
+        notified: false,
+    }
+    .track_position()
+    .periodic_access(PROGRESS_INTERVAL, move |s| {
+        let pos = s.get_pos().as_secs_f32();
+        let Ok(mut p) = progress.lock() else { return };
+        if p.track > id {
             return;
         }
+        p.track = id;
+        p.current_secs = pos;
+        p.total_known = total.is_some();
+        match total {
+            Some(total) => p.total_secs = total,
+            // When total duration is unknown, maintain a rolling estimate
+            // so the seek bar stays functional (always shows ~30s ahead).
+            None if pos + 30.0 > p.total_secs => p.total_secs = pos + 30.0,
+            None => {}
+        }
+    });
+    // Stretch after the position tracker, so it counts content time.
+    TimeStretch::new(tracked, Arc::clone(speed))
+}
+
+/// Rodio's volume for a slider position: squared, so the slider feels linear.
+fn player_volume(vol: f32) -> f32 {
+    vol.powi(2).clamp(0., 1.)
+}
+
+/// Why [`play_on`] gave up its sink.
+enum SinkExit {
+    /// The engine dropped its sender.
+    Closed,
+    /// The stream failed, or the chosen device is back while playing on the
+    /// default one.
+    Lost,
+    /// The engine selected another output.
+    Switch(Option<String>),
+}
+
+fn rodio_thread(
+    rx: std::sync::mpsc::Receiver<RodioCmd>,
+    events: mpsc::UnboundedSender<RodioEvent>,
+    mut output: Option<String>,
+    progress: Arc<Mutex<ProgressState>>,
+    eq: Arc<EqControl>,
+    speed: SpeedHandle,
+) {
+    let mut volume = 1.0;
+    let mut next_track = 0u64;
+    let mut first_open = true;
+
+    let mut wrap = |track| {
+        next_track += 1;
+        let fade = FadeTrigger::default();
+        let source = track_source(track, Arc::clone(&fade), next_track, &progress, &eq, &speed);
+        (source, fade, next_track)
     };
-    let player = rodio::Player::connect_new(sink_handle.mixer());
 
-    while let Ok(cmd) = rx.recv() {
+    loop {
+        let lost = Arc::new(AtomicBool::new(false));
+        let sink = match output::open(output.as_deref(), &lost) {
+            Ok(sink) => sink,
+            Err(e) => {
+                error!(error = %e, "failed to open audio output, retrying");
+                first_open = false;
+                // Commands meanwhile are dropped; the engine reloads the
+                // current track once an output opens.
+                match rx.recv_timeout(REOPEN_INTERVAL) {
+                    Ok(RodioCmd::SetOutput(id)) => output = id,
+                    Ok(RodioCmd::SetVolume(vol)) => volume = player_volume(vol),
+                    Ok(_) | Err(std::sync::mpsc::RecvTimeoutError::Timeout) => {}
+                    Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => return,
+                }
+                continue;
+            }
+        };
+        if !std::mem::take(&mut first_open) {
+            events.send(RodioEvent::OutputReopened).ok();
+        }
+        // On the default device because the chosen one is missing.
+        let preferred = output.as_deref().filter(|id| !output::is_available(id));
+        info!(output = output.as_deref().unwrap_or(DEFAULT_OUTPUT), "audio output opened");
+
+        match play_on(&sink, &rx, &lost, preferred, &mut volume, &progress, &mut wrap) {
+            SinkExit::Closed => return,
+            SinkExit::Lost => warn!("audio output lost, reopening"),
+            SinkExit::Switch(id) => output = id,
+        }
+    }
+}
+
+/// Run rodio commands against one sink until it fails or is switched.
+fn play_on<S: Source + Send + 'static>(
+    sink: &rodio::MixerDeviceSink,
+    rx: &std::sync::mpsc::Receiver<RodioCmd>,
+    lost: &AtomicBool,
+    preferred: Option<&str>,
+    volume: &mut f32,
+    progress: &Mutex<ProgressState>,
+    wrap: &mut impl FnMut(PlayerTrack) -> (S, FadeTrigger, u64),
+) -> SinkExit {
+    let mut player = rodio::Player::connect_new(sink.mixer());
+    player.set_volume(*volume);
+    // Player still playing out the previous track during a crossfade.
+    let mut fading: Option<rodio::Player> = None;
+    // Fade trigger of the track `player` is currently on.
+    let mut current_fade = FadeTrigger::default();
+    // Trigger of a track appended behind the current one.
+    let mut queued_fade: Option<FadeTrigger> = None;
+    let mut preferred_checked = std::time::Instant::now();
+
+    loop {
+        let cmd = match rx.recv_timeout(OUTPUT_POLL_INTERVAL) {
+            Ok(cmd) => Some(cmd),
+            Err(std::sync::mpsc::RecvTimeoutError::Timeout) => None,
+            Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => return SinkExit::Closed,
+        };
+        if lost.load(Ordering::Relaxed) {
+            return SinkExit::Lost;
+        }
+        if let Some(id) = preferred
+            && preferred_checked.elapsed() >= PREFERRED_OUTPUT_INTERVAL
+        {
+            preferred_checked = std::time::Instant::now();
+            if output::is_available(id) {
+                info!(id, "chosen output is back");
+                return SinkExit::Lost;
+            }
+        }
+        let Some(cmd) = cmd else { continue };
+
         match cmd {
-            RodioCmd::Play(bytes, done_flag) => {
+            RodioCmd::Play(track) => {
                 player.clear();
-                let cursor = Cursor::new(bytes);
-                match Decoder::new(cursor) {
-                    Ok(source) => {
-                        let maybe_total = source.total_duration();
-                        let total = maybe_total.map(|d| d.as_secs_f32()).unwrap_or(0.);
-                        if let Ok(mut p) = progress.lock() {
-                            p.total_secs = total;
-                            p.current_secs = 0.;
-                            p.total_known = maybe_total.is_some();
-                        }
-                        let progress_clone = Arc::clone(&progress);
-                        let source = CompletionNotifier {
-                            inner: source,
-                            flag: done_flag,
-                            notified: false,
-                        }
-                        .track_position()
-                        .periodic_access(PROGRESS_INTERVAL, move |s| {
-                            let pos = s.get_pos().as_secs_f32();
-                            if let Ok(mut p) = progress_clone.lock() {
-                                p.current_secs = pos;
-                                // When total duration is unknown, maintain a rolling estimate
-                                // so the seek bar stays functional (always shows ~30s ahead).
-                                if !p.total_known && pos + 30.0 > p.total_secs {
-                                    p.total_secs = pos + 30.0;
-                                }
-                            }
-                        });
-                        player.append(source);
-                        player.play();
-                        info!("playback started, total={total:.1}s, known={}", maybe_total.is_some());
-                    }
-                    Err(e) => error!(error = %e, "audio decode failed"),
+                fading = None;
+                queued_fade = None;
+                let maybe_total = track.source.total_duration();
+                let total = maybe_total.map(|d| d.as_secs_f32()).unwrap_or(0.);
+                let (source, fade, _) = wrap(track);
+                if let Ok(mut p) = progress.lock() {
+                    p.total_secs = total;
+                    p.current_secs = 0.;
+                    p.total_known = maybe_total.is_some();
                 }
+                current_fade = fade;
+                player.append(source);
+                player.play();
+                info!("playback started, total={total:.1}s, known={}", maybe_total.is_some());
+            }
+            RodioCmd::Enqueue(track) => {
+                let (source, fade, _) = wrap(track);
+                queued_fade = Some(fade);
+                player.append(source);
+            }
+            RodioCmd::Crossfade(track, duration) => {
+                // An appended track that rodio already moved on to is the
+                // one to fade out.
+                if player.len() <= 1
+                    && let Some(fade) = queued_fade.take()
+                {
+                    current_fade = fade;
+                }
+                let incoming = rodio::Player::connect_new(sink.mixer());
+                incoming.set_volume(*volume);
+                let maybe_total = track.source.total_duration();
+                let (source, fade, id) = wrap(track);
+                if let Ok(mut p) = progress.lock() {
+                    p.track = id;
+                    p.current_secs = 0.;
+                    p.total_secs = maybe_total.map(|d| d.as_secs_f32()).unwrap_or(0.);
+                    p.total_known = maybe_total.is_some();
+                }
+                incoming.append(source.fade_in(duration));
+                incoming.play();
+
+                // The fade-out runs before time-stretch, so it counts track time.
+                let track_time = duration.mul_f32(load_speed(&speed));
+                let ms = u32::try_from(track_time.as_millis()).unwrap_or(u32::MAX).max(1);
+                current_fade.store(ms, Ordering::Relaxed);
+                current_fade = fade;
+                queued_fade = None;
+                fading = Some(std::mem::replace(&mut player, incoming));
+                info!(secs = duration.as_secs_f32(), "crossfade started");
+            }
+            RodioCmd::Skip => {
+                player.skip_one();
+                if let Some(fade) = queued_fade.take() {
+                    current_fade = fade;
+                }
+            }
+            RodioCmd::DropQueued => {
+                if let Some(fade) = queued_fade.take() {
+                    fade.store(1, Ordering::Relaxed);
+                }
+            }
+            RodioCmd::Pause => {
+                player.pause();
+                if let Some(f) = &fading {
+                    f.pause();
+                }
+            }
+            RodioCmd::Resume => {
+                player.play();
+                if let Some(f) = &fading {
+                    f.play();
+                }
+            }
+            RodioCmd::Stop => {
+                player.clear();
+                fading = None;
+                queued_fade = None;
             }
-            RodioCmd::Pause => player.pause(),
-            RodioCmd::Resume => player.play(),
-            RodioCmd::Stop => player.clear(),
             RodioCmd::Seek(dur) => {
                 if let Err(e) = player.try_seek(dur) {
                     error!(error = %e, "seek failed");
                 }
             }
-            RodioCmd::SetVolume(vol) => player.set_volume(vol.powi(2).clamp(0., 1.)),
+            RodioCmd::SetVolume(vol) => {
+                *volume = player_volume(vol);
+                player.set_volume(*volume);
+                if let Some(f) = &fading {
+                    f.set_volume(*volume);
+                }
+            }
+            RodioCmd::SetOutput(id) => return SinkExit::Switch(id),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playback() -> Playback {
+        Playback::new(
+            EqSettings::default(),
+            DEFAULT_OUTPUT.to_owned(),
+            HashMap::new(),
+            AudioQuality::default(),
+        )
+    }
+
+    #[test]
+    fn seeking_a_loading_track_starts_it_there() {
+        let mut playback = playback();
+        // Another song was picked; the one before plays on until it loads.
+        playback.reset();
+        assert_eq!(playback.seek(5.), None);
+        assert_eq!(playback.start_at(), Some(Duration::from_secs(5)));
+        assert_eq!(playback.start_at(), None);
+    }
+
+    #[test]
+    fn picking_another_song_drops_a_pending_seek() {
+        let mut playback = playback();
+        playback.seek(5.);
+        playback.reset();
+        assert_eq!(playback.start_at(), None);
+    }
+
+    #[test]
+    fn seeking_a_restored_session_moves_where_it_resumes() {
+        let mut playback = playback();
+        playback.restored = Some(30.);
+        assert_eq!(playback.seek(5.), None);
+        assert_eq!(playback.restored, Some(5.));
+        assert_eq!(playback.start_at(), None);
+    }
+}
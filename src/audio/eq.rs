@@ -0,0 +1,414 @@
+//! Equalizer applied to every track between the decoder and the player.
+//!
+//! [`EqSettings`] is a list of biquad bands (peaking or shelving, RBJ audio EQ
+//! cookbook) plus a preamp. The presets are a 10-band graphic EQ at octave
+//! spacing; user-defined bands may use any frequency, gain and Q, which makes
+//! it a parametric EQ. The engine shares an [`EqControl`] with the rodio thread
+//! and every playing [`Equalizer`] picks up changes on its next sample, so
+//! adjustments apply without restarting the track.
+
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rodio::source::{SeekError, Source};
+use serde_json::{Value, json};
+
+/// Centre frequencies of the graphic EQ used by the presets.
+const GRAPHIC_FREQS: [f32; 10] = [
+    31., 62., 125., 250., 500., 1_000., 2_000., 4_000., 8_000., 16_000.,
+];
+
+/// Q of one-octave-wide bands.
+const OCTAVE_Q: f32 = 1.41;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BandKind {
+    Peak,
+    LowShelf,
+    HighShelf,
+}
+
+impl BandKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Peak => "peak",
+            Self::LowShelf => "low_shelf",
+            Self::HighShelf => "high_shelf",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "peak" => Some(Self::Peak),
+            "low_shelf" => Some(Self::LowShelf),
+            "high_shelf" => Some(Self::HighShelf),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EqBand {
+    pub kind: BandKind,
+    /// Centre (peak) or corner (shelf) frequency in Hz.
+    pub freq: f32,
+    pub gain_db: f32,
+    pub q: f32,
+}
+
+impl EqBand {
+    pub fn peak(freq: f32, gain_db: f32) -> Self {
+        Self {
+            kind: BandKind::Peak,
+            freq,
+            gain_db,
+            q: OCTAVE_Q,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EqPreset {
+    #[default]
+    Flat,
+    BassBoost,
+    TrebleBoost,
+    Vocal,
+    Loudness,
+    /// Bands edited by the user.
+    Custom,
+}
+
+impl EqPreset {
+    /// Presets offered in the player menu, in cycling order.
+    pub const ALL: [Self; 6] = [
+        Self::Flat,
+        Self::BassBoost,
+        Self::TrebleBoost,
+        Self::Vocal,
+        Self::Loudness,
+        Self::Custom,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Flat => "flat",
+            Self::BassBoost => "bass_boost",
+            Self::TrebleBoost => "treble_boost",
+            Self::Vocal => "vocal",
+            Self::Loudness => "loudness",
+            Self::Custom => "custom",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|p| p.as_str() == s)
+    }
+
+    /// Graphic EQ bands of this preset; `None` for [`EqPreset::Custom`].
+    pub fn bands(self) -> Option<Vec<EqBand>> {
+        let gains: [f32; 10] = match self {
+            Self::Flat => [0.; 10],
+            Self::BassBoost => [6., 5., 4., 2., 0., 0., 0., 0., 0., 0.],
+            Self::TrebleBoost => [0., 0., 0., 0., 0., 1., 2., 4., 5., 6.],
+            Self::Vocal => [-2., -2., -1., 0., 2., 4., 4., 2., 0., -1.],
+            Self::Loudness => [5., 4., 2., 0., -1., 0., 0., 1., 3., 4.],
+            Self::Custom => return None,
+        };
+        Some(
+            GRAPHIC_FREQS
+                .iter()
+                .zip(gains)
+                .map(|(&freq, gain)| EqBand::peak(freq, gain))
+                .collect(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EqSettings {
+    pub enabled: bool,
+    pub preset: EqPreset,
+    /// Gain applied before the bands, to leave headroom for boosts.
+    pub preamp_db: f32,
+    pub bands: Vec<EqBand>,
+}
+
+impl Default for EqSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            preset: EqPreset::Flat,
+            preamp_db: 0.,
+            bands: EqPreset::Flat.bands().unwrap_or_default(),
+        }
+    }
+}
+
+impl EqSettings {
+    /// Switch to `preset`, replacing the bands unless it is `Custom`.
+    pub fn apply_preset(&mut self, preset: EqPreset) {
+        self.preset = preset;
+        if let Some(bands) = preset.bands() {
+            self.bands = bands;
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "enabled": self.enabled,
+            "preset": self.preset.as_str(),
+            "preamp_db": self.preamp_db,
+            "bands": self.bands.iter().map(|b| json!({
+                "kind": b.kind.as_str(),
+                "freq": b.freq,
+                "gain_db": b.gain_db,
+                "q": b.q,
+            })).collect::<Vec<_>>(),
+        })
+    }
+
+    /// Parse what [`EqSettings::to_json`] wrote; missing fields keep defaults.
+    pub fn from_json(v: &Value) -> Self {
+        let mut s = Self::default();
+        if let Some(enabled) = v["enabled"].as_bool() {
+            s.enabled = enabled;
+        }
+        if let Some(preset) = v["preset"].as_str().and_then(EqPreset::parse) {
+            s.apply_preset(preset);
+        }
+        if let Some(db) = v["preamp_db"].as_f64() {
+            s.preamp_db = db as f32;
+        }
+        if s.preset == EqPreset::Custom
+            && let Some(bands) = v["bands"].as_array()
+        {
+            s.bands = bands
+                .iter()
+                .filter_map(|b| {
+                    Some(EqBand {
+                        kind: BandKind::parse(b["kind"].as_str()?)?,
+                        freq: b["freq"].as_f64()? as f32,
+                        gain_db: b["gain_db"].as_f64()? as f32,
+                        q: b["q"].as_f64()? as f32,
+                    })
+                })
+                .collect();
+        }
+        s
+    }
+}
+
+/// Settings shared between the engine and every playing [`Equalizer`].
+#[derive(Debug)]
+pub struct EqControl {
+    settings: Mutex<EqSettings>,
+    /// Bumped on every change so sources only lock when something changed.
+    version: AtomicU64,
+}
+
+impl EqControl {
+    pub fn new(settings: EqSettings) -> Arc<Self> {
+        Arc::new(Self {
+            settings: Mutex::new(settings),
+            version: AtomicU64::new(1),
+        })
+    }
+
+    /// Modify the settings and return the result.
+    pub fn update(&self, f: impl FnOnce(&mut EqSettings)) -> EqSettings {
+        let mut settings = self.settings.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut settings);
+        self.version.fetch_add(1, Ordering::Release);
+        settings.clone()
+    }
+
+    fn snapshot(&self) -> EqSettings {
+        self.settings.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+/// Normalized biquad coefficients (`a0 == 1`).
+#[derive(Debug, Clone, Copy)]
+struct Coefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Coefficients {
+    fn new(band: &EqBand, sample_rate: f32) -> Option<Self> {
+        // Inaudible or above Nyquist: leave the band out.
+        if band.gain_db.abs() < 0.01 || band.freq <= 0. || band.freq >= sample_rate * 0.49 {
+            return None;
+        }
+        let a = 10f32.powf(band.gain_db / 40.);
+        let w0 = 2. * PI * band.freq / sample_rate;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2. * band.q.max(0.1));
+        let sqrt_a_alpha = 2. * a.sqrt() * alpha;
+
+        let (b0, b1, b2, a0, a1, a2) = match band.kind {
+            BandKind::Peak => (
+                1. + alpha * a,
+                -2. * cos,
+                1. - alpha * a,
+                1. + alpha / a,
+                -2. * cos,
+                1. - alpha / a,
+            ),
+            BandKind::LowShelf => (
+                a * ((a + 1.) - (a - 1.) * cos + sqrt_a_alpha),
+                2. * a * ((a - 1.) - (a + 1.) * cos),
+                a * ((a + 1.) - (a - 1.) * cos - sqrt_a_alpha),
+                (a + 1.) + (a - 1.) * cos + sqrt_a_alpha,
+                -2. * ((a - 1.) + (a + 1.) * cos),
+                (a + 1.) + (a - 1.) * cos - sqrt_a_alpha,
+            ),
+            BandKind::HighShelf => (
+                a * ((a + 1.) + (a - 1.) * cos + sqrt_a_alpha),
+                -2. * a * ((a - 1.) + (a + 1.) * cos),
+                a * ((a + 1.) + (a - 1.) * cos - sqrt_a_alpha),
+                (a + 1.) - (a - 1.) * cos + sqrt_a_alpha,
+                2. * ((a - 1.) - (a + 1.) * cos),
+                (a + 1.) - (a - 1.) * cos - sqrt_a_alpha,
+            ),
+        };
+        Some(Self {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        })
+    }
+}
+
+/// Filter history of one band on one channel (direct form I).
+#[derive(Debug, Clone, Copy, Default)]
+struct History {
+    x1: f32,
+    x2: f32,
+    y1: f32,
+    y2: f32,
+}
+
+impl History {
+    fn process(&mut self, c: &Coefficients, x: f32) -> f32 {
+        let y = c.b0 * x + c.b1 * self.x1 + c.b2 * self.x2 - c.a1 * self.y1 - c.a2 * self.y2;
+        self.x2 = self.x1;
+        self.x1 = x;
+        self.y2 = self.y1;
+        self.y1 = y;
+        y
+    }
+}
+
+/// [`Source`] adapter running the shared [`EqSettings`] over interleaved samples.
+pub struct Equalizer<S: Source> {
+    inner: S,
+    control: Arc<EqControl>,
+    version: u64,
+    sample_rate: u32,
+    enabled: bool,
+    preamp: f32,
+    coefficients: Vec<Coefficients>,
+    /// `history[channel][band]`.
+    history: Vec<Vec<History>>,
+    channel: usize,
+}
+
+impl<S: Source> Equalizer<S> {
+    pub fn new(inner: S, control: Arc<EqControl>) -> Self {
+        Self {
+            inner,
+            control,
+            version: 0,
+            sample_rate: 0,
+            enabled: false,
+            preamp: 1.,
+            coefficients: Vec::new(),
+            history: Vec::new(),
+            channel: 0,
+        }
+    }
+
+    /// Rebuild the filters after a settings or format change.
+    fn configure(&mut self) {
+        let settings = self.control.snapshot();
+        let sample_rate = self.inner.sample_rate().get();
+        let channels = usize::from(self.inner.channels().get());
+
+        self.sample_rate = sample_rate;
+        self.enabled = settings.enabled;
+        self.preamp = 10f32.powf(settings.preamp_db / 20.);
+        self.coefficients = settings
+            .bands
+            .iter()
+            .filter_map(|b| Coefficients::new(b, sample_rate as f32))
+            .collect();
+        self.history = vec![vec![History::default(); self.coefficients.len()]; channels];
+        self.channel = 0;
+    }
+}
+
+impl<S: Source> Iterator for Equalizer<S> {
+    type Item = rodio::Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.channel == 0 {
+            let version = self.control.version.load(Ordering::Acquire);
+            if version != self.version || self.inner.sample_rate().get() != self.sample_rate {
+                self.version = version;
+                self.configure();
+            }
+        }
+
+        let sample = self.inner.next()?;
+        let channel = self.channel;
+        self.channel = (channel + 1) % self.history.len().max(1);
+        if !self.enabled {
+            return Some(sample);
+        }
+
+        let mut y = sample * self.preamp;
+        if let Some(history) = self.history.get_mut(channel) {
+            for (h, c) in history.iter_mut().zip(&self.coefficients) {
+                y = h.process(c, y);
+            }
+        }
+        Some(y)
+    }
+}
+
+impl<S: Source> Source for Equalizer<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        self.inner.current_span_len()
+    }
+
+    fn channels(&self) -> rodio::ChannelCount {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        // Old filter state would ring into the new position.
+        for h in self.history.iter_mut().flatten() {
+            *h = History::default();
+        }
+        self.channel = 0;
+        Ok(())
+    }
+}
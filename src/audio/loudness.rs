@@ -0,0 +1,141 @@
+//! Loudness normalization from `YouTube`'s per-track `loudnessDb`.
+
+/// Largest boost applied to quiet tracks. Peaks it pushes past full scale
+/// are bent back by [`limit`].
+const MAX_BOOST_DB: f32 = 6.0;
+
+/// Level above which [`limit`] starts bending samples, about -1 dBFS.
+const LIMIT_KNEE: f32 = 0.89;
+
+/// Largest cut applied to loud tracks.
+const MAX_CUT_DB: f32 = -20.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NormalizationMode {
+    Off,
+    /// Every track is brought to the reference level on its own.
+    #[default]
+    Track,
+    /// Consecutive tracks of one album share the gain of the first, keeping
+    /// the loudness differences the album was mastered with.
+    Album,
+}
+
+impl NormalizationMode {
+    /// Name stored in prefs.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Track => "track",
+            Self::Album => "album",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "off" => Some(Self::Off),
+            "track" => Some(Self::Track),
+            "album" => Some(Self::Album),
+            _ => None,
+        }
+    }
+}
+
+/// Soft limiter for boosted samples: leaves those below the knee alone and
+/// bends louder ones smoothly towards full scale, so they never clip.
+pub fn limit(sample: f32) -> f32 {
+    let level = sample.abs();
+    if level <= LIMIT_KNEE {
+        return sample;
+    }
+    let room = 1. - LIMIT_KNEE;
+    (LIMIT_KNEE + room * ((level - LIMIT_KNEE) / room).tanh()).copysign(sample)
+}
+
+/// Computes the gain of each track handed to the player.
+#[derive(Debug, Default)]
+pub struct Normalizer {
+    pub mode: NormalizationMode,
+    /// Album of the last track and the gain it was given, for album mode.
+    album: Option<(String, f32)>,
+}
+
+impl Normalizer {
+    pub fn new(mode: NormalizationMode) -> Self {
+        Self { mode, album: None }
+    }
+
+    /// Linear gain for a track of `album` with the given `loudnessDb`.
+    ///
+    /// Call in playback order: album mode remembers the previous track.
+    pub fn gain(&mut self, album: &str, loudness_db: Option<f32>) -> f32 {
+        let track_db = loudness_db.map_or(0., |db| (-db).clamp(MAX_CUT_DB, MAX_BOOST_DB));
+        let db = match self.mode {
+            NormalizationMode::Off => 0.,
+            NormalizationMode::Track => track_db,
+            NormalizationMode::Album => match &self.album {
+                Some((prev, db)) if !album.is_empty() && prev == album => *db,
+                _ => track_db,
+            },
+        };
+        self.album = Some((album.to_owned(), db));
+        10f32.powf(db / 20.)
+    }
+
+    /// Forget the album run, e.g. after the user jumps elsewhere in the queue.
+    pub fn reset(&mut self) {
+        self.album = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boost_is_capped() {
+        let mut n = Normalizer::new(NormalizationMode::Track);
+        let max = 10f32.powf(MAX_BOOST_DB / 20.);
+        assert_eq!(n.gain("", Some(-30.)), max);
+        assert_eq!(n.gain("", None), 1.);
+    }
+
+    #[test]
+    fn album_tracks_share_the_album_gain() {
+        let db = |gain: f32| (20. * gain.log10() * 10.).round() / 10.;
+        let mut n = Normalizer::new(NormalizationMode::Album);
+        // The first track of a run sets the gain for the rest of its album.
+        assert_eq!(db(n.gain("Album", Some(3.))), -3.);
+        assert_eq!(db(n.gain("Album", Some(-2.))), -3.);
+        assert_eq!(db(n.gain("Album", None)), -3.);
+        // Another album, or a single without one, gets its own gain.
+        assert_eq!(db(n.gain("Other", Some(-2.))), 2.);
+        assert_eq!(db(n.gain("", Some(1.))), -1.);
+        assert_eq!(db(n.gain("", Some(4.))), -4.);
+
+        // After a jump the album is measured afresh.
+        n.gain("Album", Some(3.));
+        n.reset();
+        assert_eq!(db(n.gain("Album", Some(-2.))), 2.);
+
+        let mut track = Normalizer::new(NormalizationMode::Track);
+        track.gain("Album", Some(3.));
+        assert_eq!(db(track.gain("Album", Some(-2.))), 2.);
+    }
+
+    #[test]
+    fn boosted_peaks_do_not_clip() {
+        let gain = 10f32.powf(MAX_BOOST_DB / 20.);
+        for i in 0..=1000 {
+            let sample = i as f32 / 1000.;
+            let out = limit(sample * gain);
+            assert!(out <= 1., "{sample} -> {out}");
+            assert_eq!(limit(-sample * gain), -out);
+        }
+        // Quiet samples pass untouched; louder ones keep their order.
+        assert_eq!(limit(0.5), 0.5);
+        assert_eq!(limit(-LIMIT_KNEE), -LIMIT_KNEE);
+        assert!(limit(0.95) < limit(1.2));
+        assert!(limit(1.2) < limit(1.9));
+    }
+}
@@ -0,0 +1,464 @@
+//! Which queue entry plays next: repeat modes, shuffle and play history.
+//!
+//! The queue itself always stays in its original order; shuffle only changes
+//! the order entries are visited in. Turning shuffle off therefore continues
+//! from the current entry in the original order. Edits to the queue go
+//! through [`QueueEdit`], whose [`Remap`] moves remembered indices along.
+
+use std::cmp::Ordering;
+
+use rand::seq::SliceRandom;
+
+/// Entries kept in the play history.
+const HISTORY_LEN: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RepeatMode {
+    #[default]
+    Off,
+    /// Start over from the first entry after the last one.
+    All,
+    /// Replay the current entry when it ends.
+    One,
+}
+
+impl RepeatMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::All => "all",
+            Self::One => "one",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "off" => Some(Self::Off),
+            "all" => Some(Self::All),
+            "one" => Some(Self::One),
+            _ => None,
+        }
+    }
+
+    /// Next mode when cycling from the player bar.
+    pub fn cycle(self) -> Self {
+        match self {
+            Self::Off => Self::All,
+            Self::All => Self::One,
+            Self::One => Self::Off,
+        }
+    }
+}
+
+/// A change to the entries of the queue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueueEdit<T> {
+    /// `entry` inserted at `at`.
+    Insert { at: usize, entry: T },
+    Remove(usize),
+    Move { from: usize, to: usize },
+    /// Every entry but this one dropped.
+    KeepOnly(usize),
+}
+
+impl<T> QueueEdit<T> {
+    /// Edit `queue`, whose indices must be in range, and return where the
+    /// entries it had went.
+    pub fn apply(self, queue: &mut Vec<T>) -> Remap {
+        match self {
+            Self::Insert { at, entry } => {
+                queue.insert(at, entry);
+                Remap::Insert(at)
+            }
+            Self::Remove(index) => {
+                queue.remove(index);
+                Remap::Remove(index)
+            }
+            Self::Move { from, to } => {
+                let entry = queue.remove(from);
+                queue.insert(to, entry);
+                Remap::Move { from, to }
+            }
+            Self::KeepOnly(index) => {
+                queue.truncate(index + 1);
+                queue.drain(..index);
+                Remap::KeepOnly(index)
+            }
+        }
+    }
+}
+
+/// New positions of the queue entries after a [`QueueEdit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Remap {
+    Insert(usize),
+    Remove(usize),
+    Move { from: usize, to: usize },
+    KeepOnly(usize),
+}
+
+impl Remap {
+    /// New index of the entry that was at `i`, `None` once it was removed.
+    pub fn index(self, i: usize) -> Option<usize> {
+        match self {
+            Self::Insert(at) => Some(if i >= at { i + 1 } else { i }),
+            Self::Remove(index) => match i.cmp(&index) {
+                Ordering::Less => Some(i),
+                Ordering::Equal => None,
+                Ordering::Greater => Some(i - 1),
+            },
+            Self::Move { from, to } => Some(if i == from {
+                to
+            } else if from < to && (from + 1..=to).contains(&i) {
+                i - 1
+            } else if to < from && (to..from).contains(&i) {
+                i + 1
+            } else {
+                i
+            }),
+            Self::KeepOnly(index) => (i == index).then_some(0),
+        }
+    }
+
+    /// Current index in the edited queue of `len` entries: the same entry,
+    /// or the one now at its position if it was removed.
+    pub fn current(self, current: usize, len: usize) -> usize {
+        self.index(current)
+            .unwrap_or_else(|| current.min(len.saturating_sub(1)))
+    }
+}
+
+/// Playing order over a queue of `len` entries.
+#[derive(Debug, Default)]
+pub struct PlayOrder {
+    pub shuffle: bool,
+    pub repeat: RepeatMode,
+    /// Shuffled indices not played yet in this round, next one last.
+    upcoming: Vec<usize>,
+    /// Indices played before the current one, most recent last.
+    history: Vec<usize>,
+}
+
+impl PlayOrder {
+    /// Shuffle in an entry appended to the queue.
+    pub fn enqueue(&mut self, index: usize) {
+        if self.shuffle {
+            let at = rand::random::<usize>() % (self.upcoming.len() + 1);
+            self.upcoming.insert(at, index);
+        }
+    }
+
+    /// Start over on a new queue, playing `current` first.
+    pub fn reset(&mut self, len: usize, current: usize) {
+        self.history.clear();
+        self.upcoming.clear();
+        if self.shuffle {
+            self.reshuffle(len, current);
+        }
+    }
+
+    pub fn set_shuffle(&mut self, shuffle: bool, len: usize, current: usize) {
+        self.shuffle = shuffle;
+        self.upcoming.clear();
+        if shuffle {
+            self.reshuffle(len, current);
+        }
+    }
+
+    fn reshuffle(&mut self, len: usize, current: usize) {
+        self.upcoming = (0..len).filter(|&i| i != current).collect();
+        self.upcoming.shuffle(&mut rand::thread_rng());
+    }
+
+    /// Index to play after `current`: `auto` when the current entry ended by
+    /// itself (repeat-one only applies then), otherwise a skip.
+    ///
+    /// Stable until [`PlayOrder::advance`], so a preload matches the advance.
+    pub fn peek_next(&mut self, len: usize, current: usize, auto: bool) -> Option<usize> {
+        if len == 0 {
+            return None;
+        }
+        if auto && self.repeat == RepeatMode::One {
+            return Some(current.min(len - 1));
+        }
+        let wrap = self.repeat != RepeatMode::Off;
+        if self.shuffle {
+            self.upcoming.retain(|&i| i < len && i != current);
+            if self.upcoming.is_empty() && wrap {
+                self.reshuffle(len, current);
+            }
+            return self.upcoming.last().copied();
+        }
+        match current + 1 {
+            next if next < len => Some(next),
+            _ if wrap => Some(0),
+            _ => None,
+        }
+    }
+
+    /// Entries left to play after `current` before the queue runs out,
+    /// ignoring repeat.
+    pub fn remaining(&self, len: usize, current: usize) -> usize {
+        if self.shuffle {
+            self.upcoming.iter().filter(|&&i| i < len && i != current).count()
+        } else {
+            len.saturating_sub(current + 1)
+        }
+    }
+
+    /// Move from `current` to `next`, remembering `current` for
+    /// [`PlayOrder::previous`].
+    pub fn advance(&mut self, current: usize, next: usize) {
+        if current != next {
+            self.history.push(current);
+            if self.history.len() > HISTORY_LEN {
+                self.history.remove(0);
+            }
+        }
+        self.upcoming.retain(|&i| i != next);
+    }
+
+    /// Rewrite remembered indices after the queue was edited.
+    pub fn remap(&mut self, remap: Remap) {
+        self.upcoming = self.upcoming.iter().filter_map(|&i| remap.index(i)).collect();
+        self.history = self.history.iter().filter_map(|&i| remap.index(i)).collect();
+    }
+
+    /// Make `index`, just inserted after the current entry, the next one.
+    pub fn play_next(&mut self, index: usize) {
+        if self.shuffle {
+            self.upcoming.push(index);
+        }
+    }
+
+    /// Index to go back to from `current`. With shuffle that is the entry
+    /// actually heard before, which `current` then follows again.
+    pub fn previous(&mut self, len: usize, current: usize) -> Option<usize> {
+        if self.shuffle {
+            self.history.retain(|&i| i < len);
+            let prev = self.history.pop()?;
+            self.upcoming.push(current);
+            return Some(prev);
+        }
+        match current.checked_sub(1) {
+            Some(prev) => Some(prev),
+            None if self.repeat == RepeatMode::All => len.checked_sub(1),
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUEUE: [&str; 6] = ["a", "b", "c", "d", "e", "f"];
+
+    /// Apply `edit` and check every remaining entry is found at its new index.
+    fn check(edit: QueueEdit<&'static str>) -> (Vec<&'static str>, Remap) {
+        let mut queue = QUEUE.to_vec();
+        let remap = edit.apply(&mut queue);
+        let kept: Vec<usize> = (0..QUEUE.len()).filter_map(|i| remap.index(i)).collect();
+        for (i, entry) in QUEUE.iter().enumerate() {
+            if let Some(j) = remap.index(i) {
+                assert_eq!(queue[j], *entry, "{remap:?} moved {entry} to {j}");
+            }
+        }
+        let mut unique = kept.clone();
+        unique.dedup();
+        assert_eq!(unique.len(), kept.len());
+        (queue, remap)
+    }
+
+    #[test]
+    fn edits_move_indices_with_their_entries() {
+        let (queue, _) = check(QueueEdit::Insert { at: 2, entry: "new" });
+        assert_eq!(queue, ["a", "b", "new", "c", "d", "e", "f"]);
+        let (queue, _) = check(QueueEdit::Remove(1));
+        assert_eq!(queue, ["a", "c", "d", "e", "f"]);
+        let (queue, _) = check(QueueEdit::Move { from: 1, to: 4 });
+        assert_eq!(queue, ["a", "c", "d", "e", "b", "f"]);
+        let (queue, _) = check(QueueEdit::Move { from: 4, to: 0 });
+        assert_eq!(queue, ["e", "a", "b", "c", "d", "f"]);
+        let (queue, _) = check(QueueEdit::KeepOnly(3));
+        assert_eq!(queue, ["d"]);
+    }
+
+    #[test]
+    fn current_entry_stays_current() {
+        // Edits before the current entry shift it; edits after leave it.
+        assert_eq!(Remap::Remove(1).current(3, 5), 2);
+        assert_eq!(Remap::Remove(4).current(3, 5), 3);
+        assert_eq!(Remap::Insert(2).current(3, 7), 4);
+        assert_eq!(Remap::Move { from: 0, to: 5 }.current(3, 6), 2);
+        assert_eq!(Remap::Move { from: 5, to: 0 }.current(3, 6), 4);
+        assert_eq!(Remap::Move { from: 3, to: 1 }.current(3, 6), 1);
+        assert_eq!(Remap::KeepOnly(3).current(3, 1), 0);
+        // A removed current entry hands over to the one taking its place,
+        // or to the new last one.
+        assert_eq!(Remap::Remove(3).current(3, 5), 3);
+        assert_eq!(Remap::Remove(5).current(5, 5), 4);
+        assert_eq!(Remap::Remove(0).current(0, 0), 0);
+    }
+
+    /// Every index `order` plays after `current` until it runs out.
+    fn play_out(order: &mut PlayOrder, len: usize, mut current: usize) -> Vec<usize> {
+        let mut played = Vec::new();
+        while let Some(next) = order.peek_next(len, current, true) {
+            order.advance(current, next);
+            played.push(next);
+            current = next;
+        }
+        played
+    }
+
+    #[test]
+    fn shuffle_follows_edits() {
+        let mut order = PlayOrder::default();
+        order.set_shuffle(true, 6, 2);
+        let mut current = order.peek_next(6, 2, false).unwrap();
+        order.advance(2, current);
+        let mut heard = 2;
+
+        // Drop an entry still to come, then move the current one to the top.
+        let mut queue = vec![(); 6];
+        let mut edit = |edit: QueueEdit<()>, current: &mut usize, heard: &mut usize| {
+            let remap = edit.apply(&mut queue);
+            order.remap(remap);
+            *current = remap.current(*current, queue.len());
+            *heard = remap.index(*heard).unwrap();
+        };
+        let gone = (0..6).find(|&i| i != heard && i != current).unwrap();
+        edit(QueueEdit::Remove(gone), &mut current, &mut heard);
+        edit(QueueEdit::Move { from: current, to: 0 }, &mut current, &mut heard);
+        let len = queue.len();
+        assert_eq!((current, len), (0, 5));
+
+        // Every other entry plays once, then going back ends at the one
+        // heard before the edits.
+        let mut played = play_out(&mut order, len, current);
+        let mut at = *played.last().unwrap();
+        played.sort_unstable();
+        let expected: Vec<usize> = (1..len).filter(|&i| i != heard).collect();
+        assert_eq!(played, expected);
+        let mut back = Vec::new();
+        while let Some(prev) = order.previous(len, at) {
+            back.push(prev);
+            at = prev;
+        }
+        assert_eq!(back.last(), Some(&heard));
+        assert_eq!(back.len(), expected.len() + 1);
+    }
+
+    #[test]
+    fn clearing_keeps_only_the_current_entry() {
+        let mut order = PlayOrder::default();
+        order.set_shuffle(true, 6, 3);
+        let current = order.peek_next(6, 3, false).unwrap();
+        order.advance(3, current);
+
+        let remap = Remap::KeepOnly(current);
+        order.remap(remap);
+        assert_eq!(remap.current(current, 1), 0);
+        assert_eq!(order.peek_next(1, 0, false), None);
+        assert_eq!(order.previous(1, 0), None);
+    }
+
+    #[test]
+    fn repeat_one_replays_only_when_the_entry_ends() {
+        let mut order = PlayOrder {
+            repeat: RepeatMode::One,
+            ..PlayOrder::default()
+        };
+        assert_eq!(order.peek_next(3, 1, true), Some(1));
+        assert_eq!(order.peek_next(3, 1, false), Some(2));
+        // A skip off the end wraps like repeat-all.
+        assert_eq!(order.peek_next(3, 2, false), Some(0));
+    }
+
+    #[test]
+    fn repeat_all_wraps_both_ways() {
+        let mut order = PlayOrder::default();
+        assert_eq!(order.peek_next(3, 2, true), None);
+        assert_eq!(order.previous(3, 0), None);
+
+        order.repeat = RepeatMode::All;
+        assert_eq!(order.peek_next(3, 1, true), Some(2));
+        assert_eq!(order.peek_next(3, 2, true), Some(0));
+        assert_eq!(order.previous(3, 2), Some(1));
+        assert_eq!(order.previous(3, 0), Some(2));
+    }
+
+    #[test]
+    fn shuffle_plays_every_entry_once_per_round() {
+        let mut order = PlayOrder::default();
+        order.set_shuffle(true, 8, 5);
+        let mut played = play_out(&mut order, 8, 5);
+        assert_eq!(order.remaining(8, *played.last().unwrap()), 0);
+        played.sort_unstable();
+        assert_eq!(played, [0, 1, 2, 3, 4, 6, 7]);
+
+        // With repeat-all, each round is a full pass over the queue.
+        order.repeat = RepeatMode::All;
+        order.reset(8, 5);
+        let mut current = 5;
+        for _ in 0..3 {
+            let mut round = Vec::new();
+            for _ in 0..7 {
+                let next = order.peek_next(8, current, true).unwrap();
+                assert_eq!(order.peek_next(8, current, true), Some(next), "peek is stable");
+                order.advance(current, next);
+                round.push(next);
+                current = next;
+            }
+            round.push(current);
+            round.sort_unstable();
+            round.dedup();
+            assert_eq!(round.len(), 7, "no entry twice in a round");
+        }
+    }
+
+    #[test]
+    fn shuffle_goes_back_through_what_was_heard() {
+        let mut order = PlayOrder::default();
+        order.set_shuffle(true, 6, 0);
+        let mut heard = vec![0];
+        for _ in 0..3 {
+            let current = *heard.last().unwrap();
+            let next = order.peek_next(6, current, false).unwrap();
+            order.advance(current, next);
+            heard.push(next);
+        }
+
+        let mut at = *heard.last().unwrap();
+        let mut back = vec![at];
+        while let Some(prev) = order.previous(6, at) {
+            back.push(prev);
+            at = prev;
+        }
+        back.reverse();
+        assert_eq!(back, heard);
+
+        // Going forward again retraces the same entries.
+        let mut forward = vec![at];
+        for _ in 0..3 {
+            let next = order.peek_next(6, at, false).unwrap();
+            order.advance(at, next);
+            forward.push(next);
+            at = next;
+        }
+        assert_eq!(forward, heard);
+    }
+
+    #[test]
+    fn unshuffling_continues_in_queue_order() {
+        let mut order = PlayOrder::default();
+        order.set_shuffle(true, 6, 0);
+        let current = order.peek_next(6, 0, false).unwrap();
+        order.advance(0, current);
+
+        order.set_shuffle(false, 6, current);
+        let expected: Vec<usize> = (current + 1..6).collect();
+        assert_eq!(play_out(&mut order, 6, current), expected);
+    }
+}
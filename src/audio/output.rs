@@ -0,0 +1,75 @@
+//! Audio output devices: listing them and opening a sink on one.
+//!
+//! Devices are identified by cpal's device ID, which stays the same across
+//! restarts, and shown by their description. A sink reports stream errors
+//! (usually the device going away) through a flag the rodio thread polls.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use rodio::cpal::traits::{DeviceTrait, HostTrait};
+use rodio::{DeviceSinkBuilder, MixerDeviceSink};
+use tracing::warn;
+
+/// An output device as shown in the device picker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputDevice {
+    pub id: String,
+    pub name: String,
+}
+
+/// Output devices of the default host. Enumerating can be slow on some
+/// backends; call it off the UI thread.
+pub fn list_outputs() -> Vec<OutputDevice> {
+    let Ok(devices) = rodio::cpal::default_host().output_devices() else {
+        return Vec::new();
+    };
+    devices
+        .filter_map(|d| {
+            Some(OutputDevice {
+                id: d.id().ok()?.to_string(),
+                name: d.description().ok()?.name().to_owned(),
+            })
+        })
+        .collect()
+}
+
+/// `true` when the output device `id` is currently available.
+pub fn is_available(id: &str) -> bool {
+    find_device(id).is_some()
+}
+
+fn find_device(id: &str) -> Option<rodio::cpal::Device> {
+    rodio::cpal::default_host()
+        .output_devices()
+        .ok()?
+        .find(|d| d.id().is_ok_and(|d| d.to_string() == id))
+}
+
+/// Open a sink on device `id`, or on the default device when `id` is `None`
+/// or not connected. `lost` is set when the stream fails afterwards.
+pub fn open(id: Option<&str>, lost: &Arc<AtomicBool>) -> Result<MixerDeviceSink, String> {
+    let device = id.and_then(find_device);
+    if let Some(id) = id
+        && device.is_none()
+    {
+        warn!(id, "output device not available, using the default one");
+    }
+
+    let builder = match device {
+        Some(device) => DeviceSinkBuilder::from_device(device),
+        None => DeviceSinkBuilder::from_default_device(),
+    }
+    .map_err(|e| e.to_string())?;
+
+    let lost = Arc::clone(lost);
+    let mut sink = builder
+        .with_error_callback(move |e| {
+            warn!(error = %e, "audio output stream failed");
+            lost.store(true, Ordering::Relaxed);
+        })
+        .open_sink()
+        .map_err(|e| e.to_string())?;
+    sink.log_on_drop(false);
+    Ok(sink)
+}
@@ -0,0 +1,166 @@
+//! The playback session kept across restarts: queue, current entry and
+//! position. Shuffle and repeat are stored with the other preferences.
+//!
+//! The engine saves it shortly after it changes; while a track plays, the
+//! position changes all the time, so it is also saved every
+//! [`MAX_SAVE_DELAY`] at most.
+
+use std::time::{Duration, Instant};
+
+use serde_json::{Value, json};
+
+use crate::app::{PlayerState, QueueSong};
+
+/// Save once nothing changed for this long.
+const SAVE_DELAY: Duration = Duration::from_secs(2);
+
+/// Longest a change goes unsaved while changes keep coming.
+const MAX_SAVE_DELAY: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Session {
+    pub queue: Vec<QueueSong>,
+    pub queue_index: usize,
+    /// Position in the current entry, in seconds.
+    pub position: f32,
+    /// Length of the current entry, in seconds; `0` when unknown.
+    pub duration: f32,
+}
+
+impl Session {
+    pub fn from_player(p: &PlayerState) -> Self {
+        Self {
+            queue: p.queue.clone(),
+            queue_index: p.queue_index,
+            position: p.current_secs,
+            duration: p.total_secs,
+        }
+    }
+
+    /// The entry to show as current, `None` for an empty queue.
+    pub fn current(&self) -> Option<&QueueSong> {
+        self.queue.get(self.queue_index)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "queue": self.queue.iter().map(QueueSong::to_json).collect::<Vec<_>>(),
+            "queue_index": self.queue_index,
+            "position": self.position,
+            "duration": self.duration,
+        })
+    }
+
+    /// Parse what [`Session::to_json`] wrote. Entries without a video ID are
+    /// dropped; an index past the end falls back to the first entry.
+    pub fn from_json(v: &Value) -> Self {
+        let queue: Vec<QueueSong> = v["queue"]
+            .as_array()
+            .map(|songs| songs.iter().filter_map(QueueSong::from_json).collect())
+            .unwrap_or_default();
+        let queue_index = v["queue_index"]
+            .as_u64()
+            .map(|i| i as usize)
+            .filter(|&i| i < queue.len())
+            .unwrap_or_default();
+        Self {
+            queue,
+            queue_index,
+            position: v["position"].as_f64().unwrap_or_default().max(0.) as f32,
+            duration: v["duration"].as_f64().unwrap_or_default().max(0.) as f32,
+        }
+    }
+}
+
+/// Decides when a changed session is due to be written.
+#[derive(Default)]
+pub struct SessionSaver {
+    /// First and latest change not saved yet.
+    dirty: Option<(Instant, Instant)>,
+}
+
+impl SessionSaver {
+    pub fn touch(&mut self) {
+        let now = Instant::now();
+        let first = self.dirty.map_or(now, |(first, _)| first);
+        self.dirty = Some((first, now));
+    }
+
+    /// `true` once the pending change should be saved; clears it.
+    pub fn due(&mut self) -> bool {
+        let Some((first, last)) = self.dirty else {
+            return false;
+        };
+        let due = last.elapsed() >= SAVE_DELAY || first.elapsed() >= MAX_SAVE_DELAY;
+        if due {
+            self.dirty = None;
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn song(id: &str, podcast: Option<&str>) -> QueueSong {
+        QueueSong {
+            video_id: id.to_owned(),
+            title: format!("Title {id}"),
+            artist: "Artist".to_owned(),
+            album: String::new(),
+            thumbnail_url: format!("https://example.com/{id}.jpg"),
+            podcast: podcast.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let session = Session {
+            queue: vec![song("a", None), song("b", Some("MPSPshow"))],
+            queue_index: 1,
+            position: 42.5,
+            duration: 180.,
+        };
+        assert_eq!(Session::from_json(&session.to_json()), session);
+        assert_eq!(session.current().map(|s| s.video_id.as_str()), Some("b"));
+    }
+
+    #[test]
+    fn repairs_bad_input() {
+        let v = json!({
+            "queue": [{ "title": "no id" }, song("a", None).to_json()],
+            "queue_index": 5,
+            "position": -3.,
+        });
+        let session = Session::from_json(&v);
+        assert_eq!(session.queue, [song("a", None)]);
+        assert_eq!((session.queue_index, session.position, session.duration), (0, 0., 0.));
+
+        let empty = Session::from_json(&Value::Null);
+        assert_eq!(empty, Session::default());
+        assert!(empty.current().is_none());
+    }
+
+    #[test]
+    fn saves_after_a_quiet_spell_or_the_longest_delay() {
+        let now = Instant::now();
+        let mut saver = SessionSaver::default();
+        assert!(!saver.due());
+
+        saver.touch();
+        assert!(!saver.due());
+        saver.dirty = Some((now, now - SAVE_DELAY));
+        assert!(saver.due());
+        assert!(!saver.due());
+
+        // Changes keep coming, but the first one has waited long enough.
+        saver.dirty = Some((now - MAX_SAVE_DELAY, now));
+        assert!(saver.due());
+
+        saver.dirty = Some((now - MAX_SAVE_DELAY / 2, now));
+        saver.touch();
+        assert_eq!(saver.dirty.map(|(first, _)| first), Some(now - MAX_SAVE_DELAY / 2));
+        assert!(!saver.due());
+    }
+}
@@ -0,0 +1,116 @@
+//! Sleep timer: stops playback after a set time, or once the current track or
+//! the queue has ended, fading the volume out over the last seconds.
+
+use std::time::{Duration, Instant};
+
+/// The volume fades out over this long before playback stops.
+pub const FADE_SECS: f32 = 10.;
+
+/// When the sleep timer stops playback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SleepMode {
+    /// After this many minutes.
+    Minutes(u32),
+    /// When the current track ends.
+    EndOfTrack,
+    /// When the last track of the queue ends. Autoplay adds nothing while
+    /// this is set.
+    EndOfQueue,
+}
+
+/// A running sleep timer.
+#[derive(Debug)]
+pub struct SleepTimer {
+    pub mode: SleepMode,
+    /// When a `Minutes` timer runs out.
+    deadline: Option<Instant>,
+    /// The volume has been lowered by the fade.
+    pub fading: bool,
+}
+
+impl SleepTimer {
+    pub fn start(mode: SleepMode) -> Self {
+        let deadline = match mode {
+            SleepMode::Minutes(minutes) => {
+                Some(Instant::now() + Duration::from_secs(u64::from(minutes) * 60))
+            }
+            SleepMode::EndOfTrack | SleepMode::EndOfQueue => None,
+        };
+        Self {
+            mode,
+            deadline,
+            fading: false,
+        }
+    }
+
+    /// Seconds until playback stops, given the seconds left of the current
+    /// track (`None` when its length is unknown) and whether it is the last
+    /// one. `None` while that can't be told yet.
+    pub fn remaining(&self, track_left: Option<f32>, last_track: bool) -> Option<f32> {
+        match self.mode {
+            SleepMode::Minutes(_) => self
+                .deadline
+                .map(|at| at.saturating_duration_since(Instant::now()).as_secs_f32()),
+            SleepMode::EndOfTrack => track_left,
+            SleepMode::EndOfQueue => track_left.filter(|_| last_track),
+        }
+    }
+
+    /// Whether playback stops once the current track ends.
+    pub fn ends_with_track(&self, last_track: bool) -> bool {
+        match self.mode {
+            SleepMode::Minutes(_) => false,
+            SleepMode::EndOfTrack => true,
+            SleepMode::EndOfQueue => last_track,
+        }
+    }
+}
+
+/// Volume multiplier with `remaining` seconds to go.
+pub fn fade_factor(remaining: f32) -> f32 {
+    (remaining / FADE_SECS).clamp(0., 1.)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minutes_count_down_from_the_start() {
+        let timer = SleepTimer::start(SleepMode::Minutes(30));
+        let left = timer.remaining(Some(5.), true).unwrap();
+        assert!((1799. ..=1800.).contains(&left), "{left}");
+        assert!(!timer.ends_with_track(true));
+
+        let expired = SleepTimer {
+            deadline: Some(Instant::now() - Duration::from_secs(1)),
+            ..timer
+        };
+        assert_eq!(expired.remaining(None, false), Some(0.));
+    }
+
+    #[test]
+    fn end_of_track_follows_the_track() {
+        let timer = SleepTimer::start(SleepMode::EndOfTrack);
+        assert_eq!(timer.remaining(Some(12.), false), Some(12.));
+        assert_eq!(timer.remaining(None, true), None);
+        assert!(timer.ends_with_track(false));
+    }
+
+    #[test]
+    fn end_of_queue_waits_for_the_last_track() {
+        let timer = SleepTimer::start(SleepMode::EndOfQueue);
+        assert_eq!(timer.remaining(Some(12.), false), None);
+        assert_eq!(timer.remaining(Some(12.), true), Some(12.));
+        assert!(!timer.ends_with_track(false));
+        assert!(timer.ends_with_track(true));
+    }
+
+    #[test]
+    fn fades_over_the_last_seconds() {
+        assert_eq!(fade_factor(FADE_SECS * 3.), 1.);
+        assert_eq!(fade_factor(FADE_SECS / 2.), 0.5);
+        assert_eq!(fade_factor(0.), 0.);
+        assert_eq!(fade_factor(-1.), 0.);
+    }
+}
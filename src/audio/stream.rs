@@ -0,0 +1,587 @@
+//! Progressive playback: a seekable reader over an audio stream that is still
+//! downloading.
+//!
+//! [`open`] returns a [`StreamReader`] right away while a background task fills
+//! a shared [`StreamBuffer`] with a [`ChunkedDownloader`]. `rodio::Decoder`
+//! reads from it directly, so playback starts after the first chunk instead of
+//! the whole file. The bytes go to an unlinked temporary file rather than
+//! memory, so an hour-long podcast costs disk space, not RAM.
+//!
+//! Reads block until the requested bytes are available. A read (usually after
+//! a seek) that lands outside what is buffered and too far from the fetcher's
+//! write position makes the fetcher drop its in-flight chunks and start a new
+//! download at that offset. Once the wanted region is done the fetcher goes
+//! back and fills the remaining gaps.
+//!
+//! Stream URLs expire, and the CDN sometimes refuses one with a 403 or 410.
+//! The fetcher then resolves the same format with the next client through a
+//! [`Reresolver`] and carries on where it stopped, so the decoder only sees
+//! a longer wait. The stream fails once no client is left.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use tokio::sync::Notify;
+use tracing::{debug, warn};
+use ytdroid::download::ChunkedDownloader;
+
+use crate::audio::youtube::{AudioError, Reresolver};
+use crate::utils::data_dir;
+
+/// Bytes per range request. Small enough that the first chunk arrives fast.
+const CHUNK_SIZE: u64 = 512 * 1024;
+
+/// Chunks downloaded ahead of the one being written.
+const PARALLELISM: usize = 3;
+
+/// A read this far ahead of the fetcher waits for it rather than restarting.
+const RESTART_DISTANCE: u64 = CHUNK_SIZE * PARALLELISM as u64;
+
+/// Longest stream accepted. Audio that size would last days; a larger
+/// length is a broken header.
+const MAX_STREAM_LEN: u64 = 2 * 1024 * 1024 * 1024;
+
+/// How long a read waits for missing bytes before the stream is given up.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+const USER_AGENT: &str = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36";
+
+struct BufferState {
+    /// Where the downloaded ranges are kept, at their own offsets.
+    file: File,
+    /// Downloaded byte ranges, sorted and merged.
+    filled: Vec<Range<u64>>,
+    /// Offset the fetcher writes next.
+    write_pos: u64,
+    /// Offset right after the decoder's last read.
+    read_pos: u64,
+    /// Offset a blocked read is waiting for, picked up by the fetcher.
+    restart_at: Option<u64>,
+    error: Option<String>,
+    closed: bool,
+}
+
+impl BufferState {
+    fn new(file: File) -> Self {
+        Self {
+            file,
+            filled: Vec::new(),
+            write_pos: 0,
+            read_pos: 0,
+            restart_at: None,
+            error: None,
+            closed: false,
+        }
+    }
+
+    fn write_at(&mut self, pos: u64, bytes: &[u8]) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(pos))?;
+        self.file.write_all(bytes)
+    }
+
+    /// Fill `buf` from `pos`, which must be downloaded.
+    fn read_at(&mut self, pos: u64, buf: &mut [u8]) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(pos))?;
+        self.file.read_exact(buf)
+    }
+
+    /// End of the filled range containing `pos`, if any.
+    fn filled_end(&self, pos: u64) -> Option<u64> {
+        self.filled
+            .iter()
+            .find(|r| r.contains(&pos))
+            .map(|r| r.end)
+    }
+
+    fn insert(&mut self, range: Range<u64>) {
+        let mut merged = range;
+        self.filled.retain(|r| {
+            let overlaps = r.start <= merged.end && merged.start <= r.end;
+            if overlaps {
+                merged = merged.start.min(r.start)..merged.end.max(r.end);
+            }
+            !overlaps
+        });
+        let at = self.filled.partition_point(|r| r.start < merged.start);
+        self.filled.insert(at, merged);
+    }
+
+    /// First missing offset at or after `from`, wrapping to the start.
+    fn next_gap(&self, from: u64, len: u64) -> Option<u64> {
+        let gap_after = |from: u64| {
+            let mut pos = from;
+            for r in &self.filled {
+                if r.contains(&pos) {
+                    pos = r.end;
+                }
+            }
+            (pos < len).then_some(pos)
+        };
+        gap_after(from).or_else(|| gap_after(0))
+    }
+
+    /// End of the gap starting at `from`: the next filled range, or `len`.
+    fn gap_end(&self, from: u64, len: u64) -> u64 {
+        self.filled
+            .iter()
+            .map(|r| r.start)
+            .find(|&start| start > from)
+            .unwrap_or(len)
+    }
+}
+
+/// Bytes of one audio stream, shared between the fetch task and the decoder.
+pub struct StreamBuffer {
+    len: u64,
+    state: Mutex<BufferState>,
+    data_ready: Condvar,
+    wake_fetcher: Notify,
+    buffering: AtomicBool,
+}
+
+impl StreamBuffer {
+    fn with_state(len: u64, state: BufferState) -> Self {
+        Self {
+            len,
+            state: Mutex::new(state),
+            data_ready: Condvar::new(),
+            wake_fetcher: Notify::new(),
+            buffering: AtomicBool::new(false),
+        }
+    }
+
+    /// An empty buffer for `len` bytes, kept in a temporary file.
+    fn new(len: u64) -> io::Result<Self> {
+        let dir = data_dir(&["cache", "stream"]);
+        std::fs::create_dir_all(&dir)?;
+        let file = tempfile::tempfile_in(dir)?;
+        Ok(Self::with_state(len, BufferState::new(file)))
+    }
+
+    /// A buffer over `file`, which already holds the whole `len` bytes.
+    fn complete(file: File, len: u64) -> Self {
+        let mut state = BufferState::new(file);
+        state.insert(0..len);
+        state.write_pos = len;
+        Self::with_state(len, state)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BufferState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// `true` while the decoder is blocked waiting for bytes.
+    pub fn is_buffering(&self) -> bool {
+        self.buffering.load(Ordering::Relaxed)
+    }
+
+    /// End of the stretch downloaded without a gap from where the decoder
+    /// reads, as a share of the stream in `0.0..=1.0`.
+    pub fn buffered_until(&self) -> f32 {
+        if self.len == 0 {
+            return 1.;
+        }
+        let state = self.lock();
+        let end = state.filled_end(state.read_pos).unwrap_or(state.read_pos);
+        end as f32 / self.len as f32
+    }
+
+    /// Stop the fetcher and fail any pending read.
+    pub fn close(&self) {
+        self.lock().closed = true;
+        self.buffering.store(false, Ordering::Relaxed);
+        self.data_ready.notify_all();
+        self.wake_fetcher.notify_one();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.lock().closed
+    }
+
+    /// Store `bytes` at `pos` and return the offset right after them.
+    fn write(&self, pos: u64, bytes: &[u8]) -> io::Result<u64> {
+        let mut state = self.lock();
+        let end = (pos + bytes.len() as u64).min(self.len);
+        let n = end.saturating_sub(pos) as usize;
+        state.write_at(pos, &bytes[..n])?;
+        state.insert(pos..end);
+        state.write_pos = end;
+        drop(state);
+        self.data_ready.notify_all();
+        Ok(end)
+    }
+
+    /// Why the download was given up, if it was.
+    pub fn error(&self) -> Option<String> {
+        self.lock().error.clone()
+    }
+
+    fn fail(&self, error: String) {
+        self.lock().error = Some(error);
+        self.data_ready.notify_all();
+    }
+
+    /// Copy bytes at `pos` into `buf`, blocking until some are available.
+    fn read_at(&self, pos: u64, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self.lock();
+        loop {
+            if state.closed {
+                return Err(io::Error::new(io::ErrorKind::ConnectionAborted, "stream closed"));
+            }
+            if let Some(end) = state.filled_end(pos) {
+                self.buffering.store(false, Ordering::Relaxed);
+                let n = buf.len().min((end - pos) as usize);
+                state.read_at(pos, &mut buf[..n])?;
+                state.read_pos = pos + n as u64;
+                return Ok(n);
+            }
+            if let Some(e) = &state.error {
+                return Err(io::Error::other(e.clone()));
+            }
+
+            self.buffering.store(true, Ordering::Relaxed);
+            let ahead = pos >= state.write_pos && pos - state.write_pos < RESTART_DISTANCE;
+            if !ahead && state.restart_at != Some(pos) {
+                debug!(pos, write_pos = state.write_pos, "read outside buffer, restarting fetch");
+                state.restart_at = Some(pos);
+                self.wake_fetcher.notify_one();
+            }
+
+            let (guard, timeout) = self
+                .data_ready
+                .wait_timeout(state, READ_TIMEOUT)
+                .unwrap_or_else(|e| e.into_inner());
+            state = guard;
+            if timeout.timed_out() && state.filled_end(pos).is_none() {
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "stream stalled"));
+            }
+        }
+    }
+}
+
+/// Blocking `Read + Seek` view of a [`StreamBuffer`], consumed by the decoder.
+///
+/// Dropping the reader closes the buffer, which stops the fetch task.
+pub struct StreamReader {
+    buffer: Arc<StreamBuffer>,
+    mime_type: Option<String>,
+    pos: u64,
+}
+
+impl StreamReader {
+    /// Total stream length in bytes.
+    pub fn byte_len(&self) -> u64 {
+        self.buffer.len
+    }
+
+    /// Container MIME type without codec parameters (e.g. `audio/webm`).
+    pub fn mime_type(&self) -> Option<&str> {
+        self.mime_type.as_deref()
+    }
+
+    pub fn buffer(&self) -> Arc<StreamBuffer> {
+        Arc::clone(&self.buffer)
+    }
+}
+
+impl Read for StreamReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.pos >= self.buffer.len {
+            return Ok(0);
+        }
+        let n = self.buffer.read_at(self.pos, buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for StreamReader {
+    fn seek(&mut self, from: SeekFrom) -> io::Result<u64> {
+        let target = match from {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::End(d) => self.buffer.len.checked_add_signed(d),
+            SeekFrom::Current(d) => self.pos.checked_add_signed(d),
+        };
+        let Some(target) = target else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "seek before start"));
+        };
+        self.pos = target;
+        Ok(target)
+    }
+}
+
+impl Drop for StreamReader {
+    fn drop(&mut self) {
+        self.buffer.close();
+    }
+}
+
+/// The bytes of a fully downloaded stream, read back from its buffer one
+/// short lock at a time so playback is not held up.
+pub struct Contents {
+    buffer: Arc<StreamBuffer>,
+    pos: u64,
+}
+
+impl Contents {
+    /// Total stream length in bytes.
+    pub fn byte_len(&self) -> u64 {
+        self.buffer.len
+    }
+}
+
+impl Read for Contents {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = (buf.len() as u64).min(self.buffer.len - self.pos) as usize;
+        if n == 0 {
+            return Ok(0);
+        }
+        self.buffer.lock().read_at(self.pos, &mut buf[..n])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+/// Called with the whole stream once it is downloaded.
+pub type OnComplete = Box<dyn FnOnce(Contents) + Send>;
+
+fn container(mime_type: Option<&str>) -> Option<String> {
+    mime_type.map(|m| m.split(';').next().unwrap_or(m).trim().to_owned())
+}
+
+/// Reader over a stream already on disk, e.g. in the cache: `file` holds
+/// all of its `len` bytes.
+pub fn from_file(file: File, len: u64, mime_type: Option<&str>) -> StreamReader {
+    StreamReader {
+        buffer: Arc::new(StreamBuffer::complete(file, len)),
+        mime_type: container(mime_type),
+        pos: 0,
+    }
+}
+
+/// Where a stream is downloaded from.
+pub struct StreamSource {
+    pub url: String,
+    /// Client that resolved `url`.
+    pub client: &'static str,
+    /// When `url` stops working.
+    pub expires_at: Option<Instant>,
+    pub reresolver: Reresolver,
+}
+
+impl StreamSource {
+    fn expired(&self) -> bool {
+        self.expires_at.is_some_and(|at| Instant::now() >= at)
+    }
+
+    /// Switch to a URL from the next client for the `len` bytes long stream.
+    async fn renew(&mut self, len: u64) -> Result<(), AudioError> {
+        let resolved = self.reresolver.next(self.client, len).await?;
+        debug!(from = self.client, to = resolved.client, "stream resolved again");
+        self.url = resolved.url;
+        self.client = resolved.client;
+        self.expires_at = resolved.expires_at;
+        Ok(())
+    }
+}
+
+/// Whether the CDN refused the URL itself, rather than failing this once.
+fn is_refused(e: &ytdroid::Error) -> bool {
+    matches!(e, ytdroid::Error::HttpStatus { status: 403 | 410 })
+}
+
+/// Downloader for stream URLs, sending `cookies` when given.
+pub fn downloader(cookies: Option<String>) -> Result<ChunkedDownloader, AudioError> {
+    let http = reqwest::Client::builder().build().map_err(AudioError::Http)?;
+    let mut downloader = ChunkedDownloader::new(http)
+        .chunk_size(CHUNK_SIZE)
+        .parallelism(PARALLELISM)
+        .header("User-Agent", USER_AGENT);
+    if let Some(cookie_str) = cookies {
+        downloader = downloader.header("cookie", cookie_str);
+    }
+    Ok(downloader)
+}
+
+/// Start streaming `source` and return a reader for it.
+///
+/// `len_hint` is the `contentLength` from the player response; when absent the
+/// length is asked from the server. `on_complete` runs on a blocking thread
+/// if the download finishes before the reader is dropped.
+pub async fn open(
+    source: StreamSource,
+    cookies: Option<String>,
+    mime_type: Option<&str>,
+    len_hint: Option<u64>,
+    on_complete: Option<OnComplete>,
+) -> Result<StreamReader, AudioError> {
+    let downloader = downloader(cookies)?;
+    let plausible = |len: &u64| (1..=MAX_STREAM_LEN).contains(len);
+    if let Some(len) = len_hint.filter(|len| !plausible(len)) {
+        warn!(len, "ignoring implausible content length");
+    }
+    let len = match len_hint.filter(plausible) {
+        Some(len) => len,
+        None => downloader.content_length(&source.url).await?,
+    };
+    if !plausible(&len) {
+        return Err(AudioError::BadLength { len });
+    }
+    debug!(len, client = source.client, "streaming audio");
+
+    let buffer = Arc::new(StreamBuffer::new(len)?);
+    tokio::spawn(fetch(Arc::clone(&buffer), downloader, source, on_complete));
+
+    Ok(StreamReader {
+        buffer,
+        mime_type: container(mime_type),
+        pos: 0,
+    })
+}
+
+/// Download the gap the reader needs, then the remaining ones, following the
+/// reader's restart requests and renewing the URL when it stops working.
+async fn fetch(
+    buffer: Arc<StreamBuffer>,
+    downloader: ChunkedDownloader,
+    mut source: StreamSource,
+    on_complete: Option<OnComplete>,
+) {
+    let mut pos = 0;
+
+    loop {
+        let (start, end) = {
+            let mut state = buffer.lock();
+            let focus = state.restart_at.take().unwrap_or(pos);
+            let Some(start) = state.next_gap(focus, buffer.len) else {
+                debug!("stream fully buffered");
+                drop(state);
+                if let Some(f) = on_complete {
+                    let contents = Contents {
+                        buffer: Arc::clone(&buffer),
+                        pos: 0,
+                    };
+                    tokio::task::spawn_blocking(move || f(contents));
+                }
+                return;
+            };
+            state.write_pos = start;
+            (start, state.gap_end(start, buffer.len))
+        };
+
+        if source.expired() {
+            debug!(client = source.client, "stream URL expired");
+            if let Err(e) = source.renew(buffer.len).await {
+                warn!(error = %e, "no client could resolve the stream again");
+                buffer.fail(e.to_string());
+                return;
+            }
+        }
+
+        let mut chunks = downloader.download(&source.url, start..end);
+        let refused = loop {
+            tokio::select! {
+                chunk = chunks.next() => match chunk {
+                    Some(Ok(chunk)) => match buffer.write(chunk.offset, &chunk.data) {
+                        Ok(end) => pos = end,
+                        Err(e) => {
+                            warn!(error = %e, "cannot store stream data");
+                            buffer.fail(e.to_string());
+                            return;
+                        }
+                    },
+                    Some(Err(e)) if is_refused(&e) => break true,
+                    Some(Err(e)) => {
+                        warn!(error = %e, start, "stream download failed");
+                        buffer.fail(e.to_string());
+                        return;
+                    }
+                    None => break false,
+                },
+                () = buffer.wake_fetcher.notified() => break false,
+            }
+        };
+        // Go on from the first byte still missing with the new URL.
+        if refused {
+            drop(chunks);
+            warn!(client = source.client, start, "stream URL refused, resolving again");
+            if let Err(e) = source.renew(buffer.len).await {
+                warn!(error = %e, "no client could resolve the stream again");
+                buffer.fail(e.to_string());
+                return;
+            }
+        }
+
+        if buffer.is_closed() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(len: u64, filled: &[Range<u64>]) -> StreamBuffer {
+        let buffer = StreamBuffer::with_state(len, BufferState::new(tempfile::tempfile().unwrap()));
+        for r in filled {
+            let bytes: Vec<u8> = (r.start..r.end).map(|i| i as u8).collect();
+            buffer.write(r.start, &bytes).unwrap();
+        }
+        buffer
+    }
+
+    #[test]
+    fn merges_filled_ranges() {
+        let b = buffer(100, &[10..20, 40..50, 20..30, 45..60, 0..5]);
+        assert_eq!(b.lock().filled, [0..5, 10..30, 40..60]);
+        b.write(5, &[0; 5]).unwrap();
+        assert_eq!(b.lock().filled, [0..30, 40..60]);
+    }
+
+    #[test]
+    fn finds_gaps_after_a_seek() {
+        let b = buffer(100, &[0..10, 50..70]);
+        let state = b.lock();
+        assert_eq!(state.next_gap(0, 100), Some(10));
+        assert_eq!(state.gap_end(10, 100), 50);
+        assert_eq!(state.next_gap(55, 100), Some(70));
+        assert_eq!(state.gap_end(70, 100), 100);
+        assert_eq!(state.next_gap(30, 100), Some(30));
+        drop(state);
+
+        // Past the last gap, it wraps to the first one.
+        let b = buffer(100, &[0..10, 50..100]);
+        assert_eq!(b.lock().next_gap(60, 100), Some(10));
+        let b = buffer(100, &[0..60, 40..100]);
+        assert_eq!(b.lock().next_gap(0, 100), None);
+    }
+
+    #[test]
+    fn buffered_until_follows_the_reader() {
+        let b = buffer(100, &[0..10, 50..70]);
+        let mut byte = [0];
+        b.read_at(5, &mut byte).unwrap();
+        assert_eq!(byte, [5]);
+        assert_eq!(b.buffered_until(), 0.1);
+        // After a seek into the later range, the bar ends with that range.
+        b.read_at(60, &mut byte).unwrap();
+        assert_eq!(byte, [60]);
+        assert_eq!(b.buffered_until(), 0.7);
+        b.lock().read_pos = 80;
+        assert_eq!(b.buffered_until(), 0.8);
+    }
+
+    #[test]
+    fn contents_reads_back_everything() {
+        let b = Arc::new(buffer(1000, &[500..1000, 0..500]));
+        let mut contents = Contents { buffer: b, pos: 0 };
+        let mut data = Vec::new();
+        contents.read_to_end(&mut data).unwrap();
+        assert_eq!(data, (0..1000).map(|i| i as u8).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,383 @@
+//! Playback speed without a pitch change.
+//!
+//! [`TimeStretch`] implements WSOLA (waveform-similarity overlap-add): output
+//! is built from short grains of the input that overlap by half. Each grain
+//! is taken `speed` times further along the input than the output advances,
+//! nudged by up to [`SEARCH_MS`] to where it lines up best with the end of
+//! the previous grain, then crossfaded into it. Grains keep their original
+//! sample rate, so pitch is unchanged.
+//!
+//! The adapter sits after `track_position()`, which therefore counts input
+//! samples: progress and seeks stay in content time at any speed.
+
+use std::f32::consts::PI;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use rodio::source::{SeekError, Source};
+
+/// Slowest and fastest supported speeds.
+pub const MIN_SPEED: f32 = 0.5;
+pub const MAX_SPEED: f32 = 2.0;
+
+/// Grain length; output advances by half of it per grain.
+const GRAIN_MS: u32 = 40;
+
+/// How far a grain may move from its nominal position to match the previous one.
+const SEARCH_MS: u32 = 10;
+
+/// Frames between candidates of the coarse search.
+const SEARCH_STEP: usize = 4;
+
+/// Samples skipped between the ones compared by the similarity measure.
+const SIMILARITY_STRIDE: usize = 4;
+
+/// Playback speed as `f32` bits, shared by every track the rodio thread plays.
+pub type SpeedHandle = Arc<AtomicU32>;
+
+pub fn new_speed(speed: f32) -> SpeedHandle {
+    Arc::new(AtomicU32::new(speed.to_bits()))
+}
+
+pub fn load_speed(handle: &SpeedHandle) -> f32 {
+    f32::from_bits(handle.load(Ordering::Relaxed)).clamp(MIN_SPEED, MAX_SPEED)
+}
+
+/// [`Source`] adapter playing its input at the shared speed, pitch preserved.
+pub struct TimeStretch<S: Source> {
+    inner: S,
+    speed: SpeedHandle,
+    /// Speed the buffers below were built for; `1.0` passes samples through.
+    active: f32,
+    channels: usize,
+    /// Frames per half grain (output hop and overlap length).
+    hop: usize,
+    /// Frames a grain may move while searching.
+    search: usize,
+    /// Interleaved input, starting at frame `0` of the positions below.
+    input: Vec<f32>,
+    /// Start frame of the previous grain, `None` before the first one.
+    prev: Option<usize>,
+    /// Where the next grain would start without the similarity search.
+    nominal: f64,
+    /// Fade-in ramp of the overlap, one weight per frame.
+    window: Vec<f32>,
+    output: Vec<f32>,
+    out_pos: usize,
+    exhausted: bool,
+}
+
+impl<S: Source> TimeStretch<S> {
+    pub fn new(inner: S, speed: SpeedHandle) -> Self {
+        Self {
+            inner,
+            speed,
+            active: 1.,
+            channels: 1,
+            hop: 0,
+            search: 0,
+            input: Vec::new(),
+            prev: None,
+            nominal: 0.,
+            window: Vec::new(),
+            output: Vec::new(),
+            out_pos: 0,
+            exhausted: false,
+        }
+    }
+
+    /// Drop buffered audio and size the grains for the current format.
+    fn reset(&mut self, speed: f32) {
+        let rate = self.inner.sample_rate().get() as usize;
+        self.active = speed;
+        self.channels = usize::from(self.inner.channels().get());
+        self.hop = (rate * GRAIN_MS as usize / 2000).max(1);
+        self.search = rate * SEARCH_MS as usize / 1000;
+        self.input.clear();
+        self.prev = None;
+        self.nominal = 0.;
+        self.window = (0..self.hop)
+            .map(|i| 0.5 - 0.5 * (PI * (i as f32 + 0.5) / self.hop as f32).cos())
+            .collect();
+        self.output.clear();
+        self.out_pos = 0;
+    }
+
+    fn frames(&self) -> usize {
+        self.input.len() / self.channels
+    }
+
+    /// Read from the inner source until `frames` input frames are buffered.
+    fn fill(&mut self, frames: usize) -> bool {
+        while self.frames() < frames {
+            match self.inner.next() {
+                Some(s) => self.input.push(s),
+                None => {
+                    self.exhausted = true;
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Cross-correlation of the `len` frames starting at `a` and `b`, over
+    /// every [`SIMILARITY_STRIDE`]th sample.
+    fn similarity(&self, a: usize, b: usize, len: usize) -> f32 {
+        let ch = self.channels;
+        let a = &self.input[a * ch..(a + len) * ch];
+        let b = &self.input[b * ch..(b + len) * ch];
+        a.iter()
+            .zip(b)
+            .step_by(SIMILARITY_STRIDE)
+            .map(|(x, y)| x * y)
+            .sum()
+    }
+
+    /// Start frame in `lo..=hi` that best continues the grain at `natural`:
+    /// a coarse pass every [`SEARCH_STEP`] frames, then a fine one around it.
+    fn best_match(&self, natural: usize, lo: usize, hi: usize) -> usize {
+        let score = |f: usize| self.similarity(natural, f, self.hop);
+        let pick = |frames: &mut dyn Iterator<Item = usize>| {
+            frames
+                .map(|f| (f, score(f)))
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .map(|(f, _)| f)
+        };
+        let coarse = pick(&mut (lo..=hi).step_by(SEARCH_STEP)).unwrap_or(lo);
+        let fine_lo = coarse.saturating_sub(SEARCH_STEP).max(lo);
+        let fine_hi = (coarse + SEARCH_STEP).min(hi);
+        pick(&mut (fine_lo..=fine_hi)).unwrap_or(coarse)
+    }
+
+    /// Produce the next `hop` frames of output. `false` once the input ran out.
+    fn next_grain(&mut self) -> bool {
+        let (hop, ch) = (self.hop, self.channels);
+        self.output.clear();
+        self.out_pos = 0;
+
+        let Some(prev) = self.prev else {
+            // First grain: nothing to overlap with.
+            if !self.fill(2 * hop) {
+                self.output.append(&mut self.input);
+                return !self.output.is_empty();
+            }
+            self.output.extend_from_slice(&self.input[..hop * ch]);
+            self.prev = Some(0);
+            self.nominal = hop as f64 * f64::from(self.active);
+            return true;
+        };
+
+        let nominal = self.nominal.round() as usize;
+        let lo = nominal.saturating_sub(self.search);
+        let mut hi = nominal + self.search;
+        // Where the previous grain would naturally continue.
+        let natural = prev + hop;
+        if !self.fill((hi + 2 * hop).max(prev + 2 * hop)) {
+            // Near the end, search only as far as the input goes.
+            hi = hi.min(self.frames().saturating_sub(hop));
+            if hi < lo || natural + hop > self.frames() {
+                // Play out the tail of the previous grain unchanged.
+                let tail = (natural * ch).min(self.input.len());
+                self.output.extend_from_slice(&self.input[tail..]);
+                self.input.clear();
+                return !self.output.is_empty();
+            }
+        }
+
+        let best = self.best_match(natural, lo, hi);
+
+        for i in 0..hop {
+            let w = self.window[i];
+            for c in 0..ch {
+                let old = self.input[(natural + i) * ch + c];
+                let new = self.input[(best + i) * ch + c];
+                self.output.push(old * (1. - w) + new * w);
+            }
+        }
+        self.prev = Some(best);
+        self.nominal += hop as f64 * f64::from(self.active);
+
+        // Forget input no later grain can reach.
+        let keep_from = best.min(self.nominal as usize).saturating_sub(self.search);
+        if keep_from > 0 {
+            self.input.drain(..keep_from * ch);
+            self.prev = Some(best - keep_from);
+            self.nominal -= keep_from as f64;
+        }
+        true
+    }
+}
+
+impl<S: Source> Iterator for TimeStretch<S> {
+    type Item = rodio::Sample;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.out_pos < self.output.len() {
+            self.out_pos += 1;
+            return Some(self.output[self.out_pos - 1]);
+        }
+
+        // Speed changes take effect between grains.
+        let speed = load_speed(&self.speed);
+        if speed != self.active {
+            // Keep what was read ahead but not played yet.
+            let pending = self.prev.map_or(0, |p| (p + self.hop) * self.channels);
+            let leftover: Vec<f32> = self.input.drain(pending.min(self.input.len())..).collect();
+            self.reset(speed);
+            if speed == 1. {
+                self.output = leftover;
+                if let Some(&first) = self.output.first() {
+                    self.out_pos = 1;
+                    return Some(first);
+                }
+            } else {
+                self.input = leftover;
+            }
+        }
+        if self.active == 1. {
+            return self.inner.next();
+        }
+
+        if self.exhausted && self.input.is_empty() {
+            return None;
+        }
+        if !self.next_grain() {
+            return None;
+        }
+        self.out_pos = 1;
+        self.output.first().copied()
+    }
+}
+
+impl<S: Source> Source for TimeStretch<S> {
+    fn current_span_len(&self) -> Option<usize> {
+        // Samples passed through keep the decoder's spans; grains do not.
+        if self.active == 1. && self.out_pos >= self.output.len() {
+            self.inner.current_span_len()
+        } else {
+            None
+        }
+    }
+
+    fn channels(&self) -> rodio::ChannelCount {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> rodio::SampleRate {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)?;
+        self.exhausted = false;
+        self.reset(self.active);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 16_000;
+    const FREQ: f32 = 440.;
+
+    /// A mono sine of `secs` seconds, in spans of a tenth of a second.
+    struct Tone {
+        samples: Vec<f32>,
+        pos: usize,
+    }
+
+    impl Tone {
+        fn new(secs: f32) -> Self {
+            let len = (RATE as f32 * secs) as usize;
+            let samples = (0..len)
+                .map(|i| (2. * PI * FREQ * i as f32 / RATE as f32).sin() * 0.5)
+                .collect();
+            Self { samples, pos: 0 }
+        }
+    }
+
+    impl Iterator for Tone {
+        type Item = rodio::Sample;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            let s = self.samples.get(self.pos).copied();
+            self.pos += 1;
+            s
+        }
+    }
+
+    impl Source for Tone {
+        fn current_span_len(&self) -> Option<usize> {
+            let span = RATE as usize / 10;
+            Some(span - self.pos % span)
+        }
+
+        fn channels(&self) -> rodio::ChannelCount {
+            rodio::ChannelCount::new(1).unwrap()
+        }
+
+        fn sample_rate(&self) -> rodio::SampleRate {
+            rodio::SampleRate::new(RATE).unwrap()
+        }
+
+        fn total_duration(&self) -> Option<Duration> {
+            None
+        }
+    }
+
+    fn stretch(speed: f32) -> Vec<f32> {
+        TimeStretch::new(Tone::new(2.), new_speed(speed)).collect()
+    }
+
+    /// Frequency of `samples`, from the zero crossings of their middle half.
+    fn frequency(samples: &[f32]) -> f32 {
+        let middle = &samples[samples.len() / 4..samples.len() * 3 / 4];
+        let crossings = middle
+            .windows(2)
+            .filter(|w| (w[0] < 0.) != (w[1] < 0.))
+            .count();
+        crossings as f32 / 2. / (middle.len() as f32 / RATE as f32)
+    }
+
+    #[test]
+    fn normal_speed_passes_samples_through() {
+        assert_eq!(stretch(1.), Tone::new(2.).samples);
+        let mut s = TimeStretch::new(Tone::new(2.), new_speed(1.));
+        s.nth(99);
+        assert_eq!(s.current_span_len(), Some(RATE as usize / 10 - 100));
+    }
+
+    #[test]
+    fn length_follows_speed() {
+        let input = 2. * RATE as f32;
+        // Up to a grain at the very end plays at normal speed.
+        let grain = (RATE * GRAIN_MS / 1000) as f32;
+        for speed in [0.5, 2.] {
+            let len = stretch(speed).len() as f32;
+            let expected = input / speed;
+            assert!(
+                (len - expected).abs() < expected * 0.01 + grain,
+                "{speed}x: {len} samples, expected {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn pitch_is_kept() {
+        for speed in [0.5, 1., 2.] {
+            let freq = frequency(&stretch(speed));
+            assert!((freq - FREQ).abs() < FREQ * 0.02, "{speed}x: {freq} Hz");
+        }
+        let mut s = TimeStretch::new(Tone::new(2.), new_speed(2.));
+        s.next();
+        assert_eq!(s.current_span_len(), None);
+    }
+}
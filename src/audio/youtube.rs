@@ -1,9 +1,13 @@
 use std::collections::HashMap;
+use std::time::Instant;
 
 use ytdroid::client::Locale;
-use ytdroid::{AudioStream, ContentHints, YouTube};
+use ytdroid::response::VideoDetails;
+use ytdroid::{AudioStream, ContentHints, FormatPreference, YouTube};
 
 use crate::audio::AudioQuality;
+use crate::audio::cache::{self, CacheEntry};
+use crate::audio::stream::{Contents, OnComplete, StreamReader, StreamSource};
 
 #[derive(Debug, thiserror::Error)]
 pub enum AudioError {
@@ -17,13 +21,157 @@ pub enum AudioError {
     NoFormat { quality: AudioQuality },
     #[error("cipher sig decryption failed")]
     CipherDecryptFailed,
+    #[error("stream length {len} is implausible")]
+    BadLength { len: u64 },
+    #[error("I/O: {0}")]
+    Io(#[from] std::io::Error),
 }
 
-pub async fn fetch_audio_bytes(
+/// A stream that is downloading, plus what the player response said about it.
+pub struct OpenedStream {
+    pub reader: StreamReader,
+    /// `loudnessDb` of the selected format, for normalization.
+    pub loudness_db: Option<f32>,
+}
+
+/// A playable URL for a video, with the format it was picked in.
+pub struct ResolvedAudio {
+    pub url: String,
+    pub itag: Option<u32>,
+    pub mime_type: Option<String>,
+    pub bitrate: Option<u64>,
+    pub content_length: Option<u64>,
+    /// `loudnessDb` of the selected format, for normalization.
+    pub loudness_db: Option<f32>,
+    pub video_details: Option<VideoDetails>,
+    /// Client that resolved the URL.
+    pub client: &'static str,
+    /// When the URL stops working.
+    pub expires_at: Option<Instant>,
+}
+
+/// Resolves a stream again once its URL expired or was refused, asking the
+/// clients not tried yet for the same format so the bytes already buffered
+/// stay valid.
+pub struct Reresolver {
+    video_id: String,
+    cookies: Option<String>,
+    pref: FormatPreference,
+    hints: ContentHints,
+}
+
+impl Reresolver {
+    /// A new URL for the `len` bytes long stream after the one from `client`
+    /// stopped working. Fails once every client has failed.
+    pub async fn next(
+        &mut self,
+        client: &'static str,
+        len: u64,
+    ) -> Result<ResolvedAudio, AudioError> {
+        self.hints.failed_clients.push(client);
+        loop {
+            let resolved = resolve(&self.video_id, &self.pref, &self.hints, self.cookies.clone())
+                .await?;
+            if resolved.content_length.is_none_or(|l| l == len) {
+                return Ok(resolved);
+            }
+            // Same itag, different file: the buffered bytes would not fit.
+            tracing::debug!(client = resolved.client, "stream length differs, trying next client");
+            self.hints.failed_clients.push(resolved.client);
+        }
+    }
+}
+
+/// Resolve `video_id` and start streaming it; see [`super::stream`].
+///
+/// A downloaded track or a stream in the [`cache`] is played from disk
+/// without touching the network; a fresh stream is added to the cache once
+/// fully downloaded.
+pub async fn fetch_audio_stream(
     video_id: &str,
     quality: AudioQuality,
     cookies: Option<String>,
-) -> Result<Vec<u8>, AudioError> {
+) -> Result<OpenedStream, AudioError> {
+    let id = video_id.to_owned();
+    let stored = tokio::task::spawn_blocking(move || {
+        crate::downloads::lookup(&id)
+            .map(|hit| ("download", hit))
+            .or_else(|| cache::lookup(&id, quality).map(|hit| ("cache", hit)))
+    })
+    .await
+    .ok()
+    .flatten();
+    if let Some((source, hit)) = stored {
+        tracing::debug!(video_id, source, itag = hit.itag, ?quality, "playing stored audio");
+        return Ok(OpenedStream {
+            reader: super::stream::from_file(hit.file, hit.len, hit.mime_type.as_deref()),
+            loudness_db: hit.loudness_db,
+        });
+    }
+
+    let resolved = resolve_audio(video_id, quality, cookies.clone()).await?;
+    tracing::debug!(url = %resolved.url, "opening audio stream");
+
+    let on_complete = resolved.itag.map(|itag| {
+        let entry = CacheEntry {
+            video_id: video_id.to_owned(),
+            itag,
+            mime_type: resolved.mime_type.clone(),
+            bitrate: resolved.bitrate,
+            loudness_db: resolved.loudness_db,
+        };
+        Box::new(move |contents: Contents| {
+            let len = contents.byte_len();
+            cache::insert(entry, contents, len);
+        }) as OnComplete
+    });
+    let mut pref = quality.format_preference();
+    if let Some(itag) = resolved.itag {
+        pref = pref.itag(itag);
+    }
+    let source = StreamSource {
+        url: resolved.url,
+        client: resolved.client,
+        expires_at: resolved.expires_at,
+        reresolver: Reresolver {
+            video_id: video_id.to_owned(),
+            cookies: cookies.clone(),
+            pref,
+            hints: ContentHints::default(),
+        },
+    };
+    let reader = super::stream::open(
+        source,
+        cookies,
+        resolved.mime_type.as_deref(),
+        resolved.content_length,
+        on_complete,
+    )
+    .await?;
+    Ok(OpenedStream {
+        reader,
+        loudness_db: resolved.loudness_db,
+    })
+}
+
+/// Pick a format of `video_id` for `quality` and resolve its URL: player
+/// request through the client fallback chain, PoToken and signature
+/// decryption.
+pub async fn resolve_audio(
+    video_id: &str,
+    quality: AudioQuality,
+    cookies: Option<String>,
+) -> Result<ResolvedAudio, AudioError> {
+    tracing::debug!(?quality, "resolving audio");
+    resolve(video_id, &quality.format_preference(), &ContentHints::default(), cookies).await
+}
+
+async fn resolve(
+    video_id: &str,
+    pref: &FormatPreference,
+    hints: &ContentHints,
+    cookies: Option<String>,
+) -> Result<ResolvedAudio, AudioError> {
     tracing::debug!(video_id, has_cookies = cookies.is_some(), "fetching audio");
 
     let yt = YouTube::new(cookies.as_deref(), Locale::default())?;
@@ -51,47 +199,41 @@ pub async fn fetch_audio_bytes(
     let stream = yt
         .audio_stream(
             video_id,
-            &ContentHints::default(),
+            hints,
+            pref,
             po_player.as_deref(),
             po_streaming.as_deref(),
             Some(sig_ts),
         )
         .await?;
 
-    let url = resolve_stream(stream).await?;
-    tracing::debug!(%url, "fetching stream bytes");
-
-    let http = reqwest::Client::builder()
-        .gzip(true)
-        .build()
-        .map_err(AudioError::Http)?;
-
-    let mut req = http
-        .get(&url)
-        .header("User-Agent", "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36");
-
-    if let Some(cookie_str) = cookies.as_deref() {
-        req = req.header("cookie", cookie_str);
-    }
-
-    let resp = req.send().await.map_err(AudioError::Http)?;
-    let status = resp.status();
-    let content_type = resp
-        .headers()
-        .get("content-type")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("unknown")
-        .to_owned();
-    if !status.is_success() {
-        tracing::warn!(%status, content_type, "stream fetch bad status");
-        return Err(AudioError::HttpStatus { status: status.as_u16() });
-    }
-    tracing::debug!(%status, content_type, "stream response headers");
-
-    let bytes = resp.bytes().await.map_err(AudioError::Http)?;
-    tracing::debug!(bytes = bytes.len(), "audio fetched");
-    let _ = quality;
-    Ok(bytes.to_vec())
+    tracing::debug!(
+        client = stream.client,
+        itag = stream.itag,
+        bitrate = stream.bitrate,
+        mime = stream.mime_type.as_deref().unwrap_or("?"),
+        loudness_db = stream.loudness_db,
+        "selected audio format"
+    );
+    let itag = stream.itag;
+    let mime_type = stream.mime_type.clone();
+    let bitrate = stream.bitrate;
+    let content_length = stream.content_length;
+    let loudness_db = stream.loudness_db;
+    let video_details = stream.video_details.clone();
+    let client = stream.client;
+    let expires_at = stream.expires_in.map(|d| Instant::now() + d);
+    Ok(ResolvedAudio {
+        url: resolve_stream(stream).await?,
+        itag,
+        mime_type,
+        bitrate,
+        content_length,
+        loudness_db,
+        video_details,
+        client,
+        expires_at,
+    })
 }
 
 /// Resolve an [`AudioStream`] to a final CDN URL.
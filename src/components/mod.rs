@@ -1,5 +1,6 @@
 mod loading_bar;
 mod player_bar;
+mod player_menu;
 mod playlist_song_row;
 mod scroll_button;
 mod section;
@@ -10,6 +11,7 @@ mod top_bar;
 
 pub use loading_bar::*;
 pub use player_bar::*;
+pub use player_menu::*;
 pub use playlist_song_row::*;
 pub use scroll_button::*;
 pub use section::*;
@@ -2,20 +2,39 @@ use std::str::FromStr;
 
 use bytes::Bytes;
 use freya::icons::lucide::{
-    chevron_up, ellipsis_vertical, pause, play, repeat, shuffle, skip_back, skip_forward,
-    thumbs_down, thumbs_up, volume_2, volume_x,
+    chevron_up, ellipsis_vertical, moon, pause, play, repeat, repeat_1, shuffle, skip_back,
+    skip_forward, thumbs_down, thumbs_up, volume_2, volume_x,
 };
 use freya::prelude::*;
 use freya::radio::use_radio;
 
 use crate::app::{Data, DataChannel};
-use crate::audio::AudioCommand;
+use crate::audio::{AudioCommand, RepeatMode, SleepMode};
+use crate::components::{PlayerOptions, player_menu};
 
 fn fmt_secs(secs: f32) -> String {
     let s = secs as u32;
     format!("{}:{:02}", s / 60, s % 60)
 }
 
+/// Time left on the sleep timer, or when it will stop.
+fn sleep_text(mode: SleepMode, remaining: Option<f32>) -> String {
+    match (remaining, mode) {
+        (Some(secs), _) => fmt_secs(secs),
+        (None, SleepMode::EndOfQueue) => "fin de la cola".to_owned(),
+        (None, _) => "fin de la canción".to_owned(),
+    }
+}
+
+/// White while the mode it toggles is on, grey while off.
+fn toggle_color(active: bool) -> Color {
+    if active {
+        Color::WHITE
+    } else {
+        Color::from_hex("#8A8A8A").unwrap()
+    }
+}
+
 fn icon_btn(icon: Bytes, size: f32) -> impl IntoElement {
     rect()
         .center()
@@ -51,6 +70,8 @@ impl Component for PlayerBar {
         let p = &state.player;
 
         let is_playing = p.is_playing;
+        let is_buffering = p.is_buffering;
+        let buffered_pct = (p.buffered_until * 100.).clamp(0., 100.);
         let current_secs = p.current_secs;
         let total_secs = p.total_secs;
         let title = p.title.clone();
@@ -59,6 +80,10 @@ impl Component for PlayerBar {
         let year = p.year.clone();
         let thumbnail_url = p.thumbnail_url.clone();
         let volume = p.volume;
+        let shuffle_on = p.shuffle;
+        let repeat_mode = p.repeat;
+        let sleep = p.sleep_timer.map(|mode| sleep_text(mode, p.sleep_remaining));
+        let options = PlayerOptions::from(p);
         let audio_cmd = state.audio_cmd.clone();
 
         let mut is_muted = use_state(|| false);
@@ -91,6 +116,7 @@ impl Component for PlayerBar {
             .child({
                 let audio_cmd = audio_cmd.clone();
                 rect()
+                    .horizontal()
                     .width(Size::window_percent(100.))
                     .height(Size::px(3.))
                     .background(Color::from_hex("#2D2D2D").unwrap())
@@ -114,6 +140,13 @@ impl Component for PlayerBar {
                             .height(Size::Fill)
                             .background(Color::from_hex("#FF0000").unwrap()),
                     )
+                    // Downloaded audio ahead of the playhead, up to the next gap.
+                    .child(
+                        rect()
+                            .width(Size::percent((buffered_pct - progress_pct).max(0.)))
+                            .height(Size::Fill)
+                            .background(Color::from_hex("#5A5A5A").unwrap()),
+                    )
             })
             // main row
             .child(
@@ -203,14 +236,37 @@ impl Component for PlayerBar {
                             })
                             .child(
                                 label()
-                                    .text(format!(
-                                        "{} / {}",
-                                        fmt_secs(current_secs),
-                                        fmt_secs(total_secs)
-                                    ))
+                                    .text(if is_buffering {
+                                        "Cargando...".to_owned()
+                                    } else {
+                                        format!(
+                                            "{} / {}",
+                                            fmt_secs(current_secs),
+                                            fmt_secs(total_secs)
+                                        )
+                                    })
                                     .font_size(12.)
                                     .color(Color::from_hex("#AAAAAA").unwrap()),
-                            ),
+                            )
+                            .maybe_child(sleep.map(|text| {
+                                rect()
+                                    .horizontal()
+                                    .spacing(4.)
+                                    .padding(Gaps::new_symmetric(0., 4.))
+                                    .cross_align(Alignment::Center)
+                                    .child(
+                                        SvgViewer::new(moon())
+                                            .color(Color::from_hex("#AAAAAA").unwrap())
+                                            .width(Size::px(12.))
+                                            .height(Size::px(12.)),
+                                    )
+                                    .child(
+                                        label()
+                                            .text(text)
+                                            .font_size(12.)
+                                            .color(Color::from_hex("#AAAAAA").unwrap()),
+                                    )
+                            })),
                     )
                     // center: thumbnail + song info + reactions
                     .child(
@@ -256,7 +312,31 @@ impl Component for PlayerBar {
                             )
                             .child(icon_btn(thumbs_up(), 18.))
                             .child(icon_btn(thumbs_down(), 18.))
-                            .child(icon_btn(ellipsis_vertical(), 18.)),
+                            .child({
+                                let audio_cmd = audio_cmd.clone();
+                                rect()
+                                    .center()
+                                    .padding(Gaps::new_all(6.))
+                                    .rounded_full()
+                                    .on_pointer_enter(|_| Cursor::set(CursorIcon::Pointer))
+                                    .on_pointer_leave(|_| Cursor::set(CursorIcon::Default))
+                                    .on_press(move |e: Event<PressEventData>| {
+                                        // Devices may have come and gone since the last look.
+                                        if let Some(tx) = &audio_cmd {
+                                            tx.try_send(AudioCommand::RefreshOutputs).ok();
+                                        }
+                                        ContextMenu::open_from_event(
+                                            &e,
+                                            player_menu(audio_cmd.clone(), options.clone()),
+                                        );
+                                    })
+                                    .child(
+                                        SvgViewer::new(ellipsis_vertical())
+                                            .color(Color::WHITE)
+                                            .width(Size::px(18.))
+                                            .height(Size::px(18.)),
+                                    )
+                            }),
                     )
                     // right: volume, repeat, shuffle, expand
                     .child(
@@ -324,8 +404,51 @@ impl Component for PlayerBar {
                                             .background(Color::WHITE),
                                     )
                             })
-                            .child(icon_btn(repeat(), 18.))
-                            .child(icon_btn(shuffle(), 18.))
+                            .child({
+                                let audio_cmd = audio_cmd.clone();
+                                let icon = match repeat_mode {
+                                    RepeatMode::One => repeat_1(),
+                                    _ => repeat(),
+                                };
+                                rect()
+                                    .center()
+                                    .padding(Gaps::new_all(6.))
+                                    .rounded_full()
+                                    .on_pointer_enter(|_| Cursor::set(CursorIcon::Pointer))
+                                    .on_pointer_leave(|_| Cursor::set(CursorIcon::Default))
+                                    .on_press(move |_| {
+                                        if let Some(tx) = &audio_cmd {
+                                            tx.try_send(AudioCommand::SetRepeat(repeat_mode.cycle()))
+                                                .ok();
+                                        }
+                                    })
+                                    .child(
+                                        SvgViewer::new(icon)
+                                            .color(toggle_color(repeat_mode != RepeatMode::Off))
+                                            .width(Size::px(18.))
+                                            .height(Size::px(18.)),
+                                    )
+                            })
+                            .child({
+                                let audio_cmd = audio_cmd.clone();
+                                rect()
+                                    .center()
+                                    .padding(Gaps::new_all(6.))
+                                    .rounded_full()
+                                    .on_pointer_enter(|_| Cursor::set(CursorIcon::Pointer))
+                                    .on_pointer_leave(|_| Cursor::set(CursorIcon::Default))
+                                    .on_press(move |_| {
+                                        if let Some(tx) = &audio_cmd {
+                                            tx.try_send(AudioCommand::SetShuffle(!shuffle_on)).ok();
+                                        }
+                                    })
+                                    .child(
+                                        SvgViewer::new(shuffle())
+                                            .color(toggle_color(shuffle_on))
+                                            .width(Size::px(18.))
+                                            .height(Size::px(18.)),
+                                    )
+                            })
                             .child(icon_btn(chevron_up(), 18.)),
                     ),
             )
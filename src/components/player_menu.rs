@@ -0,0 +1,308 @@
+use freya::icons::lucide::{
+    activity, blend, gauge, hard_drive, infinity, moon, signal, sliders_horizontal, speaker,
+};
+use freya::prelude::*;
+use tokio::sync::mpsc::Sender;
+
+use crate::app::PlayerState;
+use crate::audio::{
+    AudioCommand, AudioQuality, EqPreset, NormalizationMode, OutputDevice, SleepMode,
+};
+use crate::components::SongMenuEntry;
+
+/// Crossfade lengths offered by the menu, cycled in order.
+const CROSSFADE_STEPS: [f32; 5] = [0., 3., 6., 9., 12.];
+
+/// Playback speeds offered by the menu, cycled in order.
+const SPEED_STEPS: [f32; 6] = [0.75, 1., 1.25, 1.5, 1.75, 2.];
+
+/// Audio cache sizes offered by the menu in MiB, cycled in order; `0` is off.
+const CACHE_STEPS: [u64; 6] = [0, 256, 512, 1024, 2048, 4096];
+
+/// Sleep timers offered by the menu, cycled in order after "off".
+const SLEEP_STEPS: [SleepMode; 6] = [
+    SleepMode::Minutes(15),
+    SleepMode::Minutes(30),
+    SleepMode::Minutes(45),
+    SleepMode::Minutes(60),
+    SleepMode::EndOfTrack,
+    SleepMode::EndOfQueue,
+];
+
+/// Equalizer presets offered by the menu, cycled in order after "off".
+const EQ_STEPS: [EqPreset; 4] = [
+    EqPreset::BassBoost,
+    EqPreset::TrebleBoost,
+    EqPreset::Vocal,
+    EqPreset::Loudness,
+];
+
+/// Snapshot of the settings shown in the player menu.
+#[derive(Clone, PartialEq)]
+pub struct PlayerOptions {
+    pub normalization: NormalizationMode,
+    pub quality: AudioQuality,
+    pub crossfade: f32,
+    pub speed: f32,
+    pub eq_enabled: bool,
+    pub eq_preset: EqPreset,
+    pub outputs: Vec<OutputDevice>,
+    pub output: Option<String>,
+    pub autoplay: bool,
+    pub cache_limit: u64,
+    pub sleep: Option<SleepMode>,
+}
+
+impl From<&PlayerState> for PlayerOptions {
+    fn from(p: &PlayerState) -> Self {
+        Self {
+            normalization: p.normalization,
+            quality: p.quality,
+            crossfade: p.crossfade_secs,
+            speed: p.speed,
+            eq_enabled: p.equalizer.enabled,
+            eq_preset: p.equalizer.preset,
+            outputs: p.outputs.clone(),
+            output: p.output.clone(),
+            autoplay: p.autoplay,
+            cache_limit: p.cache_limit,
+            sleep: p.sleep_timer,
+        }
+    }
+}
+
+fn normalization_label(mode: NormalizationMode) -> &'static str {
+    match mode {
+        NormalizationMode::Off => "Normalizar volumen: no",
+        NormalizationMode::Track => "Normalizar volumen: por canción",
+        NormalizationMode::Album => "Normalizar volumen: por álbum",
+    }
+}
+
+fn quality_label(quality: AudioQuality) -> &'static str {
+    match quality {
+        AudioQuality::Low => "Calidad de audio: baja",
+        AudioQuality::Medium => "Calidad de audio: normal",
+        AudioQuality::High => "Calidad de audio: alta",
+    }
+}
+
+fn crossfade_label(secs: f32) -> &'static str {
+    match secs as u32 {
+        0 => "Fundido entre canciones: no",
+        1..=4 => "Fundido entre canciones: 3 s",
+        5..=7 => "Fundido entre canciones: 6 s",
+        8..=10 => "Fundido entre canciones: 9 s",
+        _ => "Fundido entre canciones: 12 s",
+    }
+}
+
+fn speed_label(speed: f32) -> &'static str {
+    match (speed * 100.).round() as u32 {
+        ..=80 => "Velocidad: 0,75x",
+        81..=110 => "Velocidad: normal",
+        111..=135 => "Velocidad: 1,25x",
+        136..=160 => "Velocidad: 1,5x",
+        161..=185 => "Velocidad: 1,75x",
+        _ => "Velocidad: 2x",
+    }
+}
+
+fn eq_label(enabled: bool, preset: EqPreset) -> &'static str {
+    if !enabled {
+        return "Ecualizador: no";
+    }
+    match preset {
+        EqPreset::Flat => "Ecualizador: plano",
+        EqPreset::BassBoost => "Ecualizador: graves",
+        EqPreset::TrebleBoost => "Ecualizador: agudos",
+        EqPreset::Vocal => "Ecualizador: voces",
+        EqPreset::Loudness => "Ecualizador: sonoridad",
+        EqPreset::Custom => "Ecualizador: personalizado",
+    }
+}
+
+fn cache_label(bytes: u64) -> String {
+    match bytes / (1024 * 1024) {
+        0 => "Caché de audio: no".to_owned(),
+        mib if mib < 1024 => format!("Caché de audio: {mib} MB"),
+        mib => format!("Caché de audio: {} GB", mib / 1024),
+    }
+}
+
+fn sleep_label(sleep: Option<SleepMode>) -> String {
+    match sleep {
+        None => "Temporizador: no".to_owned(),
+        Some(SleepMode::Minutes(minutes)) => format!("Temporizador: {minutes} min"),
+        Some(SleepMode::EndOfTrack) => "Temporizador: fin de la canción".to_owned(),
+        Some(SleepMode::EndOfQueue) => "Temporizador: fin de la cola".to_owned(),
+    }
+}
+
+fn output_label(outputs: &[OutputDevice], output: Option<&str>) -> String {
+    let name = output.map(|id| {
+        outputs
+            .iter()
+            .find(|o| o.id == id)
+            .map_or("no conectada", |o| o.name.as_str())
+    });
+    format!("Salida de audio: {}", name.unwrap_or("predeterminada"))
+}
+
+/// Builds the playback options `Menu` opened from the player bar.
+///
+/// Each entry cycles its setting to the next value.
+pub fn player_menu(audio_cmd: Option<Sender<AudioCommand>>, options: PlayerOptions) -> Menu {
+    let PlayerOptions {
+        normalization,
+        quality,
+        crossfade,
+        speed,
+        eq_enabled,
+        eq_preset,
+        outputs,
+        output,
+        autoplay,
+        cache_limit,
+        sleep,
+    } = options;
+
+    Menu::new()
+        .child({
+            let audio_cmd = audio_cmd.clone();
+            SongMenuEntry::new(activity(), normalization_label(normalization)).on_press(
+                move |_| {
+                    let next = match normalization {
+                        NormalizationMode::Off => NormalizationMode::Track,
+                        NormalizationMode::Track => NormalizationMode::Album,
+                        NormalizationMode::Album => NormalizationMode::Off,
+                    };
+                    if let Some(tx) = &audio_cmd {
+                        tx.try_send(AudioCommand::SetNormalization(next)).ok();
+                    }
+                },
+            )
+        })
+        .child({
+            let audio_cmd = audio_cmd.clone();
+            SongMenuEntry::new(signal(), quality_label(quality)).on_press(move |_| {
+                let next = match quality {
+                    AudioQuality::Low => AudioQuality::Medium,
+                    AudioQuality::Medium => AudioQuality::High,
+                    AudioQuality::High => AudioQuality::Low,
+                };
+                if let Some(tx) = &audio_cmd {
+                    tx.try_send(AudioCommand::SetQuality(next)).ok();
+                }
+            })
+        })
+        .child({
+            let audio_cmd = audio_cmd.clone();
+            SongMenuEntry::new(blend(), crossfade_label(crossfade)).on_press(move |_| {
+                let next = CROSSFADE_STEPS
+                    .iter()
+                    .copied()
+                    .find(|&s| s > crossfade)
+                    .unwrap_or(0.);
+                if let Some(tx) = &audio_cmd {
+                    tx.try_send(AudioCommand::SetCrossfade(next)).ok();
+                }
+            })
+        })
+        .child({
+            let audio_cmd = audio_cmd.clone();
+            SongMenuEntry::new(gauge(), speed_label(speed)).on_press(move |_| {
+                let next = SPEED_STEPS
+                    .iter()
+                    .copied()
+                    .find(|&s| s > speed + 0.01)
+                    .unwrap_or(SPEED_STEPS[0]);
+                if let Some(tx) = &audio_cmd {
+                    tx.try_send(AudioCommand::SetSpeed(next)).ok();
+                }
+            })
+        })
+        .child({
+            let audio_cmd = audio_cmd.clone();
+            SongMenuEntry::new(sliders_horizontal(), eq_label(eq_enabled, eq_preset)).on_press(
+                move |_| {
+                    let Some(tx) = &audio_cmd else { return };
+                    // Off → each preset → off. A custom curve also goes off.
+                    let next = if eq_enabled {
+                        EQ_STEPS
+                            .iter()
+                            .position(|&p| p == eq_preset)
+                            .and_then(|i| EQ_STEPS.get(i + 1))
+                    } else {
+                        EQ_STEPS.first()
+                    };
+                    match next {
+                        Some(&preset) => {
+                            tx.try_send(AudioCommand::SetEqPreset(preset)).ok();
+                            tx.try_send(AudioCommand::SetEqEnabled(true)).ok();
+                        }
+                        None => {
+                            tx.try_send(AudioCommand::SetEqEnabled(false)).ok();
+                        }
+                    }
+                },
+            )
+        })
+        .child({
+            let audio_cmd = audio_cmd.clone();
+            let text = if autoplay {
+                "Reproducción automática: sí"
+            } else {
+                "Reproducción automática: no"
+            };
+            SongMenuEntry::new(infinity(), text).on_press(move |_| {
+                if let Some(tx) = &audio_cmd {
+                    tx.try_send(AudioCommand::SetAutoplay(!autoplay)).ok();
+                }
+            })
+        })
+        .child({
+            let audio_cmd = audio_cmd.clone();
+            SongMenuEntry::new(hard_drive(), cache_label(cache_limit)).on_press(move |_| {
+                let mib = cache_limit / (1024 * 1024);
+                let next = CACHE_STEPS.iter().copied().find(|&s| s > mib).unwrap_or(0);
+                if let Some(tx) = &audio_cmd {
+                    tx.try_send(AudioCommand::SetCacheLimit(next * 1024 * 1024)).ok();
+                }
+            })
+        })
+        .child({
+            let audio_cmd = audio_cmd.clone();
+            SongMenuEntry::new(moon(), sleep_label(sleep)).on_press(move |_| {
+                // Off → each timer → off. Picking a timer starts it over.
+                let next = match sleep {
+                    Some(current) => SLEEP_STEPS
+                        .iter()
+                        .position(|&s| s == current)
+                        .and_then(|i| SLEEP_STEPS.get(i + 1)),
+                    None => SLEEP_STEPS.first(),
+                };
+                if let Some(tx) = &audio_cmd {
+                    tx.try_send(AudioCommand::SetSleepTimer(next.copied())).ok();
+                }
+            })
+        })
+        .child(
+            SongMenuEntry::new(speaker(), output_label(&outputs, output.as_deref())).on_press(
+                move |_| {
+                    // System default → each device → system default.
+                    let current = output
+                        .as_deref()
+                        .and_then(|id| outputs.iter().position(|o| o.id == id));
+                    let next = match current {
+                        Some(i) => outputs.get(i + 1),
+                        None => outputs.first(),
+                    };
+                    if let Some(tx) = &audio_cmd {
+                        tx.try_send(AudioCommand::SetOutput(next.map(|o| o.id.clone())))
+                            .ok();
+                    }
+                },
+            ),
+        )
+}
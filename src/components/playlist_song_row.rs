@@ -7,7 +7,7 @@ use freya::router::RouterContext;
 use ytdroid::models::SongItem;
 
 use crate::app::{Data, DataChannel, QueueSong, Route};
-use crate::audio::{AudioCommand, AudioQuality};
+use crate::audio::AudioCommand;
 use crate::components::song_context_menu::song_context_menu;
 
 fn fmt_duration(secs: u32) -> String {
@@ -52,7 +52,14 @@ impl Component for PlaylistSongRow {
 
         // ── data extraction ───────────────────────────────────────────────────
         let audio_cmd = audio_radio.read().audio_cmd.clone();
+        let quality = audio_radio.read().player.quality;
 
+        // Rows of a podcast show are its episodes; suggestions may be anything.
+        let podcast = if self.is_suggestion {
+            None
+        } else {
+            nav_radio.read().playlist_view.as_ref().and_then(|pv| pv.podcast.clone())
+        };
         let playlist_songs: Vec<QueueSong> = if !self.is_suggestion {
             nav_radio
                 .read()
@@ -67,6 +74,7 @@ impl Component for PlaylistSongRow {
                             artist: s.artists.first().map(|a| a.name.clone()).unwrap_or_default(),
                             album: s.album.as_ref().map(|a| a.name.clone()).unwrap_or_default(),
                             thumbnail_url: s.thumbnail.clone().unwrap_or_default(),
+                            podcast: podcast.clone(),
                         })
                         .collect()
                 })
@@ -98,6 +106,16 @@ impl Component for PlaylistSongRow {
         let ctx_artist_id = artist_id.clone();
         let ctx_album_id = album_id.clone();
         let ctx_router = router.clone();
+        let ctx_audio_cmd = audio_cmd.clone();
+        let ctx_download_cmd = audio_radio.read().download_cmd.clone();
+        let ctx_song = QueueSong {
+            video_id: video_id.clone(),
+            title: title.clone(),
+            artist: artist_name.clone(),
+            album: album_name.clone(),
+            thumbnail_url: thumbnail_url_str.clone(),
+            podcast: podcast.clone(),
+        };
 
         // ── layout ────────────────────────────────────────────────────────────
         rect()
@@ -119,9 +137,12 @@ impl Component for PlaylistSongRow {
                 ContextMenu::open_from_event(
                     &e,
                     song_context_menu(
+                        ctx_song.clone(),
                         ctx_artist_id.clone(),
                         ctx_album_id.clone(),
                         ctx_router.clone(),
+                        ctx_audio_cmd.clone(),
+                        ctx_download_cmd.clone(),
                     ),
                 );
             })
@@ -134,6 +155,7 @@ impl Component for PlaylistSongRow {
                 let artist_name = artist_name.clone();
                 let album_name = album_name.clone();
                 let thumbnail_url_str = thumbnail_url_str.clone();
+                let podcast = podcast.clone();
                 let thumb_url_display = thumb_url.clone();
 
                 rect()
@@ -154,18 +176,19 @@ impl Component for PlaylistSongRow {
                         if is_suggestion || playlist_songs.is_empty() {
                             tx.try_send(AudioCommand::Play {
                                 video_id: video_id.clone(),
-                                quality: AudioQuality::Medium,
+                                quality,
                                 title: title.clone(),
                                 artist: artist_name.clone(),
                                 album: album_name.clone(),
                                 thumbnail_url: thumbnail_url_str.clone(),
+                                podcast: podcast.clone(),
                             })
                             .ok();
                         } else {
                             tx.try_send(AudioCommand::PlayFromQueue {
                                 songs: playlist_songs.clone(),
                                 index: queue_index,
-                                quality: AudioQuality::Medium,
+                                quality,
                             })
                             .ok();
                         }
@@ -8,8 +8,9 @@ use freya::router::RouterContext;
 use ytdroid::models::YTItem;
 
 use super::TextInfo;
-use crate::app::{Data, DataChannel, NavCommand, Route};
-use crate::audio::{AudioCommand, AudioQuality};
+use crate::app::{Data, DataChannel, NavCommand, QueueSong, Route};
+use crate::audio::AudioCommand;
+use crate::components::song_context_menu::song_context_menu;
 
 #[derive(Clone, PartialEq)]
 pub struct SongInfo {
@@ -24,6 +25,11 @@ pub struct SongInfo {
     is_video: bool,
     is_playlist: bool,
     thumbnail: String,
+    /// Browse ID of the show, for podcast episodes.
+    podcast: Option<String>,
+    /// Channel of the first artist and album of a song, for its context menu.
+    artist_id: Option<String>,
+    album_id: Option<String>,
 }
 
 impl SongInfo {
@@ -71,6 +77,9 @@ impl<'a> From<&'a YTItem> for SongInfo {
                     is_video: false,
                     is_playlist: false,
                     thumbnail: v.thumbnail.clone().unwrap_or_default(),
+                    podcast: None,
+                    artist_id: None,
+                    album_id: None,
                 }
             }
             YTItem::Playlist(v) => {
@@ -95,6 +104,9 @@ impl<'a> From<&'a YTItem> for SongInfo {
                     is_video: false,
                     is_playlist: true,
                     thumbnail: v.thumbnail.clone().unwrap_or_default(),
+                    podcast: None,
+                    artist_id: None,
+                    album_id: None,
                 }
             }
             YTItem::Artist(v) => Self {
@@ -111,6 +123,9 @@ impl<'a> From<&'a YTItem> for SongInfo {
                 is_video: false,
                 is_playlist: false,
                 thumbnail: v.thumbnail.clone().unwrap_or_default(),
+                podcast: None,
+                artist_id: None,
+                album_id: None,
             },
             YTItem::Song(v) => {
                 let left = if !v.artists.is_empty() {
@@ -137,6 +152,13 @@ impl<'a> From<&'a YTItem> for SongInfo {
                     is_video: v.is_video_song(),
                     is_playlist: false,
                     thumbnail: v.thumbnail.clone().unwrap_or_default(),
+                    podcast: None,
+                    artist_id: v.artists.first().and_then(|a| a.id.clone()),
+                    album_id: v
+                        .album
+                        .as_ref()
+                        .map(|a| a.id.clone())
+                        .filter(|id| !id.is_empty()),
                 }
             }
             YTItem::Podcast(v) => Self {
@@ -155,6 +177,9 @@ impl<'a> From<&'a YTItem> for SongInfo {
                 is_video: false,
                 is_playlist: false,
                 thumbnail: v.thumbnail.clone().unwrap_or_default(),
+                podcast: None,
+                artist_id: None,
+                album_id: None,
             },
             YTItem::Episode(v) => Self {
                 id: v.id.clone(),
@@ -172,6 +197,9 @@ impl<'a> From<&'a YTItem> for SongInfo {
                 is_video: false,
                 is_playlist: false,
                 thumbnail: v.thumbnail.clone().unwrap_or_default(),
+                podcast: v.podcast.as_ref().map(|p| p.id.clone()),
+                artist_id: None,
+                album_id: None,
             },
         }
     }
@@ -183,14 +211,17 @@ impl Component for SongInfo {
         let mut hover = use_state(|| false);
         let audio_radio = use_radio::<Data, DataChannel>(DataChannel::Player);
         let audio_cmd = audio_radio.read().audio_cmd.clone();
+        let quality = audio_radio.read().player.quality;
         let nav_radio = use_radio::<Data, DataChannel>(DataChannel::Navigation);
         let nav_cmd = nav_radio.read().nav_cmd.clone();
+        let router = RouterContext::get();
 
         let video_id = self.id.clone();
         let song_title = self.title.clone();
         let song_artist = self.artist.clone();
         let song_album = self.album.clone();
         let song_thumbnail = self.thumbnail.clone();
+        let song_podcast = self.podcast.clone();
 
         let size = if self.is_video { 402. } else { 223. };
         let height = 223.;
@@ -232,7 +263,38 @@ impl Component for SongInfo {
                         Cursor::set(CursorIcon::Default);
                         hover.set(false);
                     })
-                    .on_secondary_down(move |_| ContextMenu::open(Menu::new()))
+                    .on_secondary_down({
+                        let playable = !self.is_album && !self.is_artist && !self.is_playlist;
+                        let song = QueueSong {
+                            video_id: video_id.clone(),
+                            title: song_title.clone(),
+                            artist: song_artist.clone(),
+                            album: song_album.clone(),
+                            thumbnail_url: song_thumbnail.clone(),
+                            podcast: song_podcast.clone(),
+                        };
+                        let artist_id = self.artist_id.clone();
+                        let album_id = self.album_id.clone();
+                        let audio_cmd = audio_cmd.clone();
+                        let download_cmd = audio_radio.read().download_cmd.clone();
+                        move |e: Event<PressEventData>| {
+                            if !playable {
+                                ContextMenu::open(Menu::new());
+                                return;
+                            }
+                            ContextMenu::open_from_event(
+                                &e,
+                                song_context_menu(
+                                    song.clone(),
+                                    artist_id.clone(),
+                                    album_id.clone(),
+                                    router.clone(),
+                                    audio_cmd.clone(),
+                                    download_cmd.clone(),
+                                ),
+                            );
+                        }
+                    })
                     .child(
                         ImageViewer::new(Url::from_str(self.thumbnail.as_str()).unwrap())
                             .expanded()
@@ -253,6 +315,7 @@ impl Component for SongInfo {
                                         let artist = song_artist.clone();
                                         let album = song_album.clone();
                                         let thumbnail_url = song_thumbnail.clone();
+                                        let podcast = song_podcast.clone();
                                         move |_| {
                                             if is_playlist {
                                                 // Begin navigation: show loading bar and fetch
@@ -273,11 +336,12 @@ impl Component for SongInfo {
                                                 if let Some(tx) = audio_cmd.clone() {
                                                     tx.try_send(AudioCommand::Play {
                                                         video_id: video_id.clone(),
-                                                        quality: AudioQuality::Medium,
+                                                        quality,
                                                         title: title.clone(),
                                                         artist: artist.clone(),
                                                         album: album.clone(),
                                                         thumbnail_url: thumbnail_url.clone(),
+                                                        podcast: podcast.clone(),
                                                     })
                                                     .ok();
                                                 }
@@ -322,6 +386,7 @@ impl Component for SongInfo {
                                             let artist = song_artist.clone();
                                             let album = song_album.clone();
                                             let thumbnail_url = song_thumbnail.clone();
+                                            let podcast = song_podcast.clone();
                                             move |_| {
                                                 is_playing.toggle();
                                                 if is_playlist {
@@ -334,11 +399,12 @@ impl Component for SongInfo {
                                                 } else if let Some(tx) = audio_cmd.clone() {
                                                     tx.try_send(AudioCommand::Play {
                                                         video_id: video_id.clone(),
-                                                        quality: AudioQuality::Medium,
+                                                        quality,
                                                         title: title.clone(),
                                                         artist: artist.clone(),
                                                         album: album.clone(),
                                                         thumbnail_url: thumbnail_url.clone(),
+                                                        podcast: podcast.clone(),
                                                     })
                                                     .ok();
                                                 }
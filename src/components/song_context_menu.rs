@@ -5,20 +5,26 @@ use freya::icons::lucide::{
 };
 use freya::prelude::*;
 use freya::router::RouterContext;
+use tokio::sync::mpsc::Sender;
 
-use crate::app::Route;
+use crate::app::{DownloadCommand, QueueSong, Route};
+use crate::audio::AudioCommand;
 
 /// A single row inside a song context menu: icon on the left, label on the right.
 #[derive(Clone, PartialEq)]
 pub struct SongMenuEntry {
     icon: Bytes,
-    text: &'static str,
+    text: String,
     on_press: Option<EventHandler<Event<PressEventData>>>,
 }
 
 impl SongMenuEntry {
-    pub fn new(icon: Bytes, text: &'static str) -> Self {
-        Self { icon, text, on_press: None }
+    pub fn new(icon: Bytes, text: impl Into<String>) -> Self {
+        Self {
+            icon,
+            text: text.into(),
+            on_press: None,
+        }
     }
 
     pub fn on_press(mut self, f: impl Into<EventHandler<Event<PressEventData>>>) -> Self {
@@ -30,7 +36,7 @@ impl SongMenuEntry {
 impl Component for SongMenuEntry {
     fn render(&self) -> impl IntoElement {
         let icon = self.icon.clone();
-        let text = self.text;
+        let text = self.text.clone();
         let on_press = self.on_press.clone();
 
         MenuButton::new()
@@ -61,16 +67,38 @@ impl Component for SongMenuEntry {
 ///
 /// `router` must be captured at component render time (not inside event handlers).
 pub fn song_context_menu(
+    song: QueueSong,
     artist_id: Option<String>,
     album_id: Option<String>,
     router: RouterContext,
+    audio_cmd: Option<Sender<AudioCommand>>,
+    download_cmd: Option<Sender<DownloadCommand>>,
 ) -> Menu {
     Menu::new()
         .child(SongMenuEntry::new(radio(), "Iniciar mix"))
-        .child(SongMenuEntry::new(skip_forward(), "Reproducir a continuación"))
-        .child(SongMenuEntry::new(list_plus(), "Añadir a la cola"))
+        .child({
+            let tx = audio_cmd.clone();
+            let song = song.clone();
+            SongMenuEntry::new(skip_forward(), "Reproducir a continuación").on_press(move |_| {
+                if let Some(ref tx) = tx {
+                    tx.try_send(AudioCommand::PlayNext(song.clone())).ok();
+                }
+            })
+        })
+        .child({
+            let song = song.clone();
+            SongMenuEntry::new(list_plus(), "Añadir a la cola").on_press(move |_| {
+                if let Some(ref tx) = audio_cmd {
+                    tx.try_send(AudioCommand::Enqueue(song.clone())).ok();
+                }
+            })
+        })
         .child(SongMenuEntry::new(library(), "Guardar en la biblioteca"))
-        .child(SongMenuEntry::new(download(), "Descargar"))
+        .child(SongMenuEntry::new(download(), "Descargar").on_press(move |_| {
+            if let Some(ref tx) = download_cmd {
+                tx.try_send(DownloadCommand::Song(song.clone())).ok();
+            }
+        }))
         .child(SongMenuEntry::new(list_music(), "Añadir a lista de reproducción"))
         .child(SongMenuEntry::new(list_minus(), "Quitar de la lista de reproducción"))
         .child({
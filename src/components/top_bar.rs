@@ -1,21 +1,25 @@
-use freya::icons::lucide::chevron_left;
+use freya::icons::lucide::{chevron_left, download};
 use freya::prelude::*;
 use freya::router::RouterContext;
 
-/// Standalone top navigation bar. Contains the back button on the left;
-/// the right side is reserved for future page-level actions.
+use crate::app::Route;
+
+/// Standalone top navigation bar. Contains the back button on the left and
+/// the link to the downloads view on the right.
 #[derive(Clone, PartialEq)]
 pub struct TopBar;
 
 impl Component for TopBar {
     fn render(&self) -> impl IntoElement {
         let router = RouterContext::get();
+        let downloads_router = router.clone();
 
         rect()
             .horizontal()
             .width(Size::window_percent(100.))
             .height(Size::px(52.))
             .padding(Gaps::new_symmetric(0., 16.))
+            .content(Content::Flex)
             .cross_align(Alignment::Center)
             .background(Color::from_hex("#0D0D0D").unwrap())
             .child(
@@ -41,5 +45,31 @@ impl Component for TopBar {
                             .font_size(14.),
                     ),
             )
+            .child(rect().width(Size::flex(1.)))
+            .child(
+                rect()
+                    .horizontal()
+                    .cross_align(Alignment::Center)
+                    .spacing(6.)
+                    .padding(Gaps::new_symmetric(6., 10.))
+                    .corner_radius(8.)
+                    .on_pointer_enter(|_| Cursor::set(CursorIcon::Pointer))
+                    .on_pointer_leave(|_| Cursor::set(CursorIcon::Default))
+                    .on_press(move |_| {
+                        downloads_router.push(Route::Downloads).ok();
+                    })
+                    .child(
+                        SvgViewer::new(download())
+                            .color(Color::WHITE)
+                            .width(Size::px(16.))
+                            .height(Size::px(16.)),
+                    )
+                    .child(
+                        label()
+                            .text("Descargas")
+                            .color(Color::WHITE)
+                            .font_size(14.),
+                    ),
+            )
     }
 }
@@ -0,0 +1,246 @@
+//! Headless mode (`--daemon`): the audio engine and the YouTube session run
+//! without a window and are driven over a Unix socket, e.g. by
+//! `weviwavo-ctl`.
+//!
+//! The protocol is JSON lines. Every request is an object with a `cmd` and
+//! gets exactly one reply line, in order: `{"ok": true, ...}` or
+//! `{"ok": false, "error": "..."}`.
+//!
+//! | `cmd` | arguments | reply |
+//! |---|---|---|
+//! | `play` | `id`: video ID | |
+//! | `enqueue` | `id`: video ID | |
+//! | `seek` | `secs` | |
+//! | `pause`, `resume`, `next`, `previous`, `stop` | | |
+//! | `volume` | `level` in `0.0..=1.0` | |
+//! | `status` | | `status`, see [`remote::status_json`] |
+//! | `search` | `query`, optional `limit` | `results`: songs |
+
+use std::io;
+use std::path::Path;
+
+use freya::radio::RadioStation;
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+use weviwavo_ctl::socket_path;
+use ytdroid::YouTube;
+
+use crate::app::{Data, DataChannel, QueueSong};
+use crate::audio::AudioCommand;
+use crate::remote::{self, session, status_json};
+use crate::startup::run_startup;
+
+/// Search results returned when the request sets no `limit`.
+const DEFAULT_SEARCH_LIMIT: usize = 10;
+
+/// A parsed request line and where its reply goes.
+struct Request {
+    body: Value,
+    reply: oneshot::Sender<Value>,
+}
+
+/// Run the engines and the control socket until the process is killed.
+pub async fn run_daemon(radio: RadioStation<Data, DataChannel>) {
+    let (tx, rx) = mpsc::channel::<Request>(16);
+    let path = socket_path();
+    match bind(&path) {
+        Ok(listener) => {
+            tracing::info!(path = %path.display(), "control socket listening");
+            tokio::spawn(accept_loop(listener, tx));
+        }
+        Err(e) => {
+            tracing::error!(error = %e, path = %path.display(), "cannot open control socket");
+            return;
+        }
+    }
+    tokio::join!(run_startup(radio), run_control_engine(rx, radio));
+}
+
+/// Listen on `path`, reachable only by the current user.
+fn bind(path: &Path) -> io::Result<UnixListener> {
+    use std::os::unix::fs::PermissionsExt;
+
+    if path.exists() {
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                "another daemon is listening",
+            ));
+        }
+        // A socket left behind by a daemon that didn't exit cleanly.
+        std::fs::remove_file(path)?;
+    }
+    // Bound in a directory only we can enter and moved into place once its
+    // permissions are set: in a shared temp directory, other users could
+    // connect in between otherwise.
+    let parent = path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let private = tempfile::Builder::new()
+        .prefix(".weviwavo-")
+        .permissions(std::fs::Permissions::from_mode(0o700))
+        .tempdir_in(parent)?;
+    let staged = private.path().join("sock");
+    let listener = UnixListener::bind(&staged)?;
+    std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(0o600))?;
+    std::fs::rename(&staged, path)?;
+    Ok(listener)
+}
+
+async fn accept_loop(listener: UnixListener, tx: mpsc::Sender<Request>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                tokio::spawn(serve_client(stream, tx.clone()));
+            }
+            Err(e) => tracing::warn!(error = %e, "control socket accept failed"),
+        }
+    }
+}
+
+async fn serve_client(stream: UnixStream, tx: mpsc::Sender<Request>) {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str::<Value>(&line) {
+            Ok(body) if body.is_object() => {
+                let (reply, rx) = oneshot::channel();
+                if tx.send(Request { body, reply }).await.is_err() {
+                    break;
+                }
+                rx.await.unwrap_or_else(|_| error("request dropped"))
+            }
+            Ok(_) => error("request must be a JSON object"),
+            Err(e) => error(&format!("invalid JSON: {e}")),
+        };
+        let mut out = reply.to_string();
+        out.push('\n');
+        if write.write_all(out.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+fn ok() -> Value {
+    json!({ "ok": true })
+}
+
+fn error(message: &str) -> Value {
+    json!({ "ok": false, "error": message })
+}
+
+async fn run_control_engine(
+    mut rx: mpsc::Receiver<Request>,
+    radio: RadioStation<Data, DataChannel>,
+) {
+    while let Some(Request { body, reply }) = rx.recv().await {
+        let Some(audio_cmd) = radio.read().audio_cmd.clone() else {
+            reply.send(error("player not ready")).ok();
+            continue;
+        };
+        let simple = |cmd: AudioCommand| {
+            let sent = audio_cmd.try_send(cmd).is_ok();
+            if sent { ok() } else { error("player busy") }
+        };
+        let cmd = body["cmd"].as_str().unwrap_or_default().to_owned();
+        let answer = match cmd.as_str() {
+            "status" => json!({ "ok": true, "status": status_json(&radio.read().player) }),
+            "pause" => simple(AudioCommand::Pause),
+            "resume" => simple(AudioCommand::Resume),
+            "next" => simple(AudioCommand::Next),
+            "previous" => simple(AudioCommand::Previous),
+            "stop" => simple(AudioCommand::Stop),
+            "seek" => match body["secs"].as_f64() {
+                Some(secs) if secs >= 0. => simple(AudioCommand::Seek(secs as f32)),
+                _ => error("seek needs a non-negative `secs`"),
+            },
+            "volume" => match body["level"].as_f64() {
+                Some(level) if (0. ..=1.).contains(&level) => {
+                    simple(AudioCommand::SetVolume(level as f32))
+                }
+                _ => error("volume needs a `level` between 0 and 1"),
+            },
+            // These wait on the network, so they answer from a task of their
+            // own and other clients aren't held up meanwhile.
+            "play" | "enqueue" => {
+                let yt = session(&radio);
+                let enqueue = cmd == "enqueue";
+                let quality = radio.read().player.quality;
+                let to_command = move |song| {
+                    if enqueue {
+                        AudioCommand::Enqueue(song)
+                    } else {
+                        remote::play_command(song, quality)
+                    }
+                };
+                tokio::spawn(async move {
+                    reply.send(play(yt, audio_cmd, &body, to_command).await).ok();
+                });
+                continue;
+            }
+            "search" => {
+                let yt = session(&radio);
+                tokio::spawn(async move {
+                    reply.send(search(yt, &body).await).ok();
+                });
+                continue;
+            }
+            "" => error("missing `cmd`"),
+            other => error(&format!("unknown command `{other}`")),
+        };
+        reply.send(answer).ok();
+    }
+}
+
+async fn play(
+    yt: Option<YouTube>,
+    audio_cmd: mpsc::Sender<AudioCommand>,
+    body: &Value,
+    to_command: impl FnOnce(QueueSong) -> AudioCommand,
+) -> Value {
+    let Some(video_id) = body["id"].as_str().and_then(remote::video_id) else {
+        return error("missing or invalid video `id`");
+    };
+    let Some(yt) = yt else {
+        return error("no YouTube session");
+    };
+    let song = match remote::song_details(&yt, video_id).await {
+        Ok(song) => song,
+        Err(e) => return error(&e),
+    };
+    if audio_cmd.send(to_command(song.clone())).await.is_err() {
+        return error("player stopped");
+    }
+    json!({ "ok": true, "song": song.to_json() })
+}
+
+async fn search(yt: Option<YouTube>, body: &Value) -> Value {
+    let Some(query) = body["query"].as_str().filter(|q| !q.trim().is_empty()) else {
+        return error("missing `query`");
+    };
+    let Some(yt) = yt else {
+        return error("no YouTube session");
+    };
+    let limit = body["limit"]
+        .as_u64()
+        .map_or(DEFAULT_SEARCH_LIMIT, |n| n as usize);
+    let songs = match remote::search_songs(&yt, query, limit).await {
+        Ok(songs) => songs,
+        Err(e) => return error(&e),
+    };
+    let results: Vec<Value> = songs
+        .into_iter()
+        .map(|(song, duration)| {
+            let mut entry = song.to_json();
+            entry["duration"] = json!(duration);
+            entry
+        })
+        .collect();
+    json!({ "ok": true, "results": results })
+}
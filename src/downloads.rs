@@ -0,0 +1,539 @@
+//! Songs saved for offline playback.
+//!
+//! Downloads run through a [`DownloadManager`] and land in
+//! `data_dir(["downloads"])`, one file per video ID, next to an `index.json`
+//! holding each song's metadata and format. Songs are indexed as soon as they
+//! are queued, so downloads cut short by a crash or by quitting are queued
+//! again on the next launch and continue from their partial files.
+//!
+//! Finished files get the song's tags and cover art embedded and are renamed
+//! to `<video ID>.m4a` or `<video ID>.opus`, so they stay usable outside the
+//! app. The index records the new name under `"file"`.
+//!
+//! Playback checks [`lookup`] before the audio cache and the network.
+
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use freya::radio::RadioStation;
+use serde_json::{Map, Value, json};
+use tokio::sync::mpsc;
+use tracing::{debug, error, warn};
+use ytdroid::YouTube;
+use ytdroid::offline::{DownloadManager, JobProgress, JobStatus, Resolved, Resolver};
+use ytdroid::response::VideoDetails;
+use ytdroid::tags::{Picture, Tags};
+
+use crate::app::{Data, DataChannel, DownloadCommand, DownloadItem, QueueSong};
+use crate::audio::{AudioQuality, CachedAudio, ResolvedAudio, resolve_audio, stream_downloader};
+use crate::utils::data_dir;
+
+/// Songs downloading at once.
+const CONCURRENCY: usize = 2;
+
+/// Continuation pages followed when expanding a playlist or album.
+const MAX_PAGES: u8 = 10;
+
+/// Serializes read-modify-write cycles of the index.
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+fn dir() -> PathBuf {
+    data_dir(&["downloads"])
+}
+
+fn load_index() -> Map<String, Value> {
+    std::fs::read_to_string(dir().join("index.json"))
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+/// Apply `f` to the index and write it back.
+fn update_index(f: impl FnOnce(&mut Map<String, Value>)) {
+    let _guard = INDEX_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut index = load_index();
+    f(&mut index);
+    let dir = dir();
+    let written = std::fs::create_dir_all(&dir)
+        .and_then(|()| std::fs::write(dir.join("index.json"), Value::Object(index).to_string()));
+    if let Err(e) = written {
+        warn!(error = %e, "failed to write downloads index");
+    }
+}
+
+/// Name of the downloaded file of an index entry: the video ID until the
+/// file has been tagged.
+fn file_name(entry: &Value, video_id: &str) -> String {
+    entry["file"].as_str().unwrap_or(video_id).to_owned()
+}
+
+/// The downloaded file of `video_id`, in the same shape as a cache hit.
+/// `None` until the download has finished.
+pub fn lookup(video_id: &str) -> Option<CachedAudio> {
+    let entry = {
+        let _guard = INDEX_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        load_index().remove(video_id)?
+    };
+    let file = std::fs::File::open(dir().join(file_name(&entry, video_id))).ok()?;
+    let len = file.metadata().ok()?.len();
+    Some(CachedAudio {
+        itag: entry["itag"].as_u64().and_then(|i| u32::try_from(i).ok())?,
+        file,
+        len,
+        mime_type: entry["mime_type"].as_str().map(str::to_owned),
+        loudness_db: entry["loudness_db"].as_f64().map(|db| db as f32),
+    })
+}
+
+struct Saved {
+    song: QueueSong,
+    paused: bool,
+    /// Size of the finished file; `None` while the download is incomplete.
+    size: Option<u64>,
+    tagged: bool,
+}
+
+/// Songs in the index, in the order they were added.
+fn saved() -> Vec<Saved> {
+    let _guard = INDEX_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let mut entries: Vec<_> = load_index().into_values().collect();
+    entries.sort_by_key(|e| e["added"].as_u64().unwrap_or_default());
+    entries
+        .iter()
+        .filter_map(|e| {
+            let song = QueueSong::from_json(&e["song"])?;
+            let path = dir().join(file_name(e, &song.video_id));
+            Some(Saved {
+                size: std::fs::metadata(path).ok().map(|m| m.len()),
+                paused: e["paused"].as_bool()?,
+                tagged: e["tagged"].as_bool().unwrap_or_default(),
+                song,
+            })
+        })
+        .collect()
+}
+
+/// What the song's own fields don't hold of `tags`.
+fn tags_to_json(tags: &Tags) -> Value {
+    json!({
+        "artists": tags.artists,
+        "year": tags.year,
+        "track": tags.track,
+        "track_total": tags.track_total,
+    })
+}
+
+/// Tags of an index entry, without cover art.
+fn tags_from_entry(entry: &Value) -> Option<Tags> {
+    let song = QueueSong::from_json(&entry["song"])?;
+    let extra = &entry["tags"];
+    let details = &entry["details"];
+    let mut artists: Vec<String> = extra["artists"]
+        .as_array()
+        .map(|a| a.iter().filter_map(|v| v.as_str().map(str::to_owned)).collect())
+        .unwrap_or_default();
+    if artists.is_empty() && !song.artist.is_empty() {
+        artists.push(song.artist);
+    }
+    let tags = Tags {
+        title: Some(song.title).filter(|t| !t.is_empty()),
+        artists,
+        album: Some(song.album).filter(|a| !a.is_empty()),
+        year: extra["year"].as_str().map(str::to_owned),
+        track: extra["track"].as_u64().and_then(|n| u32::try_from(n).ok()),
+        track_total: extra["track_total"].as_u64().and_then(|n| u32::try_from(n).ok()),
+        cover: None,
+    };
+    Some(tags.with_video_details(&VideoDetails {
+        title: details["title"].as_str().map(str::to_owned),
+        author: details["author"].as_str().map(str::to_owned),
+        ..VideoDetails::default()
+    }))
+}
+
+/// Cover art URL of an index entry. Music thumbnails are resized by the
+/// suffix after the last `=`, so ask for a large square one.
+fn cover_url(entry: &Value) -> Option<String> {
+    let url = entry["song"]["thumbnail_url"]
+        .as_str()
+        .filter(|u| !u.is_empty())
+        .or_else(|| entry["details"]["thumbnail"].as_str())?;
+    Some(match url.rsplit_once('=') {
+        Some((base, _)) if url.contains("googleusercontent.com") => {
+            format!("{base}=w544-h544-l90-rj")
+        }
+        _ => url.to_owned(),
+    })
+}
+
+fn add(song: QueueSong, tags: &Tags) {
+    update_index(|index| {
+        let added = index
+            .values()
+            .filter_map(|e| e["added"].as_u64())
+            .max()
+            .map_or(0, |n| n + 1);
+        index.entry(song.video_id.clone()).or_insert_with(|| {
+            json!({
+                "song": song.to_json(),
+                "tags": tags_to_json(tags),
+                "paused": false,
+                "added": added,
+            })
+        });
+    });
+}
+
+fn set_format(video_id: &str, resolved: &ResolvedAudio) {
+    update_index(|index| {
+        if let Some(e) = index.get_mut(video_id) {
+            e["itag"] = json!(resolved.itag);
+            e["mime_type"] = json!(resolved.mime_type);
+            e["loudness_db"] = json!(resolved.loudness_db);
+            if let Some(details) = &resolved.video_details {
+                e["details"] = json!({
+                    "title": details.title,
+                    "author": details.author,
+                    "thumbnail": details.thumbnail.as_ref().and_then(|t| t.best_url()),
+                });
+            }
+        }
+    });
+}
+
+fn set_paused(video_id: &str, paused: bool) {
+    update_index(|index| {
+        if let Some(e) = index.get_mut(video_id) {
+            e["paused"] = json!(paused);
+        }
+    });
+}
+
+fn remove(video_id: &str) {
+    let mut file = None;
+    update_index(|index| {
+        file = index.remove(video_id).map(|e| file_name(&e, video_id));
+    });
+    if let Some(file) = file.filter(|f| f != video_id) {
+        std::fs::remove_file(dir().join(file)).ok();
+    }
+    std::fs::remove_file(dir().join(video_id)).ok();
+}
+
+/// Tags of `video_id` and where to fetch its cover, unless it is already
+/// tagged.
+fn pending_tags(video_id: &str) -> Option<(Tags, Option<String>)> {
+    let _guard = INDEX_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+    let entry = load_index().remove(video_id)?;
+    if entry["tagged"].as_bool().unwrap_or_default() {
+        return None;
+    }
+    Some((tags_from_entry(&entry)?, cover_url(&entry)))
+}
+
+/// Embed `tags` into the finished file of `video_id` and rename it after its
+/// container. A file that can't be tagged is kept as it is.
+fn write_tags(video_id: &str, tags: &Tags) {
+    let Some(entry) = ({
+        let _guard = INDEX_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        load_index().remove(video_id)
+    }) else {
+        return;
+    };
+    let old = dir().join(file_name(&entry, video_id));
+    let data = match std::fs::read(&old) {
+        Ok(data) => data,
+        Err(e) => {
+            warn!(video_id, error = %e, "failed to read download for tagging");
+            return;
+        }
+    };
+    let tagged = match ytdroid::tags::embed(&data, tags) {
+        Ok(tagged) => {
+            let file = format!("{video_id}.{}", tagged.extension);
+            let path = dir().join(&file);
+            let tmp = dir().join(format!("{file}.tmp"));
+            let written = std::fs::write(&tmp, &tagged.data)
+                .and_then(|()| std::fs::rename(&tmp, &path));
+            if let Err(e) = written {
+                warn!(video_id, error = %e, "failed to write tagged download");
+                std::fs::remove_file(&tmp).ok();
+                return;
+            }
+            if path != old {
+                std::fs::remove_file(&old).ok();
+            }
+            Some((file, tagged.mime_type))
+        }
+        Err(e) => {
+            warn!(video_id, error = %e, "failed to tag download, keeping it untagged");
+            None
+        }
+    };
+    let mut kept = false;
+    update_index(|index| {
+        if let Some(e) = index.get_mut(video_id) {
+            kept = true;
+            e["tagged"] = json!(true);
+            if let Some((file, mime_type)) = &tagged {
+                e["file"] = json!(file);
+                e["mime_type"] = json!(mime_type);
+            }
+        }
+    });
+    // Removed while being tagged.
+    if !kept && let Some((file, _)) = tagged {
+        std::fs::remove_file(dir().join(file)).ok();
+    }
+}
+
+async fn fetch_cover(url: &str) -> Option<Picture> {
+    let resp = reqwest::get(url).await.and_then(|r| r.error_for_status());
+    match resp {
+        Ok(resp) => Picture::from_bytes(resp.bytes().await.ok()?.to_vec()),
+        Err(e) => {
+            warn!(url, error = %e, "failed to fetch cover art");
+            None
+        }
+    }
+}
+
+/// Tag the finished download of `video_id` with its metadata and cover art.
+async fn tag_download(video_id: String) {
+    let id = video_id.clone();
+    let Some((tags, cover_url)) = blocking(move || pending_tags(&id)).await.flatten() else {
+        return;
+    };
+    let cover = match cover_url {
+        Some(url) => fetch_cover(&url).await,
+        None => None,
+    };
+    let tags = match cover {
+        Some(cover) => tags.with_cover(cover),
+        None => tags,
+    };
+    debug!(video_id, "tagging download");
+    blocking(move || write_tags(&video_id, &tags)).await;
+}
+
+/// Run `f` on a blocking thread, for index updates from the engine loop.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> Option<T> {
+    tokio::task::spawn_blocking(f).await.ok()
+}
+
+/// Resolves each download at the best quality, recording the chosen format
+/// in the index.
+fn resolver(cookies: Option<String>) -> Resolver {
+    Arc::new(move |video_id: &str| {
+        let video_id = video_id.to_owned();
+        let cookies = cookies.clone();
+        Box::pin(async move {
+            let resolved = resolve_audio(&video_id, AudioQuality::High, cookies)
+                .await
+                .map_err(|e| e.to_string())?;
+            let url = resolved.url.clone();
+            let len = resolved.content_length;
+            let format = resolved
+                .itag
+                .map(|itag| itag.to_string())
+                .unwrap_or_default();
+            blocking(move || set_format(&video_id, &resolved)).await;
+            Ok(Resolved { url, len, format })
+        })
+    })
+}
+
+/// Every song of a playlist or album with its tags, following
+/// continuations.
+async fn expand(yt: YouTube, cmd: DownloadCommand) -> Result<Vec<(QueueSong, Tags)>, String> {
+    let (mut songs, mut cont, album) = match &cmd {
+        DownloadCommand::Playlist(browse_id) => {
+            let page = yt.playlist(browse_id).await.map_err(|e| e.to_string())?;
+            (page.songs, page.songs_continuation, None)
+        }
+        DownloadCommand::Album(browse_id) => {
+            let page = yt.album(browse_id).await.map_err(|e| e.to_string())?;
+            (page.songs, page.songs_continuation, Some(page.album))
+        }
+        _ => return Ok(Vec::new()),
+    };
+    let mut page_count = 0u8;
+    while let Some(token) = cont.take() {
+        if page_count >= MAX_PAGES {
+            break;
+        }
+        page_count += 1;
+        match yt.playlist_continuation(&token).await {
+            Ok(cp) => {
+                songs.extend(cp.songs);
+                cont = cp.continuation;
+            }
+            Err(e) => {
+                warn!(error = %e, "continuation failed, downloading what was found");
+                break;
+            }
+        }
+    }
+    let total = u32::try_from(songs.len()).unwrap_or(u32::MAX);
+    Ok((1..)
+        .zip(&songs)
+        .map(|(track, s)| {
+            let mut song = QueueSong::from(s);
+            let tags = Tags::from_song(s);
+            let tags = match &album {
+                Some(album) => {
+                    if song.album.is_empty() {
+                        song.album.clone_from(&album.title);
+                    }
+                    tags.with_album(album, track, total)
+                }
+                None => tags,
+            };
+            (song, tags)
+        })
+        .collect())
+}
+
+/// Add `song` to the list and queue its download, unless it has already
+/// been downloaded.
+async fn queue_song(
+    radio: &mut RadioStation<Data, DataChannel>,
+    manager: &DownloadManager,
+    song: QueueSong,
+    tags: Tags,
+) {
+    let video_id = song.video_id.clone();
+    {
+        let mut state = radio.write_channel(DataChannel::Downloads);
+        match state.downloads.iter().find(|d| d.song.video_id == video_id) {
+            Some(item) if item.status == JobStatus::Done => return,
+            Some(_) => {}
+            None => state.downloads.push(DownloadItem {
+                song: song.clone(),
+                status: JobStatus::Queued,
+                done: 0,
+                total: None,
+            }),
+        }
+    }
+    blocking(move || add(song, &tags)).await;
+    manager.enqueue(video_id.clone(), dir().join(&video_id));
+}
+
+/// Drive downloads. Like the audio engine, this needs the [`RadioStation`]
+/// and so runs in Freya's startup future.
+pub async fn run_download_engine(
+    mut rx: mpsc::Receiver<DownloadCommand>,
+    mut radio: RadioStation<Data, DataChannel>,
+) {
+    let cookies = radio.read().cookie_header.clone();
+    let downloader = match stream_downloader(cookies.clone()) {
+        Ok(d) => d,
+        Err(e) => {
+            error!(error = %e, "failed to create download client");
+            return;
+        }
+    };
+    let (manager, mut events) = DownloadManager::new(downloader, resolver(cookies), CONCURRENCY);
+    let (songs_tx, mut songs_rx) = mpsc::channel::<Vec<(QueueSong, Tags)>>(4);
+
+    // Finished songs are listed, and tagged if that was cut short; the rest
+    // continue where they stopped. Their tags are already in the index.
+    for saved in blocking(saved).await.unwrap_or_default() {
+        let video_id = saved.song.video_id.clone();
+        if let Some(size) = saved.size {
+            radio.write_channel(DataChannel::Downloads).downloads.push(DownloadItem {
+                song: saved.song,
+                status: JobStatus::Done,
+                done: size,
+                total: Some(size),
+            });
+            if !saved.tagged {
+                tokio::spawn(tag_download(video_id));
+            }
+            continue;
+        }
+        queue_song(&mut radio, &manager, saved.song, Tags::default()).await;
+        if saved.paused {
+            manager.pause(&video_id);
+        }
+    }
+
+    loop {
+        tokio::select! {
+            cmd = rx.recv() => {
+                let Some(cmd) = cmd else { break };
+                match cmd {
+                    DownloadCommand::Song(song) => {
+                        let video_id = song.video_id.clone();
+                        queue_song(&mut radio, &manager, song, Tags::default()).await;
+                        // Asking again for a paused download resumes it.
+                        manager.resume(&video_id);
+                        set_paused_async(&video_id, false).await;
+                    }
+                    DownloadCommand::Playlist(_) | DownloadCommand::Album(_) => {
+                        let Some(yt) = radio.read().yt_session.clone() else {
+                            warn!(?cmd, "no YT session to expand download");
+                            continue;
+                        };
+                        let tx = songs_tx.clone();
+                        tokio::spawn(async move {
+                            match expand(yt, cmd).await {
+                                Ok(songs) => {
+                                    tx.send(songs).await.ok();
+                                }
+                                Err(e) => error!(error = %e, "failed to list songs to download"),
+                            }
+                        });
+                    }
+                    DownloadCommand::Pause(video_id) => {
+                        manager.pause(&video_id);
+                        set_paused_async(&video_id, true).await;
+                    }
+                    DownloadCommand::Resume(video_id) => {
+                        manager.resume(&video_id);
+                        set_paused_async(&video_id, false).await;
+                    }
+                    DownloadCommand::Remove(video_id) => {
+                        manager.remove(&video_id);
+                        radio
+                            .write_channel(DataChannel::Downloads)
+                            .downloads
+                            .retain(|d| d.song.video_id != video_id);
+                        blocking(move || remove(&video_id)).await;
+                    }
+                }
+            }
+            Some(songs) = songs_rx.recv() => {
+                debug!(count = songs.len(), "queueing downloads");
+                for (song, tags) in songs {
+                    queue_song(&mut radio, &manager, song, tags).await;
+                }
+            }
+            Some(progress) = events.recv() => {
+                if progress.status == JobStatus::Done {
+                    tokio::spawn(tag_download(progress.id.clone()));
+                }
+                apply_progress(&mut radio, progress);
+            }
+        }
+    }
+}
+
+async fn set_paused_async(video_id: &str, paused: bool) {
+    let video_id = video_id.to_owned();
+    blocking(move || set_paused(&video_id, paused)).await;
+}
+
+fn apply_progress(radio: &mut RadioStation<Data, DataChannel>, progress: JobProgress) {
+    let mut state = radio.write_channel(DataChannel::Downloads);
+    if let Some(item) = state
+        .downloads
+        .iter_mut()
+        .find(|d| d.song.video_id == progress.id)
+    {
+        item.status = progress.status;
+        item.done = progress.done;
+        item.total = progress.total;
+    }
+}
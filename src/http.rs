@@ -0,0 +1,160 @@
+//! HTTP remote control, started with `--http` (on `127.0.0.1:8765`) or
+//! `--http=ADDR`: a JSON API, a live status stream and a page for phones,
+//! served by [`http_remote`]. Requests need the token from
+//! [`crate::prefs::load_remote_token`]. Only the address is logged; the page
+//! URL with the token is printed by `--http-url`, which then exits.
+
+use freya::radio::RadioStation;
+use http_remote::{Command, HttpRemote, Repeat, Request};
+use serde_json::{Value, json};
+use tokio::sync::{mpsc, oneshot};
+use tracing::{info, warn};
+
+use crate::app::{Data, DataChannel, QueueSong};
+use crate::audio::{AudioCommand, RepeatMode};
+use crate::remote;
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:8765";
+
+/// Where to listen, if the server was asked for on the command line.
+fn listen_address() -> Option<String> {
+    std::env::args().find_map(|arg| match arg.strip_prefix("--http") {
+        Some("") => Some(DEFAULT_ADDRESS.to_owned()),
+        Some(addr) => addr.strip_prefix('=').map(str::to_owned),
+        None => None,
+    })
+}
+
+/// The control page's URL, token included, for the address `--http` would
+/// listen on.
+pub fn page_url() -> String {
+    let addr = listen_address().unwrap_or_else(|| DEFAULT_ADDRESS.to_owned());
+    format!("http://{addr}/?token={}", crate::prefs::load_remote_token())
+}
+
+/// Serve the remote while the app runs, if `--http` was given. Clients get
+/// the player state each time the audio engine updates the position.
+pub async fn run_http(radio: RadioStation<Data, DataChannel>) {
+    let Some(addr) = listen_address() else { return };
+    let (Some(audio_cmd), Some(mut progress)) = ({
+        let data = radio.read();
+        (
+            data.audio_cmd.clone(),
+            data.progress_tick.as_ref().map(|tick| tick.subscribe()),
+        )
+    }) else {
+        return;
+    };
+    let token = crate::prefs::load_remote_token();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let server = match HttpRemote::bind(addr.as_str(), token.as_str(), tx).await {
+        Ok(server) => server,
+        Err(e) => {
+            warn!(error = %e, addr, "cannot start HTTP remote");
+            return;
+        }
+    };
+    info!(addr = %server.local_addr(), "HTTP remote listening");
+    server.update(remote::status_json(&radio.read().player));
+
+    loop {
+        tokio::select! {
+            Some(request) = rx.recv() => handle(&radio, &audio_cmd, request),
+            changed = progress.changed() => {
+                if changed.is_err() {
+                    return;
+                }
+                server.update(remote::status_json(&radio.read().player));
+            }
+        }
+    }
+}
+
+fn handle(
+    radio: &RadioStation<Data, DataChannel>,
+    audio_cmd: &mpsc::Sender<AudioCommand>,
+    Request { command, reply }: Request,
+) {
+    let cmd = match command {
+        Command::PlayIndex(index) => AudioCommand::PlayFromQueue {
+            songs: radio.read().player.queue.clone(),
+            index,
+            quality: radio.read().player.quality,
+        },
+        Command::Remove(index) => AudioCommand::RemoveAt(index),
+        Command::Move { from, to } => AudioCommand::Move { from, to },
+        Command::Clear => AudioCommand::ClearQueue,
+        Command::Pause => AudioCommand::Pause,
+        Command::Resume => AudioCommand::Resume,
+        Command::Next => AudioCommand::Next,
+        Command::Previous => AudioCommand::Previous,
+        Command::Stop => AudioCommand::Stop,
+        Command::Seek(secs) => AudioCommand::Seek(secs as f32),
+        Command::SetVolume(level) => AudioCommand::SetVolume(level as f32),
+        Command::SetShuffle(on) => AudioCommand::SetShuffle(on),
+        Command::SetRepeat(repeat) => AudioCommand::SetRepeat(match repeat {
+            Repeat::Off => RepeatMode::Off,
+            Repeat::All => RepeatMode::All,
+            Repeat::One => RepeatMode::One,
+        }),
+        // These wait on the network, so they reply from a task of their own.
+        Command::Play(id) => {
+            let quality = radio.read().player.quality;
+            let play = move |song| remote::play_command(song, quality);
+            return add(radio, audio_cmd, id, play, reply);
+        }
+        Command::Enqueue(id) => return add(radio, audio_cmd, id, AudioCommand::Enqueue, reply),
+        Command::PlayNext(id) => return add(radio, audio_cmd, id, AudioCommand::PlayNext, reply),
+        Command::Search { query, limit } => {
+            let yt = remote::session(radio);
+            tokio::spawn(async move {
+                let result = async {
+                    let yt = yt.ok_or("no YouTube session")?;
+                    let songs = remote::search_songs(&yt, &query, limit).await?;
+                    let results: Vec<Value> = songs
+                        .into_iter()
+                        .map(|(song, duration)| {
+                            let mut entry = song.to_json();
+                            entry["duration"] = json!(duration);
+                            entry
+                        })
+                        .collect();
+                    Ok::<_, String>(Value::Array(results))
+                };
+                reply.send(result.await).ok();
+            });
+            return;
+        }
+    };
+    let result = audio_cmd
+        .try_send(cmd)
+        .map(|()| Value::Null)
+        .map_err(|_| "player busy".to_owned());
+    reply.send(result).ok();
+}
+
+/// Look up a video and hand it to the player with `to_command`.
+fn add(
+    radio: &RadioStation<Data, DataChannel>,
+    audio_cmd: &mpsc::Sender<AudioCommand>,
+    id: String,
+    to_command: impl FnOnce(QueueSong) -> AudioCommand + Send + 'static,
+    reply: oneshot::Sender<Result<Value, String>>,
+) {
+    let yt = remote::session(radio);
+    let audio_cmd = audio_cmd.clone();
+    tokio::spawn(async move {
+        let result = async {
+            let video_id = remote::video_id(&id).ok_or("not a YouTube video")?;
+            let yt = yt.ok_or("no YouTube session")?;
+            let song = remote::song_details(&yt, video_id).await?;
+            let json = song.to_json();
+            audio_cmd
+                .send(to_command(song))
+                .await
+                .map_err(|_| "player stopped")?;
+            Ok::<_, String>(json)
+        };
+        reply.send(result.await).ok();
+    });
+}
@@ -11,8 +11,17 @@ mod components;
 
 pub use audio::nsig::{decrypt_sig, decrypt_url, signature_timestamp};
 mod cookies;
+#[cfg(unix)]
+mod daemon;
 mod dialog;
+mod downloads;
+mod http;
+mod mpd;
+#[cfg(target_os = "linux")]
+mod mpris;
 mod prefs;
+mod remote;
+mod scrobble;
 mod startup;
 mod utils;
 
@@ -63,6 +72,12 @@ fn main() {
         }
     }
 
+    // Print the HTTP remote's URL with its token, which is never logged.
+    if std::env::args().any(|a| a == "--http-url") {
+        println!("{}", http::page_url());
+        return;
+    }
+
     let builder = tracing_appender::rolling::Builder::new()
         .rotation(tracing_appender::rolling::Rotation::DAILY)
         .filename_suffix("log")
@@ -88,6 +103,20 @@ fn main() {
         .unwrap();
     let _rt = rt.enter();
 
+    // Headless mode: no window and no login, just the engines behind the
+    // control socket. Cookies saved by an earlier GUI login are used if any.
+    #[cfg(unix)]
+    if std::env::args().any(|a| a == "--daemon") {
+        let mut initial_data = app::Data::default();
+        initial_data.cookie_header = cookies::load_cookies();
+        if initial_data.cookie_header.is_none() {
+            tracing::warn!("no saved cookies — running signed out");
+        }
+        let radio = RadioStation::create_global(initial_data);
+        rt.block_on(daemon::run_daemon(radio));
+        return;
+    }
+
     // Load persisted cookies; if absent spawn self as a login subprocess so
     // that Servo and Freya each get their own winit EventLoop (one per process).
     let cookie_header: Option<String> = match cookies::load_cookies() {
@@ -0,0 +1,164 @@
+//! MPD server, started with `--mpd` (on `127.0.0.1:6600`) or `--mpd=ADDR`:
+//! ncmpcpp, MPDroid and other MPD frontends see the queue through
+//! [`mpd_server`] and control playback with [`AudioCommand`]s.
+
+use std::time::Duration;
+
+use freya::radio::RadioStation;
+use mpd_server::{Command, MpdServer, PlayState, Repeat, Request, Song, State};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::app::{Data, DataChannel, PlayerState, QueueSong};
+use crate::audio::{AudioCommand, RepeatMode};
+use crate::remote;
+
+const DEFAULT_ADDRESS: &str = "127.0.0.1:6600";
+
+/// How often the player state is mirrored to clients.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Songs a `search` returns.
+const SEARCH_LIMIT: usize = 20;
+
+/// Where to listen, if the server was asked for on the command line.
+fn listen_address() -> Option<String> {
+    std::env::args().find_map(|arg| match arg.strip_prefix("--mpd") {
+        Some("") => Some(DEFAULT_ADDRESS.to_owned()),
+        Some(addr) => addr.strip_prefix('=').map(str::to_owned),
+        None => None,
+    })
+}
+
+fn song(song: &QueueSong) -> Song {
+    Song {
+        file: remote::watch_url(&song.video_id),
+        title: song.title.clone(),
+        artist: song.artist.clone(),
+        album: song.album.clone(),
+        duration: None,
+    }
+}
+
+fn state(p: &PlayerState) -> State {
+    let current = (p.queue_index < p.queue.len()).then_some(p.queue_index);
+    State {
+        state: match (current, p.is_playing) {
+            (None, _) => PlayState::Stop,
+            (Some(_), true) => PlayState::Play,
+            (Some(_), false) => PlayState::Pause,
+        },
+        queue: p.queue.iter().map(song).collect(),
+        current,
+        elapsed: f64::from(p.current_secs.max(0.)),
+        duration: (p.total_secs > 0.).then_some(f64::from(p.total_secs)),
+        volume: (p.volume.clamp(0., 1.) * 100.).round() as u8,
+        random: p.shuffle,
+        repeat: match p.repeat {
+            RepeatMode::Off => Repeat::Off,
+            RepeatMode::All => Repeat::All,
+            RepeatMode::One => Repeat::One,
+        },
+    }
+}
+
+/// Serve MPD while the app runs, if `--mpd` was given. Like the engines, this
+/// reads the [`RadioStation`] and so runs in Freya's startup future.
+pub async fn run_mpd(radio: RadioStation<Data, DataChannel>) {
+    let Some(addr) = listen_address() else { return };
+    let Some(audio_cmd) = radio.read().audio_cmd.clone() else {
+        return;
+    };
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let server = match MpdServer::bind(addr.as_str(), tx).await {
+        Ok(server) => server,
+        Err(e) => {
+            warn!(error = %e, addr, "cannot start MPD server");
+            return;
+        }
+    };
+    info!(addr = %server.local_addr(), "MPD server listening");
+
+    let mut ticker = tokio::time::interval(UPDATE_INTERVAL);
+    loop {
+        tokio::select! {
+            Some(request) = rx.recv() => handle(&radio, &audio_cmd, request),
+            _ = ticker.tick() => server.update(state(&radio.read().player)),
+        }
+    }
+}
+
+fn handle(
+    radio: &RadioStation<Data, DataChannel>,
+    audio_cmd: &mpsc::Sender<AudioCommand>,
+    Request { command, reply }: Request,
+) {
+    let cmd = match command {
+        Command::Play(index) => AudioCommand::PlayFromQueue {
+            songs: radio.read().player.queue.clone(),
+            index,
+            quality: radio.read().player.quality,
+        },
+        Command::Resume => AudioCommand::Resume,
+        Command::Pause => AudioCommand::Pause,
+        Command::Stop => AudioCommand::Stop,
+        Command::Next => AudioCommand::Next,
+        Command::Previous => AudioCommand::Previous,
+        Command::Seek(secs) => AudioCommand::Seek(secs as f32),
+        Command::SetVolume(volume) => AudioCommand::SetVolume(f32::from(volume) / 100.),
+        Command::SetRandom(random) => AudioCommand::SetShuffle(random),
+        Command::SetRepeat(repeat) => AudioCommand::SetRepeat(match repeat {
+            Repeat::Off => RepeatMode::Off,
+            Repeat::All => RepeatMode::All,
+            Repeat::One => RepeatMode::One,
+        }),
+        Command::Delete(index) => AudioCommand::RemoveAt(index),
+        Command::Move { from, to } => AudioCommand::Move { from, to },
+        // The current song keeps playing, as everywhere else in the app.
+        Command::Clear => AudioCommand::ClearQueue,
+        // These wait on the network, so they reply from a task of their own.
+        Command::Add(uri) => {
+            let yt = remote::session(radio);
+            let audio_cmd = audio_cmd.clone();
+            tokio::spawn(async move {
+                let result = async {
+                    let video_id = remote::video_id(&uri).ok_or("not a YouTube URI")?;
+                    let yt = yt.ok_or("no YouTube session")?;
+                    let song = remote::song_details(&yt, video_id).await?;
+                    audio_cmd
+                        .send(AudioCommand::Enqueue(song))
+                        .await
+                        .map_err(|_| "player stopped")?;
+                    Ok::<_, String>(Vec::new())
+                };
+                reply.send(result.await).ok();
+            });
+            return;
+        }
+        Command::Search(query) => {
+            let yt = remote::session(radio);
+            tokio::spawn(async move {
+                let result = async {
+                    let yt = yt.ok_or("no YouTube session")?;
+                    let songs = remote::search_songs(&yt, &query, SEARCH_LIMIT).await?;
+                    Ok::<_, String>(
+                        songs
+                            .iter()
+                            .map(|(queue_song, duration)| Song {
+                                duration: duration.map(f64::from),
+                                ..song(queue_song)
+                            })
+                            .collect(),
+                    )
+                };
+                reply.send(result.await).ok();
+            });
+            return;
+        }
+    };
+    let result = audio_cmd
+        .try_send(cmd)
+        .map(|()| Vec::new())
+        .map_err(|_| "player busy".to_owned());
+    reply.send(result).ok();
+}
@@ -0,0 +1,104 @@
+//! MPRIS2 on Linux: media keys, `playerctl`, desktop media widgets and lock
+//! screens see the player through [`mpris_bridge`] and control it with
+//! [`AudioCommand`]s.
+
+use std::time::Duration;
+
+use freya::radio::RadioStation;
+use mpris_bridge::{Command, LoopStatus, Mpris, PlaybackStatus, Status, Track};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::app::{Data, DataChannel, PlayerState};
+use crate::audio::{AudioCommand, MAX_SPEED, MIN_SPEED, RepeatMode};
+
+/// How often the player state is mirrored on the bus.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(500);
+
+fn status(p: &PlayerState) -> Status {
+    let track = p.queue.get(p.queue_index).map(|song| Track {
+        id: song.video_id.clone(),
+        title: p.title.clone(),
+        artists: (!p.artist.is_empty())
+            .then(|| p.artist.clone())
+            .into_iter()
+            .collect(),
+        album: p.album.clone(),
+        art_url: (!p.thumbnail_url.is_empty()).then(|| p.thumbnail_url.clone()),
+        length: (p.total_secs > 0.).then(|| Duration::from_secs(p.total_secs as u64)),
+    });
+    Status {
+        playback: match (&track, p.is_playing) {
+            (None, _) => PlaybackStatus::Stopped,
+            (Some(_), true) => PlaybackStatus::Playing,
+            (Some(_), false) => PlaybackStatus::Paused,
+        },
+        loop_status: match p.repeat {
+            RepeatMode::Off => LoopStatus::None,
+            RepeatMode::One => LoopStatus::Track,
+            RepeatMode::All => LoopStatus::Playlist,
+        },
+        shuffle: p.shuffle,
+        volume: f64::from(p.volume),
+        rate: f64::from(p.speed),
+        position: Duration::from_secs_f32(p.current_secs.max(0.)),
+        can_go_next: p.queue_index + 1 < p.queue.len() || p.repeat != RepeatMode::Off || p.autoplay,
+        can_go_previous: !p.queue.is_empty(),
+        track,
+    }
+}
+
+fn audio_command(cmd: Command) -> AudioCommand {
+    match cmd {
+        Command::Play => AudioCommand::Resume,
+        Command::Pause => AudioCommand::Pause,
+        Command::Stop => AudioCommand::Stop,
+        Command::Next => AudioCommand::Next,
+        Command::Previous => AudioCommand::Previous,
+        Command::SetPosition(position) => AudioCommand::Seek(position.as_secs_f32()),
+        Command::SetVolume(volume) => AudioCommand::SetVolume(volume.min(1.) as f32),
+        Command::SetRate(rate) => AudioCommand::SetSpeed(rate as f32),
+        Command::SetShuffle(shuffle) => AudioCommand::SetShuffle(shuffle),
+        Command::SetLoopStatus(status) => AudioCommand::SetRepeat(match status {
+            LoopStatus::None => RepeatMode::Off,
+            LoopStatus::Track => RepeatMode::One,
+            LoopStatus::Playlist => RepeatMode::All,
+        }),
+    }
+}
+
+/// Serve MPRIS for as long as the app runs. Like the engines, this reads the
+/// [`RadioStation`] and so runs in Freya's startup future.
+pub async fn run_mpris(radio: RadioStation<Data, DataChannel>) {
+    let Some(audio_cmd) = radio.read().audio_cmd.clone() else {
+        return;
+    };
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mpris = Mpris::builder(crate::APP_NAME)
+        .identity("WeviWavo")
+        .rate_range(f64::from(MIN_SPEED), f64::from(MAX_SPEED))
+        .serve(tx)
+        .await;
+    let mpris = match mpris {
+        Ok(mpris) => mpris,
+        Err(e) => {
+            warn!(error = %e, "MPRIS unavailable");
+            return;
+        }
+    };
+
+    let mut ticker = tokio::time::interval(UPDATE_INTERVAL);
+    loop {
+        tokio::select! {
+            Some(cmd) = rx.recv() => {
+                audio_cmd.send(audio_command(cmd)).await.ok();
+            }
+            _ = ticker.tick() => {
+                let status = status(&radio.read().player);
+                if let Err(e) = mpris.update(status).await {
+                    warn!(error = %e, "failed to update MPRIS state");
+                }
+            }
+        }
+    }
+}
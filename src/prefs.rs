@@ -1,3 +1,14 @@
+use std::collections::HashMap;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::Path;
+
+use crate::audio::{
+    AudioQuality, DEFAULT_CACHE_LIMIT, EqSettings, NormalizationMode, RepeatMode, Session,
+};
+use rand::RngCore;
+
 use crate::utils::data_dir;
 
 pub fn load_volume() -> f32 {
@@ -12,3 +23,220 @@ pub fn save_volume(vol: f32) {
     let path = data_dir(&["volume"]);
     let _ = std::fs::write(path, vol.to_string());
 }
+
+/// Crossfade length in seconds; `0` (the default) plays tracks back to back.
+pub fn load_crossfade() -> f32 {
+    let path = data_dir(&["crossfade"]);
+    std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(0.0)
+}
+
+pub fn save_crossfade(secs: f32) {
+    let path = data_dir(&["crossfade"]);
+    let _ = std::fs::write(path, secs.to_string());
+}
+
+pub fn load_normalization() -> NormalizationMode {
+    let path = data_dir(&["normalization"]);
+    std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|s| NormalizationMode::parse(s.trim()))
+        .unwrap_or_default()
+}
+
+pub fn save_normalization(mode: NormalizationMode) {
+    let path = data_dir(&["normalization"]);
+    let _ = std::fs::write(path, mode.as_str());
+}
+
+/// Stream quality; `Medium` until one is chosen.
+pub fn load_quality() -> AudioQuality {
+    let path = data_dir(&["quality"]);
+    std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|s| AudioQuality::parse(s.trim()))
+        .unwrap_or_default()
+}
+
+pub fn save_quality(quality: AudioQuality) {
+    let path = data_dir(&["quality"]);
+    let _ = std::fs::write(path, quality.as_str());
+}
+
+pub fn load_shuffle() -> bool {
+    let path = data_dir(&["shuffle"]);
+    std::fs::read_to_string(&path).is_ok_and(|s| s.trim() == "true")
+}
+
+pub fn save_shuffle(shuffle: bool) {
+    let path = data_dir(&["shuffle"]);
+    let _ = std::fs::write(path, shuffle.to_string());
+}
+
+pub fn load_repeat() -> RepeatMode {
+    let path = data_dir(&["repeat"]);
+    std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|s| RepeatMode::parse(s.trim()))
+        .unwrap_or_default()
+}
+
+pub fn save_repeat(repeat: RepeatMode) {
+    let path = data_dir(&["repeat"]);
+    let _ = std::fs::write(path, repeat.as_str());
+}
+
+/// Autoplay is on unless turned off, as in YouTube Music.
+pub fn load_autoplay() -> bool {
+    let path = data_dir(&["autoplay"]);
+    !std::fs::read_to_string(&path).is_ok_and(|s| s.trim() == "false")
+}
+
+pub fn save_autoplay(enabled: bool) {
+    let path = data_dir(&["autoplay"]);
+    let _ = std::fs::write(path, enabled.to_string());
+}
+
+/// Size limit of the audio cache in bytes; `0` disables the cache.
+pub fn load_cache_limit() -> u64 {
+    let path = data_dir(&["cache_limit"]);
+    std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|s| s.trim().parse().ok())
+        .unwrap_or(DEFAULT_CACHE_LIMIT)
+}
+
+pub fn save_cache_limit(bytes: u64) {
+    let path = data_dir(&["cache_limit"]);
+    let _ = std::fs::write(path, bytes.to_string());
+}
+
+/// Equalizer settings of `output`. Each output device keeps its own, since
+/// headphones and speakers rarely want the same curve.
+pub fn load_equalizer(output: &str) -> EqSettings {
+    load_equalizers()
+        .get(output)
+        .map(EqSettings::from_json)
+        .unwrap_or_default()
+}
+
+pub fn save_equalizer(output: &str, settings: &EqSettings) {
+    let mut all = load_equalizers();
+    all.insert(output.to_owned(), settings.to_json());
+    let path = data_dir(&["equalizer"]);
+    let _ = std::fs::write(path, serde_json::Value::Object(all).to_string());
+}
+
+fn load_equalizers() -> serde_json::Map<String, serde_json::Value> {
+    let path = data_dir(&["equalizer"]);
+    std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+/// Playback speed per podcast show browse ID; `""` holds the one for songs.
+pub fn load_speeds() -> HashMap<String, f32> {
+    let path = data_dir(&["speeds"]);
+    std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|s| serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(&s).ok())
+        .map(|all| {
+            all.into_iter()
+                .filter_map(|(show, speed)| Some((show, speed.as_f64()? as f32)))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn save_speeds(speeds: &HashMap<String, f32>) {
+    let path = data_dir(&["speeds"]);
+    let _ = std::fs::write(path, serde_json::json!(speeds).to_string());
+}
+
+/// ID of the chosen output device; `None` (no file) follows the system default.
+pub fn load_output() -> Option<String> {
+    let path = data_dir(&["output"]);
+    std::fs::read_to_string(&path)
+        .ok()
+        .map(|s| s.trim().to_owned())
+        .filter(|s| !s.is_empty())
+}
+
+pub fn save_output(id: Option<&str>) {
+    let path = data_dir(&["output"]);
+    let _ = match id {
+        Some(id) => std::fs::write(path, id),
+        None => std::fs::remove_file(path),
+    };
+}
+
+/// Token the HTTP remote asks for; made up and saved on first use. Only the
+/// owner may read it: with it, anyone can control the player.
+pub fn load_remote_token() -> String {
+    let path = data_dir(&["remote_token"]);
+    if let Some(token) = std::fs::read_to_string(&path)
+        .ok()
+        .map(|s| s.trim().to_owned())
+        .filter(|s| !s.is_empty())
+    {
+        // Older versions saved it with the default permissions.
+        #[cfg(unix)]
+        let _ = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600));
+        return token;
+    }
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    if let Err(e) = write_private(&path, token.as_bytes()) {
+        tracing::warn!(error = %e, "cannot save the HTTP remote token");
+    }
+    token
+}
+
+/// Write `contents` to a file only its owner can read.
+fn write_private(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(path)?;
+    // `mode` only applies when the file is created.
+    #[cfg(unix)]
+    file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    file.write_all(contents)
+}
+
+/// Where to scrobble: a JSON object of named services, as read by
+/// [`scrobbler::Service::all_from_json`]. Empty (no file) scrobbles nowhere.
+pub fn load_scrobble_services() -> Vec<scrobbler::Service> {
+    let path = data_dir(&["scrobble"]);
+    std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .map(|v| scrobbler::Service::all_from_json(&v))
+        .unwrap_or_default()
+}
+
+/// Queue and position of the last run; empty when there is none.
+pub fn load_session() -> Session {
+    let path = data_dir(&["session"]);
+    std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .map(|v| Session::from_json(&v))
+        .unwrap_or_default()
+}
+
+pub fn save_session(session: &Session) {
+    let path = data_dir(&["session"]);
+    // Written aside and renamed so a crash never leaves half a file.
+    let tmp = path.with_extension("tmp");
+    let result = std::fs::write(&tmp, session.to_json().to_string())
+        .and_then(|()| std::fs::rename(&tmp, &path));
+    if let Err(e) = result {
+        tracing::warn!(error = %e, "cannot save the playback session");
+    }
+}
@@ -0,0 +1,128 @@
+//! What front ends driving the player from outside share: finding and
+//! describing songs by video ID, and reporting the player state.
+
+use freya::radio::RadioStation;
+use serde_json::{Value, json};
+use ytdroid::client::Locale;
+use ytdroid::{SearchFilter, SearchResult, YTItem, YouTube};
+
+use crate::app::{Data, DataChannel, PlayerState, QueueSong};
+use crate::audio::{AudioCommand, AudioQuality};
+
+/// The signed-in session, or an anonymous one when there are no cookies.
+pub fn session(radio: &RadioStation<Data, DataChannel>) -> Option<YouTube> {
+    radio.read().yt_session.clone().or_else(|| {
+        YouTube::new(None, Locale::default())
+            .inspect_err(|e| tracing::error!(error = %e, "failed to create YT client"))
+            .ok()
+    })
+}
+
+/// Title, channel and thumbnail of a video, as the player endpoint knows them.
+pub async fn song_details(yt: &YouTube, video_id: &str) -> Result<QueueSong, String> {
+    let response = yt.player(video_id, None).await.map_err(|e| e.to_string())?;
+    let details = response
+        .video_details
+        .ok_or_else(|| format!("no video `{video_id}`"))?;
+    Ok(QueueSong {
+        video_id: video_id.to_owned(),
+        title: details.title.unwrap_or_default(),
+        artist: details.author.unwrap_or_default(),
+        album: String::new(),
+        thumbnail_url: details
+            .thumbnail
+            .as_ref()
+            .and_then(|t| t.best_url())
+            .unwrap_or_default()
+            .to_owned(),
+        podcast: None,
+    })
+}
+
+/// Up to `limit` songs matching `query`, with their length in seconds.
+pub async fn search_songs(
+    yt: &YouTube,
+    query: &str,
+    limit: usize,
+) -> Result<Vec<(QueueSong, Option<u32>)>, String> {
+    let page = match yt.search(query, Some(&SearchFilter::SONGS)).await {
+        Ok(SearchResult::Filtered(page)) => page,
+        Ok(SearchResult::Summary(_)) => return Err("unexpected search result".into()),
+        Err(e) => return Err(e.to_string()),
+    };
+    Ok(page
+        .items
+        .iter()
+        .filter_map(|item| match item {
+            YTItem::Song(song) => Some((QueueSong::from(song.as_ref()), song.duration)),
+            _ => None,
+        })
+        .take(limit)
+        .collect())
+}
+
+/// Play `song` on its own at `quality`, replacing the queue.
+pub fn play_command(song: QueueSong, quality: AudioQuality) -> AudioCommand {
+    AudioCommand::Play {
+        video_id: song.video_id,
+        quality,
+        title: song.title,
+        artist: song.artist,
+        album: song.album,
+        thumbnail_url: song.thumbnail_url,
+        podcast: song.podcast,
+    }
+}
+
+/// Link to a song on YouTube Music.
+pub fn watch_url(video_id: &str) -> String {
+    format!("https://music.youtube.com/watch?v={video_id}")
+}
+
+/// The video ID in a bare ID, a `yt:` URI or a YouTube / YouTube Music link.
+pub fn video_id(uri: &str) -> Option<&str> {
+    let uri = uri.trim();
+    let id = if let Some(id) = uri.strip_prefix("yt:") {
+        id
+    } else if let Some((_, query)) = uri.split_once("watch?") {
+        query
+            .split('&')
+            .find_map(|param| param.strip_prefix("v="))?
+    } else if let Some((_, id)) = uri.split_once("youtu.be/") {
+        id.split(['?', '&']).next()?
+    } else {
+        uri
+    };
+    let valid = id.len() == 11
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    valid.then_some(id)
+}
+
+/// The player state as JSON: the current track, position and queue.
+pub fn status_json(p: &PlayerState) -> Value {
+    let current = p.queue.get(p.queue_index);
+    json!({
+        "state": match (current, p.is_playing) {
+            (None, _) => "stopped",
+            (Some(_), true) => "playing",
+            (Some(_), false) => "paused",
+        },
+        "buffering": p.is_buffering,
+        "video_id": current.map(|song| song.video_id.as_str()),
+        "title": p.title,
+        "artist": p.artist,
+        "album": p.album,
+        "thumbnail_url": p.thumbnail_url,
+        "position": p.current_secs,
+        "duration": p.total_secs,
+        "volume": p.volume,
+        "speed": p.speed,
+        "shuffle": p.shuffle,
+        "repeat": p.repeat.as_str(),
+        "autoplay": p.autoplay,
+        "queue_index": p.queue_index,
+        "queue": p.queue.iter().map(QueueSong::to_json).collect::<Vec<_>>(),
+    })
+}
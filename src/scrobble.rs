@@ -0,0 +1,105 @@
+//! Scrobbling to the services in the `scrobble` file of the data directory
+//! (see [`crate::prefs::load_scrobble_services`]). The player is looked at on
+//! each progress tick of the audio engine; [`scrobbler`] decides when a song
+//! counts and keeps failed listens in `scrobble_queue` until they go through.
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use freya::radio::RadioStation;
+use scrobbler::{Event, Scrobbler, Track, Tracker};
+use tokio::sync::mpsc;
+use tracing::info;
+
+use crate::app::{Data, DataChannel, PlayerState, QueueSong};
+use crate::utils::data_dir;
+
+/// How often listens that failed are tried again.
+const RETRY_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+enum Job {
+    NowPlaying(Track),
+    Scrobble(Track),
+}
+
+fn track(song: &QueueSong, p: &PlayerState) -> Track {
+    let started_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    Track {
+        artist: song.artist.clone(),
+        title: song.title.clone(),
+        album: song.album.clone(),
+        duration: (p.total_secs > 0.).then(|| p.total_secs.round() as u32),
+        url: Some(crate::remote::watch_url(&song.video_id)),
+        started_at,
+    }
+}
+
+/// Follow playback and scrobble it, if any service is configured.
+pub async fn run_scrobbler(radio: RadioStation<Data, DataChannel>) {
+    let services = crate::prefs::load_scrobble_services();
+    if services.is_empty() {
+        return;
+    }
+    let Some(mut progress) = radio.read().progress_tick.as_ref().map(|t| t.subscribe()) else {
+        return;
+    };
+    info!(services = services.len(), "scrobbling enabled");
+    let scrobbler = Scrobbler::new(services, data_dir(&["scrobble_queue"]));
+    let (tx, rx) = mpsc::unbounded_channel();
+    // Submissions wait on the network, so they do not hold up the ticks.
+    tokio::spawn(submit(scrobbler, rx));
+
+    let mut tracker = Tracker::default();
+    let mut listen = None;
+    let mut last_tick = Instant::now();
+    while progress.changed().await.is_ok() {
+        let elapsed = last_tick.elapsed().as_secs_f64();
+        last_tick = Instant::now();
+
+        let p = &radio.read().player;
+        // Podcast episodes are not music listens.
+        let song = p
+            .queue
+            .get(p.queue_index)
+            .filter(|song| song.podcast.is_none());
+        let event = tracker.observe(
+            song.map(|song| song.video_id.as_str()),
+            f64::from(p.current_secs),
+            f64::from(p.total_secs),
+            p.is_playing && !p.is_buffering,
+            elapsed,
+        );
+        let job = match (event, song) {
+            (Some(Event::Started), Some(song)) => {
+                let started = track(song, p);
+                listen = Some(started.clone());
+                Job::NowPlaying(started)
+            }
+            // The length is known by now even if it was not at the start.
+            (Some(Event::Reached), Some(song)) => match listen.take() {
+                Some(started) => Job::Scrobble(Track {
+                    duration: track(song, p).duration,
+                    ..started
+                }),
+                None => continue,
+            },
+            _ => continue,
+        };
+        tx.send(job).ok();
+    }
+}
+
+async fn submit(mut scrobbler: Scrobbler, mut rx: mpsc::UnboundedReceiver<Job>) {
+    let mut retry = tokio::time::interval(RETRY_INTERVAL);
+    loop {
+        tokio::select! {
+            job = rx.recv() => match job {
+                Some(Job::NowPlaying(track)) => scrobbler.now_playing(&track).await,
+                Some(Job::Scrobble(track)) => scrobbler.scrobble(track).await,
+                None => return,
+            },
+            _ = retry.tick() => scrobbler.retry().await,
+        }
+    }
+}
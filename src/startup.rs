@@ -1,34 +1,82 @@
 use freya::radio::RadioStation;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 use ytdroid::client::Locale;
+use ytdroid::filters::browse_id::is_podcast_show;
 use ytdroid::YouTube;
 
-use crate::app::{Data, DataChannel, NavCommand, PlaylistViewData};
-use crate::audio::{AudioCommand, AudioEngine, AudioQuality, run_audio_engine};
+use crate::app::{Data, DataChannel, DownloadCommand, NavCommand, PlaylistViewData};
+use crate::audio::{AudioCommand, AudioEngine, run_audio_engine};
+use crate::downloads::run_download_engine;
 
 pub async fn run_startup(mut radio: RadioStation<Data, DataChannel>) {
     let (engine, audio_rx) = AudioEngine::new();
     let (nav_tx, nav_rx) = mpsc::channel::<NavCommand>(16);
+    let (download_tx, download_rx) = mpsc::channel::<DownloadCommand>(16);
 
     let sender = engine.sender();
     {
         let mut state = radio.write_channel(DataChannel::Player);
         state.audio_cmd = Some(sender.clone());
+        state.progress_tick = Some(watch::channel(()).0);
         state.player.volume = crate::prefs::load_volume();
+        state.player.crossfade_secs = crate::prefs::load_crossfade();
+        state.player.normalization = crate::prefs::load_normalization();
+        state.player.shuffle = crate::prefs::load_shuffle();
+        state.player.repeat = crate::prefs::load_repeat();
+        state.player.autoplay = crate::prefs::load_autoplay();
+        state.player.cache_limit = crate::prefs::load_cache_limit();
     }
     {
         radio.write_channel(DataChannel::Navigation).nav_cmd = Some(nav_tx);
     }
+    {
+        radio.write_channel(DataChannel::Downloads).download_cmd = Some(download_tx);
+    }
 
     sender.try_send(AudioCommand::SetVolume(radio.read().player.volume)).ok();
+    sender
+        .try_send(AudioCommand::SetCrossfade(radio.read().player.crossfade_secs))
+        .ok();
+    sender
+        .try_send(AudioCommand::SetNormalization(radio.read().player.normalization))
+        .ok();
+    sender
+        .try_send(AudioCommand::SetShuffle(radio.read().player.shuffle))
+        .ok();
+    sender
+        .try_send(AudioCommand::SetRepeat(radio.read().player.repeat))
+        .ok();
+    sender
+        .try_send(AudioCommand::SetAutoplay(radio.read().player.autoplay))
+        .ok();
+    sender
+        .try_send(AudioCommand::SetCacheLimit(radio.read().player.cache_limit))
+        .ok();
+    // Sent after shuffle so the restored queue gets a shuffled order.
+    sender
+        .try_send(AudioCommand::Restore(crate::prefs::load_session()))
+        .ok();
 
     tokio::join!(
         startup_inner(radio),
         run_audio_engine(audio_rx, radio),
         run_nav_engine(nav_rx, radio),
+        run_download_engine(download_rx, radio),
+        run_media_controls(radio),
+        crate::mpd::run_mpd(radio),
+        crate::http::run_http(radio),
+        crate::scrobble::run_scrobbler(radio),
     );
 }
 
+/// Desktop media controls: MPRIS on Linux, nothing elsewhere yet.
+async fn run_media_controls(radio: RadioStation<Data, DataChannel>) {
+    #[cfg(target_os = "linux")]
+    crate::mpris::run_mpris(radio).await;
+    #[cfg(not(target_os = "linux"))]
+    let _ = radio;
+}
+
 async fn startup_inner(mut radio: RadioStation<Data, DataChannel>) {
     let Some(cookie_header) = radio.read().cookie_header.clone() else {
         tracing::warn!("no cookies available — skipping YT client init");
@@ -145,6 +193,7 @@ async fn run_nav_engine(
                             let mut state = radio.write_channel(DataChannel::Navigation);
                             state.playlist_view = Some(PlaylistViewData {
                                 playlist: page.playlist,
+                                podcast: is_podcast_show(&browse_id).then_some(browse_id),
                                 songs,
                                 suggestions,
                                 related_playlists,
@@ -173,7 +222,7 @@ async fn run_nav_engine(
                         if let Some(first) = page.songs.first() {
                             tx.try_send(AudioCommand::Play {
                                 video_id: first.id.clone(),
-                                quality: AudioQuality::Medium,
+                                quality: radio.read().player.quality,
                                 title: first.title.clone(),
                                 artist: first
                                     .artists
@@ -186,6 +235,7 @@ async fn run_nav_engine(
                                     .map(|a| a.name.clone())
                                     .unwrap_or_default(),
                                 thumbnail_url: first.thumbnail.clone().unwrap_or_default(),
+                                podcast: None,
                             })
                             .ok();
                         }